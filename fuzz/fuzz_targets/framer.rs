@@ -0,0 +1,27 @@
+//! Feeds arbitrary bytes split at arbitrary places to the framers, run it with
+//! `cargo +nightly fuzz run framer`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ttytee::framing::{Frame, Protocol};
+use ttytee::testing::{assert_resyncs, assert_split_invariant};
+
+const VALID: &[u8] = b"$GPGSA,A,3,04*3A\r\n";
+
+fuzz_target!(|input: (Vec<usize>, Vec<u8>)| {
+    let (splits, data) = input;
+    for protocols in [
+        &[Protocol::Nmea, Protocol::Ubx, Protocol::Rtcm][..],
+        &[Protocol::Nmea],
+        &[Protocol::Ubx],
+        &[Protocol::Rtcm],
+    ] {
+        assert_split_invariant(protocols, &data, &splits);
+    }
+    let valid = Frame {
+        protocol: Protocol::Nmea,
+        data: VALID.to_vec(),
+    };
+    assert_resyncs(&[Protocol::Nmea, Protocol::Ubx, Protocol::Rtcm], &data, &[valid]);
+});
@@ -0,0 +1,132 @@
+//! Access log of the consumers: each process opening or closing a PTY endpoint and each client
+//! connecting to or disconnecting from a TCP endpoint is logged with the number of accesses so far,
+//! an audit trail of which software consumed the stream and when.
+//!
+//! The PTYs are checked every second through /proc, a consumer opening and closing a PTY in between
+//! is missed. The TCP clients are noticed when the data is written to them.
+
+use crate::endpoint::ManagedEndpoint;
+use log::info;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// How often the consumers are checked.
+const CHECK_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct EndpointAccesses {
+    // the consumers attached at the last check, with the time they were first seen.
+    attached: HashMap<String, Instant>,
+    opens: u64,
+}
+
+/// Logs the consumers attaching to and detaching from the endpoints.
+pub struct AccessLog {
+    last_check: Option<Instant>,
+    endpoints: HashMap<String, EndpointAccesses>,
+}
+
+impl AccessLog {
+    pub fn new() -> Self {
+        Self {
+            last_check: None,
+            endpoints: HashMap::new(),
+        }
+    }
+
+    /// Look for the consumers if it is time to, and log the changes.
+    pub fn poll(&mut self, now: Instant, endpoints: &[ManagedEndpoint]) {
+        if matches!(self.last_check, Some(last_check) if now.duration_since(last_check) < CHECK_PERIOD)
+        {
+            return;
+        }
+        self.last_check = Some(now);
+        for endpoint in endpoints {
+            if let Some(consumers) = endpoint.endpoint.consumers() {
+                for line in self.update(&endpoint.name, consumers, now) {
+                    info!("Access: {}", line);
+                }
+            }
+        }
+    }
+
+    /// Compare the consumers of an endpoint with the previous ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: the name of the endpoint.
+    /// * `consumers`: the consumers attached now.
+    /// * `now`: the current time.
+    ///
+    /// returns: Vec<String> a line per consumer that attached or detached.
+    ///
+    fn update(&mut self, name: &str, consumers: Vec<String>, now: Instant) -> Vec<String> {
+        let accesses = self.endpoints.entry(name.to_string()).or_default();
+        let mut lines = Vec::new();
+        let mut closed: Vec<(String, Instant)> = accesses
+            .attached
+            .iter()
+            .filter(|(consumer, _)| !consumers.contains(consumer))
+            .map(|(consumer, &since)| (consumer.clone(), since))
+            .collect();
+        closed.sort();
+        for (consumer, since) in closed {
+            accesses.attached.remove(&consumer);
+            lines.push(format!(
+                "{} closed {} after {} s, {} attached now.",
+                consumer,
+                name,
+                now.saturating_duration_since(since).as_secs(),
+                accesses.attached.len()
+            ));
+        }
+        for consumer in consumers {
+            if accesses.attached.contains_key(&consumer) {
+                continue;
+            }
+            accesses.opens += 1;
+            accesses.attached.insert(consumer.clone(), now);
+            lines.push(format!(
+                "{} opened {}, {} opens so far, {} attached now.",
+                consumer,
+                name,
+                accesses.opens,
+                accesses.attached.len()
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::access::AccessLog;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_accesses() {
+        let mut log = AccessLog::new();
+        let start = Instant::now();
+        let gpsd = "pid 42 (gpsd)".to_string();
+        let chrony = "pid 43 (chronyd)".to_string();
+        assert_eq!(
+            log.update("slave0", vec![gpsd.clone()], start),
+            vec!["pid 42 (gpsd) opened slave0, 1 opens so far, 1 attached now."]
+        );
+        assert!(log
+            .update("slave0", vec![gpsd.clone()], start + Duration::from_secs(1))
+            .is_empty());
+        assert_eq!(
+            log.update("slave0", vec![chrony], start + Duration::from_secs(90)),
+            vec![
+                "pid 42 (gpsd) closed slave0 after 90 s, 0 attached now.",
+                "pid 43 (chronyd) opened slave0, 2 opens so far, 1 attached now."
+            ]
+        );
+        // counted per endpoint.
+        assert_eq!(
+            log.update("net", vec!["10.0.0.3:51234".to_string()], start),
+            vec!["10.0.0.3:51234 opened net, 1 opens so far, 1 attached now."]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Statistics of a capture file, for the investigations after the fact: how long it lasted, how
+//! the throughput changed, which messages the device sent, where the stream stopped and how much of
+//! it was corrupted.
+
+use crate::endpoint::capture::parse;
+use crate::framing::{Framer, Protocol};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// The report of a capture file.
+///
+/// # Arguments
+///
+/// * `data`: the content of the capture file.
+/// * `interval`: the period of the throughput table.
+/// * `min_gap`: the silences of the master reported as gaps are at least this long.
+///
+/// returns: Result<String, String> the report, or where the file is corrupted.
+///
+pub fn analyze(data: &[u8], interval: Duration, min_gap: Duration) -> Result<String, String> {
+    let capture = parse(data)?;
+    let mut report = capture.headers.clone();
+    let (Some(first), Some(last)) = (capture.chunks.first(), capture.chunks.last()) else {
+        report += "No data.\n";
+        return Ok(report);
+    };
+    let duration = Duration::from_micros(last.micros.saturating_sub(first.micros));
+    let bytes: usize = capture.chunks.iter().map(|chunk| chunk.data.len()).sum();
+    writeln!(
+        report,
+        "Duration: {:.1} s, {} bytes in {} chunks, {:.1} B/s on average.",
+        duration.as_secs_f64(),
+        bytes,
+        capture.chunks.len(),
+        bytes as f64 / duration.as_secs_f64().max(1.0)
+    )
+    .unwrap();
+
+    // the bytes received in each interval since the first chunk.
+    let interval_micros = interval.as_micros().max(1) as u64;
+    let mut throughput: BTreeMap<u64, usize> = BTreeMap::new();
+    let mut framer = Framer::new(&[Protocol::Nmea, Protocol::Ubx, Protocol::Rtcm]);
+    let mut frames = Vec::new();
+    let mut messages: BTreeMap<String, usize> = BTreeMap::new();
+    let mut gaps = Vec::new();
+    let mut previous = first.micros;
+    for chunk in &capture.chunks {
+        let elapsed = chunk.micros.saturating_sub(first.micros);
+        *throughput.entry(elapsed / interval_micros).or_default() += chunk.data.len();
+        let silence = Duration::from_micros(chunk.micros.saturating_sub(previous));
+        if silence >= min_gap {
+            gaps.push((previous.saturating_sub(first.micros), silence));
+        }
+        previous = chunk.micros;
+        frames.clear();
+        framer.push(chunk.data, &mut frames);
+        for frame in &frames {
+            *messages.entry(frame.message_type()).or_default() += 1;
+        }
+    }
+
+    report += "Throughput:\n";
+    for (index, bytes) in throughput {
+        writeln!(
+            report,
+            "  {:>8} s  {:.1} B/s",
+            index * interval.as_secs(),
+            bytes as f64 / interval.as_secs_f64()
+        )
+        .unwrap();
+    }
+    report += "Messages:\n";
+    for (message_type, count) in messages {
+        writeln!(report, "  {:>8}  {}", message_type, count).unwrap();
+    }
+    writeln!(report, "Gaps of {} ms or more:", min_gap.as_millis()).unwrap();
+    for (at, silence) in &gaps {
+        writeln!(
+            report,
+            "  {:>8.1} s  {:.1} s",
+            Duration::from_micros(*at).as_secs_f64(),
+            silence.as_secs_f64()
+        )
+        .unwrap();
+    }
+    writeln!(
+        report,
+        "Framing: {} bytes out of frames, {} invalid frames.",
+        framer.skipped_bytes(),
+        framer.checksum_errors()
+    )
+    .unwrap();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analyze::analyze;
+    use crate::endpoint::capture::{encode_chunk, CaptureHeader};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    const GGA: &[u8] = b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n";
+    const START: u64 = 1_699_963_200_000_000;
+
+    #[test]
+    fn test_analyze() {
+        let mut capture = CaptureHeader {
+            device: PathBuf::from("/dev/ttyUSB0"),
+            baudrate: 9600,
+        }
+        .encode("2023-11-14T12:00:00+0000");
+        // 1 Hz for 10 s, a silence of 5 s then a corrupted sentence.
+        for second in 0..10 {
+            capture.extend(encode_chunk(GGA, START + second * 1_000_000));
+        }
+        capture.extend(encode_chunk(b"$GPGGA,0*00\r\n", START + 14_000_000));
+        let report = analyze(&capture, Duration::from_secs(5), Duration::from_secs(2)).unwrap();
+        assert!(report.starts_with("device=/dev/ttyUSB0\n"));
+        assert!(report.contains("Duration: 14.0 s, 683 bytes in 11 chunks, 48.8 B/s on average.\n"));
+        assert!(report.contains(
+            "Throughput:\n         0 s  67.0 B/s\n         5 s  67.0 B/s\n        10 s  2.6 B/s\n"
+        ));
+        assert!(report.contains("Messages:\n       GGA  10\n"));
+        assert!(report.contains("Gaps of 2000 ms or more:\n       9.0 s  5.0 s\n"));
+        assert!(report.ends_with("Framing: 13 bytes out of frames, 1 invalid frames.\n"));
+    }
+}
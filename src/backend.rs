@@ -0,0 +1,231 @@
+//! How the master device is opened and configured: serialport by default, or with the raw-linux
+//! feature `--master-backend raw-linux` opening it with libc and setting its termios2 directly, with
+//! VMIN/VTIME. Either way the reader polls the file descriptor and reads it directly.
+//!
+//! Both backends take any baudrate, with termios2 and BOTHER on Linux, like 250000 or 921600 on the
+//! SoCs with no such standard rate: the rate the driver really set is read back, and a device that
+//! cannot do it is an error naming both rates instead of a master silently sending garbage.
+
+use crate::rs485;
+use crate::rs485::Rs485Mode;
+use crate::termios::get_termios;
+use clap::ValueEnum;
+use serialport::TTYPort;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+
+/// The code opening the master.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum MasterBackend {
+    /// The serialport crate.
+    #[default]
+    Serialport,
+    /// termios2 set directly, build with --features raw-linux.
+    RawLinux,
+}
+
+/// How to open and configure the master.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MasterSettings {
+    pub backend: MasterBackend,
+    pub baudrate: u32,
+    // VMIN and VTIME of the raw-linux backend.
+    pub vmin: u8,
+    pub vtime: u8,
+    // the half-duplex mode of a RS-485 master.
+    pub rs485: Option<Rs485Mode>,
+    // with the frames delimited by silences, a read lasts until the line is silent this long.
+    pub frame_gap: Option<Duration>,
+}
+
+/// Open a device as the master, for exclusive use.
+///
+/// # Arguments
+///
+/// * `device`: the device.
+/// * `settings`: how to open and configure it.
+///
+/// returns: Result<TTYPort, Error>
+///
+pub fn open_device(device: &Path, settings: &MasterSettings) -> io::Result<TTYPort> {
+    let mut port = match settings.backend {
+        MasterBackend::Serialport => serialport::new(device.to_string_lossy(), settings.baudrate)
+            .open_native()
+            .map_err(|err| match err.kind {
+                serialport::ErrorKind::InvalidInput => io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{:?} does not take {} bauds: {}",
+                        device, settings.baudrate, err
+                    ),
+                ),
+                _ => err.into(),
+            })?,
+        #[cfg(feature = "raw-linux")]
+        MasterBackend::RawLinux => raw::open(device, settings)?,
+        #[cfg(not(feature = "raw-linux"))]
+        MasterBackend::RawLinux => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "ttytee was built without the raw-linux feature",
+            ))
+        }
+    };
+    check_baudrate(&port, device, settings.baudrate)?;
+    port.set_exclusive(true)?;
+    if let Some(mode) = settings.rs485 {
+        rs485::configure(&mut port, mode)?;
+    }
+    Ok(port)
+}
+
+/// Check that the driver of a device set the baudrate asked, it may not do a non-standard one.
+///
+/// # Arguments
+///
+/// * `port`: the device, configured.
+/// * `device`: its path, for the error.
+/// * `baudrate`: the baudrate asked.
+///
+/// returns: Result<(), Error> an InvalidInput error with the two rates if they differ.
+///
+pub fn check_baudrate(port: &TTYPort, device: &Path, baudrate: u32) -> io::Result<()> {
+    let applied = get_termios(port.as_raw_fd())?;
+    if applied.c_ospeed != baudrate {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "the driver of {:?} cannot do {} bauds, it set {}",
+                device, baudrate, applied.c_ospeed
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{open_device, MasterSettings};
+    use crate::termios::get_termios;
+    use serialport::{SerialPort, TTYPort};
+    use std::os::unix::io::AsRawFd;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_non_standard_baudrate() {
+        let (_gps, device) = TTYPort::pair().unwrap();
+        let settings = MasterSettings {
+            baudrate: 250000,
+            ..Default::default()
+        };
+        let master = open_device(&PathBuf::from(device.name().unwrap()), &settings).unwrap();
+        assert_eq!(get_termios(master.as_raw_fd()).unwrap().c_ospeed, 250000);
+    }
+}
+
+#[cfg(feature = "raw-linux")]
+mod raw {
+    use crate::backend::MasterSettings;
+    use crate::termios::{get_termios, set_termios};
+    use serialport::TTYPort;
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// The settings of a raw 8N1 line without flow control at any baudrate, like cfmakeraw.
+    pub fn make_raw(termios: &mut libc::termios2, settings: &MasterSettings) {
+        termios.c_iflag &= !(libc::IGNBRK
+            | libc::BRKINT
+            | libc::PARMRK
+            | libc::ISTRIP
+            | libc::INLCR
+            | libc::IGNCR
+            | libc::ICRNL
+            | libc::IXON
+            | libc::IXOFF
+            | libc::IXANY);
+        termios.c_oflag &= !libc::OPOST;
+        termios.c_lflag &= !(libc::ECHO | libc::ECHONL | libc::ICANON | libc::ISIG | libc::IEXTEN);
+        termios.c_cflag &= !(libc::CSIZE
+            | libc::PARENB
+            | libc::CSTOPB
+            | libc::CRTSCTS
+            | libc::CBAUD
+            | (libc::CBAUD << libc::IBSHIFT));
+        // BOTHER: the speeds are the numbers in c_ispeed and c_ospeed.
+        termios.c_cflag |=
+            libc::CS8 | libc::CREAD | libc::CLOCAL | libc::BOTHER | (libc::BOTHER << libc::IBSHIFT);
+        termios.c_ispeed = settings.baudrate;
+        termios.c_ospeed = settings.baudrate;
+        termios.c_cc[libc::VMIN] = settings.vmin;
+        termios.c_cc[libc::VTIME] = settings.vtime;
+    }
+
+    /// Open a device and set its termios2.
+    pub fn open(device: &Path, settings: &MasterSettings) -> io::Result<TTYPort> {
+        let path = CString::new(device.as_os_str().as_bytes())?;
+        // not blocked by the carrier detect line until CLOCAL is set.
+        let fd = unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // closed on the errors.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let raw_fd = fd.as_raw_fd();
+        let mut termios = get_termios(raw_fd)?;
+        make_raw(&mut termios, settings);
+        set_termios(raw_fd, &termios)?;
+        // the reads block, the reader polls before reading.
+        let flags = unsafe { libc::fcntl(raw_fd, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(raw_fd, libc::F_SETFL, flags & !libc::O_NONBLOCK) } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { TTYPort::from_raw_fd(fd.into_raw_fd()) })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::backend::raw::open;
+        use crate::backend::{MasterBackend, MasterSettings};
+        use crate::termios::get_termios;
+        use serialport::{SerialPort, TTYPort};
+        use std::io::{Read, Write};
+        use std::os::unix::io::AsRawFd;
+        use std::path::PathBuf;
+        use std::time::Duration;
+
+        #[test]
+        fn test_open_raw() {
+            let (mut gps, device) = TTYPort::pair().unwrap();
+            let settings = MasterSettings {
+                backend: MasterBackend::RawLinux,
+                baudrate: 250000,
+                vmin: 4,
+                vtime: 1,
+                rs485: None,
+                frame_gap: None,
+            };
+            let mut master = open(&PathBuf::from(device.name().unwrap()), &settings).unwrap();
+            let termios = get_termios(master.as_raw_fd()).unwrap();
+            assert_eq!(termios.c_ospeed, 250000);
+            assert_eq!(termios.c_cc[libc::VMIN], 4);
+            assert_eq!(termios.c_lflag & libc::ICANON, 0);
+            // raw, the line ends are not translated.
+            master.set_timeout(Duration::from_secs(1)).unwrap();
+            gps.write_all(b"\r\n\x00\xff").unwrap();
+            let mut buffer = [0; 4];
+            master.read_exact(&mut buffer).unwrap();
+            assert_eq!(&buffer, b"\r\n\x00\xff");
+        }
+    }
+}
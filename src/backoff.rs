@@ -0,0 +1,73 @@
+//! Exponential backoff state, kept per endpoint so an error on one never delays the others.
+
+use std::time::{Duration, Instant};
+
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    /// Create a backoff.
+    ///
+    /// # Arguments
+    ///
+    /// * `min`: the delay after the first error.
+    /// * `max`: the delay doubles at each consecutive error up to this.
+    ///
+    /// returns: Backoff
+    ///
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            next: min,
+            retry_at: None,
+        }
+    }
+
+    /// Whether the endpoint can be used again at this time.
+    pub fn is_ready(&self, now: Instant) -> bool {
+        !matches!(self.retry_at, Some(retry_at) if now < retry_at)
+    }
+
+    /// Account for an error, returns how long the endpoint should be left alone.
+    pub fn failure(&mut self, now: Instant) -> Duration {
+        let delay = self.next;
+        self.retry_at = Some(now + delay);
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    /// Account for a success, the next error starts over from the minimum delay.
+    pub fn success(&mut self) {
+        self.next = self.min;
+        self.retry_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_exponential_backoff() {
+        let ms = Duration::from_millis;
+        let mut backoff = Backoff::new(ms(50), ms(300));
+        let now = Instant::now();
+        assert!(backoff.is_ready(now));
+        assert_eq!(backoff.failure(now), ms(50));
+        assert!(!backoff.is_ready(now + ms(49)));
+        assert!(backoff.is_ready(now + ms(50)));
+        assert_eq!(backoff.failure(now), ms(100));
+        assert_eq!(backoff.failure(now), ms(200));
+        assert_eq!(backoff.failure(now), ms(300));
+        assert_eq!(backoff.failure(now), ms(300));
+        backoff.success();
+        assert!(backoff.is_ready(now));
+        assert_eq!(backoff.failure(now), ms(50));
+    }
+}
@@ -0,0 +1,171 @@
+//! Banners injected into the PTY endpoints when a consumer attaches, for the legacy applications
+//! that expect the real device to greet them, like an identification or the startup sentences of
+//! the receiver.
+//!
+//! The banner is the `banner` endpoint option, with the escapes of the Rust byte strings (`\r`,
+//! `\n`, `\t`, `\\` and `\xHH`), for example `--endpoint-option 'slave1:banner=$PMTK705*1D\r\n'`.
+//! The delivery to such a PTY is paused while no consumer has it open, so when one attaches the
+//! banner is the first thing it reads, then the data of the master from the next read (or frame).
+
+use crate::consumers::consumer_pids;
+use crate::endpoint::ManagedEndpoint;
+use log::{info, warn};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// How often the consumers of the PTYs with a banner are looked for.
+const CHECK_PERIOD: Duration = Duration::from_millis(250);
+
+/// Parse a banner from its escaped form.
+///
+/// # Arguments
+///
+/// * `value`: the text of the banner, like `$PMTK705*1D\r\n`.
+///
+/// returns: Result<Vec<u8>, String> the bytes to write.
+///
+pub fn parse_banner(value: &str) -> Result<Vec<u8>, String> {
+    let mut banner = Vec::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            banner.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => banner.push(b'\r'),
+            Some('n') => banner.push(b'\n'),
+            Some('t') => banner.push(b'\t'),
+            Some('0') => banner.push(0),
+            Some(c @ ('\\' | '\'' | '"')) => banner.push(c as u8),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 2)
+                    .ok_or_else(|| format!("invalid escape \\x{}", hex))?;
+                banner.push(byte);
+            }
+            Some(c) => return Err(format!("invalid escape \\{}", c)),
+            None => return Err("the text ends with a \\".to_string()),
+        }
+    }
+    Ok(banner)
+}
+
+/// Writes the banners to the PTYs their consumers attach to.
+pub struct Banners {
+    // the master is split into frames, the delivery then resumes at a frame boundary.
+    framed: bool,
+    last_check: Option<Instant>,
+    // the endpoints paused until a consumer attaches.
+    waiting: HashSet<String>,
+}
+
+impl Banners {
+    pub fn new(framed: bool) -> Self {
+        Self {
+            framed,
+            last_check: None,
+            waiting: HashSet::new(),
+        }
+    }
+
+    /// Look for the new consumers if it is time to, and greet them.
+    pub fn poll(&mut self, now: Instant, endpoints: &mut [ManagedEndpoint]) {
+        if matches!(self.last_check, Some(last_check) if now.duration_since(last_check) < CHECK_PERIOD)
+        {
+            return;
+        }
+        self.last_check = Some(now);
+        for endpoint in endpoints.iter_mut() {
+            if endpoint.options.banner.is_none() {
+                continue;
+            }
+            let Some(device) = endpoint.endpoint.device().map(PathBuf::from) else {
+                continue;
+            };
+            let attached = !consumer_pids(&[device]).is_empty();
+            self.greet(endpoint, attached);
+        }
+    }
+
+    fn greet(&mut self, endpoint: &mut ManagedEndpoint, attached: bool) {
+        if !attached {
+            // unless it was paused from the control socket.
+            if !endpoint.is_paused() {
+                endpoint.pause();
+                self.waiting.insert(endpoint.name.clone());
+            }
+            return;
+        }
+        if !self.waiting.remove(&endpoint.name) {
+            return;
+        }
+        let banner = endpoint.options.banner.clone().unwrap_or_default();
+        match endpoint
+            .resume(self.framed)
+            .and_then(|_| endpoint.inject(&banner))
+        {
+            Ok(()) => info!("A consumer attached to {}, banner sent.", endpoint.name),
+            Err(err) => warn!("Could not send the banner to {}: {}.", endpoint.name, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::banner::{parse_banner, Banners, CHECK_PERIOD};
+    use crate::endpoint::pty::PtyEndpoint;
+    use crate::endpoint::{EndpointOptions, ManagedEndpoint};
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+    use std::slice;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_banner() {
+        assert_eq!(
+            parse_banner(r"$PMTK705*1D\r\n\x00\\").unwrap(),
+            b"$PMTK705*1D\r\n\0\\"
+        );
+        assert!(parse_banner(r"\q").is_err());
+        assert!(parse_banner(r"\x4").is_err());
+        assert!(parse_banner(r"\xzz").is_err());
+        assert!(parse_banner("\\").is_err());
+    }
+
+    #[test]
+    fn test_banner_on_attach() {
+        let link = PathBuf::from("/tmp/ttytee_banner_test.pty");
+        let mut options = EndpointOptions::default();
+        options.set("banner", r"HELLO\r\n").unwrap();
+        let mut endpoint = ManagedEndpoint::new(
+            "legacy",
+            Box::new(PtyEndpoint::create(&link).unwrap()),
+            options,
+            Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+        );
+        let mut banners = Banners::new(false);
+        let start = Instant::now();
+        // nobody is there yet, nothing is delivered.
+        banners.poll(start, slice::from_mut(&mut endpoint));
+        endpoint.send(b"old", &[], 0, start).unwrap();
+
+        let consumer = Command::new("head")
+            .args(["-c", "10"])
+            .stdin(File::open(&link).unwrap())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        banners.poll(start + CHECK_PERIOD, slice::from_mut(&mut endpoint));
+        // only once per attachment.
+        banners.poll(start + CHECK_PERIOD * 2, slice::from_mut(&mut endpoint));
+        endpoint.send(b"$GP", &[], 0, start).unwrap();
+        let output = consumer.wait_with_output().unwrap();
+        assert_eq!(output.stdout, b"HELLO\r\n$GP");
+    }
+}
@@ -0,0 +1,111 @@
+//! The baudrate of the master reported by the PTYs: a consumer reading the settings of its PTY gets
+//! the baudrate of the master, and a consumer setting another one on its PTY is warned about, once
+//! per change. The rate of a PTY changes nothing to the data, but such a consumer usually expects
+//! another device, or is configured for another receiver, and will not make sense of the stream.
+
+use crate::endpoint::ManagedEndpoint;
+use crate::events::Event;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// How often the rates of the PTYs are read.
+const CHECK_PERIOD: Duration = Duration::from_secs(1);
+
+/// Watches the baudrates the consumers set on their PTYs.
+pub struct BaudrateWatch {
+    // the baudrate of the master.
+    baudrate: u32,
+    last_check: Option<Instant>,
+    // the other rates already warned about, by endpoint.
+    mismatches: HashMap<String, u32>,
+}
+
+impl BaudrateWatch {
+    pub fn new(baudrate: u32) -> Self {
+        Self {
+            baudrate,
+            last_check: None,
+            mismatches: HashMap::new(),
+        }
+    }
+
+    /// Read the rates of the PTYs if it is time to, and warn about the new mismatches.
+    pub fn poll(&mut self, now: Instant, endpoints: &[ManagedEndpoint]) {
+        if matches!(self.last_check, Some(last_check) if now.duration_since(last_check) < CHECK_PERIOD)
+        {
+            return;
+        }
+        self.last_check = Some(now);
+        for endpoint in endpoints {
+            let Some(baudrate) = endpoint.endpoint.baudrate() else {
+                continue;
+            };
+            if baudrate == self.baudrate {
+                if self.mismatches.remove(&endpoint.name).is_some() {
+                    info!(
+                        "{} is back at the {} bauds of the master.",
+                        endpoint.name, baudrate
+                    );
+                }
+                continue;
+            }
+            if self.mismatches.insert(endpoint.name.clone(), baudrate) == Some(baudrate) {
+                continue;
+            }
+            let consumers = endpoint.endpoint.consumers().unwrap_or_default();
+            warn!(
+                event = Event::BaudrateMismatch.code();
+                "{} was set to {} bauds by {}, the master runs at {}: the consumer may expect another device.",
+                endpoint.name,
+                baudrate,
+                if consumers.is_empty() {
+                    "its consumer".to_string()
+                } else {
+                    consumers.join(", ")
+                },
+                self.baudrate
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::baudrate::{BaudrateWatch, CHECK_PERIOD};
+    use crate::endpoint::pty::PtyEndpoint;
+    use crate::endpoint::{EndpointOptions, ManagedEndpoint};
+    use serialport::{SerialPort, TTYPort};
+    use std::path::PathBuf;
+    use std::slice;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_baudrate_watch() {
+        let link = PathBuf::from("/tmp/ttytee_baudrate_test.pty");
+        let mut pty = PtyEndpoint::create(&link).unwrap();
+        pty.set_baudrate(115200).unwrap();
+        let endpoint = ManagedEndpoint::new(
+            "slave0",
+            Box::new(pty),
+            EndpointOptions::default(),
+            Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+        );
+        // what a consumer reading the settings gets.
+        assert_eq!(endpoint.endpoint.baudrate(), Some(115200));
+        let mut consumer =
+            TTYPort::open(&serialport::new(link.to_string_lossy(), 9600).timeout(Duration::ZERO))
+                .unwrap();
+        let mut watch = BaudrateWatch::new(115200);
+        let start = Instant::now();
+        // opened at 9600.
+        watch.poll(start, slice::from_ref(&endpoint));
+        assert_eq!(watch.mismatches.get("slave0"), Some(&9600));
+        consumer.set_baud_rate(115200).unwrap();
+        watch.poll(start + CHECK_PERIOD / 2, slice::from_ref(&endpoint));
+        assert!(watch.mismatches.contains_key("slave0"));
+        watch.poll(start + CHECK_PERIOD, slice::from_ref(&endpoint));
+        assert!(watch.mismatches.is_empty());
+    }
+}
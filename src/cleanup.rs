@@ -0,0 +1,99 @@
+//! Last resort cleanup when ttytee panics.
+//!
+//! The resources that would outlive the process (symlinks) or that would prevent a restart
+//! (exclusive access to the master) are registered here while they are alive, so the panic hook
+//! can release them even when the panic does not unwind (ie. `panic = "abort"` in the stripped
+//! profile) or happens in another thread than the one owning them.
+
+use crate::events::Event;
+use crate::recorder::{dump_path, dump_ring_file};
+use log::error;
+use std::fs::remove_file;
+use std::os::unix::io::RawFd;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::{Mutex, MutexGuard};
+
+/// Exit code of the process after a panic.
+pub const PANIC_EXIT_CODE: i32 = 3;
+
+struct Registry {
+    symlinks: Vec<PathBuf>,
+    master_fd: Option<RawFd>,
+    flight_recorder: Option<PathBuf>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    symlinks: Vec::new(),
+    master_fd: None,
+    flight_recorder: None,
+});
+
+fn registry() -> MutexGuard<'static, Registry> {
+    // nothing can be left inconsistent in the registry, ignore the poisoning.
+    REGISTRY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Remember a symlink to remove if we panic.
+pub fn register_symlink(path: &Path) {
+    registry().symlinks.push(path.to_path_buf());
+}
+
+/// Forget a symlink that has been cleaned up normally.
+pub fn unregister_symlink(path: &Path) {
+    registry().symlinks.retain(|symlink| symlink != path);
+}
+
+/// Remember the master device to release if we panic, None once it is closed.
+pub fn register_master(fd: Option<RawFd>) {
+    registry().master_fd = fd;
+}
+
+/// Remember the flight recorder to dump if we panic, None once it is closed.
+pub fn register_flight_recorder(path: Option<&Path>) {
+    registry().flight_recorder = path.map(Path::to_path_buf);
+}
+
+/// Log panics, clean up the registered resources and exit with PANIC_EXIT_CODE.
+pub fn install_panic_hook() {
+    log_panics::init();
+    let log_panic = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        log_panic(info);
+        // if the panic happened while holding the registry, don't deadlock on it.
+        let registry = match REGISTRY.try_lock() {
+            Ok(registry) => registry,
+            Err(_) => {
+                error!("Could not clean up after the panic, the registry is locked.");
+                exit(PANIC_EXIT_CODE);
+            }
+        };
+        if let Some(ring) = &registry.flight_recorder {
+            let dump = dump_path(ring);
+            match dump_ring_file(ring, &dump) {
+                Ok(_) => error!("Flight recording dumped to {:?}.", dump),
+                Err(err) => error!("Could not dump the flight recording to {:?}: {}", dump, err),
+            }
+        }
+        for symlink in &registry.symlinks {
+            remove_file(symlink).ok();
+        }
+        if let Some(fd) = registry.master_fd {
+            // the exclusivity is released with the last close but be explicit about it.
+            unsafe {
+                libc::ioctl(fd, libc::TIOCNXCL);
+                libc::close(fd);
+            }
+        }
+        error!(
+            event = Event::Panic.code();
+            "ttytee panicked, cleaned up {} symlink(s) and exiting with {}.",
+            registry.symlinks.len(),
+            PANIC_EXIT_CODE
+        );
+        exit(PANIC_EXIT_CODE);
+    }));
+}
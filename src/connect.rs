@@ -0,0 +1,174 @@
+//! The consumer side of a TCP endpoint on another machine, serial over IP: `ttytee connect` reads a
+//! `tcp://` or a `tcpz://` endpoint and writes what it gets to a local PTY, the compressed stream is
+//! recognized by its magic. The connection is made again after a growing delay when it is lost,
+//! the PTY and its link stay.
+
+use crate::backoff::Backoff;
+use crate::endpoint::deflate::{Inflater, MAGIC};
+use crate::endpoint::pty::PtyEndpoint;
+use crate::endpoint::Endpoint;
+use log::{debug, info, warn};
+use std::io;
+use std::io::Read;
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often `running` is checked while nothing is received.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+const MIN_RETRY: Duration = Duration::from_millis(500);
+const MAX_RETRY: Duration = Duration::from_secs(30);
+
+// Copy a connection to the PTY until it is lost or `running` is cleared.
+fn forward(mut stream: TcpStream, pty: &mut PtyEndpoint, running: &AtomicBool) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    // the first bytes, until they tell if the stream is compressed.
+    let mut start = Vec::new();
+    let mut inflater: Option<Option<Inflater>> = None;
+    let mut buffer = [0; 4096];
+    while running.load(Ordering::Relaxed) {
+        let len = match stream.read(&mut buffer) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(len) => len,
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue
+            }
+            Err(err) => return Err(err),
+        };
+        let mut received = &buffer[..len];
+        if inflater.is_none() {
+            start.extend_from_slice(received);
+            if start.len() < MAGIC.len() && MAGIC.starts_with(&start) {
+                continue;
+            }
+            let compressed = start.starts_with(MAGIC);
+            debug!(
+                "The stream is {}.",
+                if compressed { "compressed" } else { "raw" }
+            );
+            inflater = Some(compressed.then(Inflater::default));
+            received = if compressed {
+                &start[MAGIC.len()..]
+            } else {
+                &start
+            };
+        }
+        let data = match &mut inflater {
+            Some(Some(inflater)) => inflater
+                .inflate(received)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            _ => received.to_vec(),
+        };
+        start = Vec::new();
+        // like the PTY endpoints, a consumer that does not keep up misses chunks.
+        match pty.write(&data) {
+            Err(err) if err.kind() != io::ErrorKind::WouldBlock => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Recreate a TCP endpoint as a local PTY, until `running` is cleared.
+///
+/// # Arguments
+///
+/// * `address`: the endpoint, for example `base.local:5000`.
+/// * `link`: where to create the symlink to the PTY.
+/// * `running`: cleared to stop.
+///
+/// returns: Result<(), Error> if the PTY cannot be created.
+///
+pub fn connect(address: &str, link: &Path, running: &AtomicBool) -> io::Result<()> {
+    let mut pty = PtyEndpoint::create(link)?;
+    let mut backoff = Backoff::new(MIN_RETRY, MAX_RETRY);
+    while running.load(Ordering::Relaxed) {
+        let err = match TcpStream::connect(address) {
+            Ok(stream) => {
+                info!("Connected to {}, writing to {:?}.", address, link);
+                backoff.success();
+                match forward(stream, &mut pty, running) {
+                    Ok(()) => return Ok(()),
+                    Err(err) => err,
+                }
+            }
+            Err(err) => err,
+        };
+        let delay = backoff.failure(Instant::now());
+        warn!(
+            "Lost {}: {}, connecting again in {} ms.",
+            address,
+            err,
+            delay.as_millis()
+        );
+        let retry_at = Instant::now() + delay;
+        while Instant::now() < retry_at && running.load(Ordering::Relaxed) {
+            thread::sleep(READ_TIMEOUT.min(retry_at.saturating_duration_since(Instant::now())));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connect::connect;
+    use crate::endpoint::tcp::TcpEndpoint;
+    use crate::endpoint::Endpoint;
+    use serialport::TTYPort;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_connect_compressed() {
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let mut endpoint = TcpEndpoint::bind(&address, true).unwrap();
+        let link = PathBuf::from("/tmp/ttytee_connect_test.pty");
+        let running = Arc::new(AtomicBool::new(true));
+        let client = {
+            let link = link.clone();
+            let running = running.clone();
+            thread::spawn(move || connect(&address, &link, &running).unwrap())
+        };
+        let start = Instant::now();
+        while !link.exists() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mut consumer = TTYPort::open(
+            &serialport::new(link.to_string_lossy(), 9600).timeout(Duration::from_millis(100)),
+        )
+        .unwrap();
+        // the client connects in the background.
+        while endpoint.consumers().unwrap().is_empty() && start.elapsed() < Duration::from_secs(5) {
+            endpoint.write(b"").unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        let sentence = b"$GPGGA,120000.00,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n";
+        let mut received = Vec::new();
+        let mut buffer = [0; 4096];
+        for _ in 0..10 {
+            endpoint.write(sentence).unwrap();
+            thread::sleep(Duration::from_millis(10));
+            while let Ok(len) = consumer.read(&mut buffer) {
+                received.extend_from_slice(&buffer[..len]);
+            }
+        }
+        assert_eq!(received, sentence.repeat(10));
+        running.store(false, Ordering::Relaxed);
+        client.join().unwrap();
+        assert!(!link.exists());
+    }
+}
@@ -0,0 +1,203 @@
+//! Detection of the processes that have the slave PTYs open.
+//!
+//! ttytee itself keeps both ends of its PTY pairs open so the kernel cannot tell us when a consumer
+//! attaches. Instead we look for other processes holding the slave devices in `/proc/*/fd`.
+
+use log::{info, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::fs::{read_dir, read_link};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the PTY openers are checked while waiting for them.
+const POLL_PERIOD: Duration = Duration::from_millis(100);
+
+/// How many consumers to wait for before reading from the master, and for how long at most.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConsumerBarrier {
+    pub count: usize,
+    pub timeout: Option<Duration>,
+}
+
+/// Parse a consumer barrier from the command line.
+///
+/// # Arguments
+///
+/// * `spec`: a string of the form `N` or `N:TIMEOUT` with TIMEOUT in ms.
+///
+/// returns: Result<ConsumerBarrier, String>
+///
+pub fn parse_consumer_barrier(spec: &str) -> Result<ConsumerBarrier, String> {
+    let (count, timeout) = match spec.split_once(':') {
+        Some((count, timeout)) => (count, Some(timeout)),
+        None => (spec, None),
+    };
+    let count = count
+        .trim()
+        .parse::<usize>()
+        .map_err(|err| format!("invalid consumer count {:?}: {}", count, err))?;
+    let timeout = match timeout {
+        Some(timeout) => Some(Duration::from_millis(
+            timeout
+                .trim()
+                .parse::<u64>()
+                .map_err(|err| format!("invalid timeout {:?}: {}", timeout, err))?,
+        )),
+        None => None,
+    };
+    Ok(ConsumerBarrier { count, timeout })
+}
+
+/// List the processes, other than this one, that have any of the given devices open.
+///
+/// # Arguments
+///
+/// * `devices`: the real paths of the devices (ie. /dev/pts/N, not the symlinks).
+///
+/// returns: HashSet<u32> the pids of the processes.
+///
+pub fn consumer_pids(devices: &[PathBuf]) -> HashSet<u32> {
+    let mut pids = HashSet::new();
+    let own_pid = process::id();
+    let Ok(procs) = read_dir("/proc") else {
+        return pids;
+    };
+    for entry in procs.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid == own_pid {
+            continue;
+        }
+        // Processes can vanish or be unreadable (other users), just skip them.
+        let Ok(fds) = read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        if fds
+            .flatten()
+            .filter_map(|fd| read_link(fd.path()).ok())
+            .any(|target| devices.contains(&target))
+        {
+            pids.insert(pid);
+        }
+    }
+    pids
+}
+
+/// The name of a process, for the logs.
+pub fn process_name(pid: u32) -> String {
+    fs::read_to_string(format!("/proc/{}/comm", pid))
+        .map(|comm| comm.trim_end().to_string())
+        .unwrap_or_else(|_| "?".to_string())
+}
+
+/// Block until enough consumers have opened the given devices.
+///
+/// # Arguments
+///
+/// * `devices`: the real paths of the slave devices.
+/// * `barrier`: how many consumers to wait for and for how long.
+/// * `running`: stop waiting when this goes false.
+///
+/// returns: bool true if the expected consumers are there, false on timeout or stop.
+///
+pub fn wait_for_consumers(
+    devices: &[PathBuf],
+    barrier: &ConsumerBarrier,
+    running: &AtomicBool,
+) -> bool {
+    info!("Waiting for {} consumer(s) to attach...", barrier.count);
+    let start = Instant::now();
+    while running.load(Ordering::Relaxed) {
+        let attached = consumer_pids(devices).len();
+        if attached >= barrier.count {
+            info!("{} consumer(s) attached, starting.", attached);
+            return true;
+        }
+        if let Some(timeout) = barrier.timeout {
+            if start.elapsed() > timeout {
+                warn!(
+                    "Only {} of {} consumer(s) attached after {:?}, starting anyway.",
+                    attached, barrier.count, timeout
+                );
+                return false;
+            }
+        }
+        thread::sleep(POLL_PERIOD);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consumers::{
+        consumer_pids, parse_consumer_barrier, process_name, wait_for_consumers, ConsumerBarrier,
+    };
+    use serialport::{SerialPort, TTYPort};
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::sync::atomic::AtomicBool;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_consumer_barrier() {
+        assert_eq!(
+            parse_consumer_barrier("2"),
+            Ok(ConsumerBarrier {
+                count: 2,
+                timeout: None
+            })
+        );
+        assert_eq!(
+            parse_consumer_barrier("1:5000"),
+            Ok(ConsumerBarrier {
+                count: 1,
+                timeout: Some(Duration::from_millis(5000))
+            })
+        );
+        assert!(parse_consumer_barrier("two").is_err());
+        assert!(parse_consumer_barrier("1:soon").is_err());
+    }
+
+    #[test]
+    fn test_consumer_detection() {
+        let (_master, slave) = TTYPort::pair().unwrap();
+        let pty = PathBuf::from(slave.name().unwrap());
+        let devices = vec![pty.clone()];
+        // we hold the slave ourselves but we don't count as a consumer.
+        assert!(consumer_pids(&devices).is_empty());
+        let barrier = ConsumerBarrier {
+            count: 1,
+            timeout: Some(Duration::from_millis(200)),
+        };
+        assert!(!wait_for_consumers(
+            &devices,
+            &barrier,
+            &AtomicBool::new(true)
+        ));
+
+        let mut consumer = Command::new("sleep")
+            .arg("5")
+            .stdin(File::open(&pty).unwrap())
+            .spawn()
+            .unwrap();
+        assert!(wait_for_consumers(
+            &devices,
+            &barrier,
+            &AtomicBool::new(true)
+        ));
+        assert!(consumer_pids(&devices).contains(&consumer.id()));
+        assert_eq!(process_name(consumer.id()), "sleep");
+        consumer.kill().unwrap();
+        consumer.wait().unwrap();
+    }
+}
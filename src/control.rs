@@ -0,0 +1,744 @@
+//! Control socket to inspect and tune a running instance without restarting it.
+//!
+//! The protocol is line based, each command gets a single line reply starting with `ok` or
+//! `error:`. For example with `socat - UNIX-CONNECT:/run/ttytee.sock`:
+//!
+//! ```text
+//! list
+//! ok slave0 slave1
+//! set slave0 timeout 200
+//! ok
+//! get slave0
+//! ok stale-timeout=200 max-backlog=2048 max-lag-frames=none on-write-error=keep-trying format=raw
+//! set log level warn
+//! ok
+//! pause slave1
+//! ok
+//! resume slave1
+//! ok
+//! set besteffort max-lag-frames 5
+//! ok
+//! ```
+//!
+//! `break 500` sends a BREAK of 500 ms on the master (250 ms by default, at most 2 s), for the
+//! bootloaders and radios switching modes with it. The main loop waits for the end of the BREAK.
+//!
+//! `dump [PATH]` writes the flight recording in chronological order to PATH, `<ring>.dump` by
+//! default, and replies with the path written, to look at what the master sent right before an
+//! incident without stopping the tee.
+//!
+//! `set`, `pause` and `resume` also take the name of a group of endpoints, they then apply to all
+//! its endpoints.
+//!
+//! `stats` gives the counters of the master, of each endpoint and of each message type, the records
+//! separated by `;`, for `ttytee top`:
+//! `ok master bytes_read=1200 skipped_bytes=0 invalid_frames=0 eofs=0 timeouts=3 read_errors=0
+//! interruptions=0 state=streaming;endpoint slave0 written=1200 dropped=0 pending=0 errors=0 state=flowing;message GGA count=10`, then how
+//! many times each event of the catalog of the log messages was logged, if it was:
+//! `;event TT2005 name=stale-clear count=3`.
+//!
+//! `master` gives the state of the master, for how many seconds it is in it, and its last
+//! transitions with their time and reason, the records separated by `;`:
+//! `ok state=streaming for=3600.2;1699963200.123 streaming: data received`.
+//!
+//! `list`, `get`, `stats` and `master` only read, the other commands change the running instance.
+//! With `--control-admin`, only root, the user of ttytee and the given users (`uid:N`) or groups
+//! (`gid:N`, the primary group of the client) can run them, found with SO_PEERCRED, so a monitoring
+//! agent can query the stats without being able to reconfigure the tee. Without it every client can.
+
+use crate::endpoint::ManagedEndpoint;
+use crate::lifecycle::MasterLifecycle;
+use crate::rate::RateMonitor;
+use crate::recorder::{dump_path, FlightRecorder};
+use crate::stats::Stats;
+use crate::uart::send_break;
+use log::{debug, info, warn, LevelFilter};
+use std::fs::remove_file;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// How often the listener checks if it has to stop.
+const POLL_PERIOD: Duration = Duration::from_millis(100);
+
+// The commands are executed by the main loop, give up if it does not answer in time.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const DEFAULT_BREAK: Duration = Duration::from_millis(250);
+// The writers are blocked while the BREAK lasts.
+const MAX_BREAK: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// The names of the endpoints.
+    List,
+    /// The options of an endpoint, followed by `paused` if it is.
+    Get { target: String },
+    /// Change a parameter: `set <target> <key> <value>`.
+    Set {
+        target: String,
+        key: String,
+        value: String,
+    },
+    /// Stop delivering to an endpoint, for example while its consumer restarts.
+    Pause { target: String },
+    /// Deliver again to a paused endpoint, from the next frame boundary.
+    Resume { target: String },
+    /// Send a BREAK on the master.
+    Break { duration: Duration },
+    /// The counters of the master, of the endpoints and of the message types.
+    Stats,
+    /// The state of the master and its last transitions.
+    Master,
+    /// Write the flight recording to a file, `<ring>.dump` by default.
+    Dump { path: Option<PathBuf> },
+}
+
+impl Command {
+    /// Whether the command only reads, any client can run it.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Self::List | Self::Get { .. } | Self::Stats | Self::Master
+        )
+    }
+}
+
+/// Parse a command line from a control client.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["list"] => Ok(Command::List),
+        ["stats"] => Ok(Command::Stats),
+        ["master"] => Ok(Command::Master),
+        ["get", target] => Ok(Command::Get {
+            target: target.to_string(),
+        }),
+        ["set", target, key, value] => Ok(Command::Set {
+            target: target.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        }),
+        ["pause", target] => Ok(Command::Pause {
+            target: target.to_string(),
+        }),
+        ["resume", target] => Ok(Command::Resume {
+            target: target.to_string(),
+        }),
+        ["dump"] => Ok(Command::Dump { path: None }),
+        ["dump", path] => Ok(Command::Dump {
+            path: Some(PathBuf::from(path)),
+        }),
+        ["break"] => Ok(Command::Break {
+            duration: DEFAULT_BREAK,
+        }),
+        ["break", ms] => match ms.parse().map(Duration::from_millis) {
+            Ok(duration) if !duration.is_zero() && duration <= MAX_BREAK => {
+                Ok(Command::Break { duration })
+            }
+            _ => Err(format!(
+                "invalid BREAK duration {:?}, expected 1 to {} ms",
+                ms,
+                MAX_BREAK.as_millis()
+            )),
+        },
+        _ => Err(format!(
+            "unknown command {:?}, expected list, get <TARGET>, set <TARGET> <KEY> <VALUE>, \
+             pause <TARGET>, resume <TARGET>, break [MS], dump [PATH], stats or master",
+            line.trim()
+        )),
+    }
+}
+
+/// What the commands can act on, borrowed from the main loop.
+pub struct Tunables<'a> {
+    // the read timeout of the master in ms, applied by the reader thread.
+    pub master_timeout: &'a AtomicU64,
+    // for the BREAK, the master is owned by the reader thread.
+    pub master_fd: RawFd,
+    pub endpoints: &'a mut [ManagedEndpoint],
+    pub rate_monitor: Option<&'a mut RateMonitor>,
+    pub stats: &'a Stats,
+    // moved by the reader thread.
+    pub lifecycle: &'a Mutex<MasterLifecycle>,
+    // the master is split into frames, the endpoints resume on a frame boundary.
+    pub framed: bool,
+    pub recorder: Option<&'a FlightRecorder>,
+}
+
+/// Execute a command, returns the reply to send to the client.
+pub fn execute(command: &Command, tunables: &mut Tunables) -> Result<String, String> {
+    match command {
+        Command::List => Ok(tunables
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.name.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")),
+        Command::Get { target } => {
+            let endpoint = find_endpoint(tunables.endpoints, target)?;
+            if endpoint.is_paused() {
+                Ok(format!("{} paused", endpoint.options))
+            } else {
+                Ok(endpoint.options.to_string())
+            }
+        }
+        Command::Set { target, key, value } => {
+            match target.as_str() {
+                "master" if key == "timeout" => {
+                    let timeout = value
+                        .parse()
+                        .map_err(|err| format!("invalid timeout {:?}: {}", value, err))?;
+                    tunables.master_timeout.store(timeout, Ordering::Relaxed);
+                }
+                "log" if key == "level" => {
+                    let level: LevelFilter = value
+                        .parse()
+                        .map_err(|_| format!("invalid log level {:?}", value))?;
+                    log::set_max_level(level);
+                }
+                "rate-alert" if key == "threshold" => {
+                    let threshold = value
+                        .parse()
+                        .map_err(|err| format!("invalid threshold {:?}: {}", value, err))?;
+                    tunables
+                        .rate_monitor
+                        .as_mut()
+                        .ok_or("the rate alerts are not enabled")?
+                        .set_threshold(threshold);
+                }
+                _ => {
+                    // timeout is the short name of the staleness of the endpoints.
+                    let key = if key == "timeout" {
+                        "stale-timeout"
+                    } else {
+                        key
+                    };
+                    for endpoint in find_endpoints(tunables.endpoints, target)? {
+                        endpoint.set_option(key, value)?;
+                    }
+                }
+            }
+            info!("Control: {} {} set to {}.", target, key, value);
+            Ok(String::new())
+        }
+        Command::Pause { target } => {
+            for endpoint in find_endpoints(tunables.endpoints, target)? {
+                endpoint.pause();
+            }
+            info!("Control: {} paused.", target);
+            Ok(String::new())
+        }
+        Command::Resume { target } => {
+            for endpoint in find_endpoints(tunables.endpoints, target)? {
+                endpoint.resume(tunables.framed).map_err(|err| {
+                    format!("could not clear the buffer of {}: {}", endpoint.name, err)
+                })?;
+            }
+            info!("Control: {} resumed.", target);
+            Ok(String::new())
+        }
+        Command::Break { duration } => {
+            send_break(tunables.master_fd, *duration)
+                .map_err(|err| format!("could not send the BREAK: {}", err))?;
+            info!("Control: BREAK of {:?} sent on master.", duration);
+            Ok(String::new())
+        }
+        Command::Stats => Ok(tunables.stats.snapshot(tunables.endpoints)),
+        Command::Master => Ok(tunables.lifecycle.lock().unwrap().describe(Instant::now())),
+        Command::Dump { path } => {
+            let recorder = tunables
+                .recorder
+                .ok_or("the flight recorder is not enabled")?;
+            let path = path.clone().unwrap_or_else(|| dump_path(recorder.path()));
+            recorder
+                .dump(&path)
+                .map_err(|err| format!("could not dump to {:?}: {}", path, err))?;
+            info!("Control: flight recording dumped to {:?}.", path);
+            Ok(path.to_string_lossy().into_owned())
+        }
+    }
+}
+
+fn find_endpoint<'a>(
+    endpoints: &'a mut [ManagedEndpoint],
+    name: &str,
+) -> Result<&'a mut ManagedEndpoint, String> {
+    endpoints
+        .iter_mut()
+        .find(|endpoint| endpoint.name == name)
+        .ok_or_else(|| format!("unknown target {:?}", name))
+}
+
+// The endpoint with this name, or else the endpoints of the group with this name.
+fn find_endpoints<'a>(
+    endpoints: &'a mut [ManagedEndpoint],
+    name: &str,
+) -> Result<Vec<&'a mut ManagedEndpoint>, String> {
+    let is_endpoint = endpoints.iter().any(|endpoint| endpoint.name == name);
+    let targets: Vec<&mut ManagedEndpoint> = endpoints
+        .iter_mut()
+        .filter(|endpoint| {
+            if is_endpoint {
+                endpoint.name == name
+            } else {
+                endpoint.options.group.as_deref() == Some(name)
+            }
+        })
+        .collect();
+    if targets.is_empty() {
+        return Err(format!("unknown target {:?}", name));
+    }
+    Ok(targets)
+}
+
+/// A client allowed to run the commands changing the running instance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlAdmin {
+    Uid(u32),
+    Gid(u32),
+}
+
+impl FromStr for ControlAdmin {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let id = |id: &str| {
+            id.parse()
+                .map_err(|_| format!("invalid id in {:?}, expected uid:<N> or gid:<N>", value))
+        };
+        match value.split_once(':') {
+            Some(("uid", uid)) => Ok(Self::Uid(id(uid)?)),
+            Some(("gid", gid)) => Ok(Self::Gid(id(gid)?)),
+            _ => Err(format!(
+                "invalid control admin {:?}, expected uid:<N> or gid:<N>",
+                value
+            )),
+        }
+    }
+}
+
+/// Who can run the commands changing the running instance.
+#[derive(Clone, Debug, Default)]
+pub struct ControlAccess {
+    // empty if every client can.
+    admins: Vec<ControlAdmin>,
+}
+
+impl ControlAccess {
+    pub fn new(admins: &[ControlAdmin]) -> Self {
+        Self {
+            admins: admins.to_vec(),
+        }
+    }
+
+    /// Whether a client can run the commands changing the running instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `credentials`: the user and the group of the client, None if they are unknown.
+    ///
+    /// returns: bool
+    ///
+    pub fn is_admin(&self, credentials: Option<(u32, u32)>) -> bool {
+        if self.admins.is_empty() {
+            return true;
+        }
+        let Some((uid, gid)) = credentials else {
+            return false;
+        };
+        uid == 0
+            || uid == unsafe { libc::geteuid() }
+            || self.admins.iter().any(|admin| match *admin {
+                ControlAdmin::Uid(admin) => admin == uid,
+                ControlAdmin::Gid(admin) => admin == gid,
+            })
+    }
+}
+
+// The user and the group of the peer of a unix socket.
+fn peer_credentials(stream: &UnixStream) -> std::io::Result<(u32, u32)> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok((credentials.uid, credentials.gid))
+}
+
+/// A command waiting for the main loop, with where to send its reply.
+pub struct Request {
+    pub command: Command,
+    reply: Sender<Result<String, String>>,
+}
+
+impl Request {
+    pub fn reply(self, reply: Result<String, String>) {
+        // the client may be gone already.
+        self.reply.send(reply).ok();
+    }
+}
+
+/// Accepts the control clients in the background and forwards their commands.
+pub struct ControlServer {
+    path: PathBuf,
+    requests: Receiver<Request>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ControlServer {
+    /// Listen on a unix socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: where to create the socket, a leftover from a previous run is replaced.
+    /// * `access`: who can run the commands changing the running instance.
+    ///
+    /// returns: Result<ControlServer, Error>
+    ///
+    pub fn start(path: &Path, access: ControlAccess) -> std::io::Result<Self> {
+        remove_file(path).ok();
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        info!("Control socket listening on {:?}.", path);
+        let (sender, requests) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_ref = Arc::clone(&stop);
+        let handle = thread::spawn(move || accept_clients(listener, sender, &access, &stop_ref));
+        Ok(Self {
+            path: path.to_path_buf(),
+            requests,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// The next command to execute, if any, without blocking.
+    pub fn next_request(&self) -> Option<Request> {
+        self.requests.try_recv().ok()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+        remove_file(&self.path).ok();
+    }
+}
+
+fn accept_clients(
+    listener: UnixListener,
+    requests: Sender<Request>,
+    access: &ControlAccess,
+    stop: &AtomicBool,
+) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let requests = requests.clone();
+                let credentials = peer_credentials(&stream)
+                    .map_err(|err| {
+                        warn!(
+                            "Could not get the credentials of a control client: {}.",
+                            err
+                        )
+                    })
+                    .ok();
+                let admin = access.is_admin(credentials);
+                thread::spawn(move || serve_client(stream, requests, credentials, admin));
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => thread::sleep(POLL_PERIOD),
+            Err(err) => {
+                warn!("Could not accept a control client: {}.", err);
+                thread::sleep(POLL_PERIOD);
+            }
+        }
+    }
+}
+
+fn serve_client(
+    stream: UnixStream,
+    requests: Sender<Request>,
+    credentials: Option<(u32, u32)>,
+    admin: bool,
+) {
+    debug!(
+        "Control client {:?} connected{}.",
+        credentials,
+        if admin { "" } else { ", read only" }
+    );
+    // the listener is non blocking, not its clients.
+    stream.set_nonblocking(false).ok();
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match parse_command(&line) {
+            Ok(command) if !admin && !command.is_read_only() => Err(format!(
+                "permission denied, {:?} changes the running instance",
+                line.trim()
+            )),
+            Ok(command) => {
+                let (reply, replies) = channel();
+                if requests.send(Request { command, reply }).is_err() {
+                    // ttytee is stopping.
+                    break;
+                }
+                replies
+                    .recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| Err("no reply from the main loop".to_string()))
+            }
+            Err(err) => Err(err),
+        };
+        let reply = match reply {
+            Ok(reply) if reply.is_empty() => "ok\n".to_string(),
+            Ok(reply) => format!("ok {}\n", reply),
+            Err(err) => format!("error: {}\n", err),
+        };
+        if writer.write_all(reply.as_bytes()).is_err() {
+            break;
+        }
+    }
+    debug!("Control client disconnected.");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::control::{
+        execute, parse_command, Command, ControlAccess, ControlAdmin, ControlServer, Tunables,
+    };
+    use crate::endpoint::stdout::StdoutEndpoint;
+    use crate::endpoint::{EndpointOptions, ManagedEndpoint};
+    use crate::lifecycle::MasterLifecycle;
+    use crate::rate::RateMonitor;
+    use crate::recorder::FlightRecorder;
+    use crate::stats::Stats;
+    use std::fs::{read, remove_file};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn endpoint(name: &str) -> ManagedEndpoint {
+        ManagedEndpoint::new(
+            name,
+            Box::new(StdoutEndpoint),
+            EndpointOptions::default(),
+            Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+        )
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("list\n"), Ok(Command::List));
+        assert_eq!(
+            parse_command("set slave0  timeout 200"),
+            Ok(Command::Set {
+                target: "slave0".to_string(),
+                key: "timeout".to_string(),
+                value: "200".to_string()
+            })
+        );
+        assert!(parse_command("set slave0 timeout").is_err());
+        assert!(parse_command("reboot").is_err());
+        assert_eq!(
+            parse_command("break"),
+            Ok(Command::Break {
+                duration: Duration::from_millis(250)
+            })
+        );
+        assert!(parse_command("break 0").is_err());
+        assert!(parse_command("break 5000").is_err());
+        assert_eq!(parse_command("dump"), Ok(Command::Dump { path: None }));
+        assert_eq!(
+            parse_command("dump /tmp/incident.nmea"),
+            Ok(Command::Dump {
+                path: Some(PathBuf::from("/tmp/incident.nmea"))
+            })
+        );
+        assert!(parse_command("dump a b").is_err());
+    }
+
+    #[test]
+    fn test_execute() {
+        let master_timeout = AtomicU64::new(1000);
+        let mut endpoints = vec![endpoint("slave0"), endpoint("slave1"), endpoint("net")];
+        for endpoint in &mut endpoints[1..] {
+            endpoint.set_option("group", "besteffort").unwrap();
+        }
+        let mut rate_monitor = RateMonitor::new(50, None);
+        let stats = Stats::new(Instant::now());
+        let lifecycle = Mutex::new(MasterLifecycle::new(Instant::now()));
+        let mut tunables = Tunables {
+            master_timeout: &master_timeout,
+            master_fd: -1,
+            endpoints: &mut endpoints,
+            rate_monitor: Some(&mut rate_monitor),
+            stats: &stats,
+            lifecycle: &lifecycle,
+            framed: false,
+            recorder: None,
+        };
+        let mut run = |line: &str| execute(&parse_command(line).unwrap(), &mut tunables);
+        assert_eq!(run("list"), Ok("slave0 slave1 net".to_string()));
+        assert!(run("dump").is_err());
+        assert!(run("set slave1 timeout 200").is_ok());
+        assert!(run("set slave1 on-write-error disable:3").is_ok());
+        assert_eq!(
+            run("get slave1"),
+            Ok(
+                "stale-timeout=200 max-backlog=2048 max-lag-frames=none on-write-error=disable:3 format=raw group=besteffort"
+                    .to_string()
+            )
+        );
+        assert!(run("set master timeout 200").is_ok());
+        assert!(run("set rate-alert threshold 20").is_ok());
+        assert!(run("set slave2 timeout 200").is_err());
+        assert!(run("set slave1 timeout soon").is_err());
+        assert!(run("pause slave0").is_ok());
+        assert!(run("get slave0").unwrap().ends_with(" paused"));
+        assert!(run("resume slave3").is_err());
+        assert!(run("set besteffort max-lag-frames 5").is_ok());
+        assert!(run("break 10").is_err());
+        assert!(run("pause besteffort").is_ok());
+        assert!(run("stats").unwrap().starts_with(
+            "master bytes_read=0 skipped_bytes=0 invalid_frames=0 eofs=0 timeouts=0 read_errors=0 interruptions=0 state=opening;endpoint slave0 written=0 dropped=0 pending=0 errors=0 state=paused;"
+        ));
+        assert!(run("master").unwrap().starts_with("state=opening for="));
+        assert_eq!(master_timeout.load(Ordering::Relaxed), 200);
+        assert!(endpoints.iter().all(|endpoint| endpoint.is_paused()));
+        assert_eq!(endpoints[2].options.max_lag_frames, Some(5));
+        assert_eq!(endpoints[0].options.max_lag_frames, None);
+        assert_eq!(
+            endpoints[1].options.stale_timeout,
+            Duration::from_millis(200)
+        );
+    }
+
+    #[test]
+    fn test_execute_dump() {
+        let ring = PathBuf::from("/tmp/ttytee_control_dump.ring");
+        let dump = PathBuf::from("/tmp/ttytee_control_dump.ring.dump");
+        let other = PathBuf::from("/tmp/ttytee_control_dump.nmea");
+        remove_file(&ring).ok();
+        let mut recorder = FlightRecorder::create(&ring, 64).unwrap();
+        recorder.record(b"$GPGGA,incident\r\n");
+        let master_timeout = AtomicU64::new(1000);
+        let stats = Stats::new(Instant::now());
+        let lifecycle = Mutex::new(MasterLifecycle::new(Instant::now()));
+        let mut tunables = Tunables {
+            master_timeout: &master_timeout,
+            master_fd: -1,
+            endpoints: &mut [],
+            rate_monitor: None,
+            stats: &stats,
+            lifecycle: &lifecycle,
+            framed: false,
+            recorder: Some(&recorder),
+        };
+        let mut run = |line: &str| execute(&parse_command(line).unwrap(), &mut tunables);
+        assert_eq!(run("dump"), Ok(dump.to_string_lossy().into_owned()));
+        assert_eq!(
+            run("dump /tmp/ttytee_control_dump.nmea"),
+            Ok(other.to_string_lossy().into_owned())
+        );
+        assert!(run("dump /nonexistent/dir/dump").is_err());
+        assert!(!Command::Dump { path: None }.is_read_only());
+        assert_eq!(read(&dump).unwrap(), b"$GPGGA,incident\r\n");
+        assert_eq!(read(&other).unwrap(), b"$GPGGA,incident\r\n");
+        drop(recorder);
+        for file in [ring, dump, other] {
+            remove_file(file).ok();
+        }
+    }
+
+    #[test]
+    fn test_control_access() {
+        assert_eq!("uid:1000".parse(), Ok(ControlAdmin::Uid(1000)));
+        assert_eq!("gid:20".parse(), Ok(ControlAdmin::Gid(20)));
+        assert!("user:1000".parse::<ControlAdmin>().is_err());
+        assert!("uid:bob".parse::<ControlAdmin>().is_err());
+        assert!(Command::Stats.is_read_only());
+        assert!(!Command::Pause {
+            target: "slave0".to_string()
+        }
+        .is_read_only());
+
+        let everyone = ControlAccess::default();
+        assert!(everyone.is_admin(Some((4242, 4242))));
+        assert!(everyone.is_admin(None));
+        let access = ControlAccess::new(&[ControlAdmin::Uid(1000), ControlAdmin::Gid(20)]);
+        assert!(access.is_admin(Some((1000, 100))));
+        assert!(access.is_admin(Some((1001, 20))));
+        assert!(access.is_admin(Some((0, 0))));
+        assert!(!access.is_admin(None));
+        let euid = unsafe { libc::geteuid() };
+        assert!(access.is_admin(Some((euid, 100))));
+        if euid != 4242 {
+            assert!(!access.is_admin(Some((4242, 4242))));
+        }
+    }
+
+    #[test]
+    fn test_control_server() {
+        let path = PathBuf::from("/tmp/ttytee_control_test.sock");
+        let server = ControlServer::start(&path, ControlAccess::default()).unwrap();
+        let client = UnixStream::connect(&path).unwrap();
+        let mut replies = BufReader::new(client.try_clone().unwrap());
+        // plays the main loop.
+        let main_loop = thread::spawn(move || loop {
+            if let Some(request) = server.next_request() {
+                let done = request.command == Command::List;
+                request.reply(Ok("slave0".to_string()));
+                if done {
+                    return;
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        });
+        let mut reply = String::new();
+        (&client).write_all(b"nonsense\nlist\n").unwrap();
+        replies.read_line(&mut reply).unwrap();
+        assert!(reply.starts_with("error: unknown command"));
+        reply.clear();
+        replies.read_line(&mut reply).unwrap();
+        assert_eq!(reply, "ok slave0\n");
+        main_loop.join().unwrap();
+        assert!(!path.exists());
+    }
+}
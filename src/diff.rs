@@ -0,0 +1,268 @@
+//! Comparison of two masters that should send the same stream, like the redundant receivers of an
+//! installation to validate before it flies: both are read at the same time for a while, split
+//! into frames, and the frames of each message type are paired in their order of arrival.
+//!
+//! A frame of the first master and a frame of the second arriving within the tolerance of each
+//! other are the same frame, identical or differing. A frame with no counterpart within the
+//! tolerance is only in its master. The report gives these counts per message type and the offset
+//! of the second master on the first for the identical frames.
+
+use crate::framing::{Framer, Protocol};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+// A frame and when it arrived since the start.
+struct Arrival {
+    at: Duration,
+    data: Vec<u8>,
+}
+
+// The frames of a master by message type.
+type Arrivals = BTreeMap<String, Vec<Arrival>>;
+
+/// How the frames of a message type compare.
+#[derive(Debug, Default, PartialEq)]
+struct TypeDiff {
+    identical: u64,
+    differing: u64,
+    only_first: u64,
+    only_second: u64,
+    // the total and the largest offset of the second master on the first, in µs.
+    offset_sum: i64,
+    offset_max: i64,
+}
+
+/// Pair the frames of a message type.
+///
+/// # Arguments
+///
+/// * `first`: the frames of the first master, in their order of arrival.
+/// * `second`: the frames of the second master.
+/// * `tolerance`: the largest difference of arrival of the same frame.
+///
+/// returns: TypeDiff
+///
+fn compare_frames(first: &[Arrival], second: &[Arrival], tolerance: Duration) -> TypeDiff {
+    let mut diff = TypeDiff::default();
+    let (mut i, mut j) = (0, 0);
+    while i < first.len() && j < second.len() {
+        let (a, b) = (&first[i], &second[j]);
+        if a.at + tolerance < b.at {
+            diff.only_first += 1;
+            i += 1;
+        } else if b.at + tolerance < a.at {
+            diff.only_second += 1;
+            j += 1;
+        } else {
+            if a.data == b.data {
+                let offset = b.at.as_micros() as i64 - a.at.as_micros() as i64;
+                diff.identical += 1;
+                diff.offset_sum += offset;
+                if offset.abs() > diff.offset_max.abs() {
+                    diff.offset_max = offset;
+                }
+            } else {
+                diff.differing += 1;
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+    diff.only_first += (first.len() - i) as u64;
+    diff.only_second += (second.len() - j) as u64;
+    diff
+}
+
+/// The report of the comparison.
+///
+/// # Arguments
+///
+/// * `first`: the frames of the first master.
+/// * `second`: the frames of the second master.
+/// * `tolerance`: the largest difference of arrival of the same frame.
+///
+/// returns: String
+///
+fn report(first: &Arrivals, second: &Arrivals, tolerance: Duration) -> String {
+    let mut types: Vec<&String> = first.keys().chain(second.keys()).collect();
+    types.sort();
+    types.dedup();
+    let mut report = String::from(
+        "    type  identical  differing  only first  only second  offset avg  offset max\n",
+    );
+    let mut total = TypeDiff::default();
+    for message_type in types {
+        let diff = compare_frames(
+            first.get(message_type).map_or(&[], Vec::as_slice),
+            second.get(message_type).map_or(&[], Vec::as_slice),
+            tolerance,
+        );
+        let offset_avg = match diff.identical {
+            0 => "-".to_string(),
+            identical => format!(
+                "{:.1} ms",
+                diff.offset_sum as f64 / identical as f64 / 1000.0
+            ),
+        };
+        let offset_max = match diff.identical {
+            0 => "-".to_string(),
+            _ => format!("{:.1} ms", diff.offset_max as f64 / 1000.0),
+        };
+        writeln!(
+            report,
+            "{:>8}  {:>9}  {:>9}  {:>10}  {:>11}  {:>10}  {:>10}",
+            message_type,
+            diff.identical,
+            diff.differing,
+            diff.only_first,
+            diff.only_second,
+            offset_avg,
+            offset_max
+        )
+        .unwrap();
+        total.identical += diff.identical;
+        total.differing += diff.differing;
+        total.only_first += diff.only_first;
+        total.only_second += diff.only_second;
+    }
+    let frames = total.identical + total.differing + total.only_first + total.only_second;
+    writeln!(
+        report,
+        "Total: {} frames, {} identical ({:.1}%), {} differing, {} only in the first master, {} only in the second.",
+        frames,
+        total.identical,
+        total.identical as f64 * 100.0 / frames.max(1) as f64,
+        total.differing,
+        total.only_first,
+        total.only_second
+    )
+    .unwrap();
+    report
+}
+
+// Read the frames of a master until the end of the comparison.
+fn record(
+    master: &Path,
+    baudrate: u32,
+    start: Instant,
+    duration: Duration,
+) -> io::Result<Arrivals> {
+    let mut port = serialport::new(master.to_string_lossy(), baudrate)
+        .timeout(READ_TIMEOUT)
+        .open_native()?;
+    let mut framer = Framer::new(&[Protocol::Nmea, Protocol::Ubx, Protocol::Rtcm]);
+    let mut arrivals = Arrivals::new();
+    let mut buffer = [0; 4096];
+    let mut frames = Vec::new();
+    while start.elapsed() < duration {
+        match port.read(&mut buffer) {
+            Ok(len) => framer.push(&buffer[..len], &mut frames),
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => {}
+            Err(err) => return Err(err),
+        }
+        let at = start.elapsed();
+        for frame in frames.drain(..) {
+            arrivals
+                .entry(frame.message_type())
+                .or_default()
+                .push(Arrival {
+                    at,
+                    data: frame.data,
+                });
+        }
+    }
+    Ok(arrivals)
+}
+
+/// Compare two masters.
+///
+/// # Arguments
+///
+/// * `first`: the device of the first receiver.
+/// * `second`: the device of the second receiver.
+/// * `baudrate`: the baudrate of both.
+/// * `duration`: how long they are compared.
+/// * `tolerance`: the largest difference of arrival of the same frame.
+///
+/// returns: Result<String, Error> the report.
+///
+pub fn diff(
+    first: &Path,
+    second: &Path,
+    baudrate: u32,
+    duration: Duration,
+    tolerance: Duration,
+) -> io::Result<String> {
+    let start = Instant::now();
+    let (first_arrivals, second_arrivals) = thread::scope(|scope| {
+        let second_reader = scope.spawn(|| record(second, baudrate, start, duration));
+        let first_arrivals = record(first, baudrate, start, duration);
+        let second_arrivals = second_reader
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("the reader of the second master panicked")));
+        (first_arrivals, second_arrivals)
+    });
+    let (first_arrivals, second_arrivals) = (
+        first_arrivals
+            .map_err(|err| io::Error::new(err.kind(), format!("{:?}: {}", first, err)))?,
+        second_arrivals
+            .map_err(|err| io::Error::new(err.kind(), format!("{:?}: {}", second, err)))?,
+    );
+    let mut text = format!(
+        "Frames of {:?} and {:?} compared for {} s, tolerance {} ms:\n",
+        first,
+        second,
+        duration.as_secs(),
+        tolerance.as_millis()
+    );
+    text += &report(&first_arrivals, &second_arrivals, tolerance);
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{compare_frames, report, Arrival, Arrivals, TypeDiff};
+    use std::time::Duration;
+
+    fn arrivals(frames: &[(u64, &str)]) -> Vec<Arrival> {
+        frames
+            .iter()
+            .map(|(at, data)| Arrival {
+                at: Duration::from_millis(*at),
+                data: data.as_bytes().to_vec(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compare_frames() {
+        let first = arrivals(&[(0, "A"), (1000, "B"), (2000, "C"), (3000, "D")]);
+        // the second receiver lags by 20 ms, misses B and gets C wrong.
+        let second = arrivals(&[(20, "A"), (2030, "c"), (3010, "D"), (4010, "E")]);
+        let tolerance = Duration::from_millis(200);
+        assert_eq!(
+            compare_frames(&first, &second, tolerance),
+            TypeDiff {
+                identical: 2,
+                differing: 1,
+                only_first: 1,
+                only_second: 1,
+                offset_sum: 30_000,
+                offset_max: 20_000,
+            }
+        );
+
+        let first: Arrivals = [("GGA".to_string(), first)].into_iter().collect();
+        let second: Arrivals = [("RMC".to_string(), second)].into_iter().collect();
+        let report = report(&first, &second, tolerance);
+        assert!(report.contains("\n     GGA          0          0           4            0           -           -\n"), "{}", report);
+        assert!(report.ends_with("Total: 8 frames, 0 identical (0.0%), 0 differing, 4 only in the first master, 4 only in the second.\n"));
+    }
+}
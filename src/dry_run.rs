@@ -0,0 +1,193 @@
+//! Dry run for the configuration management pipelines: with `--dry-run` the configuration is
+//! validated, then what the validation cannot know without touching the system is checked (the
+//! master can be opened for reading and writing, the ports to listen on are free, the serial
+//! endpoints can be written) and what would be created is printed, without opening the master nor
+//! moving any data.
+
+use crate::endpoint::{EndpointKind, EndpointSpec};
+use crate::i2c::parse_i2c_master;
+use crate::remote::parse_remote_master;
+use crate::rs485::Rs485Mode;
+use crate::validate::{problem, Problem};
+use crate::{endpoint_options, Args};
+use std::ffi::CString;
+use std::fmt::Write;
+use std::net::TcpListener;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+// Whether this process can read and write a device.
+fn is_accessible(device: &Path) -> Result<(), String> {
+    let path = CString::new(device.as_os_str().as_bytes()).map_err(|err| err.to_string())?;
+    if unsafe { libc::access(path.as_ptr(), libc::R_OK | libc::W_OK) } < 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+    Ok(())
+}
+
+// The address an endpoint listens on.
+fn listen_address(kind: &EndpointKind) -> Option<String> {
+    match kind {
+        EndpointKind::Tcp(address)
+        | EndpointKind::CompressedTcp(address)
+        | EndpointKind::Gpsd(address) => Some(address.clone()),
+        EndpointKind::Ntrip(mountpoint) => Some(format!("{}:{}", mountpoint.host, mountpoint.port)),
+        _ => None,
+    }
+}
+
+/// Check what the validation cannot, the master and the endpoints are left untouched.
+///
+/// # Arguments
+///
+/// * `args`: the command line.
+/// * `specs`: the endpoints.
+///
+/// returns: Vec<Problem> empty if everything would open.
+///
+pub fn preflight(args: &Args, specs: &[EndpointSpec]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let local_master =
+        parse_remote_master(&args.master).is_none() && parse_i2c_master(&args.master).is_none();
+    if local_master {
+        if let Err(err) = is_accessible(&args.master) {
+            problems.push(problem(
+                "master-unavailable",
+                format!("The master {:?} cannot be opened: {}.", args.master, err),
+            ));
+        }
+    }
+    if let Some(device) = &args.merge_master {
+        if let Err(err) = is_accessible(device) {
+            problems.push(problem(
+                "master-unavailable",
+                format!("The merged device {:?} cannot be opened: {}.", device, err),
+            ));
+        }
+    }
+    for spec in specs {
+        if let Some(address) = listen_address(&spec.kind) {
+            // the listener is closed right away, the port is free again.
+            if let Err(err) = TcpListener::bind(&address) {
+                problems.push(problem(
+                    "port-unavailable",
+                    format!("{} cannot listen on {}: {}.", spec.name, address, err),
+                ));
+            }
+        }
+        if let EndpointKind::Serial(device, _) = &spec.kind {
+            if let Err(err) = is_accessible(device) {
+                problems.push(problem(
+                    "device-unavailable",
+                    format!("{} cannot open {:?}: {}.", spec.name, device, err),
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// What ttytee would create, one line per endpoint after the master.
+///
+/// # Arguments
+///
+/// * `args`: the command line.
+/// * `specs`: the endpoints.
+///
+/// returns: String
+///
+pub fn plan(args: &Args, specs: &[EndpointSpec]) -> String {
+    let mut plan = format!("master {:?} at {} bauds", args.master, args.baudrate);
+    if let Some(mode) = args.rs485 {
+        write!(
+            plan,
+            ", half-duplex RS-485 switched by {}",
+            if mode == Rs485Mode::Kernel {
+                "the driver"
+            } else {
+                "RTS"
+            }
+        )
+        .unwrap();
+    }
+    plan.push('\n');
+    if let Some(device) = &args.merge_master {
+        writeln!(
+            plan,
+            "merged {:?} at {} bauds: {}{}",
+            device,
+            args.merge_baudrate.unwrap_or(args.baudrate),
+            args.merge_sentences.join(", "),
+            args.merge_reorder
+                .map_or(String::new(), |ms| format!(", in order within {} ms", ms))
+        )
+        .unwrap();
+    }
+    for spec in specs {
+        let what = match &spec.kind {
+            EndpointKind::Pty(link) => format!("PTY linked at {:?}", link),
+            EndpointKind::Tcp(address) => format!("TCP server on {}", address),
+            EndpointKind::CompressedTcp(address) => {
+                format!("compressed TCP server on {}", address)
+            }
+            EndpointKind::Gpsd(address) => format!("gpsd server on {}", address),
+            EndpointKind::Udp(address) => format!("UDP datagrams to {}", address),
+            EndpointKind::File(path) => format!("file {:?}", path),
+            EndpointKind::Capture(path) => format!("capture file {:?}", path),
+            EndpointKind::Stdout => "standard output".to_string(),
+            EndpointKind::Sqlite(path) => format!("sqlite database {:?}", path),
+            EndpointKind::Ntrip(mountpoint) => format!("NTRIP caster {}", mountpoint),
+            EndpointKind::Serial(device, baudrate) => format!(
+                "serial device {:?} at {} bauds",
+                device,
+                baudrate.unwrap_or(args.baudrate)
+            ),
+            EndpointKind::Can(_) => "CAN bus".to_string(),
+        };
+        writeln!(
+            plan,
+            "{}: {}, {}",
+            spec.name,
+            what,
+            endpoint_options(args, spec)
+        )
+        .unwrap();
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dry_run::{plan, preflight};
+    use crate::endpoint::parse_endpoint_spec;
+    use crate::{endpoint_specs, Args};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_dry_run() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let args = Args {
+            master: PathBuf::from("/nonexistent/ttyUSB0"),
+            slave0: PathBuf::from("/tmp/slave0"),
+            slave1: PathBuf::from("/tmp/slave1"),
+            baudrate: 9600,
+            endpoint: vec![
+                parse_endpoint_spec(&format!("tcp://{}", taken.local_addr().unwrap())).unwrap(),
+                parse_endpoint_spec("tcp://127.0.0.1:0?name=net").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let specs = endpoint_specs(&args);
+        let codes: Vec<&str> = preflight(&args, &specs)
+            .iter()
+            .map(|problem| problem.code)
+            .collect();
+        assert_eq!(codes, vec!["master-unavailable", "port-unavailable"]);
+        let plan = plan(&args, &specs);
+        assert!(plan.starts_with(
+            "master \"/nonexistent/ttyUSB0\" at 9600 bauds\nslave0: PTY linked at \"/tmp/slave0\", stale-timeout="
+        ));
+        assert!(plan.contains("\nnet: TCP server on 127.0.0.1:0, stale-timeout="));
+    }
+}
@@ -0,0 +1,247 @@
+//! SocketCAN endpoints, for the vehicles whose autopilot only has a CAN bus: the stream is cut into
+//! CAN frames with a fixed ID, `can://can0:0x123`, or sent as ISO-TP messages,
+//! `isotp://can0:0x123:0x124` with the ID of the flow control frames of the receiver last.
+//!
+//! The ISO-TP segmentation and flow control are done by the kernel (can-isotp, Linux 5.10). A write
+//! waits a little for room in the queue of the interface, then fails like a broken endpoint.
+
+use crate::endpoint::Endpoint;
+use log::info;
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+// A write waits at most this long for room in the queue of the interface.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+// The largest ISO-TP message.
+const MAX_ISOTP_MESSAGE: usize = 4095;
+const MAX_STANDARD_ID: u32 = 0x7ff;
+const MAX_EXTENDED_ID: u32 = 0x1fff_ffff;
+
+/// A CAN interface and the IDs to send with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CanSpec {
+    pub interface: String,
+    pub id: u32,
+    // the ID the receiver sends its flow control frames with, for ISO-TP.
+    pub rx_id: Option<u32>,
+}
+
+fn parse_id(id: &str) -> Result<u32, String> {
+    let parsed = match id.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => id.parse(),
+    };
+    parsed
+        .ok()
+        .filter(|&id| id <= MAX_EXTENDED_ID)
+        .ok_or_else(|| format!("invalid CAN ID {:?}", id))
+}
+
+/// Parse the target of a CAN endpoint.
+///
+/// # Arguments
+///
+/// * `target`: `IFACE:ID` for the frames, like `can0:0x123`.
+/// * `isotp`: expect `IFACE:ID:RX_ID` for ISO-TP.
+///
+/// returns: Result<CanSpec, String>
+///
+pub fn parse_can_spec(target: &str, isotp: bool) -> Result<CanSpec, String> {
+    let parts: Vec<&str> = target.split(':').collect();
+    match (parts.as_slice(), isotp) {
+        (&[interface, id], false) if !interface.is_empty() => Ok(CanSpec {
+            interface: interface.to_string(),
+            id: parse_id(id)?,
+            rx_id: None,
+        }),
+        (&[interface, id, rx_id], true) if !interface.is_empty() => Ok(CanSpec {
+            interface: interface.to_string(),
+            id: parse_id(id)?,
+            rx_id: Some(parse_id(rx_id)?),
+        }),
+        (_, false) => Err(format!("expected <INTERFACE>:<ID>, got {:?}", target)),
+        (_, true) => Err(format!(
+            "expected <INTERFACE>:<ID>:<RX_ID>, got {:?}",
+            target
+        )),
+    }
+}
+
+// The IDs beyond 11 bits are sent in the extended format.
+fn frame_id(id: u32) -> libc::canid_t {
+    if id > MAX_STANDARD_ID {
+        id | libc::CAN_EFF_FLAG
+    } else {
+        id
+    }
+}
+
+// Cut data into CAN frames.
+fn can_frames(data: &[u8], id: u32) -> Vec<libc::can_frame> {
+    data.chunks(libc::CAN_MAX_DLEN)
+        .map(|chunk| {
+            // the padding fields are private.
+            let mut frame: libc::can_frame = unsafe { mem::zeroed() };
+            frame.can_id = frame_id(id);
+            frame.can_dlc = chunk.len() as u8;
+            frame.data[..chunk.len()].copy_from_slice(chunk);
+            frame
+        })
+        .collect()
+}
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+pub struct CanEndpoint {
+    socket: OwnedFd,
+    spec: CanSpec,
+}
+
+impl CanEndpoint {
+    /// Open a CAN socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec`: the interface and the IDs, with a receive ID for ISO-TP.
+    ///
+    /// returns: Result<CanEndpoint, Error>
+    ///
+    pub fn open(spec: &CanSpec) -> io::Result<Self> {
+        let name = CString::new(spec.interface.as_str())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let ifindex = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (kind, protocol) = match spec.rx_id {
+            Some(_) => (libc::SOCK_DGRAM, libc::CAN_ISOTP),
+            None => (libc::SOCK_RAW, libc::CAN_RAW),
+        };
+        let socket = unsafe {
+            OwnedFd::from_raw_fd(check(libc::socket(
+                libc::AF_CAN,
+                kind | libc::SOCK_CLOEXEC,
+                protocol,
+            ))?)
+        };
+        let mut address: libc::sockaddr_can = unsafe { mem::zeroed() };
+        address.can_family = libc::AF_CAN as libc::sa_family_t;
+        address.can_ifindex = ifindex as libc::c_int;
+        if let Some(rx_id) = spec.rx_id {
+            address.can_addr.tp.tx_id = frame_id(spec.id);
+            address.can_addr.tp.rx_id = frame_id(rx_id);
+        }
+        let timeout = libc::timeval {
+            tv_sec: 0,
+            tv_usec: WRITE_TIMEOUT.as_micros() as libc::suseconds_t,
+        };
+        unsafe {
+            check(libc::bind(
+                socket.as_raw_fd(),
+                &address as *const libc::sockaddr_can as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_can>() as libc::socklen_t,
+            ))?;
+            check(libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_SNDTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            ))?;
+        }
+        info!(
+            "Writing to the CAN interface {} with the ID {:#x}{}.",
+            spec.interface,
+            spec.id,
+            if spec.rx_id.is_some() {
+                " (ISO-TP)"
+            } else {
+                ""
+            }
+        );
+        Ok(Self {
+            socket,
+            spec: spec.clone(),
+        })
+    }
+
+    fn send(&self, data: *const libc::c_void, len: usize) -> io::Result<()> {
+        let written = unsafe { libc::write(self.socket.as_raw_fd(), data, len) };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Endpoint for CanEndpoint {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.spec.rx_id.is_some() {
+            for message in data.chunks(MAX_ISOTP_MESSAGE) {
+                self.send(message.as_ptr().cast(), message.len())?;
+            }
+        } else {
+            for frame in can_frames(data, self.spec.id) {
+                self.send(
+                    &frame as *const libc::can_frame as *const libc::c_void,
+                    libc::CAN_MTU,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::can::{can_frames, parse_can_spec, CanEndpoint, CanSpec};
+
+    #[test]
+    fn test_parse_can_spec() {
+        assert_eq!(
+            parse_can_spec("can0:0x123", false),
+            Ok(CanSpec {
+                interface: "can0".to_string(),
+                id: 0x123,
+                rx_id: None
+            })
+        );
+        assert_eq!(
+            parse_can_spec("can1:1000:0x18DAF110", true).unwrap().rx_id,
+            Some(0x18da_f110)
+        );
+        assert!(parse_can_spec("can0:0x123", true).is_err());
+        assert!(parse_can_spec("can0", false).is_err());
+        assert!(parse_can_spec(":0x123", false).is_err());
+        assert!(parse_can_spec("can0:0x20000000", false).is_err());
+    }
+
+    #[test]
+    fn test_can_frames() {
+        let frames = can_frames(b"$GPGGA,123519*47\r\n", 0x123);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].can_id, 0x123);
+        assert_eq!(&frames[1].data, b"23519*47");
+        assert_eq!(frames[2].can_dlc, 2);
+        assert_eq!(&frames[2].data[..2], b"\r\n");
+        // the extended format.
+        assert_eq!(
+            can_frames(b"x", 0x18da_f110)[0].can_id,
+            0x18da_f110 | libc::CAN_EFF_FLAG
+        );
+    }
+
+    #[test]
+    fn test_unknown_interface() {
+        assert!(CanEndpoint::open(&parse_can_spec("nocan0:0x123", false).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Capture files: the stream of the master with what is needed to check and understand it months
+//! later, written by the `capture://` endpoints.
+//!
+//! Each time a file is opened, a text header describes the recording:
+//!
+//! ```text
+//! TTYTEE CAPTURE 1
+//! device=/dev/ttyUSB0
+//! baudrate=9600
+//! start=2023-11-14T12:00:00+0000
+//! version=1.0.2
+//!
+//! ```
+//!
+//! followed by the chunks as read from the master: the size of the data (u32), the time of
+//! receipt in µs since the epoch (u64), the data and the CRC-32 of all of this (u32), integers in
+//! little endian. A file appended to by several runs has several headers.
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+pub const MAGIC: &[u8] = b"TTYTEE CAPTURE 1\n";
+
+// size, time and CRC around the data of a chunk.
+const CHUNK_OVERHEAD: usize = 4 + 8 + 4;
+
+/// What describes the master in the header of the captures.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaptureHeader {
+    pub device: PathBuf,
+    pub baudrate: u32,
+}
+
+impl CaptureHeader {
+    /// The header starting a capture file.
+    ///
+    /// # Arguments
+    ///
+    /// * `start`: the time the file is opened, already formatted.
+    ///
+    /// returns: Vec<u8>
+    ///
+    pub fn encode(&self, start: &str) -> Vec<u8> {
+        let mut header = String::from_utf8(MAGIC.to_vec()).unwrap();
+        writeln!(header, "device={}", self.device.display()).unwrap();
+        writeln!(header, "baudrate={}", self.baudrate).unwrap();
+        writeln!(header, "start={}", start).unwrap();
+        writeln!(header, "version={}", env!("CARGO_PKG_VERSION")).unwrap();
+        header.push('\n');
+        header.into_bytes()
+    }
+}
+
+/// A chunk of the master stream with its time of receipt and its CRC.
+pub fn encode_chunk(data: &[u8], micros: u64) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + CHUNK_OVERHEAD);
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(&micros.to_le_bytes());
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk);
+    chunk.extend_from_slice(&crc.to_le_bytes());
+    chunk
+}
+
+/// The CRC-32 of IEEE 802.3, the one of zip and PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// A chunk read from the master.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chunk<'a> {
+    // time of receipt in µs since the epoch.
+    pub micros: u64,
+    pub data: &'a [u8],
+}
+
+/// The content of a capture file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capture<'a> {
+    // the `key=value` lines of all the headers.
+    pub headers: String,
+    pub chunks: Vec<Chunk<'a>>,
+}
+
+/// Decode a capture file, checking the CRCs.
+///
+/// # Arguments
+///
+/// * `data`: the content of the file.
+///
+/// returns: Result<Capture, String> or where the file is corrupted.
+///
+pub fn parse(data: &[u8]) -> Result<Capture<'_>, String> {
+    let mut capture = Capture {
+        headers: String::new(),
+        chunks: Vec::new(),
+    };
+    let mut offset = 0;
+    while offset < data.len() {
+        let rest = &data[offset..];
+        if rest.starts_with(MAGIC) {
+            let end = rest
+                .windows(2)
+                .position(|window| window == b"\n\n")
+                .ok_or_else(|| format!("truncated header at byte {}", offset))?;
+            capture.headers += &String::from_utf8_lossy(&rest[MAGIC.len()..end + 1]);
+            offset += end + 2;
+            continue;
+        }
+        if offset == 0 {
+            return Err("not a ttytee capture file".to_string());
+        }
+        let size = rest
+            .get(..4)
+            .map(|size| u32::from_le_bytes(size.try_into().unwrap()) as usize)
+            .filter(|size| size + CHUNK_OVERHEAD <= rest.len())
+            .ok_or_else(|| format!("truncated chunk at byte {}", offset))?;
+        let (chunk, crc) = rest[..size + CHUNK_OVERHEAD].split_at(size + CHUNK_OVERHEAD - 4);
+        if crc32(chunk) != u32::from_le_bytes(crc.try_into().unwrap()) {
+            return Err(format!("bad CRC in the chunk at byte {}", offset));
+        }
+        capture.chunks.push(Chunk {
+            micros: u64::from_le_bytes(chunk[4..12].try_into().unwrap()),
+            data: &chunk[12..],
+        });
+        offset += size + CHUNK_OVERHEAD;
+    }
+    Ok(capture)
+}
+
+/// Check a capture file.
+///
+/// # Arguments
+///
+/// * `data`: the content of the file.
+///
+/// returns: Result<String, String> a summary with the headers, or where the file is corrupted.
+///
+pub fn verify(data: &[u8]) -> Result<String, String> {
+    let capture = parse(data)?;
+    let bytes: usize = capture.chunks.iter().map(|chunk| chunk.data.len()).sum();
+    Ok(format!(
+        "{}{} chunks, {} bytes, all the CRCs are valid.\n",
+        capture.headers,
+        capture.chunks.len(),
+        bytes
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::capture::{crc32, encode_chunk, verify, CaptureHeader};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_verify() {
+        let header = CaptureHeader {
+            device: PathBuf::from("/dev/ttyUSB0"),
+            baudrate: 9600,
+        };
+        let mut capture = header.encode("2023-11-14T12:00:00+0000");
+        capture.extend(encode_chunk(b"$GPGGA,", 1_699_963_200_000_000));
+        capture.extend(encode_chunk(b"$GPRMC,", 1_699_963_200_100_000));
+        // appended by a second run.
+        capture.extend(header.encode("2023-11-14T13:00:00+0000"));
+        let last_chunk = capture.len();
+        capture.extend(encode_chunk(b"$GPVTG,", 1_699_966_800_000_000));
+        let summary = verify(&capture).unwrap();
+        assert!(
+            summary.starts_with("device=/dev/ttyUSB0\nbaudrate=9600\nstart=2023-11-14T12:00:00")
+        );
+        assert!(summary.contains("start=2023-11-14T13:00:00+0000\n"));
+        assert!(summary.ends_with("3 chunks, 21 bytes, all the CRCs are valid.\n"));
+
+        // the first byte of its data.
+        capture[last_chunk + 12] = b'X';
+        assert_eq!(
+            verify(&capture),
+            Err(format!("bad CRC in the chunk at byte {}", last_chunk))
+        );
+        capture.pop();
+        assert!(verify(&capture).unwrap_err().starts_with("truncated chunk"));
+        assert!(verify(b"$GPGGA,").is_err());
+    }
+}
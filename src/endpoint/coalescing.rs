@@ -0,0 +1,152 @@
+//! Coalescing of the writes to an endpoint: the small reads of the master are gathered before they
+//! are written, so the consumer polling its PTY wakes up less often, at the cost of some latency.
+//! It matters to the battery powered companions.
+//!
+//! It is set with the `coalesce=TIMEOUT_MS,MAX_BYTES` endpoint option: the gathered bytes are
+//! written when they reach MAX_BYTES or when the oldest of them waited TIMEOUT_MS.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coalesce {
+    pub timeout: Duration,
+    pub max_bytes: usize,
+}
+
+impl FromStr for Coalesce {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected <TIMEOUT_MS>,<MAX_BYTES>, got {:?}", value);
+        let (timeout, max_bytes) = value.split_once(',').ok_or_else(invalid)?;
+        let timeout: u64 = timeout.parse().map_err(|_| invalid())?;
+        let max_bytes: usize = max_bytes.parse().map_err(|_| invalid())?;
+        if timeout == 0 || max_bytes == 0 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            timeout: Duration::from_millis(timeout),
+            max_bytes,
+        })
+    }
+}
+
+impl fmt::Display for Coalesce {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.timeout.as_millis(), self.max_bytes)
+    }
+}
+
+pub struct Coalescer {
+    config: Coalesce,
+    buffer: Vec<u8>,
+    // the frames completed in the buffer.
+    frames: usize,
+    // when the oldest byte of the buffer arrived.
+    since: Option<Instant>,
+}
+
+impl Coalescer {
+    pub fn new(config: Coalesce) -> Self {
+        Self {
+            config,
+            buffer: Vec::new(),
+            frames: 0,
+            since: None,
+        }
+    }
+
+    /// Gather bytes, returns everything gathered with its number of frames when it is enough.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: the bytes to write.
+    /// * `frames`: the frames completed in these bytes.
+    /// * `now`: the current time.
+    ///
+    /// returns: Option<(Vec<u8>, usize)> None while gathering.
+    ///
+    pub fn push(&mut self, data: &[u8], frames: usize, now: Instant) -> Option<(Vec<u8>, usize)> {
+        self.buffer.extend_from_slice(data);
+        self.frames += frames;
+        self.since.get_or_insert(now);
+        if self.buffer.len() >= self.config.max_bytes {
+            return self.take();
+        }
+        self.take_due(now)
+    }
+
+    /// Everything gathered with its number of frames, if the oldest byte waited long enough.
+    pub fn take_due(&mut self, now: Instant) -> Option<(Vec<u8>, usize)> {
+        match self.since {
+            Some(since) if now.saturating_duration_since(since) >= self.config.timeout => {
+                self.take()
+            }
+            _ => None,
+        }
+    }
+
+    fn take(&mut self) -> Option<(Vec<u8>, usize)> {
+        self.since = None;
+        let frames = std::mem::take(&mut self.frames);
+        Some((std::mem::take(&mut self.buffer), frames)).filter(|(data, _)| !data.is_empty())
+    }
+
+    /// When the gathered bytes are due, None if there are none.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.since.map(|since| since + self.config.timeout)
+    }
+
+    /// Drop the bytes gathered, returns how many there were.
+    pub fn clear(&mut self) -> usize {
+        self.since = None;
+        self.frames = 0;
+        let gathered = self.buffer.len();
+        self.buffer.clear();
+        gathered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::coalescing::{Coalesce, Coalescer};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_coalesce() {
+        let coalesce: Coalesce = "50,512".parse().unwrap();
+        assert_eq!(coalesce.timeout, Duration::from_millis(50));
+        assert_eq!(coalesce.max_bytes, 512);
+        assert_eq!(coalesce.to_string(), "50,512");
+        assert!("50".parse::<Coalesce>().is_err());
+        assert!("0,512".parse::<Coalesce>().is_err());
+        assert!("50,0".parse::<Coalesce>().is_err());
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let mut coalescer = Coalescer::new("50,8".parse().unwrap());
+        let start = Instant::now();
+        assert_eq!(coalescer.push(b"$GP", 0, start), None);
+        assert_eq!(
+            coalescer.deadline(),
+            Some(start + Duration::from_millis(50))
+        );
+        assert_eq!(
+            coalescer.push(b"GGA\r\n", 1, start + Duration::from_millis(10)),
+            Some((b"$GPGGA\r\n".to_vec(), 1))
+        );
+        assert_eq!(coalescer.deadline(), None);
+        assert_eq!(coalescer.push(b"$GP", 0, start), None);
+        assert_eq!(coalescer.take_due(start + Duration::from_millis(49)), None);
+        assert_eq!(
+            coalescer.take_due(start + Duration::from_millis(50)),
+            Some((b"$GP".to_vec(), 0))
+        );
+        assert_eq!(coalescer.push(b"RMC", 0, start), None);
+        assert_eq!(coalescer.clear(), 3);
+        assert_eq!(coalescer.take_due(start + Duration::from_secs(1)), None);
+    }
+}
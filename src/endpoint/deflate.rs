@@ -0,0 +1,233 @@
+//! The compressed stream of the `tcpz://` endpoints, for the radio backhauls: a client first gets
+//! `TTYTEE DEFLATE 2\n`, then a single raw deflate stream flushed after each chunk, so each chunk
+//! can be decoded as soon as it arrives.
+//!
+//! Before the deflate, a chunk that mostly repeats the previous one, like the next epoch of a
+//! receiver, is XORed with it byte per byte: the unchanged bytes become runs of zeros and only the
+//! changed fields are left. Each chunk in the stream has a header of 5 bytes, whether it is a delta
+//! and its length on 4 bytes, big endian. The chunks that do not line up with the previous one go
+//! as they are, the window of 32 KB still makes their repeated parts references to the previous
+//! ones. A client only gets whole chunks, in order, so both sides always XOR with the same chunk.
+//!
+//! Deflate rather than zstd: zstd is a C library, it would need a C compiler for each target like
+//! the sqlite endpoint does, where miniz_oxide is pure Rust. At the rates of a receiver, the ratio
+//! of zstd is not much better on a 32 KB window.
+//!
+//! `ttytee connect` decodes it back into a local PTY.
+
+use miniz_oxide::deflate::core::{create_comp_flags_from_zip_params, CompressorOxide};
+use miniz_oxide::inflate::stream::InflateState;
+use miniz_oxide::{DataFormat, MZFlush, MZStatus};
+
+pub const MAGIC: &[u8] = b"TTYTEE DEFLATE 2\n";
+
+// A fast level, the master is read at the pace of the receiver on small targets.
+const LEVEL: i32 = 3;
+// The raw deflate stream without the zlib header, with the largest window.
+const WINDOW_BITS: i32 = -15;
+// The type of the chunk and its length.
+const HEADER_LEN: usize = 5;
+const PLAIN: u8 = 0;
+const DELTA: u8 = 1;
+
+// XOR a chunk with the previous one, the bytes past its end are kept.
+fn xor(chunk: &[u8], previous: &[u8]) -> Vec<u8> {
+    let mut xored = chunk.to_vec();
+    for (c, p) in xored.iter_mut().zip(previous) {
+        *c ^= p;
+    }
+    xored
+}
+
+/// Compresses the chunks of a client, in the same stream.
+pub struct Deflater {
+    compressor: Box<CompressorOxide>,
+    // the last chunk in the stream, the reference of the next delta.
+    previous: Vec<u8>,
+}
+
+impl Default for Deflater {
+    fn default() -> Self {
+        Self {
+            compressor: Box::new(CompressorOxide::new(create_comp_flags_from_zip_params(
+                LEVEL,
+                WINDOW_BITS,
+                0,
+            ))),
+            previous: Vec::new(),
+        }
+    }
+}
+
+impl Deflater {
+    /// Compress a chunk, decodable as soon as it is received. The chunk must then be sent, the
+    /// next one may be a delta against it.
+    pub fn compress(&mut self, data: &[u8]) -> Vec<u8> {
+        // a delta when more than half of the chunk is unchanged.
+        let unchanged = data
+            .iter()
+            .zip(&self.previous)
+            .filter(|(c, p)| c == p)
+            .count();
+        let (kind, body) = if unchanged * 2 > data.len() {
+            (DELTA, xor(data, &self.previous))
+        } else {
+            (PLAIN, data.to_vec())
+        };
+        let mut chunk = Vec::with_capacity(HEADER_LEN + body.len());
+        chunk.push(kind);
+        chunk.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(&body);
+        self.previous = data.to_vec();
+
+        let mut compressed = Vec::with_capacity(data.len() / 2 + 64);
+        let mut input = &chunk[..];
+        let mut output = [0; 4096];
+        loop {
+            let result = miniz_oxide::deflate::stream::deflate(
+                &mut self.compressor,
+                input,
+                &mut output,
+                MZFlush::Sync,
+            );
+            compressed.extend_from_slice(&output[..result.bytes_written]);
+            input = &input[result.bytes_consumed..];
+            // the flush is complete when the output was not filled.
+            if input.is_empty() && result.bytes_written < output.len() {
+                return compressed;
+            }
+        }
+    }
+}
+
+/// Decompresses the stream of a `tcpz://` endpoint.
+pub struct Inflater {
+    state: Box<InflateState>,
+    // the start of a chunk not entirely received yet.
+    pending: Vec<u8>,
+    previous: Vec<u8>,
+}
+
+impl Default for Inflater {
+    fn default() -> Self {
+        Self {
+            state: InflateState::new_boxed(DataFormat::Raw),
+            pending: Vec::new(),
+            previous: Vec::new(),
+        }
+    }
+}
+
+impl Inflater {
+    /// Decompress what was received, whatever its boundaries.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: the next bytes of the stream.
+    ///
+    /// returns: Result<Vec<u8>, String> the bytes of the master, or why the stream is corrupted.
+    ///
+    pub fn inflate(&mut self, data: &[u8]) -> Result<Vec<u8>, String> {
+        self.inflate_chunks(data)?;
+        let mut chunks = Vec::new();
+        while self.pending.len() >= HEADER_LEN {
+            let len = u32::from_be_bytes(self.pending[1..HEADER_LEN].try_into().unwrap()) as usize;
+            if self.pending.len() < HEADER_LEN + len {
+                break;
+            }
+            let body = &self.pending[HEADER_LEN..HEADER_LEN + len];
+            let chunk = match self.pending[0] {
+                PLAIN => body.to_vec(),
+                DELTA => xor(body, &self.previous),
+                kind => return Err(format!("unknown chunk type {}", kind)),
+            };
+            chunks.extend_from_slice(&chunk);
+            self.previous = chunk;
+            self.pending.drain(..HEADER_LEN + len);
+        }
+        Ok(chunks)
+    }
+
+    // Decompress into the pending chunks.
+    fn inflate_chunks(&mut self, data: &[u8]) -> Result<(), String> {
+        let inflated = &mut self.pending;
+        let mut input = data;
+        let mut output = [0; 16384];
+        loop {
+            let result = miniz_oxide::inflate::stream::inflate(
+                &mut self.state,
+                input,
+                &mut output,
+                MZFlush::None,
+            );
+            inflated.extend_from_slice(&output[..result.bytes_written]);
+            input = &input[result.bytes_consumed..];
+            match result.status {
+                Ok(MZStatus::Ok) if input.is_empty() && result.bytes_written < output.len() => {
+                    return Ok(())
+                }
+                Ok(MZStatus::Ok) => {}
+                // the server never ends the stream.
+                Ok(status) => return Err(format!("unexpected end of the stream: {:?}", status)),
+                // nothing more can be done with the input received so far.
+                Err(miniz_oxide::MZError::Buf) => return Ok(()),
+                Err(err) => return Err(format!("corrupted stream: {:?}", err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::deflate::{Deflater, Inflater};
+    use crate::nmea::nmea_sentence;
+
+    #[test]
+    fn test_deflate_stream() {
+        let mut deflater = Deflater::default();
+        let mut inflater = Inflater::default();
+        let mut sent = Vec::new();
+        let mut stream = Vec::new();
+        for second in 0..100 {
+            let chunk = nmea_sentence(&[
+                "GPGGA",
+                &format!("1200{:02}.00", second % 60),
+                "4807.038",
+                "N",
+                "01131.000",
+                "E",
+                "1",
+                "08",
+                "0.9",
+                "545.4",
+                "M",
+                "46.9",
+                "M",
+                "",
+                "",
+            ]);
+            let compressed = deflater.compress(&chunk);
+            // each chunk is decoded as soon as it is received.
+            assert_eq!(inflater.inflate(&compressed).unwrap(), chunk);
+            sent.extend(chunk);
+            stream.extend(compressed);
+        }
+        // the epochs only differ by their time, they go as deltas.
+        assert!(stream.len() * 3 < sent.len(), "{}", stream.len());
+
+        // whatever the boundaries of the reads.
+        let mut inflater = Inflater::default();
+        let mut received = Vec::new();
+        for read in stream.chunks(7) {
+            received.extend(inflater.inflate(read).unwrap());
+        }
+        assert_eq!(received, sent);
+
+        // the chunks that do not line up with the previous one go as they are.
+        let mut deflater = Deflater::default();
+        let mut inflater = Inflater::default();
+        for chunk in sent.chunks(33) {
+            assert_eq!(inflater.inflate(&deflater.compress(chunk)).unwrap(), chunk);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Delay of the writes to an endpoint, to test how its consumer copes with a slow link: with the
+//! `delay=MEAN±JITTER` endpoint option, like `delay=50ms±20`, each write is held MEAN ms give or
+//! take up to JITTER ms at random. The order of the writes is kept, as on a real link.
+
+use crate::transform::chaos::Rng;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Delay {
+    pub mean: Duration,
+    pub jitter: Duration,
+}
+
+impl FromStr for Delay {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected <MEAN_MS>[±<JITTER_MS>], got {:?}", value);
+        let parse_ms = |ms: &str| -> Result<Duration, String> {
+            let ms = ms.trim();
+            ms.strip_suffix("ms")
+                .unwrap_or(ms)
+                .parse()
+                .map(Duration::from_millis)
+                .map_err(|_| invalid())
+        };
+        let (mean, jitter) = match value.split_once('±').or_else(|| value.split_once("+-")) {
+            Some((mean, jitter)) => (parse_ms(mean)?, parse_ms(jitter)?),
+            None => (parse_ms(value)?, Duration::ZERO),
+        };
+        Ok(Self { mean, jitter })
+    }
+}
+
+impl fmt::Display for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}ms", self.mean.as_millis())?;
+        if !self.jitter.is_zero() {
+            write!(f, "±{}", self.jitter.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+pub struct DelayLine {
+    config: Delay,
+    rng: Rng,
+    // (when due, bytes, number of frames) in the order of the writes.
+    queue: VecDeque<(Instant, Vec<u8>, usize)>,
+}
+
+impl DelayLine {
+    /// Create a delay line.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`: the delay of the writes.
+    /// * `rng`: the random generator of the jitter.
+    ///
+    /// returns: DelayLine
+    ///
+    pub fn new(config: Delay, rng: Rng) -> Self {
+        Self {
+            config,
+            rng,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Hold bytes until their delay is over.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: the bytes to write.
+    /// * `frames`: the frames completed in these bytes.
+    /// * `now`: the current time.
+    ///
+    pub fn push(&mut self, data: &[u8], frames: usize, now: Instant) {
+        let jitter = self.config.jitter.as_secs_f64() * (2.0 * self.rng.next_f64() - 1.0);
+        let delay = Duration::from_secs_f64((self.config.mean.as_secs_f64() + jitter).max(0.0));
+        // not before the previous write.
+        let due = self
+            .queue
+            .back()
+            .map_or(now + delay, |&(last, _, _)| last.max(now + delay));
+        self.queue.push_back((due, data.to_vec(), frames));
+    }
+
+    /// The bytes whose delay is over with their number of frames, None if there are none.
+    pub fn take_due(&mut self, now: Instant) -> Option<(Vec<u8>, usize)> {
+        let mut due: Option<(Vec<u8>, usize)> = None;
+        while matches!(self.queue.front(), Some(&(at, _, _)) if at <= now) {
+            let (_, data, frames) = self.queue.pop_front()?;
+            let (gathered, gathered_frames) = due.get_or_insert_with(Default::default);
+            gathered.extend_from_slice(&data);
+            *gathered_frames += frames;
+        }
+        due
+    }
+
+    /// When the next bytes are due, None if there are none.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.queue.front().map(|&(at, _, _)| at)
+    }
+
+    /// Drop the bytes held, returns how many there were.
+    pub fn clear(&mut self) -> usize {
+        self.queue.drain(..).map(|(_, data, _)| data.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::delay::{Delay, DelayLine};
+    use crate::transform::chaos::Rng;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_delay() {
+        let delay: Delay = "50ms±20".parse().unwrap();
+        assert_eq!(delay.mean, Duration::from_millis(50));
+        assert_eq!(delay.jitter, Duration::from_millis(20));
+        assert_eq!(delay.to_string(), "50ms±20");
+        assert_eq!("50+-20ms".parse(), Ok(delay));
+        assert_eq!("100".parse::<Delay>().unwrap().to_string(), "100ms");
+        assert!("soon".parse::<Delay>().is_err());
+        assert!("50ms±".parse::<Delay>().is_err());
+    }
+
+    #[test]
+    fn test_delay_line() {
+        let mut line = DelayLine::new("50ms±20".parse().unwrap(), Rng::new(42));
+        let start = Instant::now();
+        line.push(b"$GPGGA\r\n", 1, start);
+        line.push(b"$GPRMC\r\n", 1, start + Duration::from_millis(1));
+        assert!(line.deadline().unwrap() >= start + Duration::from_millis(30));
+        assert_eq!(line.take_due(start + Duration::from_millis(29)), None);
+        assert_eq!(
+            line.take_due(start + Duration::from_millis(71)),
+            Some((b"$GPGGA\r\n$GPRMC\r\n".to_vec(), 2))
+        );
+        assert_eq!(line.deadline(), None);
+        line.push(b"$GPGSA", 0, start);
+        assert_eq!(line.clear(), 6);
+        assert_eq!(line.take_due(start + Duration::from_secs(1)), None);
+    }
+}
@@ -0,0 +1,246 @@
+//! File endpoints: the stream is appended to a file.
+//!
+//! The path can contain strftime patterns in local time, for example
+//! `file:///var/log/gps/%Y-%m-%d.nmea`, a new file is then started each time the formatted path
+//! changes (here every day). Set `TZ=UTC` in the environment of ttytee for UTC names.
+//!
+//! The `capture://` endpoints are the same with the header and the chunks of the capture format.
+
+use crate::endpoint::capture::{encode_chunk, CaptureHeader};
+use crate::endpoint::Endpoint;
+use log::info;
+use std::ffi::CString;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct FileEndpoint {
+    pattern: String,
+    path: PathBuf,
+    file: File,
+    // the second the path was last formatted at, it doesn't need to be done more often.
+    checked_at: i64,
+    // the header of the capture files, None for the plain files.
+    capture: Option<CaptureHeader>,
+}
+
+impl FileEndpoint {
+    /// Open a file for appending, it is created if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern`: the path of the file, with optional strftime patterns.
+    ///
+    /// returns: Result<FileEndpoint, Error>
+    ///
+    pub fn open(pattern: &Path) -> io::Result<Self> {
+        Self::open_at(pattern, now_micros(), None)
+    }
+
+    /// Open a capture file for appending, it is created if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern`: the path of the file, with optional strftime patterns.
+    /// * `header`: the description of the master, written each time a file is opened.
+    ///
+    /// returns: Result<FileEndpoint, Error>
+    ///
+    pub fn open_capture(pattern: &Path, header: CaptureHeader) -> io::Result<Self> {
+        Self::open_at(pattern, now_micros(), Some(header))
+    }
+
+    fn open_at(pattern: &Path, micros: u64, capture: Option<CaptureHeader>) -> io::Result<Self> {
+        let pattern = pattern
+            .to_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "non UTF-8 path"))?
+            .to_string();
+        let time = seconds(micros);
+        let path = PathBuf::from(format_time(&pattern, time)?);
+        let mut endpoint = Self {
+            pattern,
+            file: open_append(&path)?,
+            path,
+            checked_at: time,
+            capture,
+        };
+        endpoint.write_header(time)?;
+        Ok(endpoint)
+    }
+
+    fn write_header(&mut self, time: i64) -> io::Result<()> {
+        match &self.capture {
+            Some(header) => {
+                let start = format_time("%Y-%m-%dT%H:%M:%S%z", time)?;
+                self.file.write_all(&header.encode(&start))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn write_at(&mut self, data: &[u8], micros: u64) -> io::Result<()> {
+        let time = seconds(micros);
+        if time != self.checked_at {
+            self.checked_at = time;
+            let path = PathBuf::from(format_time(&self.pattern, time)?);
+            if path != self.path {
+                self.file = open_append(&path)?;
+                info!("Now writing to {:?}.", path);
+                self.path = path;
+                self.write_header(time)?;
+            }
+        }
+        if self.capture.is_some() {
+            self.file.write_all(&encode_chunk(data, micros))
+        } else {
+            self.file.write_all(data)
+        }
+    }
+}
+
+impl Endpoint for FileEndpoint {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_at(data, now_micros())
+    }
+}
+
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_micros() as u64)
+}
+
+fn seconds(micros: u64) -> i64 {
+    (micros / 1_000_000) as i64
+}
+
+pub fn open_append(path: &Path) -> io::Result<File> {
+    // the patterns often make a directory per month or year.
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Format a time with strftime in local time.
+///
+/// # Arguments
+///
+/// * `pattern`: the strftime pattern, returned as is when it has no `%`.
+/// * `time`: seconds since the epoch.
+///
+/// returns: Result<String, Error>
+///
+pub fn format_time(pattern: &str, time: i64) -> io::Result<String> {
+    if !pattern.contains('%') {
+        return Ok(pattern.to_string());
+    }
+    let c_pattern =
+        CString::new(pattern).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let time = time as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buffer = [0u8; 4096];
+    let len = unsafe {
+        libc::strftime(
+            buffer.as_mut_ptr() as *mut libc::c_char,
+            buffer.len(),
+            c_pattern.as_ptr(),
+            &tm,
+        )
+    };
+    if len == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("could not format {:?}", pattern),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&buffer[..len]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::capture::{verify, CaptureHeader};
+    use crate::endpoint::file::{format_time, FileEndpoint};
+    use crate::endpoint::Endpoint;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // 2023-11-14 at noon UTC, still the same day in most timezones.
+    const NOON: i64 = 1_699_963_200;
+    const DAY: i64 = 86_400;
+    const MICROS: u64 = 1_000_000;
+
+    #[test]
+    fn test_file_appends() {
+        let path = PathBuf::from("/tmp/ttytee_file_endpoint_test");
+        fs::write(&path, b"before,").unwrap();
+        let mut endpoint = FileEndpoint::open(&path).unwrap();
+        endpoint.write(b"$GPGGA,").unwrap();
+        endpoint.write(b"$GPRMC").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"before,$GPGGA,$GPRMC");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(
+            format_time("/var/log/gps.nmea", NOON).unwrap(),
+            "/var/log/gps.nmea"
+        );
+        assert_eq!(format_time("%Y/%%", NOON).unwrap(), "2023/%");
+    }
+
+    #[test]
+    fn test_daily_files() {
+        let directory = PathBuf::from("/tmp/ttytee_daily_files_test");
+        fs::remove_dir_all(&directory).ok();
+        let pattern = directory.join("%Y/%j.nmea");
+        let noon = NOON as u64 * MICROS;
+        let mut endpoint = FileEndpoint::open_at(&pattern, noon, None).unwrap();
+        endpoint.write_at(b"$GPGGA,", noon).unwrap();
+        endpoint.write_at(b"$GPRMC,", noon + MICROS).unwrap();
+        endpoint
+            .write_at(b"$GPVTG,", noon + DAY as u64 * MICROS)
+            .unwrap();
+        assert_eq!(
+            fs::read(directory.join("2023/318.nmea")).unwrap(),
+            b"$GPGGA,$GPRMC,"
+        );
+        assert_eq!(
+            fs::read(directory.join("2023/319.nmea")).unwrap(),
+            b"$GPVTG,"
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_capture_files() {
+        let directory = PathBuf::from("/tmp/ttytee_capture_files_test");
+        fs::remove_dir_all(&directory).ok();
+        let pattern = directory.join("%j.cap");
+        let header = CaptureHeader {
+            device: PathBuf::from("/dev/ttyUSB0"),
+            baudrate: 115200,
+        };
+        let noon = NOON as u64 * MICROS;
+        let mut endpoint = FileEndpoint::open_at(&pattern, noon, Some(header)).unwrap();
+        endpoint.write_at(b"$GPGGA,", noon).unwrap();
+        endpoint
+            .write_at(b"$GPVTG,", noon + DAY as u64 * MICROS)
+            .unwrap();
+        // each file is self describing.
+        for day in ["318", "319"] {
+            let summary = verify(&fs::read(directory.join(format!("{}.cap", day))).unwrap());
+            assert!(summary.unwrap().contains("baudrate=115200\n"));
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+//! The encoding of the stream sent to an endpoint.
+
+use crate::framing::{Frame, Protocol};
+use crate::nmea::nmea_fields;
+use crate::rtcm::rtcm_station;
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// How the frames are written to an endpoint.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// The bytes as received from the master.
+    #[default]
+    Raw,
+    /// One JSON object per line and per frame, for the log pipelines and jq.
+    Json,
+    /// One JSON object per line and per frame of the master with its sequence number and its time
+    /// of receipt, a sidecar for the consumers that need precise timings.
+    Metadata,
+    /// An annotated hexdump of the bytes of the master, for the field engineers inspecting the
+    /// binary protocols.
+    Hexdump,
+    /// One JSON object per line pairing a pulse of the --pps device with the RMC time of its second,
+    /// for the time-sync consumers, nothing else of the master.
+    Timebase,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 5] = [
+        Self::Raw,
+        Self::Json,
+        Self::Metadata,
+        Self::Hexdump,
+        Self::Timebase,
+    ];
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "raw" => Ok(Self::Raw),
+            "json" => Ok(Self::Json),
+            "metadata" => Ok(Self::Metadata),
+            "hexdump" => Ok(Self::Hexdump),
+            "timebase" => Ok(Self::Timebase),
+            _ => Err(format!(
+                "unknown format {:?}, expected raw, json, metadata, hexdump or timebase",
+                format
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Raw => write!(f, "raw"),
+            Self::Json => write!(f, "json"),
+            Self::Metadata => write!(f, "metadata"),
+            Self::Hexdump => write!(f, "hexdump"),
+            Self::Timebase => write!(f, "timebase"),
+        }
+    }
+}
+
+pub fn json_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Encode a frame as a line of JSON.
+///
+/// A NMEA sentence gives `{"protocol":"nmea","talker":"GP","type":"GGA","fields":["123519",...]}`
+/// a UBX message `{"protocol":"ubx","type":"NAV-PVT","payload":"<hex>"}` and a RTCM message
+/// `{"protocol":"rtcm","type":"RTCM-1077","station":2003,"payload":"<hex>"}`, with the station for
+/// the messages that have one, and a SLIP frame `{"protocol":"slip","payload":"<hex>"}` with its
+/// datagram unescaped.
+///
+/// # Arguments
+///
+/// * `frame`: a complete frame.
+///
+/// returns: Option<Vec<u8>> None if the sentence cannot be decoded.
+///
+pub fn json_line(frame: &Frame) -> Option<Vec<u8>> {
+    let mut line = String::new();
+    match frame.protocol {
+        Protocol::Nmea => {
+            let fields = nmea_fields(&frame.data)?;
+            let message_type = frame.message_type();
+            let talker = &fields[0][..fields[0].len() - message_type.len()];
+            line.push_str("{\"protocol\":\"nmea\",\"talker\":");
+            json_string(talker, &mut line);
+            line.push_str(",\"type\":");
+            json_string(&message_type, &mut line);
+            line.push_str(",\"fields\":[");
+            for (i, field) in fields[1..].iter().enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                json_string(field, &mut line);
+            }
+            line.push_str("]}\n");
+        }
+        Protocol::Ubx => {
+            line.push_str("{\"protocol\":\"ubx\",\"type\":");
+            json_string(&frame.message_type(), &mut line);
+            line.push_str(",\"payload\":\"");
+            for byte in &frame.data[6..frame.data.len() - 2] {
+                write!(line, "{:02x}", byte).unwrap();
+            }
+            line.push_str("\"}\n");
+        }
+        Protocol::Rtcm => {
+            line.push_str("{\"protocol\":\"rtcm\",\"type\":");
+            json_string(&frame.message_type(), &mut line);
+            if let Some(station) = rtcm_station(frame) {
+                write!(line, ",\"station\":{}", station).unwrap();
+            }
+            line.push_str(",\"payload\":\"");
+            for byte in &frame.data[3..frame.data.len() - 3] {
+                write!(line, "{:02x}", byte).unwrap();
+            }
+            line.push_str("\"}\n");
+        }
+        Protocol::Modbus => {
+            line.push_str("{\"protocol\":\"modbus\",\"type\":");
+            json_string(&frame.message_type(), &mut line);
+            write!(line, ",\"address\":{},\"payload\":\"", frame.data[0]).unwrap();
+            for byte in &frame.data[2..frame.data.len() - 2] {
+                write!(line, "{:02x}", byte).unwrap();
+            }
+            line.push_str("\"}\n");
+        }
+        Protocol::Slip => {
+            line.push_str("{\"protocol\":\"slip\",\"payload\":\"");
+            for byte in frame.payload() {
+                write!(line, "{:02x}", byte).unwrap();
+            }
+            line.push_str("\"}\n");
+        }
+    }
+    Some(line.into_bytes())
+}
+
+/// Describe a frame as a line of JSON, for example
+/// `{"sequence":42,"received_at":1699963200.123456,"type":"GGA","bytes":72}`.
+///
+/// # Arguments
+///
+/// * `frame`: a frame of the master.
+/// * `sequence`: its number since ttytee started, the first one is 0.
+/// * `received_at`: when it was received, in seconds since the epoch.
+///
+/// returns: Vec<u8>
+///
+pub fn metadata_line(frame: &Frame, sequence: u64, received_at: f64) -> Vec<u8> {
+    let mut line = format!(
+        "{{\"sequence\":{},\"received_at\":{:.6},\"type\":",
+        sequence, received_at
+    );
+    json_string(&frame.message_type(), &mut line);
+    writeln!(line, ",\"bytes\":{}}}", frame.data.len()).unwrap();
+    line.into_bytes()
+}
+
+/// Dump a read of the master in hexadecimal, after a line with its time of receipt, its size and
+/// the frames it completes:
+///
+/// ```text
+/// @1699963200.123456 18 bytes: GSA
+/// 0000  24 47 50 47 53 41 2c 41  2c 33 2c 30 34 2a 33 41  |$GPGSA,A,3,04*3A|
+/// 0010  0d 0a                                             |..|
+/// ```
+///
+/// # Arguments
+///
+/// * `buffer`: the bytes read from the master.
+/// * `frames`: the frames completed by these bytes.
+/// * `received_at`: when it was received, in seconds since the epoch.
+///
+/// returns: Vec<u8>
+///
+pub fn hexdump(buffer: &[u8], frames: &[Frame], received_at: f64) -> Vec<u8> {
+    let mut dump = format!("@{:.6} {} bytes", received_at, buffer.len());
+    for (i, frame) in frames.iter().enumerate() {
+        dump.push_str(if i == 0 { ": " } else { " " });
+        dump.push_str(&frame.message_type());
+    }
+    dump.push('\n');
+    for (offset, line) in (0..).step_by(16).zip(buffer.chunks(16)) {
+        write!(dump, "{:04x}  ", offset).unwrap();
+        for i in 0..16 {
+            if i == 8 {
+                dump.push(' ');
+            }
+            match line.get(i) {
+                Some(byte) => write!(dump, "{:02x} ", byte).unwrap(),
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str(" |");
+        dump.extend(line.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        }));
+        dump.push_str("|\n");
+    }
+    dump.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::format::{hexdump, json_line, metadata_line};
+    use crate::framing::{Frame, Protocol};
+    use std::slice;
+
+    #[test]
+    fn test_json_line() {
+        let nmea = |data: &[u8]| {
+            json_line(&Frame {
+                protocol: Protocol::Nmea,
+                data: data.to_vec(),
+            })
+        };
+        assert_eq!(
+            nmea(b"$GPGSA,A,3,04*3A\r\n").unwrap(),
+            b"{\"protocol\":\"nmea\",\"talker\":\"GP\",\"type\":\"GSA\",\"fields\":[\"A\",\"3\",\"04\"]}\n"
+        );
+        assert_eq!(
+            nmea(b"$PUBX,00,\"a\\*00\r\n").unwrap(),
+            b"{\"protocol\":\"nmea\",\"talker\":\"\",\"type\":\"PUBX\",\"fields\":[\"00\",\"\\\"a\\\\\"]}\n"
+        );
+        let ubx = json_line(&Frame {
+            protocol: Protocol::Ubx,
+            data: vec![0xB5, 0x62, 0x05, 0x01, 0x02, 0x00, 0x06, 0x01, 0x0F, 0x38],
+        });
+        assert_eq!(
+            ubx.unwrap(),
+            b"{\"protocol\":\"ubx\",\"type\":\"ACK-ACK\",\"payload\":\"0601\"}\n"
+        );
+        let modbus = json_line(&Frame {
+            protocol: Protocol::Modbus,
+            data: vec![0x11, 0x03, 0x00, 0x6B, 0x00, 0x03, 0x76, 0x87],
+        });
+        assert_eq!(
+            modbus.unwrap(),
+            b"{\"protocol\":\"modbus\",\"type\":\"MODBUS-3\",\"address\":17,\"payload\":\"006b0003\"}\n"
+        );
+        let slip = json_line(&Frame {
+            protocol: Protocol::Slip,
+            data: vec![0xC0, 0x01, 0xDB, 0xDD],
+        });
+        assert_eq!(
+            slip.unwrap(),
+            b"{\"protocol\":\"slip\",\"payload\":\"01db\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_metadata_line() {
+        let frame = Frame {
+            protocol: Protocol::Nmea,
+            data: b"$GPGSA,A,3,04*3A\r\n".to_vec(),
+        };
+        assert_eq!(
+            metadata_line(&frame, 42, 1_699_963_200.123_456),
+            b"{\"sequence\":42,\"received_at\":1699963200.123456,\"type\":\"GSA\",\"bytes\":18}\n"
+        );
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let frame = Frame {
+            protocol: Protocol::Nmea,
+            data: b"$GPGSA,A,3,04*3A\r\n".to_vec(),
+        };
+        assert_eq!(
+            String::from_utf8(hexdump(
+                &frame.data,
+                slice::from_ref(&frame),
+                1_699_963_200.123_456
+            ))
+            .unwrap(),
+            "@1699963200.123456 18 bytes: GSA\n\
+             0000  24 47 50 47 53 41 2c 41  2c 33 2c 30 34 2a 33 41  |$GPGSA,A,3,04*3A|\n\
+             0010  0d 0a                                             |..|\n"
+        );
+        assert_eq!(hexdump(b"", &[], 0.5), b"@0.500000 0 bytes\n");
+    }
+}
@@ -0,0 +1,471 @@
+//! gpsd endpoints: the clients written against gpsd connect to ttytee directly, like
+//! `gpsd://127.0.0.1:2947`, when running gpsd is overkill.
+//!
+//! Only a subset of the gpsd JSON protocol is spoken: a client gets the VERSION when it connects,
+//! `?WATCH={"enable":true,"json":true};` answers DEVICES and WATCH and starts the TPV reports, one
+//! per epoch from the GGA and RMC sentences of the master. `?VERSION;` and `?DEVICES;` are
+//! answered, the other requests get an ERROR. The NMEA output of the master is not forwarded.
+
+use crate::endpoint::format::json_string;
+use crate::endpoint::tail::{Delivery, WriteTail};
+use crate::endpoint::Endpoint;
+use crate::events::Event;
+use crate::nmea::{Gga, Rmc};
+use log::{info, warn};
+use std::fmt::Write as _;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use ttytee::framing::{Framer, Protocol};
+
+const MAX_REQUEST_SIZE: usize = 4096;
+// The version of the protocol of gpsd 3.25.
+const PROTO_MAJOR: u32 = 3;
+const PROTO_MINOR: u32 = 15;
+const KNOTS_TO_MPS: f64 = 0.514_444;
+
+struct GpsdClient {
+    address: SocketAddr,
+    stream: TcpStream,
+    // what the client sent, up to the end of its last request.
+    request: Vec<u8>,
+    watching: bool,
+    // the rest of a report it could not take yet.
+    tail: WriteTail,
+}
+
+// The sentences of an epoch, they share its time.
+#[derive(Default)]
+struct Epoch {
+    time: String,
+    gga: Option<Gga>,
+    rmc: Option<Rmc>,
+    reported: bool,
+}
+
+pub struct GpsdEndpoint {
+    listener: TcpListener,
+    device: String,
+    clients: Vec<GpsdClient>,
+    framer: Framer,
+    epoch: Epoch,
+    // ddmmyy of the last RMC, the GGA don't have it.
+    date: Option<String>,
+    partial_writes: u64,
+}
+
+impl GpsdEndpoint {
+    /// Listen for the gpsd clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: the address to listen on, for example `127.0.0.1:2947`.
+    /// * `device`: the master, the device of the reports.
+    ///
+    /// returns: Result<GpsdEndpoint, Error>
+    ///
+    pub fn bind(address: &str, device: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        // the clients are accepted from the fan-out loop, it must never wait for them.
+        listener.set_nonblocking(true)?;
+        info!("Listening for gpsd clients on {}.", listener.local_addr()?);
+        Ok(Self {
+            listener,
+            device: device.to_string(),
+            clients: Vec::new(),
+            framer: Framer::new(&[Protocol::Nmea]),
+            epoch: Epoch::default(),
+            date: None,
+            partial_writes: 0,
+        })
+    }
+
+    fn accept_clients(&mut self) -> io::Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((mut stream, address)) => {
+                    stream.set_nonblocking(true)?;
+                    stream.set_nodelay(true)?;
+                    if stream.write_all(version().as_bytes()).is_ok() {
+                        info!("gpsd client {} connected.", address);
+                        self.clients.push(GpsdClient {
+                            address,
+                            stream,
+                            request: Vec::new(),
+                            watching: false,
+                            tail: WriteTail::default(),
+                        });
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Read the requests that arrived and answer them.
+    fn serve_requests(&mut self) {
+        let device = &self.device;
+        self.clients.retain_mut(|client| {
+            let mut buffer = [0; 1024];
+            loop {
+                match client.stream.read(&mut buffer) {
+                    Ok(0) => {
+                        info!("gpsd client {} disconnected.", client.address);
+                        return false;
+                    }
+                    Ok(len) => client.request.extend_from_slice(&buffer[..len]),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        warn!(
+                            event = Event::ClientDisconnected.code();
+                            "gpsd client {} disconnected: {}.",
+                            client.address, err
+                        );
+                        return false;
+                    }
+                }
+            }
+            while let Some(end) = client
+                .request
+                .iter()
+                .position(|&byte| byte == b';' || byte == b'\n')
+            {
+                let request: Vec<u8> = client.request.drain(..=end).collect();
+                let request = String::from_utf8_lossy(&request[..end]).trim().to_string();
+                if request.is_empty() {
+                    continue;
+                }
+                let (response, watching) = answer(&request, device, client.watching);
+                client.watching = watching;
+                if let Err(err) = client.stream.write_all(response.as_bytes()) {
+                    warn!(
+                        "gpsd client {} could not be answered: {}.",
+                        client.address, err
+                    );
+                    return false;
+                }
+            }
+            // not a gpsd client.
+            client.request.len() < MAX_REQUEST_SIZE
+        });
+    }
+
+    // Add a sentence to its epoch, returns the TPV reports that are complete.
+    fn push_sentence(&mut self, sentence: &[u8], reports: &mut Vec<String>) {
+        let (gga, rmc) = (Gga::parse(sentence), Rmc::parse(sentence));
+        let time = match (&gga, &rmc) {
+            (Some(gga), _) => gga.time.clone(),
+            (_, Some(rmc)) => rmc.time.clone(),
+            _ => return,
+        };
+        if time != self.epoch.time {
+            // the previous epoch did not have both sentences.
+            if !self.epoch.reported && (self.epoch.gga.is_some() || self.epoch.rmc.is_some()) {
+                reports.push(self.tpv());
+            }
+            self.epoch = Epoch {
+                time,
+                ..Default::default()
+            };
+        }
+        if let Some(rmc) = rmc {
+            self.date = Some(rmc.date.clone()).filter(|date| date.len() == 6);
+            self.epoch.rmc = Some(rmc);
+        }
+        if gga.is_some() {
+            self.epoch.gga = gga;
+        }
+        if !self.epoch.reported && self.epoch.gga.is_some() && self.epoch.rmc.is_some() {
+            self.epoch.reported = true;
+            reports.push(self.tpv());
+        }
+    }
+
+    // The TPV report of the current epoch.
+    fn tpv(&self) -> String {
+        let (gga, rmc) = (self.epoch.gga.as_ref(), self.epoch.rmc.as_ref());
+        // 1 no fix, 2 2D, 3 3D.
+        let mode = match (gga, rmc) {
+            (Some(gga), _) if gga.fix_quality == 0 => 1,
+            (Some(gga), _) if gga.altitude.is_some() => 3,
+            (Some(_), _) => 2,
+            (None, Some(rmc)) if rmc.valid => 2,
+            _ => 1,
+        };
+        let mut report = String::from("{\"class\":\"TPV\",\"device\":");
+        json_string(&self.device, &mut report);
+        write!(report, ",\"mode\":{}", mode).unwrap();
+        if let Some(time) = self
+            .date
+            .as_deref()
+            .and_then(|date| iso_time(date, &self.epoch.time))
+        {
+            write!(report, ",\"time\":\"{}\"", time).unwrap();
+        }
+        if mode >= 2 {
+            let latitude = gga
+                .and_then(|gga| gga.latitude)
+                .or(rmc.and_then(|rmc| rmc.latitude));
+            let longitude = gga
+                .and_then(|gga| gga.longitude)
+                .or(rmc.and_then(|rmc| rmc.longitude));
+            if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+                write!(report, ",\"lat\":{:.9},\"lon\":{:.9}", latitude, longitude).unwrap();
+            }
+            if let Some(altitude) = gga.and_then(|gga| gga.altitude).filter(|_| mode == 3) {
+                write!(report, ",\"altMSL\":{:.3}", altitude).unwrap();
+            }
+            if let Some(speed) = rmc.and_then(|rmc| rmc.speed) {
+                write!(report, ",\"speed\":{:.3}", speed * KNOTS_TO_MPS).unwrap();
+            }
+            if let Some(course) = rmc.and_then(|rmc| rmc.course) {
+                write!(report, ",\"track\":{:.4}", course).unwrap();
+            }
+        }
+        report.push_str("}\r\n");
+        report
+    }
+}
+
+/// The time of an epoch in ISO 8601, like gpsd.
+///
+/// # Arguments
+///
+/// * `date`: ddmmyy from a RMC sentence.
+/// * `time`: hhmmss.ss from a GGA or RMC sentence.
+///
+/// returns: Option<String> like `2024-03-23T12:35:19.000Z`, None if they are malformed.
+///
+fn iso_time(date: &str, time: &str) -> Option<String> {
+    if !date.bytes().all(|byte| byte.is_ascii_digit()) || date.len() != 6 || time.len() < 6 {
+        return None;
+    }
+    let seconds: f64 = time.get(4..)?.parse().ok()?;
+    Some(format!(
+        "20{}-{}-{}T{}:{}:{:06.3}Z",
+        &date[4..6],
+        &date[2..4],
+        &date[..2],
+        time.get(..2)?,
+        time.get(2..4)?,
+        seconds
+    ))
+}
+
+fn version() -> String {
+    format!(
+        "{{\"class\":\"VERSION\",\"release\":\"{}\",\"rev\":\"ttytee\",\"proto_major\":{},\"proto_minor\":{}}}\r\n",
+        env!("CARGO_PKG_VERSION"),
+        PROTO_MAJOR,
+        PROTO_MINOR
+    )
+}
+
+fn devices(device: &str) -> String {
+    let mut devices =
+        String::from("{\"class\":\"DEVICES\",\"devices\":[{\"class\":\"DEVICE\",\"path\":");
+    json_string(device, &mut devices);
+    devices.push_str(",\"driver\":\"NMEA0183\"}]}\r\n");
+    devices
+}
+
+/// The answer to a request.
+///
+/// # Arguments
+///
+/// * `request`: a request without its `;`, like `?WATCH={"enable":true}`.
+/// * `device`: the master.
+/// * `watching`: whether the client gets the reports.
+///
+/// returns: (String, bool) the response, and whether the client gets the reports from now on.
+///
+fn answer(request: &str, device: &str, watching: bool) -> (String, bool) {
+    let (command, parameters) = request.split_once('=').unwrap_or((request, ""));
+    match command {
+        "?VERSION" => (version(), watching),
+        "?DEVICES" => (devices(device), watching),
+        "?WATCH" => {
+            let parameters: String = parameters.split_whitespace().collect();
+            let enable = !parameters.contains("\"enable\":false");
+            let watch = format!(
+                "{{\"class\":\"WATCH\",\"enable\":{},\"json\":true}}\r\n",
+                enable
+            );
+            (devices(device) + &watch, enable)
+        }
+        _ => {
+            let mut error = String::from("{\"class\":\"ERROR\",\"message\":");
+            json_string(&format!("Unrecognized request '{}'", command), &mut error);
+            error.push_str("}\r\n");
+            (error, watching)
+        }
+    }
+}
+
+impl Endpoint for GpsdEndpoint {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.accept_clients()?;
+        self.serve_requests();
+        let mut frames = Vec::new();
+        self.framer.push(data, &mut frames);
+        let mut reports = Vec::new();
+        for frame in &frames {
+            self.push_sentence(&frame.data, &mut reports);
+        }
+        for report in &reports {
+            self.clients.retain_mut(|client| {
+                if !client.watching {
+                    return true;
+                }
+                match client.tail.write(&mut client.stream, report.as_bytes()) {
+                    Ok(Delivery::Whole) => true,
+                    Ok(Delivery::Partial) => {
+                        self.partial_writes += 1;
+                        true
+                    }
+                    // like the PTYs, a client that cannot keep up misses reports.
+                    Ok(Delivery::Dropped) => true,
+                    Err(err) => {
+                        warn!(
+                            event = Event::ClientDisconnected.code();
+                            "gpsd client {} disconnected: {}.",
+                            client.address, err
+                        );
+                        false
+                    }
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn consumers(&self) -> Option<Vec<String>> {
+        Some(
+            self.clients
+                .iter()
+                .map(|client| client.address.to_string())
+                .collect(),
+        )
+    }
+
+    fn partial_writes(&self) -> u64 {
+        self.partial_writes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::gpsd::{answer, iso_time, GpsdEndpoint};
+    use crate::endpoint::Endpoint;
+    use crate::nmea::nmea_sentence;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::Duration;
+
+    fn gga(time: &str) -> Vec<u8> {
+        nmea_sentence(&[
+            "GPGGA",
+            time,
+            "4807.038",
+            "N",
+            "01131.000",
+            "E",
+            "1",
+            "08",
+            "0.9",
+            "545.4",
+            "M",
+            "46.9",
+            "M",
+            "",
+            "",
+        ])
+    }
+
+    fn rmc(time: &str) -> Vec<u8> {
+        nmea_sentence(&[
+            "GPRMC",
+            time,
+            "A",
+            "4807.038",
+            "N",
+            "01131.000",
+            "E",
+            "022.4",
+            "084.4",
+            "230324",
+            "003.1",
+            "W",
+        ])
+    }
+
+    #[test]
+    fn test_answer() {
+        let (response, watching) = answer(
+            "?WATCH={\"enable\":true,\"json\":true}",
+            "/dev/ttyUSB0",
+            false,
+        );
+        assert!(watching);
+        assert!(response.starts_with(
+            "{\"class\":\"DEVICES\",\"devices\":[{\"class\":\"DEVICE\",\"path\":\"/dev/ttyUSB0\""
+        ));
+        assert!(response.ends_with("{\"class\":\"WATCH\",\"enable\":true,\"json\":true}\r\n"));
+        assert!(!answer("?WATCH={\"enable\": false}", "/dev/ttyUSB0", true).1);
+        assert!(answer("?VERSION", "/dev/ttyUSB0", false)
+            .0
+            .contains("\"proto_major\":3"));
+        assert_eq!(
+            answer("?POLL", "/dev/ttyUSB0", true),
+            (
+                "{\"class\":\"ERROR\",\"message\":\"Unrecognized request '?POLL'\"}\r\n"
+                    .to_string(),
+                true
+            )
+        );
+        assert_eq!(
+            iso_time("230324", "123519.5"),
+            Some("2024-03-23T12:35:19.500Z".to_string())
+        );
+        assert_eq!(iso_time("", "123519"), None);
+    }
+
+    #[test]
+    fn test_tpv_reports() {
+        let mut endpoint = GpsdEndpoint::bind("127.0.0.1:0", "/dev/ttyUSB0").unwrap();
+        let address = endpoint.listener.local_addr().unwrap();
+        let mut client = TcpStream::connect(address).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut lines = BufReader::new(client.try_clone().unwrap()).lines();
+        thread::sleep(Duration::from_millis(100));
+        endpoint.write(b"").unwrap();
+        assert!(lines
+            .next()
+            .unwrap()
+            .unwrap()
+            .starts_with("{\"class\":\"VERSION\""));
+
+        // not watching yet.
+        endpoint.write(&gga("123519")).unwrap();
+        endpoint.write(&rmc("123519")).unwrap();
+        client
+            .write_all(b"?WATCH={\"enable\":true,\"json\":true};")
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        endpoint.write(b"").unwrap();
+        assert!(lines.next().unwrap().unwrap().contains("DEVICES"));
+        assert!(lines.next().unwrap().unwrap().contains("WATCH"));
+        endpoint.write(&gga("123520")).unwrap();
+        endpoint.write(&rmc("123520")).unwrap();
+        assert_eq!(
+            lines.next().unwrap().unwrap(),
+            "{\"class\":\"TPV\",\"device\":\"/dev/ttyUSB0\",\"mode\":3,\
+             \"time\":\"2024-03-23T12:35:20.000Z\",\"lat\":48.117300000,\"lon\":11.516666667,\
+             \"altMSL\":545.400,\"speed\":11.524,\"track\":84.4000}"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! How the fan-out reacts to the errors of an endpoint.
+
+use crate::backoff::Backoff;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// What to do when writing to an endpoint fails.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WriteErrorPolicy {
+    /// Log and try again with the next data.
+    #[default]
+    KeepTrying,
+    /// Stop writing to the endpoint after this many consecutive errors.
+    Disable(u32),
+    /// Stop ttytee.
+    Exit,
+}
+
+impl FromStr for WriteErrorPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy.split_once(':') {
+            None if policy == "keep-trying" => Ok(Self::KeepTrying),
+            None if policy == "exit" => Ok(Self::Exit),
+            Some(("disable", errors)) => errors
+                .parse::<u32>()
+                .ok()
+                .filter(|&errors| errors > 0)
+                .map(Self::Disable)
+                .ok_or_else(|| format!("invalid number of errors {:?}", errors)),
+            _ => Err(format!(
+                "unknown policy {:?}, expected keep-trying, disable:N or exit",
+                policy
+            )),
+        }
+    }
+}
+
+impl fmt::Display for WriteErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::KeepTrying => write!(f, "keep-trying"),
+            Self::Disable(errors) => write!(f, "disable:{}", errors),
+            Self::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+/// Parse a per endpoint write error policy from the command line.
+///
+/// # Arguments
+///
+/// * `spec`: a string of the form `slave0=disable:5`.
+///
+/// returns: Result<(String, WriteErrorPolicy), String>
+///
+pub fn parse_write_error_policy(spec: &str) -> Result<(String, WriteErrorPolicy), String> {
+    let (endpoint, policy) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("expected <ENDPOINT>=<POLICY>, got {:?}", spec))?;
+    Ok((endpoint.to_string(), policy.parse()?))
+}
+
+/// What the fan-out should do after an error on an endpoint.
+#[derive(Debug, PartialEq)]
+pub enum ErrorAction {
+    /// Skip the endpoint until its exponential backoff is over.
+    Backoff,
+    /// The endpoint has just been disabled.
+    Disable,
+    /// Stop ttytee.
+    Exit,
+}
+
+/// Error accounting of an endpoint, so a failing endpoint doesn't slow down the others.
+pub struct EndpointHealth {
+    policy: WriteErrorPolicy,
+    backoff: Backoff,
+    consecutive_errors: u32,
+    // the total of the errors, for the error rate.
+    errors: u64,
+    disabled: bool,
+}
+
+impl EndpointHealth {
+    /// Create the health of an endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy`: what to do on errors.
+    /// * `backoff`: how long the endpoint is skipped after consecutive errors.
+    ///
+    /// returns: EndpointHealth
+    ///
+    pub fn new(policy: WriteErrorPolicy, backoff: Backoff) -> Self {
+        Self {
+            policy,
+            backoff,
+            consecutive_errors: 0,
+            errors: 0,
+            disabled: false,
+        }
+    }
+
+    /// Whether the endpoint should be written to at this time.
+    pub fn is_ready(&self, now: Instant) -> bool {
+        !self.disabled && self.backoff.is_ready(now)
+    }
+
+    /// Whether the endpoint has been disabled for good.
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    /// Stop writing to the endpoint for good.
+    pub fn disable(&mut self) {
+        self.disabled = true;
+    }
+
+    /// Change the policy, the errors already counted still count.
+    pub fn set_policy(&mut self, policy: WriteErrorPolicy) {
+        self.policy = policy;
+    }
+
+    /// The total of the write errors.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    pub fn success(&mut self) {
+        self.consecutive_errors = 0;
+        self.backoff.success();
+    }
+
+    pub fn failure(&mut self, now: Instant) -> ErrorAction {
+        self.consecutive_errors += 1;
+        self.errors += 1;
+        match self.policy {
+            WriteErrorPolicy::Exit => ErrorAction::Exit,
+            WriteErrorPolicy::Disable(max_errors) if self.consecutive_errors >= max_errors => {
+                self.disabled = true;
+                ErrorAction::Disable
+            }
+            _ => {
+                self.backoff.failure(now);
+                ErrorAction::Backoff
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::endpoint::health::{
+        parse_write_error_policy, EndpointHealth, ErrorAction, WriteErrorPolicy,
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_write_error_policy() {
+        assert_eq!(
+            parse_write_error_policy("slave0=keep-trying"),
+            Ok(("slave0".to_string(), WriteErrorPolicy::KeepTrying))
+        );
+        assert_eq!(
+            parse_write_error_policy("slave1=disable:5"),
+            Ok(("slave1".to_string(), WriteErrorPolicy::Disable(5)))
+        );
+        assert_eq!(
+            parse_write_error_policy("slave1=exit"),
+            Ok(("slave1".to_string(), WriteErrorPolicy::Exit))
+        );
+        assert!(parse_write_error_policy("slave1=disable:0").is_err());
+        assert!(parse_write_error_policy("slave1").is_err());
+        assert!(parse_write_error_policy("slave0=explode").is_err());
+    }
+
+    #[test]
+    fn test_backoff_then_disable() {
+        let backoff = Duration::from_millis(500);
+        let mut health =
+            EndpointHealth::new(WriteErrorPolicy::Disable(2), Backoff::new(backoff, backoff));
+        let now = Instant::now();
+        assert!(health.is_ready(now));
+        assert_eq!(health.failure(now), ErrorAction::Backoff);
+        assert!(!health.is_ready(now));
+        assert!(health.is_ready(now + backoff));
+        // a success resets the count of consecutive errors.
+        health.success();
+        assert_eq!(health.failure(now), ErrorAction::Backoff);
+        assert_eq!(health.failure(now + backoff), ErrorAction::Disable);
+        assert!(!health.is_ready(now + backoff * 10));
+    }
+}
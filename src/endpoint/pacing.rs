@@ -0,0 +1,119 @@
+//! Pacing of the writes to an endpoint: a token bucket spreads the bursts of the master, like the
+//! USB bulk transfers of the receivers, into writes evenly timed at the rate of a serial line, for
+//! the consumers relying on the time of arrival of the bytes (time sync without PPS).
+//!
+//! The rate is given in bauds with the `pace` endpoint option, 10 bits per byte as with 8N1.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How often the main loop releases the paced bytes.
+pub const PACE_TICK: Duration = Duration::from_millis(2);
+// The bytes waiting are dropped beyond this much time at the rate, so it stays real time.
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+pub struct Pacer {
+    // in bytes per second.
+    rate: f64,
+    tokens: f64,
+    last_refill: Option<Instant>,
+    queue: VecDeque<u8>,
+}
+
+impl Pacer {
+    /// Create a pacer.
+    ///
+    /// # Arguments
+    ///
+    /// * `baudrate`: the rate of the serial line to mimic.
+    ///
+    /// returns: Pacer
+    ///
+    pub fn new(baudrate: u32) -> Self {
+        Self {
+            rate: baudrate as f64 / 10.0,
+            tokens: 0.0,
+            last_refill: None,
+            queue: VecDeque::new(),
+        }
+    }
+
+    // The tokens kept at most: a tick and the fraction of a byte left by the previous one, so a
+    // late tick doesn't release a burst and the rate is kept whatever the rounding.
+    fn capacity(&self) -> f64 {
+        (self.rate * PACE_TICK.as_secs_f64()).max(1.0) + 1.0
+    }
+
+    /// Queue bytes to write, returns how many of the oldest ones were dropped to stay in time.
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        self.queue.extend(data);
+        let max = ((self.rate * MAX_DELAY.as_secs_f64()) as usize).max(1);
+        let dropped = self.queue.len().saturating_sub(max);
+        self.queue.drain(..dropped);
+        dropped
+    }
+
+    /// The bytes to write now.
+    pub fn take(&mut self, now: Instant) -> Vec<u8> {
+        let elapsed = self
+            .last_refill
+            .map_or(Duration::MAX, |last_refill| {
+                now.saturating_duration_since(last_refill)
+            })
+            .as_secs_f64();
+        self.last_refill = Some(now);
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity());
+        let count = (self.tokens as usize).min(self.queue.len());
+        self.tokens -= count as f64;
+        self.queue.drain(..count).collect()
+    }
+
+    /// How many bytes are waiting.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Drop the bytes waiting, returns how many there were.
+    pub fn clear(&mut self) -> usize {
+        let queued = self.queue.len();
+        self.queue.clear();
+        queued
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::pacing::Pacer;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_even_writes() {
+        // 1 byte per ms.
+        let mut pacer = Pacer::new(10_000);
+        let start = Instant::now();
+        assert_eq!(pacer.push(&[b'x'; 100]), 0);
+        // a burst is released 2 bytes every 2 ms.
+        assert_eq!(pacer.take(start).len(), 3);
+        assert_eq!(pacer.take(start).len(), 0);
+        let mut released = 3;
+        for tick in 1..=10 {
+            let chunk = pacer.take(start + Duration::from_millis(2 * tick));
+            assert_eq!(chunk.len(), 2);
+            released += chunk.len();
+        }
+        assert_eq!(pacer.queued(), 100 - released);
+        // a late tick doesn't release much more.
+        assert_eq!(pacer.take(start + Duration::from_secs(1)).len(), 3);
+        assert_eq!(pacer.clear(), 100 - released - 3);
+    }
+
+    #[test]
+    fn test_stays_in_time() {
+        // 96 bytes per second, at most a second of delay.
+        let mut pacer = Pacer::new(960);
+        assert_eq!(pacer.push(&[0; 60]), 0);
+        assert_eq!(pacer.push(&[1; 60]), 24);
+        let start = Instant::now();
+        assert_eq!(pacer.take(start), vec![0, 0]);
+    }
+}
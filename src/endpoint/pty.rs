@@ -0,0 +1,234 @@
+//! PTY endpoints: a PTY pair with a symlink to the consumer side.
+
+use crate::cleanup::{register_symlink, unregister_symlink};
+use crate::consumers::{consumer_pids, process_name};
+use crate::endpoint::tail::{set_nonblocking, Delivery, WriteTail};
+use crate::endpoint::Endpoint;
+use crate::modem::{set_window_size, LineState};
+use crate::termios::{get_termios, set_termios};
+use log::{debug, error};
+use serialport::{ClearBuffer, SerialPort, TTYPort};
+use std::fs::remove_file;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::time::Duration;
+
+pub struct PtyEndpoint {
+    // our side of the PTY pair, where we write.
+    master: TTYPort,
+    // the consumer side of the PTY pair.
+    slave: TTYPort,
+    device: PathBuf,
+    tail: WriteTail,
+    partial_writes: u64,
+    // declared last so the link goes away after the PTY is closed.
+    _symlink: SelfCleaningSymlink,
+}
+
+impl PtyEndpoint {
+    /// Create a PTY pair and a symlink to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `link`: where to create the symlink to the consumer side of the PTY.
+    ///
+    /// returns: Result<PtyEndpoint, Error>
+    ///
+    pub fn create(link: &Path) -> io::Result<Self> {
+        let (mut master, slave) = TTYPort::pair()?;
+        // the writes never wait for the consumer, the tail keeps what it could not take.
+        master.set_timeout(Duration::ZERO)?;
+        set_nonblocking(master.as_raw_fd())?;
+        let device = PathBuf::from(
+            slave
+                .name()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "the PTY has no name"))?,
+        );
+        let symlink = SelfCleaningSymlink::create(&device, &link.to_path_buf());
+        Ok(Self {
+            master,
+            slave,
+            device,
+            tail: WriteTail::default(),
+            partial_writes: 0,
+            _symlink: symlink,
+        })
+    }
+
+    /// Report a baudrate to the consumers reading the settings of the PTY, the one of the master.
+    pub fn set_baudrate(&mut self, baudrate: u32) -> io::Result<()> {
+        let fd = self.slave.as_raw_fd();
+        let mut termios = get_termios(fd)?;
+        termios.c_cflag &= !(libc::CBAUD | (libc::CBAUD << libc::IBSHIFT));
+        termios.c_cflag |= libc::BOTHER | (libc::BOTHER << libc::IBSHIFT);
+        termios.c_ispeed = baudrate;
+        termios.c_ospeed = baudrate;
+        set_termios(fd, &termios)
+    }
+}
+
+impl Endpoint for PtyEndpoint {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match self.tail.write(&mut self.master, data)? {
+            Delivery::Whole => Ok(()),
+            Delivery::Partial => {
+                self.partial_writes += 1;
+                Ok(())
+            }
+            Delivery::Dropped => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn pending(&self) -> io::Result<usize> {
+        Ok(self.slave.bytes_to_read()? as usize + self.tail.waiting())
+    }
+
+    fn discard(&mut self) -> io::Result<()> {
+        self.tail.clear();
+        self.master.clear(ClearBuffer::All)?;
+        self.slave.clear(ClearBuffer::All)?;
+        Ok(())
+    }
+
+    fn device(&self) -> Option<&Path> {
+        Some(&self.device)
+    }
+
+    fn partial_writes(&self) -> u64 {
+        self.partial_writes
+    }
+
+    fn set_line_state(&mut self, state: &LineState) -> io::Result<()> {
+        set_window_size(self.master.as_raw_fd(), state.rows, state.cols)
+    }
+
+    fn baudrate(&self) -> Option<u32> {
+        get_termios(self.slave.as_raw_fd())
+            .ok()
+            .map(|termios| termios.c_ospeed)
+    }
+
+    fn consumers(&self) -> Option<Vec<String>> {
+        Some(
+            consumer_pids(slice::from_ref(&self.device))
+                .into_iter()
+                .map(|pid| format!("pid {} ({})", pid, process_name(pid)))
+                .collect(),
+        )
+    }
+}
+
+struct SelfCleaningSymlink {
+    path: PathBuf,
+    // false when the link could not be created, there is nothing to clean up then.
+    created: bool,
+}
+
+impl SelfCleaningSymlink {
+    /// Create a symlink that will clean up at drop time.
+    ///
+    /// # Arguments
+    ///
+    /// * `from`: source of the link
+    /// * `to`: destination of the link (where it will be created).
+    ///
+    /// returns: SelfCleaningSymlink
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     fn myfunc() {
+    ///         let _link = SelfCleaningSymlink::create("/from/real_file", "/to/symlink");
+    ///         // Note: it needs to be binding so use _name not _.
+    ///         //
+    ///         //
+    ///         // ... do things.
+    ///         //
+    ///         //
+    ///         //  <- here it will remove /to/symlink.
+    ///     }
+    /// ```
+    pub fn create(from: &PathBuf, to: &PathBuf) -> Self {
+        remove_file(to).ok(); // ok to ignore if the links are not there.
+        let created = match fs::symlink(from, to) {
+            Err(err) => {
+                error!(
+                    "Could not create the symlink from {:?} -> {:?}: {:?}.",
+                    from, to, err
+                );
+                false
+            }
+            Ok(_) => {
+                debug!("Symlink {:?} -> {:?} created successfully.", from, to);
+                register_symlink(to);
+                true
+            }
+        };
+        Self {
+            path: to.clone(),
+            created,
+        }
+    }
+}
+
+impl Drop for SelfCleaningSymlink {
+    fn drop(&mut self) {
+        if !self.created {
+            return;
+        }
+        unregister_symlink(&self.path);
+        remove_file(&self.path).unwrap(); // for the cleanup, the link should be there!
+        debug!("Symlink {:?} cleaned up.", self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::pty::PtyEndpoint;
+    use crate::endpoint::Endpoint;
+    use serialport::TTYPort;
+    use std::io;
+    use std::io::Read;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn test_chunks_are_never_truncated() {
+        let link = Path::new("/tmp/ttytee_partial_test.pty");
+        let mut endpoint = PtyEndpoint::create(link).unwrap();
+        let mut consumer = TTYPort::open(
+            &serialport::new(link.to_string_lossy(), 9600).timeout(Duration::from_millis(100)),
+        )
+        .unwrap();
+        // nobody reads until the PTY is full, the writes must not wait.
+        let mut chunks = Vec::new();
+        let mut chunk = 0u8;
+        while endpoint.partial_writes() == 0 {
+            chunk = chunk.wrapping_add(1);
+            endpoint.write(&[chunk; 100]).unwrap();
+            chunks.push(chunk);
+        }
+        assert!(endpoint.pending().unwrap() > 0);
+        let err = endpoint.write(&[0; 100]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        let mut read = Vec::new();
+        let mut buffer = [0; 4096];
+        let mut read_all = |consumer: &mut TTYPort| {
+            while let Ok(len) = consumer.read(&mut buffer) {
+                read.extend_from_slice(&buffer[..len]);
+            }
+        };
+        read_all(&mut consumer);
+        // the rest of the last chunk goes before the next one.
+        endpoint.write(&[0xFF; 100]).unwrap();
+        chunks.push(0xFF);
+        read_all(&mut consumer);
+        assert!(read.chunks(100).all(|chunk| chunk == [chunk[0]; 100]));
+        let read_chunks: Vec<u8> = read.chunks(100).map(|chunk| chunk[0]).collect();
+        assert_eq!(read_chunks, chunks);
+    }
+}
@@ -0,0 +1,106 @@
+//! Serial endpoints: the stream is re-transmitted on a real UART, for example to another board,
+//! like a hardware splitter would.
+
+use crate::backend::check_baudrate;
+use crate::endpoint::tail::{set_nonblocking, Delivery, WriteTail};
+use crate::endpoint::Endpoint;
+use crate::modem::LineState;
+use log::info;
+use serialport::{ClearBuffer, SerialPort, TTYPort};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+
+pub struct SerialEndpoint {
+    port: TTYPort,
+    tail: WriteTail,
+    partial_writes: u64,
+}
+
+impl SerialEndpoint {
+    /// Open a serial device.
+    ///
+    /// # Arguments
+    ///
+    /// * `device`: the path of the device, for example `/dev/ttyS2`.
+    /// * `baudrate`: its baudrate.
+    ///
+    /// returns: Result<SerialEndpoint, Error>
+    ///
+    pub fn open(device: &Path, baudrate: u32) -> io::Result<Self> {
+        let mut port = TTYPort::open(&serialport::new(device.to_string_lossy(), baudrate))?;
+        check_baudrate(&port, device, baudrate)?;
+        // the writes never wait for room in the output buffer, the tail keeps what did not fit.
+        port.set_timeout(Duration::ZERO)?;
+        set_nonblocking(port.as_raw_fd())?;
+        info!(
+            "Writing to the serial device {:?} at {} bauds.",
+            device, baudrate
+        );
+        Ok(Self {
+            port,
+            tail: WriteTail::default(),
+            partial_writes: 0,
+        })
+    }
+}
+
+impl Endpoint for SerialEndpoint {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match self.tail.write(&mut self.port, data)? {
+            Delivery::Whole => Ok(()),
+            Delivery::Partial => {
+                self.partial_writes += 1;
+                Ok(())
+            }
+            Delivery::Dropped => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn pending(&self) -> io::Result<usize> {
+        Ok(self.port.bytes_to_write()? as usize + self.tail.waiting())
+    }
+
+    fn discard(&mut self) -> io::Result<()> {
+        self.tail.clear();
+        self.port.clear(ClearBuffer::Output)?;
+        Ok(())
+    }
+
+    fn partial_writes(&self) -> u64 {
+        self.partial_writes
+    }
+
+    fn set_line_state(&mut self, state: &LineState) -> io::Result<()> {
+        // through a null-modem cable, DTR drives the DCD and the DSR of the other side, RTS its CTS.
+        if let Some(lines) = state.modem {
+            self.port.write_data_terminal_ready(lines.cd || lines.dsr)?;
+            self.port.write_request_to_send(lines.cts)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::serial::SerialEndpoint;
+    use crate::endpoint::Endpoint;
+    use serialport::{SerialPort, TTYPort};
+    use std::io::Read;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn test_serial_endpoint() {
+        // the consumer side of a PTY plays the UART.
+        let (mut board, uart) = TTYPort::pair().unwrap();
+        board.set_timeout(Duration::from_secs(5)).unwrap();
+        let mut endpoint = SerialEndpoint::open(Path::new(&uart.name().unwrap()), 115_200).unwrap();
+        endpoint.write(b"$GPGGA\r\n").unwrap();
+        let mut received = [0; 8];
+        board.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"$GPGGA\r\n");
+        assert_eq!(endpoint.pending().unwrap(), 0);
+    }
+}
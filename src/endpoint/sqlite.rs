@@ -0,0 +1,164 @@
+//! SQLite endpoints: the GGA epochs of the stream are decoded and stored in a database.
+//!
+//! The rows are inserted in batches, one transaction per batch, so the database is not synced at
+//! each epoch.
+
+use crate::endpoint::Endpoint;
+use crate::framing::{Frame, Framer, Protocol};
+use crate::nmea::Gga;
+use log::{error, info};
+use rusqlite::{params, Connection};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// The pending epochs are inserted when there are this many of them...
+const BATCH_SIZE: usize = 50;
+// ... or when the oldest one has waited this long.
+const BATCH_PERIOD: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS epochs (
+    id INTEGER PRIMARY KEY,
+    received_at REAL NOT NULL,
+    time TEXT NOT NULL,
+    latitude REAL,
+    longitude REAL,
+    altitude REAL,
+    fix_quality INTEGER NOT NULL,
+    satellites INTEGER NOT NULL,
+    hdop REAL
+)";
+
+struct Epoch {
+    // seconds since the epoch when ttytee received it.
+    received_at: f64,
+    gga: Gga,
+}
+
+pub struct SqliteEndpoint {
+    connection: Connection,
+    framer: Framer,
+    frames: Vec<Frame>,
+    pending: Vec<Epoch>,
+    batch_started: Option<Instant>,
+}
+
+impl SqliteEndpoint {
+    /// Open or create a database and its table.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let connection = Connection::open(path).map_err(to_io_error)?;
+        connection.execute(SCHEMA, []).map_err(to_io_error)?;
+        info!("Storing the epochs in {:?}.", path);
+        Ok(Self {
+            connection,
+            framer: Framer::new(&[Protocol::Nmea]),
+            frames: Vec::new(),
+            pending: Vec::new(),
+            batch_started: None,
+        })
+    }
+
+    fn insert_pending(&mut self) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT INTO epochs (received_at, time, latitude, longitude, altitude, \
+                 fix_quality, satellites, hdop) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for epoch in &self.pending {
+                let gga = &epoch.gga;
+                insert.execute(params![
+                    epoch.received_at,
+                    gga.time,
+                    gga.latitude,
+                    gga.longitude,
+                    gga.altitude,
+                    gga.fix_quality,
+                    gga.satellites,
+                    gga.hdop
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        self.pending.clear();
+        self.batch_started = None;
+        Ok(())
+    }
+}
+
+impl Endpoint for SqliteEndpoint {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.framer.push(data, &mut self.frames);
+        let received_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since_epoch| since_epoch.as_secs_f64());
+        for frame in self.frames.drain(..) {
+            if let Some(gga) = Gga::parse(&frame.data) {
+                self.pending.push(Epoch { received_at, gga });
+            }
+        }
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let batch_started = *self.batch_started.get_or_insert_with(Instant::now);
+        if self.pending.len() >= BATCH_SIZE || batch_started.elapsed() >= BATCH_PERIOD {
+            self.insert_pending().map_err(to_io_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SqliteEndpoint {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            if let Err(err) = self.insert_pending() {
+                error!("Could not store the last epochs: {}.", err);
+            }
+        }
+    }
+}
+
+fn to_io_error(err: rusqlite::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::sqlite::SqliteEndpoint;
+    use crate::endpoint::Endpoint;
+    use rusqlite::Connection;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const GGA: &[u8] = b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n";
+    const RMC: &[u8] = b"$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n";
+
+    #[test]
+    fn test_epochs_are_stored() {
+        let path = PathBuf::from("/tmp/ttytee_sqlite_test.db");
+        fs::remove_file(&path).ok();
+        let mut endpoint = SqliteEndpoint::open(&path).unwrap();
+        endpoint.write(&GGA[..20]).unwrap();
+        endpoint.write(&GGA[20..]).unwrap();
+        endpoint.write(RMC).unwrap();
+        endpoint.write(GGA).unwrap();
+        // the batch is not full yet.
+        let database = Connection::open(&path).unwrap();
+        let count = |database: &Connection| -> u32 {
+            database
+                .query_row("SELECT COUNT(*) FROM epochs", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(count(&database), 0);
+        drop(endpoint);
+        assert_eq!(count(&database), 2);
+        let (satellites, altitude): (u32, f64) = database
+            .query_row("SELECT satellites, altitude FROM epochs", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((satellites, altitude), (8, 545.4));
+        drop(database);
+        fs::remove_file(&path).unwrap();
+    }
+}
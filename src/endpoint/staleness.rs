@@ -0,0 +1,85 @@
+//! Adaptive staleness, so the same defaults work for a 1 Hz NMEA stream and a 50 Hz binary one:
+//! with `stale-timeout=auto` the data is stale after 3 times the interval between the reads of the
+//! consumer, smoothed exponentially, instead of a fixed timeout. The fixed timeout applies until
+//! the consumer has been seen reading twice.
+
+use std::time::{Duration, Instant};
+
+// The weight of a new interval in the smoothed one.
+const ALPHA: f64 = 0.2;
+// The threshold in smoothed intervals.
+const FACTOR: f64 = 3.0;
+// The bounds of the threshold, a consumer reading in a burst or rarely still gets sane values.
+const MIN_THRESHOLD: Duration = Duration::from_millis(100);
+const MAX_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// The smoothed interval between the reads of a consumer.
+#[derive(Debug, Default)]
+pub struct AdaptiveStaleness {
+    last_drain: Option<Instant>,
+    // in seconds.
+    smoothed: Option<f64>,
+}
+
+impl AdaptiveStaleness {
+    /// Account for the consumer seen reading.
+    pub fn observe(&mut self, now: Instant) {
+        if let Some(last_drain) = self.last_drain {
+            let interval = now.saturating_duration_since(last_drain).as_secs_f64();
+            self.smoothed = Some(match self.smoothed {
+                Some(smoothed) => ALPHA * interval + (1.0 - ALPHA) * smoothed,
+                None => interval,
+            });
+        }
+        self.last_drain = Some(now);
+    }
+
+    /// How old the data can be before it is stale.
+    ///
+    /// # Arguments
+    ///
+    /// * `fallback`: the threshold until the interval is known.
+    ///
+    /// returns: Duration
+    ///
+    pub fn threshold(&self, fallback: Duration) -> Duration {
+        match self.smoothed {
+            Some(smoothed) => {
+                Duration::from_secs_f64(smoothed * FACTOR).clamp(MIN_THRESHOLD, MAX_THRESHOLD)
+            }
+            None => fallback,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::staleness::AdaptiveStaleness;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_adaptive_threshold() {
+        let fallback = Duration::from_millis(1000);
+        let start = Instant::now();
+        let mut staleness = AdaptiveStaleness::default();
+        staleness.observe(start);
+        assert_eq!(staleness.threshold(fallback), fallback);
+        // a 50 Hz consumer.
+        for i in 1..=10 {
+            staleness.observe(start + Duration::from_millis(20 * i));
+        }
+        assert_eq!(staleness.threshold(fallback), Duration::from_millis(100));
+        // then a 1 Hz one, the threshold follows.
+        let mut now = start + Duration::from_millis(200);
+        for _ in 0..30 {
+            now += Duration::from_secs(1);
+            staleness.observe(now);
+        }
+        let threshold = staleness.threshold(fallback);
+        assert!(
+            threshold > Duration::from_millis(2900) && threshold <= Duration::from_secs(3),
+            "{:?}",
+            threshold
+        );
+    }
+}
@@ -0,0 +1,15 @@
+//! The standard output of ttytee as an endpoint, handy to pipe the stream into another tool.
+
+use crate::endpoint::Endpoint;
+use std::io;
+use std::io::Write;
+
+pub struct StdoutEndpoint;
+
+impl Endpoint for StdoutEndpoint {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()
+    }
+}
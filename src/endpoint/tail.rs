@@ -0,0 +1,163 @@
+//! The rest of the chunks a consumer could only take in part, so the frames reach it whole or not at
+//! all: the writes never wait for a consumer, what it could not take is written before the next
+//! chunk, and a chunk that still finds a rest waiting is dropped whole instead of being truncated.
+
+use std::io;
+use std::io::Write;
+use std::os::fd::RawFd;
+
+/// What became of a chunk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Delivery {
+    Whole,
+    // the consumer took the start of the chunk, the rest is kept for the next write.
+    Partial,
+    // the rest of the previous chunk is still waiting.
+    Dropped,
+}
+
+#[derive(Default)]
+pub struct WriteTail {
+    rest: Vec<u8>,
+}
+
+/// Make the writes to a device return what fitted instead of waiting for room, the serial ports
+/// only poll for some room before writing.
+pub fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Write as much as the consumer takes without waiting.
+fn write_some(writer: &mut impl Write, data: &[u8]) -> io::Result<usize> {
+    let mut written = 0;
+    while written < data.len() {
+        match writer.write(&data[written..]) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(len) => written += len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            // the serial ports wait for room up to their timeout, then time out.
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                break
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(written)
+}
+
+impl WriteTail {
+    /// Write a chunk, after the rest of the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: the consumer, non-blocking.
+    /// * `data`: the chunk.
+    ///
+    /// returns: Result<Delivery, Error>
+    ///
+    pub fn write(&mut self, writer: &mut impl Write, data: &[u8]) -> io::Result<Delivery> {
+        if !self.drain(writer)? {
+            return Ok(Delivery::Dropped);
+        }
+        let written = write_some(writer, data)?;
+        if written == data.len() {
+            return Ok(Delivery::Whole);
+        }
+        self.rest.extend_from_slice(&data[written..]);
+        Ok(Delivery::Partial)
+    }
+
+    /// Write the rest of the previous chunk, for the consumers of a stream that cannot drop a chunk
+    /// once it is encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: the consumer, non-blocking.
+    ///
+    /// returns: Result<bool, Error> true if nothing is waiting anymore, a chunk can be written.
+    ///
+    pub fn drain(&mut self, writer: &mut impl Write) -> io::Result<bool> {
+        if !self.rest.is_empty() {
+            let written = write_some(writer, &self.rest)?;
+            self.rest.drain(..written);
+        }
+        Ok(self.rest.is_empty())
+    }
+
+    /// The bytes waiting for the consumer.
+    pub fn waiting(&self) -> usize {
+        self.rest.len()
+    }
+
+    /// Drop the rest, returns its length.
+    pub fn clear(&mut self) -> usize {
+        let len = self.rest.len();
+        self.rest.clear();
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::tail::{Delivery, WriteTail};
+    use std::io;
+    use std::io::Write;
+
+    // A consumer taking at most `room` bytes.
+    struct SlowConsumer {
+        data: Vec<u8>,
+        room: usize,
+    }
+
+    impl Write for SlowConsumer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.room == 0 {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let len = buf.len().min(self.room).min(3);
+            self.data.extend_from_slice(&buf[..len]);
+            self.room -= len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_frames_are_whole() {
+        let mut consumer = SlowConsumer {
+            data: Vec::new(),
+            room: 10,
+        };
+        let mut tail = WriteTail::default();
+        assert_eq!(
+            tail.write(&mut consumer, b"$GPGGA\r\n").unwrap(),
+            Delivery::Whole
+        );
+        assert_eq!(
+            tail.write(&mut consumer, b"$GPRMC\r\n").unwrap(),
+            Delivery::Partial
+        );
+        assert_eq!(tail.waiting(), 6);
+        assert_eq!(
+            tail.write(&mut consumer, b"$GPGSA\r\n").unwrap(),
+            Delivery::Dropped
+        );
+        consumer.room = 100;
+        assert_eq!(
+            tail.write(&mut consumer, b"$GPVTG\r\n").unwrap(),
+            Delivery::Whole
+        );
+        assert_eq!(consumer.data, b"$GPGGA\r\n$GPRMC\r\n$GPVTG\r\n");
+        assert_eq!(tail.clear(), 0);
+    }
+}
@@ -0,0 +1,200 @@
+//! TCP server endpoints: every connected client gets the stream, compressed with `tcpz://`.
+
+use crate::endpoint::deflate::{Deflater, MAGIC};
+use crate::endpoint::tail::{Delivery, WriteTail};
+use crate::endpoint::Endpoint;
+use crate::events::Event;
+use log::{debug, info, warn};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+/// A connected client and the rest of the chunk it could not take yet.
+pub struct TcpClient {
+    pub address: SocketAddr,
+    stream: TcpStream,
+    tail: WriteTail,
+    // the stream of the client, None if it gets the bytes as they are.
+    deflater: Option<Deflater>,
+}
+
+impl TcpClient {
+    pub fn new(address: SocketAddr, stream: TcpStream) -> Self {
+        Self {
+            address,
+            stream,
+            tail: WriteTail::default(),
+            deflater: None,
+        }
+    }
+
+    /// A client getting the compressed stream, starting with its magic.
+    pub fn compressed(address: SocketAddr, stream: TcpStream) -> io::Result<Self> {
+        let mut client = Self {
+            deflater: Some(Deflater::default()),
+            ..Self::new(address, stream)
+        };
+        client.tail.write(&mut client.stream, MAGIC)?;
+        Ok(client)
+    }
+}
+
+pub struct TcpEndpoint {
+    listener: TcpListener,
+    clients: Vec<TcpClient>,
+    partial_writes: u64,
+    compressed: bool,
+}
+
+impl TcpEndpoint {
+    /// Listen for clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: the address to listen on, for example `0.0.0.0:5000`.
+    /// * `compressed`: whether the clients get the compressed stream.
+    ///
+    /// returns: Result<TcpEndpoint, Error>
+    ///
+    pub fn bind(address: &str, compressed: bool) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        // the clients are accepted from the fan-out loop, it must never wait for them.
+        listener.set_nonblocking(true)?;
+        info!("Listening for TCP clients on {}.", listener.local_addr()?);
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            partial_writes: 0,
+            compressed,
+        })
+    }
+
+    fn accept_clients(&mut self) -> io::Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    stream.set_nonblocking(true)?;
+                    stream.set_nodelay(true)?;
+                    info!("TCP client {} connected.", address);
+                    let client = if self.compressed {
+                        TcpClient::compressed(address, stream)?
+                    } else {
+                        TcpClient::new(address, stream)
+                    };
+                    self.clients.push(client);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Write to the connected clients, the disconnected ones are removed.
+///
+/// # Arguments
+///
+/// * `clients`: the connected clients.
+/// * `data`: a chunk of the master stream.
+///
+/// returns: u64 the clients that could only take the chunk in part.
+///
+pub fn write_clients(clients: &mut Vec<TcpClient>, data: &[u8]) -> u64 {
+    let mut partial_writes = 0;
+    clients.retain_mut(|client| {
+        let delivery = match &mut client.deflater {
+            // a chunk cannot be dropped once it is in the stream, it is compressed only if it
+            // can be written.
+            Some(deflater) => client.tail.drain(&mut client.stream).and_then(|drained| {
+                if drained {
+                    client
+                        .tail
+                        .write(&mut client.stream, &deflater.compress(data))
+                } else {
+                    Ok(Delivery::Dropped)
+                }
+            }),
+            None => client.tail.write(&mut client.stream, data),
+        };
+        match delivery {
+            Ok(Delivery::Whole) => true,
+            Ok(Delivery::Partial) => {
+                partial_writes += 1;
+                true
+            }
+            Ok(Delivery::Dropped) => {
+                // like the PTYs, a client that cannot keep up misses chunks.
+                debug!("TCP client {} could not keep up.", client.address);
+                true
+            }
+            Err(err) => {
+                warn!(
+                    event = Event::ClientDisconnected.code();
+                    "TCP client {} disconnected: {}.",
+                    client.address, err
+                );
+                false
+            }
+        }
+    });
+    partial_writes
+}
+
+impl Endpoint for TcpEndpoint {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.accept_clients()?;
+        self.partial_writes += write_clients(&mut self.clients, data);
+        Ok(())
+    }
+
+    fn consumers(&self) -> Option<Vec<String>> {
+        Some(
+            self.clients
+                .iter()
+                .map(|client| client.address.to_string())
+                .collect(),
+        )
+    }
+
+    fn partial_writes(&self) -> u64 {
+        self.partial_writes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::tcp::TcpEndpoint;
+    use crate::endpoint::Endpoint;
+    use std::io::Read;
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_tcp_clients() {
+        let mut endpoint = TcpEndpoint::bind("127.0.0.1:0", false).unwrap();
+        let address = endpoint.listener.local_addr().unwrap();
+        endpoint.write(b"nobody listens").unwrap();
+
+        let mut client = TcpStream::connect(address).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        endpoint.write(b"$GPGGA").unwrap();
+        let mut buffer = [0; 6];
+        client.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"$GPGGA");
+        assert_eq!(
+            endpoint.consumers(),
+            Some(vec![client.local_addr().unwrap().to_string()])
+        );
+
+        drop(client);
+        thread::sleep(Duration::from_millis(100));
+        // the first write after the disconnection may still succeed.
+        for _ in 0..10 {
+            endpoint.write(b"$GPRMC").unwrap();
+        }
+        assert!(endpoint.clients.is_empty());
+    }
+}
@@ -0,0 +1,53 @@
+//! UDP endpoints: the stream is sent as datagrams to a fixed address.
+
+use crate::endpoint::Endpoint;
+use std::io;
+use std::net::UdpSocket;
+
+pub struct UdpEndpoint {
+    socket: UdpSocket,
+}
+
+impl UdpEndpoint {
+    /// Create a socket sending to an address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: where to send the datagrams, for example `192.168.1.10:5000`.
+    ///
+    /// returns: Result<UdpEndpoint, Error>
+    ///
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(address)?;
+        Ok(Self { socket })
+    }
+}
+
+impl Endpoint for UdpEndpoint {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.socket.send(data).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::udp::UdpEndpoint;
+    use crate::endpoint::Endpoint;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    #[test]
+    fn test_udp_datagrams() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let address = receiver.local_addr().unwrap().to_string();
+        let mut endpoint = UdpEndpoint::connect(&address).unwrap();
+        endpoint.write(b"$GPGGA").unwrap();
+        let mut buffer = [0; 100];
+        let len = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"$GPGGA");
+    }
+}
@@ -0,0 +1,242 @@
+//! The catalog of the significant log messages, each with a stable event code so the alerting rules
+//! don't depend on their text: the code is the `event` key-value of the log record, like
+//! `warn!(event = Event::StaleClear.code(); ...)`, and its target stays the module. The code is in
+//! the `event` field of the JSON logs and the `TTYTEE_EVENT` field of journald, and each event is
+//! counted for the `stats` command of the control socket. The codes never change meaning, new events get new codes.
+//!
+//! * `TT1xxx`: the master.
+//! * `TT2xxx`: the endpoints and their consumers.
+//! * `TT3xxx`: the configuration and the startup.
+//! * `TT4xxx`: the system, the resources and the helpers.
+
+use log::kv::Key;
+use log::{LevelFilter, Log, Metadata, Record};
+use simplelog::{Config, SharedLogger};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// In the order of the codes, the index of the counters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    MasterOpenFailed,
+    MasterOpening,
+    MasterStreaming,
+    MasterStalled,
+    MasterReconnecting,
+    MasterFailed,
+    MasterSettingsChanged,
+    UartErrors,
+    RateAnomaly,
+    MasterInterference,
+    EndpointOpenFailed,
+    EndpointIoError,
+    EndpointDisabled,
+    EndpointErrorExit,
+    StaleClear,
+    LagClear,
+    EndpointDead,
+    ConsumerExited,
+    ConsumerSpawnFailed,
+    ClientDisconnected,
+    BaudrateMismatch,
+    InvalidConfiguration,
+    PreflightFailed,
+    SetupFailed,
+    MemoryLimit,
+    WatchdogStarving,
+    Panic,
+    NtripFailed,
+    CaptureFailed,
+}
+
+impl Event {
+    pub const ALL: [Event; 29] = [
+        Self::MasterOpenFailed,
+        Self::MasterOpening,
+        Self::MasterStreaming,
+        Self::MasterStalled,
+        Self::MasterReconnecting,
+        Self::MasterFailed,
+        Self::MasterSettingsChanged,
+        Self::UartErrors,
+        Self::RateAnomaly,
+        Self::MasterInterference,
+        Self::EndpointOpenFailed,
+        Self::EndpointIoError,
+        Self::EndpointDisabled,
+        Self::EndpointErrorExit,
+        Self::StaleClear,
+        Self::LagClear,
+        Self::EndpointDead,
+        Self::ConsumerExited,
+        Self::ConsumerSpawnFailed,
+        Self::ClientDisconnected,
+        Self::BaudrateMismatch,
+        Self::InvalidConfiguration,
+        Self::PreflightFailed,
+        Self::SetupFailed,
+        Self::MemoryLimit,
+        Self::WatchdogStarving,
+        Self::Panic,
+        Self::NtripFailed,
+        Self::CaptureFailed,
+    ];
+
+    /// The stable code of the event, the `event` key-value of its log records.
+    pub const fn code(self) -> &'static str {
+        match self {
+            Self::MasterOpenFailed => "TT1001",
+            Self::MasterOpening => "TT1002",
+            Self::MasterStreaming => "TT1003",
+            Self::MasterStalled => "TT1004",
+            Self::MasterReconnecting => "TT1005",
+            Self::MasterFailed => "TT1006",
+            Self::MasterSettingsChanged => "TT1007",
+            Self::UartErrors => "TT1008",
+            Self::RateAnomaly => "TT1009",
+            Self::MasterInterference => "TT1010",
+            Self::EndpointOpenFailed => "TT2001",
+            Self::EndpointIoError => "TT2002",
+            Self::EndpointDisabled => "TT2003",
+            Self::EndpointErrorExit => "TT2004",
+            Self::StaleClear => "TT2005",
+            Self::LagClear => "TT2006",
+            Self::EndpointDead => "TT2007",
+            Self::ConsumerExited => "TT2008",
+            Self::ConsumerSpawnFailed => "TT2009",
+            Self::ClientDisconnected => "TT2010",
+            Self::BaudrateMismatch => "TT2011",
+            Self::InvalidConfiguration => "TT3001",
+            Self::PreflightFailed => "TT3002",
+            Self::SetupFailed => "TT3003",
+            Self::MemoryLimit => "TT4001",
+            Self::WatchdogStarving => "TT4002",
+            Self::Panic => "TT4003",
+            Self::NtripFailed => "TT4004",
+            Self::CaptureFailed => "TT4005",
+        }
+    }
+
+    /// A short name of the event, for the humans reading the stats.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::MasterOpenFailed => "master-open-failed",
+            Self::MasterOpening => "master-opening",
+            Self::MasterStreaming => "master-streaming",
+            Self::MasterStalled => "master-stalled",
+            Self::MasterReconnecting => "master-reconnecting",
+            Self::MasterFailed => "master-failed",
+            Self::MasterSettingsChanged => "master-settings-changed",
+            Self::UartErrors => "uart-errors",
+            Self::RateAnomaly => "rate-anomaly",
+            Self::MasterInterference => "master-interference",
+            Self::EndpointOpenFailed => "endpoint-open-failed",
+            Self::EndpointIoError => "endpoint-io-error",
+            Self::EndpointDisabled => "endpoint-disabled",
+            Self::EndpointErrorExit => "endpoint-error-exit",
+            Self::StaleClear => "stale-clear",
+            Self::LagClear => "lag-clear",
+            Self::EndpointDead => "endpoint-dead",
+            Self::ConsumerExited => "consumer-exited",
+            Self::ConsumerSpawnFailed => "consumer-spawn-failed",
+            Self::ClientDisconnected => "client-disconnected",
+            Self::BaudrateMismatch => "baudrate-mismatch",
+            Self::InvalidConfiguration => "invalid-configuration",
+            Self::PreflightFailed => "preflight-failed",
+            Self::SetupFailed => "setup-failed",
+            Self::MemoryLimit => "memory-limit",
+            Self::WatchdogStarving => "watchdog-starving",
+            Self::Panic => "panic",
+            Self::NtripFailed => "ntrip-failed",
+            Self::CaptureFailed => "capture-failed",
+        }
+    }
+
+    /// The event of a log record, None if its message is not in the catalog.
+    pub fn of(record: &Record) -> Option<Self> {
+        let code = record.key_values().get(Key::from_str("event"))?;
+        Self::ALL
+            .into_iter()
+            .find(|event| Some(event.code()) == code.to_borrowed_str())
+    }
+}
+
+// How many times each event was logged, in the order of the catalog.
+static COUNTS: [AtomicU64; Event::ALL.len()] = [const { AtomicU64::new(0) }; Event::ALL.len()];
+
+/// The events logged so far with how many times, in the order of the catalog.
+pub fn event_counts() -> Vec<(Event, u64)> {
+    Event::ALL
+        .into_iter()
+        .zip(&COUNTS)
+        .map(|(event, count)| (event, count.load(Ordering::Relaxed)))
+        .filter(|&(_, count)| count > 0)
+        .collect()
+}
+
+/// Counts the events, whatever the levels of the other loggers.
+pub struct EventCounter;
+
+impl Log for EventCounter {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // all the events are logged at the info level or above.
+        metadata.level() <= LevelFilter::Info
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(event) = Event::of(record) {
+            COUNTS[event as usize].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for EventCounter {
+    fn level(&self) -> LevelFilter {
+        LevelFilter::Info
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{event_counts, Event, EventCounter};
+    use log::{Level, Log, Record};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_catalog() {
+        let codes: HashSet<&str> = Event::ALL.iter().map(|event| event.code()).collect();
+        let names: HashSet<&str> = Event::ALL.iter().map(|event| event.name()).collect();
+        assert_eq!(codes.len(), Event::ALL.len());
+        assert_eq!(names.len(), Event::ALL.len());
+        assert_eq!(Event::StaleClear.code(), "TT2005");
+
+        let stale_clear = [("event", "TT2005")];
+        let record = Record::builder()
+            .args(format_args!("Cleared stale buffer from slave0."))
+            .level(Level::Warn)
+            .target("ttytee::endpoint")
+            .key_values(&stale_clear)
+            .build();
+        assert_eq!(Event::of(&record), Some(Event::StaleClear));
+        // the target is the module, whatever it is.
+        assert_eq!(Event::of(&Record::builder().target("TT2005").build()), None);
+        // the only test logging the panic event, the counters are shared by the tests.
+        let panic = [("event", Event::Panic.code())];
+        let record = Record::builder()
+            .level(Level::Error)
+            .key_values(&panic)
+            .build();
+        EventCounter.log(&record);
+        EventCounter.log(&record);
+        assert!(event_counts().contains(&(Event::Panic, 2)));
+    }
+}
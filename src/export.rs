@@ -0,0 +1,134 @@
+//! Conversion of the capture files to the formats of the usual tools: the NMEA sentences or the
+//! UBX messages alone for RTKLIB or u-center, or pcapng with the time of receipt of each chunk for
+//! Wireshark.
+
+use crate::endpoint::capture::Capture;
+use crate::framing::{Framer, Protocol};
+use clap::ValueEnum;
+
+// pcapng has no link type for a serial stream, the first one reserved for private use is taken.
+const LINKTYPE_USER0: u16 = 147;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Nmea,
+    Ubx,
+    Pcapng,
+}
+
+/// Convert a capture.
+///
+/// # Arguments
+///
+/// * `capture`: the decoded capture file.
+/// * `format`: the output format.
+///
+/// returns: Vec<u8> the content of the converted file.
+///
+pub fn export(capture: &Capture, format: ExportFormat) -> Vec<u8> {
+    match format {
+        ExportFormat::Nmea => frames_of(capture, Protocol::Nmea),
+        ExportFormat::Ubx => frames_of(capture, Protocol::Ubx),
+        ExportFormat::Pcapng => pcapng(capture),
+    }
+}
+
+// The frames of a protocol, without the other protocols and the bytes out of any frame.
+fn frames_of(capture: &Capture, protocol: Protocol) -> Vec<u8> {
+    let mut framer = Framer::new(&[protocol]);
+    let mut frames = Vec::new();
+    for chunk in &capture.chunks {
+        framer.push(chunk.data, &mut frames);
+    }
+    frames.into_iter().flat_map(|frame| frame.data).collect()
+}
+
+// A section header, an interface and a packet per chunk, see RFC draft-ietf-opsawg-pcapng.
+fn pcapng(capture: &Capture) -> Vec<u8> {
+    let mut file = Vec::new();
+    let mut section = Vec::new();
+    section.extend_from_slice(&0x1a2b_3c4du32.to_le_bytes());
+    // version 1.0, the length of the section is not known in advance.
+    section.extend_from_slice(&1u16.to_le_bytes());
+    section.extend_from_slice(&0u16.to_le_bytes());
+    section.extend_from_slice(&(-1i64).to_le_bytes());
+    block(&mut file, 0x0a0d_0d0a, &section);
+
+    let mut interface = Vec::new();
+    interface.extend_from_slice(&LINKTYPE_USER0.to_le_bytes());
+    interface.extend_from_slice(&0u16.to_le_bytes());
+    // no snapshot length, the timestamps are in µs by default.
+    interface.extend_from_slice(&0u32.to_le_bytes());
+    block(&mut file, 1, &interface);
+
+    for chunk in &capture.chunks {
+        let mut packet = Vec::with_capacity(20 + chunk.data.len() + 3);
+        packet.extend_from_slice(&0u32.to_le_bytes());
+        packet.extend_from_slice(&((chunk.micros >> 32) as u32).to_le_bytes());
+        packet.extend_from_slice(&(chunk.micros as u32).to_le_bytes());
+        packet.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(chunk.data);
+        packet.resize(packet.len().next_multiple_of(4), 0);
+        block(&mut file, 6, &packet);
+    }
+    file
+}
+
+// A block: its type and total length around the body, the length is repeated at the end.
+fn block(file: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+    let length = (body.len() + 12) as u32;
+    file.extend_from_slice(&block_type.to_le_bytes());
+    file.extend_from_slice(&length.to_le_bytes());
+    file.extend_from_slice(body);
+    file.extend_from_slice(&length.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::capture::{Capture, Chunk};
+    use crate::export::{export, ExportFormat};
+
+    const GGA: &[u8] = b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n";
+    const ACK: &[u8] = &[0xb5, 0x62, 0x05, 0x01, 0x02, 0x00, 0x06, 0x8a, 0x98, 0xc1];
+
+    #[test]
+    fn test_export() {
+        let mixed = [GGA, ACK, GGA].concat();
+        let capture = Capture {
+            headers: String::new(),
+            chunks: vec![
+                Chunk {
+                    micros: 1_699_963_200_000_000,
+                    data: &mixed[..30],
+                },
+                Chunk {
+                    micros: 1_699_963_200_100_000,
+                    data: &mixed[30..],
+                },
+            ],
+        };
+        assert_eq!(export(&capture, ExportFormat::Nmea), [GGA, GGA].concat());
+        assert_eq!(export(&capture, ExportFormat::Ubx), ACK);
+
+        let pcapng = export(&capture, ExportFormat::Pcapng);
+        // the section header and the interface.
+        assert_eq!(&pcapng[..4], &[0x0a, 0x0d, 0x0d, 0x0a]);
+        assert_eq!(&pcapng[8..12], &[0x4d, 0x3c, 0x2b, 0x1a]);
+        assert_eq!(&pcapng[28..32], &[1, 0, 0, 0]);
+        assert_eq!(&pcapng[36..38], &[147, 0]);
+        // the first packet, 30 bytes padded to 32.
+        let packet = &pcapng[48..];
+        assert_eq!(&packet[..8], &[6, 0, 0, 0, 64, 0, 0, 0]);
+        let micros = 1_699_963_200_000_000u64;
+        assert_eq!(packet[12..16], ((micros >> 32) as u32).to_le_bytes());
+        assert_eq!(packet[16..20], (micros as u32).to_le_bytes());
+        assert_eq!(&packet[20..28], &[30, 0, 0, 0, 30, 0, 0, 0]);
+        assert_eq!(&packet[28..58], &mixed[..30]);
+        assert_eq!(&packet[60..64], &[64, 0, 0, 0]);
+        assert_eq!(
+            pcapng.len(),
+            48 + 64 + 32 + (mixed.len() - 30).next_multiple_of(4)
+        );
+    }
+}
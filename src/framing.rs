@@ -0,0 +1,622 @@
+//! Splitting of the master byte stream into protocol frames.
+//!
+//! The framer recognizes the frames of the enabled protocols by their sync bytes and validates
+//! their checksums. Whatever is in between (noise, unknown protocols) is skipped.
+//!
+//! Modbus RTU has no sync bytes, its frames are delimited by a silence of 3.5 characters on the
+//! line: the reader of the master reads until such a silence, and each push of the framer must end
+//! with one. The frames in a push are split by their CRC.
+//!
+//! SLIP (RFC 1055) has no checksum either, its frames start at an END byte and go up to the next
+//! one, which starts the next frame. A frame is only complete once that END is received.
+
+use clap::ValueEnum;
+use std::time::Duration;
+
+// Longest NMEA sentence we accept, the standard says 82 but some receivers go further.
+const MAX_NMEA_LEN: usize = 256;
+
+// Biggest UBX payload we accept before considering it is a false sync.
+const MAX_UBX_PAYLOAD: usize = 8192;
+
+const UBX_SYNC: [u8; 2] = [0xB5, 0x62];
+
+const RTCM_PREAMBLE: u8 = 0xD3;
+// The header and the CRC around the payload of a RTCM 3 frame.
+const RTCM_HEADER_LEN: usize = 3;
+const RTCM_CRC_LEN: usize = 3;
+
+// The address and the function code, then the CRC of a Modbus RTU frame.
+const MODBUS_MIN_LEN: usize = 4;
+const MODBUS_MAX_LEN: usize = 256;
+// The highest address of a slave, the ones above are reserved.
+const MODBUS_MAX_ADDRESS: u8 = 247;
+
+// The delimiter of the SLIP frames and the escapes of the END and ESC bytes in them.
+pub const SLIP_END: u8 = 0xC0;
+pub const SLIP_ESC: u8 = 0xDB;
+pub const SLIP_ESC_END: u8 = 0xDC;
+pub const SLIP_ESC_ESC: u8 = 0xDD;
+// Longest SLIP frame we accept, escapes included.
+const MAX_SLIP_LEN: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Protocol {
+    Nmea,
+    Ubx,
+    Rtcm,
+    Modbus,
+    Slip,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub protocol: Protocol,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// A short name for the type of message carried by the frame, for example GGA, NAV-PVT or
+    /// RTCM-1077.
+    pub fn message_type(&self) -> String {
+        match self.protocol {
+            Protocol::Nmea => {
+                let address = self.data[1..]
+                    .split(|&c| c == b',' || c == b'*' || c == b'\r')
+                    .next()
+                    .unwrap_or_default();
+                // proprietary sentences (P...) have no talker id, the bytes are cut before the
+                // conversion so noise in the address cannot split a character.
+                let address = if address.starts_with(b"P") || address.len() <= 2 {
+                    address
+                } else {
+                    &address[2..]
+                };
+                String::from_utf8_lossy(address).into_owned()
+            }
+            Protocol::Ubx => ubx_message_name(self.data[2], self.data[3]),
+            Protocol::Rtcm => match self.rtcm_message_number() {
+                Some(number) => format!("RTCM-{}", number),
+                // some receivers send empty frames to keep the link alive.
+                None => "RTCM".to_string(),
+            },
+            // the high bit of the function code marks the exception responses.
+            Protocol::Modbus if self.data[1] & 0x80 != 0 => {
+                format!("MODBUS-{}-EXCEPTION", self.data[1] & 0x7F)
+            }
+            Protocol::Modbus => format!("MODBUS-{}", self.data[1]),
+            Protocol::Slip => "SLIP".to_string(),
+        }
+    }
+
+    /// The message carried by the frame without the framing of its protocol: the unescaped
+    /// datagram of a SLIP frame, the whole frame for the other protocols, their headers and
+    /// checksums are part of the message for their consumers.
+    pub fn payload(&self) -> Vec<u8> {
+        if self.protocol != Protocol::Slip {
+            return self.data.clone();
+        }
+        let mut payload = Vec::with_capacity(self.data.len());
+        let mut escaped = false;
+        for &c in &self.data[1..] {
+            match (escaped, c) {
+                (false, SLIP_ESC) => escaped = true,
+                (false, c) => payload.push(c),
+                (true, SLIP_ESC_END) => {
+                    payload.push(SLIP_END);
+                    escaped = false;
+                }
+                (true, _) => {
+                    payload.push(SLIP_ESC);
+                    escaped = false;
+                }
+            }
+        }
+        payload
+    }
+
+    /// The message number of a RTCM frame, the first 12 bits of its payload.
+    pub fn rtcm_message_number(&self) -> Option<u16> {
+        if self.protocol != Protocol::Rtcm {
+            return None;
+        }
+        let payload = &self.data[RTCM_HEADER_LEN..self.data.len() - RTCM_CRC_LEN];
+        match payload {
+            [first, second, ..] => Some(((*first as u16) << 4) | (*second as u16 >> 4)),
+            _ => None,
+        }
+    }
+}
+
+enum Parse {
+    Complete(Frame),
+    Incomplete,
+    Invalid,
+}
+
+/// Accumulates bytes from the master and extracts the complete frames from them.
+pub struct Framer {
+    protocols: Vec<Protocol>,
+    buffer: Vec<u8>,
+    skipped_bytes: u64,
+    checksum_errors: u64,
+}
+
+impl Framer {
+    pub fn new(protocols: &[Protocol]) -> Self {
+        Self {
+            protocols: protocols.to_vec(),
+            buffer: Vec::new(),
+            skipped_bytes: 0,
+            checksum_errors: 0,
+        }
+    }
+
+    /// Number of bytes that did not belong to any frame so far.
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped_bytes
+    }
+
+    /// Number of frames dropped because of a bad checksum so far.
+    pub fn checksum_errors(&self) -> u64 {
+        self.checksum_errors
+    }
+
+    /// Feed bytes from the master.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: the bytes as read, they don't need to be aligned on frames.
+    /// * `frames`: where the complete frames are appended.
+    ///
+    pub fn push(&mut self, data: &[u8], frames: &mut Vec<Frame>) {
+        self.buffer.extend_from_slice(data);
+        let mut consumed = 0;
+        loop {
+            let rest = &self.buffer[consumed..];
+            // a lone first byte of an UBX sync at the end counts as a sync so it is kept.
+            let Some(start) = (0..rest.len()).find(|&i| self.is_sync(&rest[i..])) else {
+                self.skipped_bytes += rest.len() as u64;
+                consumed += rest.len();
+                break;
+            };
+            self.skipped_bytes += start as u64;
+            consumed += start;
+            match self.parse(&self.buffer[consumed..]) {
+                Parse::Complete(frame) => {
+                    consumed += frame.data.len();
+                    frames.push(frame);
+                }
+                Parse::Incomplete => break,
+                Parse::Invalid => {
+                    // false sync or corruption, look for the next sync after this one.
+                    self.checksum_errors += 1;
+                    self.skipped_bytes += 1;
+                    consumed += 1;
+                }
+            }
+        }
+        self.buffer.drain(..consumed);
+    }
+
+    fn is_sync(&self, data: &[u8]) -> bool {
+        self.protocols.iter().any(|protocol| match protocol {
+            Protocol::Nmea => data[0] == b'$' || data[0] == b'!',
+            Protocol::Ubx => data == [UBX_SYNC[0]] || data.starts_with(&UBX_SYNC),
+            Protocol::Rtcm => data[0] == RTCM_PREAMBLE,
+            Protocol::Modbus => data[0] <= MODBUS_MAX_ADDRESS,
+            // the ENDs in a row, sent to flush the noise of the line, are out of the frames.
+            Protocol::Slip => data[0] == SLIP_END && data.get(1) != Some(&SLIP_END),
+        })
+    }
+
+    fn parse(&self, data: &[u8]) -> Parse {
+        // no other protocol goes with Modbus or SLIP, their frames can contain any byte.
+        if self.protocols.contains(&Protocol::Modbus) {
+            parse_modbus(data)
+        } else if self.protocols.contains(&Protocol::Slip) {
+            parse_slip(data)
+        } else if data[0] == UBX_SYNC[0] {
+            parse_ubx(data)
+        } else if data[0] == RTCM_PREAMBLE {
+            parse_rtcm(data)
+        } else {
+            parse_nmea(data)
+        }
+    }
+}
+
+fn parse_nmea(data: &[u8]) -> Parse {
+    let Some(end) = data.iter().take(MAX_NMEA_LEN).position(|&c| c == b'\n') else {
+        return if data.len() < MAX_NMEA_LEN {
+            Parse::Incomplete
+        } else {
+            Parse::Invalid
+        };
+    };
+    let sentence = &data[..=end];
+    if !nmea_checksum_ok(sentence) {
+        return Parse::Invalid;
+    }
+    Parse::Complete(Frame {
+        protocol: Protocol::Nmea,
+        data: sentence.to_vec(),
+    })
+}
+
+/// Check the optional `*XX` checksum of a complete NMEA sentence.
+pub fn nmea_checksum_ok(sentence: &[u8]) -> bool {
+    // sentences are printable ASCII, another start or a control character means the end of line of
+    // a sentence was lost and it got merged with the next one, whatever its checksum says.
+    let body = sentence[1..].strip_suffix(b"\n").unwrap_or(&sentence[1..]);
+    let body = body.strip_suffix(b"\r").unwrap_or(body);
+    if body
+        .iter()
+        .any(|&c| c == b'$' || c == b'!' || !(b' '..=b'~').contains(&c))
+    {
+        return false;
+    }
+    let Some(star) = sentence.iter().position(|&c| c == b'*') else {
+        // the checksum is optional for most sentences.
+        return true;
+    };
+    let Some(expected) = sentence
+        .get(star + 1..star + 3)
+        .and_then(|hex| std::str::from_utf8(hex).ok())
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+    else {
+        return false;
+    };
+    nmea_checksum(&sentence[1..star]) == expected
+}
+
+/// The XOR checksum of the characters between `$` and `*`.
+pub fn nmea_checksum(body: &[u8]) -> u8 {
+    body.iter().fold(0, |checksum, c| checksum ^ c)
+}
+
+fn parse_ubx(data: &[u8]) -> Parse {
+    if data.len() < 6 {
+        return Parse::Incomplete;
+    }
+    let payload_len = u16::from_le_bytes([data[4], data[5]]) as usize;
+    if payload_len > MAX_UBX_PAYLOAD {
+        return Parse::Invalid;
+    }
+    let frame_len = 6 + payload_len + 2;
+    if data.len() < frame_len {
+        return Parse::Incomplete;
+    }
+    if ubx_checksum(&data[2..frame_len - 2]) != (data[frame_len - 2], data[frame_len - 1]) {
+        return Parse::Invalid;
+    }
+    Parse::Complete(Frame {
+        protocol: Protocol::Ubx,
+        data: data[..frame_len].to_vec(),
+    })
+}
+
+fn parse_rtcm(data: &[u8]) -> Parse {
+    if data.len() < RTCM_HEADER_LEN {
+        return Parse::Incomplete;
+    }
+    // the 6 bits before the length are reserved and always 0.
+    if data[1] & 0xFC != 0 {
+        return Parse::Invalid;
+    }
+    let payload_len = u16::from_be_bytes([data[1], data[2]]) as usize;
+    let frame_len = RTCM_HEADER_LEN + payload_len + RTCM_CRC_LEN;
+    if data.len() < frame_len {
+        return Parse::Incomplete;
+    }
+    let crc = &data[frame_len - RTCM_CRC_LEN..frame_len];
+    if crc24q(&data[..frame_len - RTCM_CRC_LEN]).to_be_bytes()[1..] != *crc {
+        return Parse::Invalid;
+    }
+    Parse::Complete(Frame {
+        protocol: Protocol::Rtcm,
+        data: data[..frame_len].to_vec(),
+    })
+}
+
+// The shortest frame at the start of data whose CRC matches, there is a silence at the end of data.
+fn parse_modbus(data: &[u8]) -> Parse {
+    let mut crc = 0xFFFF;
+    for len in MODBUS_MIN_LEN..=data.len().min(MODBUS_MAX_LEN) {
+        // the CRC of the bytes before the last two, updated one byte at a time.
+        if len == MODBUS_MIN_LEN {
+            crc = modbus_crc_update(modbus_crc_update(crc, data[0]), data[1]);
+        } else {
+            crc = modbus_crc_update(crc, data[len - 3]);
+        }
+        if crc.to_le_bytes() == data[len - 2..len] {
+            return Parse::Complete(Frame {
+                protocol: Protocol::Modbus,
+                data: data[..len].to_vec(),
+            });
+        }
+    }
+    Parse::Invalid
+}
+
+// A SLIP frame from its END up to the next END, excluded.
+fn parse_slip(data: &[u8]) -> Parse {
+    let Some(len) = data[1..]
+        .iter()
+        .take(MAX_SLIP_LEN)
+        .position(|&c| c == SLIP_END)
+    else {
+        return if data.len() <= MAX_SLIP_LEN {
+            Parse::Incomplete
+        } else {
+            Parse::Invalid
+        };
+    };
+    let frame = &data[..1 + len];
+    // an escape is always followed by one of the two escaped bytes.
+    if frame.last() == Some(&SLIP_ESC)
+        || frame
+            .windows(2)
+            .any(|pair| pair[0] == SLIP_ESC && !matches!(pair[1], SLIP_ESC_END | SLIP_ESC_ESC))
+    {
+        return Parse::Invalid;
+    }
+    Parse::Complete(Frame {
+        protocol: Protocol::Slip,
+        data: frame.to_vec(),
+    })
+}
+
+fn modbus_crc_update(crc: u16, c: u8) -> u16 {
+    let mut crc = crc ^ c as u16;
+    for _ in 0..8 {
+        crc = if crc & 1 != 0 {
+            (crc >> 1) ^ 0xA001
+        } else {
+            crc >> 1
+        };
+    }
+    crc
+}
+
+/// The CRC-16 of a Modbus RTU frame, computed from the address to the end of the data, sent low
+/// byte first.
+pub fn modbus_crc(data: &[u8]) -> u16 {
+    data.iter()
+        .fold(0xFFFF, |crc, &c| modbus_crc_update(crc, c))
+}
+
+/// The silence delimiting the Modbus RTU frames: 3.5 characters of 11 bits, and 1.75 ms above
+/// 19200 bauds as the standard says.
+pub fn modbus_silence(baudrate: u32) -> Duration {
+    if baudrate > 19200 {
+        Duration::from_micros(1750)
+    } else {
+        Duration::from_micros(3_500_000 * 11 / baudrate.max(1) as u64)
+    }
+}
+
+/// The CRC-24Q of a RTCM 3 frame, computed from the preamble to the end of the payload.
+pub fn crc24q(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, &c| {
+        let mut crc = crc ^ ((c as u32) << 16);
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x100_0000 != 0 {
+                crc ^= 0x186_4CFB;
+            }
+        }
+        crc
+    })
+}
+
+/// The 8-bit Fletcher checksum of an UBX frame, computed from the class to the end of the payload.
+pub fn ubx_checksum(data: &[u8]) -> (u8, u8) {
+    data.iter().fold((0u8, 0u8), |(a, b), &c| {
+        let a = a.wrapping_add(c);
+        (a, b.wrapping_add(a))
+    })
+}
+
+/// The usual name of an UBX message from its class and id.
+pub fn ubx_message_name(class: u8, id: u8) -> String {
+    let name = match (class, id) {
+        (0x01, 0x02) => "NAV-POSLLH",
+        (0x01, 0x03) => "NAV-STATUS",
+        (0x01, 0x07) => "NAV-PVT",
+        (0x01, 0x12) => "NAV-VELNED",
+        (0x01, 0x21) => "NAV-TIMEUTC",
+        (0x01, 0x35) => "NAV-SAT",
+        (0x02, 0x13) => "RXM-SFRBX",
+        (0x02, 0x15) => "RXM-RAWX",
+        (0x05, 0x00) => "ACK-NAK",
+        (0x05, 0x01) => "ACK-ACK",
+        (0x06, 0x00) => "CFG-PRT",
+        (0x06, 0x01) => "CFG-MSG",
+        (0x06, 0x04) => "CFG-RST",
+        (0x06, 0x08) => "CFG-RATE",
+        (0x06, 0x8A) => "CFG-VALSET",
+        (0x0A, 0x04) => "MON-VER",
+        (0x0D, 0x01) => "TIM-TP",
+        _ => return format!("UBX-{:02X}-{:02X}", class, id),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{
+        crc24q, modbus_crc, modbus_silence, ubx_checksum, Frame, Framer, Protocol, SLIP_END,
+    };
+    use std::time::Duration;
+
+    const GGA: &[u8] = b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n";
+    const RMC: &[u8] = b"$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n";
+
+    fn ubx(class: u8, id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0xB5, 0x62, class, id];
+        frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        frame.extend_from_slice(payload);
+        let (a, b) = ubx_checksum(&frame[2..]);
+        frame.extend_from_slice(&[a, b]);
+        frame
+    }
+
+    fn rtcm(payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0xD3];
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        frame.extend_from_slice(payload);
+        let crc = crc24q(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes()[1..]);
+        frame
+    }
+
+    fn frame_all(framer: &mut Framer, chunks: &[&[u8]]) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        for chunk in chunks {
+            framer.push(chunk, &mut frames);
+        }
+        frames
+    }
+
+    #[test]
+    fn test_nmea_split_across_reads() {
+        let mut framer = Framer::new(&[Protocol::Nmea]);
+        let frames = frame_all(&mut framer, &[&GGA[..10], &GGA[10..], RMC]);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].data, GGA);
+        assert_eq!(frames[0].message_type(), "GGA");
+        assert_eq!(frames[1].message_type(), "RMC");
+        assert_eq!(framer.skipped_bytes(), 0);
+    }
+
+    #[test]
+    fn test_non_ascii_address() {
+        let frame = Frame {
+            protocol: Protocol::Nmea,
+            data: "$G\u{e9}GA,1*00\r\n".as_bytes().to_vec(),
+        };
+        assert_eq!(frame.message_type(), "\u{fffd}GA");
+    }
+
+    #[test]
+    fn test_bad_checksum_and_garbage_are_skipped() {
+        let mut framer = Framer::new(&[Protocol::Nmea]);
+        let mut corrupted = GGA.to_vec();
+        corrupted[10] = b'9';
+        let frames = frame_all(&mut framer, &[b"noise", &corrupted, RMC]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data, RMC);
+        assert_eq!(framer.checksum_errors(), 1);
+    }
+
+    #[test]
+    fn test_mixed_nmea_and_ubx() {
+        let mut framer = Framer::new(&[Protocol::Nmea, Protocol::Ubx]);
+        let pvt = ubx(0x01, 0x07, &[0; 92]);
+        let mut stream = GGA.to_vec();
+        stream.extend_from_slice(&pvt);
+        stream.extend_from_slice(RMC);
+        // byte per byte to exercise every split point.
+        let chunks: Vec<&[u8]> = stream.chunks(1).collect();
+        let frames = frame_all(&mut framer, &chunks);
+        let types: Vec<String> = frames.iter().map(Frame::message_type).collect();
+        assert_eq!(types, vec!["GGA", "NAV-PVT", "RMC"]);
+        assert_eq!(frames[1].data, pvt);
+        assert_eq!(framer.skipped_bytes(), 0);
+    }
+
+    #[test]
+    fn test_ubx_only_ignores_nmea() {
+        let mut framer = Framer::new(&[Protocol::Ubx]);
+        let ack = ubx(0x05, 0x01, &[0x06, 0x01]);
+        let frames = frame_all(&mut framer, &[GGA, &ack]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].message_type(), "ACK-ACK");
+        assert_eq!(framer.skipped_bytes(), GGA.len() as u64);
+    }
+
+    #[test]
+    fn test_rtcm() {
+        let mut framer = Framer::new(&[Protocol::Nmea, Protocol::Rtcm]);
+        let msm = rtcm(&[0x43, 0x50, 0x01, 0x00, 0x00]);
+        let mut corrupted = rtcm(&[0x3E, 0xD7, 0xD3, 0x02]);
+        corrupted[5] ^= 0x01;
+        let mut stream = msm.clone();
+        stream.extend_from_slice(&corrupted);
+        stream.extend_from_slice(GGA);
+        let chunks: Vec<&[u8]> = stream.chunks(1).collect();
+        let frames = frame_all(&mut framer, &chunks);
+        let types: Vec<String> = frames.iter().map(Frame::message_type).collect();
+        assert_eq!(types, vec!["RTCM-1077", "GGA"]);
+        assert_eq!(frames[0].data, msm);
+        assert_eq!(frames[0].rtcm_message_number(), Some(1077));
+        assert_eq!(framer.checksum_errors(), 1);
+        // the CRC-24Q check value.
+        assert_eq!(crc24q(b"123456789"), 0xCDE703);
+    }
+
+    #[test]
+    fn test_slip() {
+        let mut framer = Framer::new(&[Protocol::Slip]);
+        // the end of a frame started before, the frames, an END and an ESC escaped, a bad escape.
+        let stream = [
+            &b"tail"[..],
+            &[SLIP_END, SLIP_END, 0x01, 0x02],
+            &[SLIP_END, 0x03, 0xDB, 0xDC, 0xDB, 0xDD],
+            &[SLIP_END, 0x04, 0xDB, 0x05],
+            &[SLIP_END, 0x06],
+        ]
+        .concat();
+        let chunks: Vec<&[u8]> = stream.chunks(1).collect();
+        let frames = frame_all(&mut framer, &chunks);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].message_type(), "SLIP");
+        assert_eq!(frames[0].data, [SLIP_END, 0x01, 0x02]);
+        assert_eq!(frames[0].payload(), [0x01, 0x02]);
+        assert_eq!(frames[1].payload(), [0x03, SLIP_END, 0xDB]);
+        assert_eq!(framer.checksum_errors(), 1);
+        // the tail, the first of the ENDs in a row and the bad frame.
+        assert_eq!(framer.skipped_bytes(), 4 + 1 + 4);
+        // the last frame waits for the next END.
+        assert_eq!(frame_all(&mut framer, &[&[SLIP_END]])[0].payload(), [0x06]);
+    }
+
+    #[test]
+    fn test_modbus() {
+        let modbus = |data: &[u8]| {
+            let mut frame = data.to_vec();
+            frame.extend_from_slice(&modbus_crc(data).to_le_bytes());
+            frame
+        };
+        // read 3 holding registers at 0x006B of the slave 17, its response and an exception.
+        let request = modbus(&[0x11, 0x03, 0x00, 0x6B, 0x00, 0x03]);
+        assert_eq!(request[6..], [0x76, 0x87]);
+        let response = modbus(&[0x11, 0x03, 0x06, 0x02, 0x2B, 0x00, 0x00, 0x00, 0x64]);
+        let exception = modbus(&[0x11, 0x83, 0x02]);
+        let mut corrupted = response.clone();
+        corrupted[4] ^= 0x01;
+        let mut framer = Framer::new(&[Protocol::Modbus]);
+        // each push ends with a silence, the frames in a push are split by their CRC.
+        let frames = frame_all(
+            &mut framer,
+            &[
+                &request,
+                &[&response[..], &exception].concat(),
+                &corrupted,
+                &request,
+            ],
+        );
+        let types: Vec<String> = frames.iter().map(Frame::message_type).collect();
+        assert_eq!(
+            types,
+            vec!["MODBUS-3", "MODBUS-3", "MODBUS-3-EXCEPTION", "MODBUS-3"]
+        );
+        assert_eq!(frames[1].data, response);
+        assert!(framer.checksum_errors() > 0);
+        // the CRC-16/MODBUS check value.
+        assert_eq!(modbus_crc(b"123456789"), 0x4B37);
+        assert_eq!(modbus_silence(9600), Duration::from_micros(4010));
+        assert_eq!(modbus_silence(115200), Duration::from_micros(1750));
+    }
+}
@@ -0,0 +1,294 @@
+//! Generation of the shell completions and of the man page from the command line definition,
+//! for the packagers, and of the description of the capabilities of the binary, for the deployment
+//! tools checking it supports a configuration before rolling it out. The check, the analysis and
+//! the export of the capture files are here too, like the rest of what runs without the tee, the
+//! probe of a master, the comparison of two, the replay of a capture into one, the PTY of a remote
+//! TCP endpoint and the dashboard of a running instance.
+
+use crate::analyze::analyze;
+use crate::connect::connect;
+use crate::diff::diff;
+use crate::endpoint::capture::{parse, verify};
+use crate::endpoint::endpoint_types;
+use crate::endpoint::format::OutputFormat;
+use crate::export::{export, ExportFormat};
+use crate::framing::Protocol;
+use crate::probe::probe;
+use crate::replay::replay;
+use crate::top::top;
+use crate::transform::TRANSFORM_KEYS;
+use clap::{Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+#[derive(Subcommand, Clone, Debug, PartialEq)]
+pub enum Generate {
+    /// Print the completion script of a shell, for example `ttytee completions bash`.
+    Completions {
+        /// Shell to print the completion script of.
+        shell: Shell,
+    },
+    /// Print the man page in roff, for example `ttytee man > ttytee.1`.
+    #[command(visible_alias = "man")]
+    Manpage,
+    /// Print in JSON the features, framers, endpoint types and transforms this binary supports.
+    Capabilities,
+    /// Check the CRCs of a capture file and print its headers, for example `ttytee verify gps.cap`.
+    Verify {
+        /// Capture file written by --capture.
+        capture: PathBuf,
+    },
+    /// Print the duration, throughput, message types, gaps and framing errors of a capture file.
+    Analyze {
+        /// Capture file written by --capture.
+        capture: PathBuf,
+        /// Period in s of the throughput table.
+        #[arg(long, default_value_t = 60, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+        /// Silences of the master reported as gaps, in ms.
+        #[arg(long, default_value_t = 1000, value_name = "MS")]
+        min_gap: u64,
+    },
+    /// Print the NMEA sentences or the UBX messages of a capture file, or convert it to pcapng,
+    /// for example `ttytee export gps.cap --format pcapng > gps.pcapng`.
+    Export {
+        /// Capture file written by --capture.
+        capture: PathBuf,
+        /// Format to print the capture in.
+        #[arg(long)]
+        format: ExportFormat,
+    },
+    /// Find the baudrate, the protocols, the message rates and the versions of a receiver and print
+    /// them in JSON, for example `ttytee probe --master /dev/ttyUSB0`.
+    Probe {
+        /// Serial device of the receiver.
+        #[arg(short, long, default_value = crate::DEFAULT_MASTER, value_name = "MASTER")]
+        master: PathBuf,
+        /// How long the messages are counted once the baudrate is found, in s.
+        #[arg(long, default_value_t = 10, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        duration: u64,
+    },
+    /// Read two masters that should send the same stream, like redundant receivers, and print how
+    /// their frames differ, for example `ttytee diff /dev/ttyUSB0 /dev/ttyUSB1`.
+    Diff {
+        /// First master, the reference.
+        first: PathBuf,
+        /// Second master, compared to the first one.
+        second: PathBuf,
+        /// Baudrate of both masters.
+        #[arg(long, default_value_t = crate::DEFAULT_BAUDRATE, value_name = "BAUDRATE")]
+        baudrate: u32,
+        /// How long the masters are compared, in s.
+        #[arg(long, default_value_t = 60, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        duration: u64,
+        /// The largest difference of arrival of the same frame on both masters, in ms.
+        #[arg(long, default_value_t = 500, value_name = "MS")]
+        tolerance: u64,
+    },
+    /// Write a capture file into a master with its timing, or any file as it is, to exercise a
+    /// device under test, for example `ttytee replay corrections.cap --master /dev/ttyUSB1`.
+    Replay {
+        /// Capture file, or any file to write as it is.
+        input: PathBuf,
+        /// Serial device to write to.
+        #[arg(short, long, default_value = crate::DEFAULT_MASTER, value_name = "MASTER")]
+        master: PathBuf,
+        /// Baudrate of MASTER, the one in the capture by default.
+        #[arg(long, value_name = "BAUDRATE")]
+        baudrate: Option<u32>,
+        /// How much faster than recorded, 0 to write as fast as MASTER takes it.
+        #[arg(long, default_value_t = 1.0, value_name = "FACTOR")]
+        speed: f64,
+    },
+    /// Write what a tcp:// or tcpz:// endpoint of another machine sends to a local PTY, for
+    /// example `ttytee connect base.local:5000 --link /tmp/gps.pty`.
+    Connect {
+        /// Host and port of the tcp:// or tcpz:// endpoint.
+        address: String,
+        /// Where to create the symlink to the PTY.
+        #[arg(long, value_name = "PATH")]
+        link: PathBuf,
+    },
+    /// Show a live dashboard of a running instance through its control socket, for example
+    /// `ttytee top --control-socket /run/ttytee.sock`.
+    Top {
+        /// Control socket of the running instance.
+        #[arg(long, value_name = "PATH")]
+        control_socket: PathBuf,
+        /// How often the dashboard is redrawn, in s.
+        #[arg(long, default_value_t = 1, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+    },
+}
+
+fn json_list<T: ToString>(items: impl IntoIterator<Item = T>) -> String {
+    let items: Vec<String> = items
+        .into_iter()
+        .map(|item| format!("\"{}\"", item.to_string()))
+        .collect();
+    format!("[{}]", items.join(","))
+}
+
+/// The capabilities of this binary as a JSON object.
+pub fn capabilities() -> String {
+    let mut features = Vec::new();
+    if cfg!(feature = "sqlite") {
+        features.push("sqlite");
+    }
+    if cfg!(feature = "raw-linux") {
+        features.push("raw-linux");
+    }
+    let framers = Protocol::value_variants()
+        .iter()
+        .filter_map(|protocol| protocol.to_possible_value())
+        .map(|value| value.get_name().to_string());
+    format!(
+        "{{\"version\":\"{}\",\"features\":{},\"framers\":{},\"endpoints\":{},\"formats\":{},\"transforms\":{},\"sandbox\":[\"landlock\"]}}\n",
+        env!("CARGO_PKG_VERSION"),
+        json_list(features),
+        json_list(framers),
+        json_list(endpoint_types()),
+        json_list(OutputFormat::ALL),
+        json_list(TRANSFORM_KEYS)
+    )
+}
+
+fn corrupted(capture: &Path, err: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{:?}: {}", capture, err),
+    )
+}
+
+/// Write the generated file.
+///
+/// # Arguments
+///
+/// * `what`: the file to generate.
+/// * `command`: the command line definition of ttytee.
+/// * `out`: where to write it.
+///
+/// returns: Result<(), Error>
+///
+pub fn generate(
+    what: &Generate,
+    mut command: clap::Command,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    match what {
+        Generate::Completions { shell } => {
+            let name = command.get_name().to_string();
+            clap_complete::generate(*shell, &mut command, name, out);
+            Ok(())
+        }
+        Generate::Manpage => clap_mangen::Man::new(command).render(out),
+        Generate::Capabilities => out.write_all(capabilities().as_bytes()),
+        Generate::Verify { capture } => {
+            let summary = verify(&fs::read(capture)?).map_err(|err| corrupted(capture, err))?;
+            out.write_all(summary.as_bytes())
+        }
+        Generate::Analyze {
+            capture,
+            interval,
+            min_gap,
+        } => {
+            let report = analyze(
+                &fs::read(capture)?,
+                Duration::from_secs(*interval),
+                Duration::from_millis(*min_gap),
+            )
+            .map_err(|err| corrupted(capture, err))?;
+            out.write_all(report.as_bytes())
+        }
+        Generate::Export { capture, format } => {
+            let data = fs::read(capture)?;
+            let parsed = parse(&data).map_err(|err| corrupted(capture, err))?;
+            out.write_all(&export(&parsed, *format))
+        }
+        Generate::Probe { master, duration } => {
+            let report = probe(master, Duration::from_secs(*duration))?;
+            out.write_all(report.as_bytes())
+        }
+        Generate::Diff {
+            first,
+            second,
+            baudrate,
+            duration,
+            tolerance,
+        } => {
+            let report = diff(
+                first,
+                second,
+                *baudrate,
+                Duration::from_secs(*duration),
+                Duration::from_millis(*tolerance),
+            )?;
+            out.write_all(report.as_bytes())
+        }
+        Generate::Replay {
+            input,
+            master,
+            baudrate,
+            speed,
+        } => {
+            let summary = replay(input, master, *baudrate, *speed)?;
+            out.write_all(summary.as_bytes())
+        }
+        Generate::Connect { address, link } => connect(address, link, &AtomicBool::new(true)),
+        Generate::Top {
+            control_socket,
+            interval,
+        } => top(control_socket, Duration::from_secs(*interval), out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generate::{generate, Generate};
+    use crate::Args;
+    use clap::{CommandFactory, Parser};
+    use clap_complete::Shell;
+
+    #[test]
+    fn test_generate() {
+        let mut completions = Vec::new();
+        generate(
+            &Generate::Completions { shell: Shell::Bash },
+            Args::command(),
+            &mut completions,
+        )
+        .unwrap();
+        assert!(String::from_utf8(completions)
+            .unwrap()
+            .contains("--slave-read-timeout"));
+
+        let args = Args::try_parse_from(["ttytee", "man"]).unwrap();
+        assert_eq!(args.generate, Some(Generate::Manpage));
+        let mut manpage = Vec::new();
+        generate(&Generate::Manpage, Args::command(), &mut manpage).unwrap();
+        let manpage = String::from_utf8(manpage).unwrap();
+        assert!(manpage.starts_with(".ie"));
+        assert!(manpage.contains("ttytee"));
+        // the options are described, not only listed.
+        assert!(manpage.contains("Baudrate to read the master from"));
+
+        let mut capabilities = Vec::new();
+        generate(&Generate::Capabilities, Args::command(), &mut capabilities).unwrap();
+        let capabilities = String::from_utf8(capabilities).unwrap();
+        assert!(capabilities.starts_with("{\"version\":\""));
+        assert!(
+            capabilities.contains("\"framers\":[\"nmea\",\"ubx\",\"rtcm\",\"modbus\",\"slip\"]")
+        );
+        assert!(capabilities
+            .contains("\"formats\":[\"raw\",\"json\",\"metadata\",\"hexdump\",\"timebase\"]"));
+        assert_eq!(
+            capabilities.contains("\"sqlite\""),
+            cfg!(feature = "sqlite")
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! A master on an I2C bus: `--master i2c:///dev/i2c-1:0x42` polls a GNSS module wired with I2C,
+//! like the DDC interface of the u-blox receivers, and shares its stream like a serial one.
+//!
+//! The module tells how many bytes it has in its registers 0xFD and 0xFE, then they are read from
+//! its stream register 0xFF. As for a remote master, a thread writes them into a local PTY and
+//! ttytee reads the other side of it, so the reader, the timeouts and the endpoints are the same.
+//! Nothing is written to the module.
+
+use crate::backoff::Backoff;
+use log::{debug, info, warn};
+use serialport::{SerialPort, TTYPort};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const SCHEME: &str = "i2c://";
+// From linux/i2c-dev.h.
+const I2C_SLAVE: libc::c_ulong = 0x0703;
+// The registers of the DDC interface.
+const BYTES_AVAILABLE_REGISTER: u8 = 0xfd;
+const STREAM_REGISTER: u8 = 0xff;
+// How often the module is asked for new data when it has none.
+const POLL_PERIOD: Duration = Duration::from_millis(20);
+// The largest read, the i2c-dev transfers are limited to 8192 bytes.
+const MAX_READ: usize = 4096;
+// How long the PTY may be full before the data is dropped.
+const PTY_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// A GNSS module on an I2C bus.
+#[derive(Clone, Debug, PartialEq)]
+pub struct I2cDevice {
+    pub bus: PathBuf,
+    pub address: u16,
+}
+
+/// Parse an I2C master.
+///
+/// # Arguments
+///
+/// * `master`: the --master argument, like `i2c:///dev/i2c-1:0x42`.
+///
+/// returns: Option<Result<I2cDevice, String>> None for another kind of master.
+///
+pub fn parse_i2c_master(master: &Path) -> Option<Result<I2cDevice, String>> {
+    let target = master.to_str()?.strip_prefix(SCHEME)?;
+    let address = |address: &str| {
+        u16::from_str_radix(address.strip_prefix("0x")?, 16)
+            .ok()
+            .filter(|&address| address <= 0x7f)
+    };
+    Some(match target.rsplit_once(':') {
+        Some((bus, address_text)) if bus.starts_with('/') => match address(address_text) {
+            Some(address) => Ok(I2cDevice {
+                bus: PathBuf::from(bus),
+                address,
+            }),
+            None => Err(format!("invalid I2C address {:?}", address_text)),
+        },
+        _ => Err(format!(
+            "expected i2c://<BUS DEVICE>:<ADDRESS>, got {:?}",
+            master
+        )),
+    })
+}
+
+/// Read what the module has in its stream, empty if it has nothing.
+fn read_stream(bus: &mut (impl Read + Write)) -> io::Result<Vec<u8>> {
+    bus.write_all(&[BYTES_AVAILABLE_REGISTER])?;
+    let mut count = [0; 2];
+    bus.read_exact(&mut count)?;
+    let available = match u16::from_be_bytes(count) {
+        // not ready.
+        0xffff => 0,
+        available => available as usize,
+    };
+    if available == 0 {
+        return Ok(Vec::new());
+    }
+    bus.write_all(&[STREAM_REGISTER])?;
+    let mut data = vec![0; available.min(MAX_READ)];
+    bus.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// The local side of an I2C master, the polling stops when it is dropped.
+pub struct I2cMaster {
+    // the bus and the PTY to write, until the polling starts.
+    idle: Option<(File, TTYPort)>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl I2cMaster {
+    /// Open the bus of a module, it is polled once `start` is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `device`: the bus and the address of the module.
+    ///
+    /// returns: Result<(TTYPort, I2cMaster), Error> the port to read as the master.
+    ///
+    pub fn open(device: &I2cDevice) -> io::Result<(TTYPort, Self)> {
+        let bus = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&device.bus)?;
+        if unsafe { libc::ioctl(bus.as_raw_fd(), I2C_SLAVE, device.address as libc::c_ulong) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (master, mut pty) = TTYPort::pair()?;
+        pty.set_timeout(PTY_WRITE_TIMEOUT)?;
+        info!(
+            "Polling the I2C module {:#04x} on {:?}.",
+            device.address, device.bus
+        );
+        Ok((
+            master,
+            Self {
+                idle: Some((bus, pty)),
+                stop: Arc::new(AtomicBool::new(false)),
+                handle: None,
+            },
+        ))
+    }
+
+    /// Start polling the module from its own thread, after the sandbox so the thread is in it.
+    pub fn start(&mut self) {
+        if let Some((bus, pty)) = self.idle.take() {
+            let stop_ref = Arc::clone(&self.stop);
+            self.handle = Some(thread::spawn(move || poll_module(bus, pty, &stop_ref)));
+        }
+    }
+}
+
+impl Drop for I2cMaster {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+fn poll_module(mut bus: File, mut pty: TTYPort, stop: &AtomicBool) {
+    let mut backoff = Backoff::new(POLL_PERIOD, MAX_BACKOFF);
+    while !stop.load(Ordering::Relaxed) {
+        match read_stream(&mut bus) {
+            Ok(data) if data.is_empty() => thread::sleep(POLL_PERIOD),
+            Ok(data) => {
+                backoff.success();
+                if let Err(err) = pty.write_all(&data) {
+                    debug!("Dropped {} bytes of the I2C module: {}.", data.len(), err);
+                }
+            }
+            Err(err) => {
+                warn!("Error reading the I2C module: {}. Trying again.", err);
+                thread::sleep(backoff.failure(Instant::now()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::i2c::{parse_i2c_master, read_stream, I2cDevice};
+    use std::io;
+    use std::io::{Read, Write};
+    use std::path::{Path, PathBuf};
+
+    // plays a module with a stream to read.
+    struct FakeModule {
+        stream: Vec<u8>,
+        register: u8,
+    }
+
+    impl Write for FakeModule {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.register = data[0];
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for FakeModule {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            match self.register {
+                0xfd => buffer[..2].copy_from_slice(&(self.stream.len() as u16).to_be_bytes()),
+                0xff => {
+                    let data: Vec<u8> = self.stream.drain(..buffer.len()).collect();
+                    buffer.copy_from_slice(&data);
+                }
+                _ => unreachable!(),
+            }
+            Ok(buffer.len())
+        }
+    }
+
+    #[test]
+    fn test_parse_i2c_master() {
+        assert_eq!(parse_i2c_master(Path::new("/dev/ttyUSB0")), None);
+        assert_eq!(
+            parse_i2c_master(Path::new("i2c:///dev/i2c-1:0x42")),
+            Some(Ok(I2cDevice {
+                bus: PathBuf::from("/dev/i2c-1"),
+                address: 0x42
+            }))
+        );
+        assert!(parse_i2c_master(Path::new("i2c:///dev/i2c-1"))
+            .unwrap()
+            .is_err());
+        assert!(parse_i2c_master(Path::new("i2c:///dev/i2c-1:0x80"))
+            .unwrap()
+            .is_err());
+        assert!(parse_i2c_master(Path::new("i2c://i2c-1:0x42"))
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_read_stream() {
+        let mut module = FakeModule {
+            stream: b"$GPGGA\r\n".to_vec(),
+            register: 0,
+        };
+        assert_eq!(read_stream(&mut module).unwrap(), b"$GPGGA\r\n");
+        assert!(read_stream(&mut module).unwrap().is_empty());
+    }
+}
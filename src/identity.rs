@@ -0,0 +1,206 @@
+//! The USB identity of the master for the consumers selecting their port by it: with
+//! `--usb-identity` a `LINK.env` file is written next to the link of each PTY, with the properties
+//! udev gives the real device (ID_VENDOR_ID, ID_MODEL_ID, ID_SERIAL...).
+//!
+//! udev does not manage the PTYs so they cannot get these properties themselves, but the consumers
+//! can read the file, like with the `EnvironmentFile=` of their systemd unit.
+
+use log::{info, warn};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const SYSFS: &str = "/sys";
+
+/// The USB attributes of a device, from sysfs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsbIdentity {
+    pub vendor_id: String,
+    pub model_id: String,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+}
+
+fn read_attribute(dir: &Path, name: &str) -> Option<String> {
+    let value = fs::read_to_string(dir.join(name)).ok()?;
+    Some(value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+/// The USB attributes of a TTY, None if it is not a USB device.
+///
+/// # Arguments
+///
+/// * `sys`: where sysfs is mounted.
+/// * `master`: the TTY, or a link to it.
+///
+/// returns: Option<UsbIdentity>
+///
+pub fn usb_identity(sys: &Path, master: &Path) -> Option<UsbIdentity> {
+    let master = master
+        .canonicalize()
+        .unwrap_or_else(|_| master.to_path_buf());
+    let device = sys
+        .join("class/tty")
+        .join(master.file_name()?)
+        .join("device")
+        .canonicalize()
+        .ok()?;
+    // the TTY belongs to an interface, the attributes are on the USB device above it.
+    let usb_device = device
+        .ancestors()
+        .take_while(|dir| dir.starts_with(sys))
+        .find(|dir| dir.join("idVendor").exists())?;
+    Some(UsbIdentity {
+        vendor_id: read_attribute(usb_device, "idVendor")?,
+        model_id: read_attribute(usb_device, "idProduct")?,
+        vendor: read_attribute(usb_device, "manufacturer"),
+        model: read_attribute(usb_device, "product"),
+        serial: read_attribute(usb_device, "serial"),
+    })
+}
+
+// udev replaces the whitespaces of the strings by underscores.
+fn udev_string(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+/// The content of the environment file of a PTY.
+///
+/// # Arguments
+///
+/// * `identity`: the USB attributes of the master.
+/// * `link`: the link to the PTY, given as its DEVNAME.
+///
+/// returns: String
+///
+pub fn environment(identity: &UsbIdentity, link: &Path) -> String {
+    let vendor = udev_string(identity.vendor.as_deref().unwrap_or(&identity.vendor_id));
+    let model = udev_string(identity.model.as_deref().unwrap_or(&identity.model_id));
+    let mut variables = vec![
+        ("DEVNAME", link.display().to_string()),
+        ("ID_VENDOR_ID", identity.vendor_id.clone()),
+        ("ID_MODEL_ID", identity.model_id.clone()),
+        ("ID_VENDOR", vendor.clone()),
+        ("ID_MODEL", model.clone()),
+    ];
+    match &identity.serial {
+        Some(serial) => {
+            let serial = udev_string(serial);
+            variables.push(("ID_SERIAL", format!("{}_{}_{}", vendor, model, serial)));
+            variables.push(("ID_SERIAL_SHORT", serial));
+        }
+        None => variables.push(("ID_SERIAL", format!("{}_{}", vendor, model))),
+    }
+    variables
+        .iter()
+        .map(|(name, value)| format!("{}={}\n", name, value))
+        .collect()
+}
+
+fn environment_path(link: &Path) -> PathBuf {
+    let mut path = OsString::from(link);
+    path.push(".env");
+    PathBuf::from(path)
+}
+
+/// The environment files of the PTYs, removed when dropped.
+pub struct IdentityFiles {
+    paths: Vec<PathBuf>,
+}
+
+impl IdentityFiles {
+    /// Write the environment file of each PTY.
+    ///
+    /// # Arguments
+    ///
+    /// * `master`: the master, a USB device.
+    /// * `links`: the links to the PTYs.
+    ///
+    /// returns: Result<IdentityFiles, Error> with no file if the master is not a USB device.
+    ///
+    pub fn write(master: &Path, links: &[&Path]) -> io::Result<Self> {
+        let mut files = Self { paths: Vec::new() };
+        let Some(identity) = usb_identity(Path::new(SYSFS), master) else {
+            warn!(
+                "{:?} is not a USB device, no USB identity is written.",
+                master
+            );
+            return Ok(files);
+        };
+        for link in links {
+            let path = environment_path(link);
+            fs::write(&path, environment(&identity, link))?;
+            files.paths.push(path);
+        }
+        info!(
+            "The USB identity {}:{} of {:?} is in {:?}.",
+            identity.vendor_id, identity.model_id, master, files.paths
+        );
+        Ok(files)
+    }
+}
+
+impl Drop for IdentityFiles {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            fs::remove_file(path).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::{environment, usb_identity, UsbIdentity};
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+
+    #[test]
+    fn test_usb_identity() {
+        let sys = std::env::temp_dir().join("ttytee_identity_test");
+        fs::remove_dir_all(&sys).ok();
+        let usb_device = sys.join("devices/pci0000:00/usb1/1-1");
+        let interface = usb_device.join("1-1:1.0");
+        fs::create_dir_all(&interface).unwrap();
+        fs::create_dir_all(sys.join("class/tty/ttyACM0")).unwrap();
+        symlink(&interface, sys.join("class/tty/ttyACM0/device")).unwrap();
+        for (name, value) in [
+            ("idVendor", "1546\n"),
+            ("idProduct", "01a9\n"),
+            ("manufacturer", "u-blox AG - www.u-blox.com\n"),
+            ("product", "u-blox GNSS receiver\n"),
+        ] {
+            fs::write(usb_device.join(name), value).unwrap();
+        }
+        let identity = usb_identity(&sys, Path::new("/dev/ttyACM0")).unwrap();
+        assert_eq!(
+            identity,
+            UsbIdentity {
+                vendor_id: "1546".to_string(),
+                model_id: "01a9".to_string(),
+                vendor: Some("u-blox AG - www.u-blox.com".to_string()),
+                model: Some("u-blox GNSS receiver".to_string()),
+                serial: None,
+            }
+        );
+        assert_eq!(usb_identity(&sys, Path::new("/dev/ttyS0")), None);
+        fs::remove_dir_all(&sys).unwrap();
+    }
+
+    #[test]
+    fn test_environment() {
+        let identity = UsbIdentity {
+            vendor_id: "1546".to_string(),
+            model_id: "01a9".to_string(),
+            vendor: Some("u-blox AG - www.u-blox.com".to_string()),
+            model: None,
+            serial: Some("0123 456".to_string()),
+        };
+        assert_eq!(
+            environment(&identity, Path::new("/tmp/ttyS0")),
+            "DEVNAME=/tmp/ttyS0\nID_VENDOR_ID=1546\nID_MODEL_ID=01a9\nID_VENDOR=u-blox_AG_-_www.u-blox.com\nID_MODEL=01a9\nID_SERIAL=u-blox_AG_-_www.u-blox.com_01a9_0123_456\nID_SERIAL_SHORT=0123_456\n"
+        );
+    }
+}
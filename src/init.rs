@@ -0,0 +1,242 @@
+//! Configuration of the receiver at startup: `--init-commands FILE` sends commands to the master
+//! before the endpoints are created, so the consumers only ever see the configured output.
+//!
+//! The file has one step per line, the empty lines and the lines starting with `#` are skipped:
+//!
+//! * `send TEXT` writes TEXT with the escapes of the banners (`\r`, `\n`, `\xHH`...).
+//! * `send-hex HH HH ...` writes bytes given in hexadecimal, like a UBX message.
+//! * `expect TEXT` and `expect-hex HH HH ...` wait for the master to send these bytes, a failed
+//!   expectation stops ttytee.
+//! * `delay MS` waits MS milliseconds.
+//!
+//! What the master sends meanwhile is not given to the endpoints.
+
+use crate::banner::parse_banner;
+use log::info;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// How long an expected response may take.
+pub const EXPECT_TIMEOUT: Duration = Duration::from_secs(2);
+// The bytes kept while looking for a response.
+const MAX_RESPONSE_WINDOW: usize = 64 << 10;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InitStep {
+    Send(Vec<u8>),
+    Expect(Vec<u8>),
+    Delay(Duration),
+}
+
+/// The steps of an --init-commands file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InitCommands {
+    pub path: PathBuf,
+    pub steps: Vec<InitStep>,
+}
+
+fn parse_hex(value: &str) -> Result<Vec<u8>, String> {
+    let bytes: Result<Vec<u8>, _> = value
+        .split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect();
+    match bytes {
+        Ok(bytes) if !bytes.is_empty() => Ok(bytes),
+        _ => Err(format!("expected bytes in hexadecimal, got {:?}", value)),
+    }
+}
+
+/// Parse the steps of an --init-commands file.
+///
+/// # Arguments
+///
+/// * `text`: the content of the file.
+///
+/// returns: Result<Vec<InitStep>, String>
+///
+pub fn parse_init_steps(text: &str) -> Result<Vec<InitStep>, String> {
+    let mut steps = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (command, value) = line.split_once(' ').unwrap_or((line, ""));
+        let value = value.trim();
+        let step = match command {
+            "send" if !value.is_empty() => parse_banner(value).map(InitStep::Send),
+            "send-hex" => parse_hex(value).map(InitStep::Send),
+            "expect" if !value.is_empty() => parse_banner(value).map(InitStep::Expect),
+            "expect-hex" => parse_hex(value).map(InitStep::Expect),
+            "delay" => value
+                .parse()
+                .map(|ms| InitStep::Delay(Duration::from_millis(ms)))
+                .map_err(|_| format!("expected a delay in ms, got {:?}", value)),
+            _ => Err(format!("unknown step {:?}", line)),
+        };
+        steps.push(step.map_err(|err| format!("line {}: {}", number + 1, err))?);
+    }
+    Ok(steps)
+}
+
+/// Read an --init-commands file.
+pub fn read_init_commands(path: &str) -> Result<InitCommands, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    Ok(InitCommands {
+        path: PathBuf::from(path),
+        steps: parse_init_steps(&text).map_err(|err| format!("{}, {}", path, err))?,
+    })
+}
+
+// Read from the master until it sent the expected bytes.
+fn wait_for(master: &mut impl Read, expected: &[u8], timeout: Duration) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    let mut received = Vec::new();
+    let mut buffer = [0; 1024];
+    while Instant::now() < deadline {
+        match master.read(&mut buffer) {
+            Ok(len) => received.extend_from_slice(&buffer[..len]),
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => {}
+            Err(err) => return Err(err),
+        }
+        if received
+            .windows(expected.len())
+            .any(|window| window == expected)
+        {
+            return Ok(());
+        }
+        if received.len() > MAX_RESPONSE_WINDOW {
+            received.drain(..received.len() - expected.len());
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!(
+            "no {:?} from the master after {} ms",
+            String::from_utf8_lossy(expected),
+            timeout.as_millis()
+        ),
+    ))
+}
+
+/// Send the commands of an --init-commands file to the master.
+///
+/// # Arguments
+///
+/// * `master`: the master, it should have a read timeout shorter than `expect_timeout`.
+/// * `commands`: the steps to go through.
+/// * `expect_timeout`: how long an expected response may take.
+///
+/// returns: Result<(), Error>
+///
+pub fn run_init_commands(
+    master: &mut (impl Read + Write),
+    commands: &InitCommands,
+    expect_timeout: Duration,
+) -> io::Result<()> {
+    for step in &commands.steps {
+        match step {
+            InitStep::Send(data) => {
+                master.write_all(data)?;
+                master.flush()?;
+            }
+            InitStep::Expect(expected) => wait_for(master, expected, expect_timeout)?,
+            InitStep::Delay(delay) => std::thread::sleep(*delay),
+        }
+    }
+    info!(
+        "Sent the {} steps of {:?} to the master.",
+        commands.steps.len(),
+        commands.path
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::init::{parse_init_steps, run_init_commands, InitCommands, InitStep};
+    use std::io;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    // answers each write with the given response.
+    struct FakeReceiver {
+        written: Vec<u8>,
+        response: &'static [u8],
+        pending: Vec<u8>,
+    }
+
+    impl Write for FakeReceiver {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(data);
+            self.pending.extend_from_slice(self.response);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for FakeReceiver {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.pending.is_empty() {
+                std::thread::sleep(Duration::from_millis(10));
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let len = buffer.len().min(self.pending.len());
+            buffer[..len].copy_from_slice(&self.pending[..len]);
+            self.pending.drain(..len);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_parse_init_steps() {
+        let steps = parse_init_steps(
+            "# disable the GSV\nsend $PUBX,40,GSV,0,0,0,0*59\\r\\n\n\nsend-hex B5 62 06 08\ndelay 200\nexpect-hex b5 62 05 01\n",
+        )
+        .unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                InitStep::Send(b"$PUBX,40,GSV,0,0,0,0*59\r\n".to_vec()),
+                InitStep::Send(vec![0xB5, 0x62, 0x06, 0x08]),
+                InitStep::Delay(Duration::from_millis(200)),
+                InitStep::Expect(vec![0xB5, 0x62, 0x05, 0x01]),
+            ]
+        );
+        assert_eq!(
+            parse_init_steps("send $PMTK\ndelay soon").unwrap_err(),
+            "line 2: expected a delay in ms, got \"soon\""
+        );
+        assert!(parse_init_steps("send-hex B5 6").is_ok());
+        assert!(parse_init_steps("send-hex").is_err());
+        assert!(parse_init_steps("expect").is_err());
+        assert!(parse_init_steps("reset").is_err());
+    }
+
+    #[test]
+    fn test_run_init_commands() {
+        let commands = InitCommands {
+            path: PathBuf::from("init.txt"),
+            steps: parse_init_steps("send $PMTK220,200*2C\\r\\n\nexpect $PMTK001,220,3").unwrap(),
+        };
+        let mut receiver = FakeReceiver {
+            written: Vec::new(),
+            response: b"$GPGGA,,*56\r\n$PMTK001,220,3*30\r\n",
+            pending: Vec::new(),
+        };
+        run_init_commands(&mut receiver, &commands, Duration::from_secs(1)).unwrap();
+        assert_eq!(receiver.written, b"$PMTK220,200*2C\r\n");
+
+        receiver.response = b"$PMTK001,220,2*31\r\n";
+        let err =
+            run_init_commands(&mut receiver, &commands, Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
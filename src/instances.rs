@@ -0,0 +1,153 @@
+//! Detection of the other ttytee instances of the host, to refuse the configurations connecting
+//! them in a loop: each instance would send back what it receives and the data would grow without
+//! bounds.
+//!
+//! The instances are found through /proc like the consumers of the slaves: a process named ttytee
+//! that has a PTY open is the one writing it unless it reads it as its master, which its command
+//! line tells.
+
+use crate::consumers::consumer_pids;
+use crate::Args;
+use clap::Parser;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+// The command line of a process, the arguments are separated by NUL bytes.
+fn cmdline(pid: u32) -> Option<Vec<u8>> {
+    fs::read(format!("/proc/{}/cmdline", pid)).ok()
+}
+
+/// The master read by an instance, from its command line.
+///
+/// # Arguments
+///
+/// * `cmdline`: the content of /proc/PID/cmdline.
+/// * `cwd`: the working directory of the process, for the relative paths.
+///
+/// returns: Option<PathBuf> None if it is not ttytee.
+///
+fn master_of(cmdline: &[u8], cwd: &Path) -> Option<PathBuf> {
+    let args: Vec<&OsStr> = cmdline
+        .split(|&byte| byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(OsStr::from_bytes)
+        .collect();
+    if Path::new(args.first()?).file_name()? != "ttytee" {
+        return None;
+    }
+    let args = Args::try_parse_from(args).ok()?;
+    Some(cwd.join(args.master))
+}
+
+/// The other ttytee instances writing to a device, ie. the device is one of their slaves.
+///
+/// # Arguments
+///
+/// * `device`: the real path of a device.
+///
+/// returns: Vec<(u32, PathBuf)> the pid and the master of each instance.
+///
+pub fn writers_of(device: &Path) -> Vec<(u32, PathBuf)> {
+    let mut writers: Vec<(u32, PathBuf)> = consumer_pids(&[device.to_path_buf()])
+        .into_iter()
+        .filter_map(|pid| {
+            let cwd = fs::read_link(format!("/proc/{}/cwd", pid)).ok()?;
+            Some((pid, master_of(&cmdline(pid)?, &cwd)?))
+        })
+        // the instances reading it.
+        .filter(|(_, master)| master.canonicalize().ok().as_deref() != Some(device))
+        .collect();
+    writers.sort();
+    writers
+}
+
+// The same link, whatever the relative paths and the symlinks of the directories.
+fn same_link(a: &Path, b: &Path) -> bool {
+    let dir = |path: &Path| path.parent().and_then(|parent| parent.canonicalize().ok());
+    a.file_name() == b.file_name() && dir(a).is_some() && dir(a) == dir(b)
+}
+
+/// Follow the instances feeding a master, up to one reading a link of this instance.
+///
+/// # Arguments
+///
+/// * `master`: the master of this instance.
+/// * `links`: the PTY links this instance will create.
+///
+/// returns: Vec<u32> the pids of the instances in the loop from the one writing the master, empty
+/// if there is no loop.
+///
+pub fn find_loop(master: &Path, links: &[PathBuf]) -> Vec<u32> {
+    let mut visited = HashSet::new();
+    let mut chain = Vec::new();
+    follow(master, links, &mut visited, &mut chain);
+    chain
+}
+
+fn follow(
+    master: &Path,
+    links: &[PathBuf],
+    visited: &mut HashSet<u32>,
+    chain: &mut Vec<u32>,
+) -> bool {
+    let Ok(device) = master.canonicalize() else {
+        return false;
+    };
+    for (pid, upstream) in writers_of(&device) {
+        if !visited.insert(pid) {
+            continue;
+        }
+        chain.push(pid);
+        if links.iter().any(|link| same_link(link, &upstream))
+            || follow(&upstream, links, visited, chain)
+        {
+            return true;
+        }
+        chain.pop();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instances::{master_of, same_link};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_master_of() {
+        let cwd = Path::new("/run/gps");
+        assert_eq!(
+            master_of(b"/usr/bin/ttytee\0--master\0front.pty\0--slave0\0a\0", cwd),
+            Some(PathBuf::from("/run/gps/front.pty"))
+        );
+        assert_eq!(
+            master_of(b"ttytee\0-m\0/dev/ttyACM0\0", cwd),
+            Some(PathBuf::from("/dev/ttyACM0"))
+        );
+        assert_eq!(
+            master_of(b"ttytee\0", cwd),
+            Some(PathBuf::from("/dev/ttyUSB0"))
+        );
+        assert_eq!(master_of(b"gpsd\0-N\0/dev/pts/3\0", cwd), None);
+        assert_eq!(master_of(b"ttytee\0--no-such-option\0", cwd), None);
+    }
+
+    #[test]
+    fn test_same_link() {
+        assert!(same_link(
+            Path::new("/tmp/slave0.pty"),
+            Path::new("/tmp/../tmp/./slave0.pty")
+        ));
+        assert!(!same_link(
+            Path::new("/tmp/slave0.pty"),
+            Path::new("/tmp/slave1.pty")
+        ));
+        assert!(!same_link(
+            Path::new("/nonexistent/slave0.pty"),
+            Path::new("/nonexistent/slave0.pty")
+        ));
+    }
+}
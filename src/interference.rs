@@ -0,0 +1,192 @@
+//! Detection of the usual interferers of the master: ModemManager probing the serial ports for
+//! modems and the login prompts of serial-getty. Both write to the port and read from it behind
+//! ttytee, the receiver gets garbage and the consumers miss data.
+//!
+//! At startup the services that could take the port are reported, then the other processes
+//! holding the master are looked for periodically. Each one is logged once with what to do about it.
+
+use crate::consumers::{consumer_pids, process_name};
+use crate::events::Event;
+use log::warn;
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// How often the processes holding the master are looked for.
+const CHECK_PERIOD: Duration = Duration::from_secs(30);
+const GETTY_WANTS: &str = "/etc/systemd/system/getty.target.wants";
+const UDEV_DATA: &str = "/run/udev/data";
+
+fn is_getty(name: &str) -> bool {
+    matches!(name, "agetty" | "getty" | "mgetty" | "mingetty" | "login")
+}
+
+/// What to do about a process holding the master.
+///
+/// # Arguments
+///
+/// * `name`: the name of the process.
+/// * `device`: the master.
+///
+/// returns: String
+///
+pub fn guidance(name: &str, device: &Path) -> String {
+    let tty = device.file_name().unwrap_or_default().to_string_lossy();
+    match name {
+        "ModemManager" => "tell ModemManager to ignore it with the udev rule \
+                           ENV{ID_MM_DEVICE_IGNORE}=\"1\" or stop it with \
+                           `systemctl disable --now ModemManager`"
+            .to_string(),
+        name if is_getty(name) => format!(
+            "a login prompt runs on it, stop it with `systemctl disable --now serial-getty@{}.service`",
+            tty
+        ),
+        _ => "it takes the data of ttytee, make it read a slave instead".to_string(),
+    }
+}
+
+fn find_process(name: &str) -> Option<u32> {
+    fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .find(|&pid| process_name(pid) == name)
+}
+
+// Whether udev tells ModemManager to leave the device alone.
+fn ignored_by_modem_manager(device: &Path) -> bool {
+    let Ok(metadata) = fs::metadata(device) else {
+        return false;
+    };
+    let rdev = metadata.rdev();
+    let data = Path::new(UDEV_DATA).join(format!("c{}:{}", libc::major(rdev), libc::minor(rdev)));
+    fs::read_to_string(data).is_ok_and(|data| {
+        data.lines()
+            .any(|line| line == "E:ID_MM_DEVICE_IGNORE=1" || line == "E:ID_MM_PORT_IGNORE=1")
+    })
+}
+
+/// Looks for the processes interfering with the master.
+pub struct InterferenceMonitor {
+    device: PathBuf,
+    last_check: Option<Instant>,
+    // the interferers already reported.
+    known: HashSet<u32>,
+}
+
+impl InterferenceMonitor {
+    /// Watch the master, None if it is not a local device.
+    pub fn new(master: &Path) -> Option<Self> {
+        let device = master.canonicalize().ok()?;
+        if !fs::metadata(&device).ok()?.file_type().is_char_device() {
+            return None;
+        }
+        Some(Self {
+            device,
+            last_check: None,
+            known: HashSet::new(),
+        })
+    }
+
+    /// Warn about the services that could take the master.
+    pub fn check_services(&self) {
+        if find_process("ModemManager").is_some() && !ignored_by_modem_manager(&self.device) {
+            warn!(
+                event = Event::MasterInterference.code();
+                "ModemManager is running and may probe the master {:?}: {}.",
+                self.device,
+                guidance("ModemManager", &self.device)
+            );
+        }
+        let tty = self
+            .device
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let getty = Path::new(GETTY_WANTS).join(format!("serial-getty@{}.service", tty));
+        if getty.exists() {
+            warn!(
+                event = Event::MasterInterference.code();
+                "A login prompt is enabled on the master {:?}: {}.",
+                self.device,
+                guidance("agetty", &self.device)
+            );
+        }
+    }
+
+    /// Look for the other processes holding the master if it is time to.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: the current time.
+    ///
+    /// returns: Vec<(u32, String)> the pids and names of the new interferers, they are logged.
+    ///
+    pub fn poll(&mut self, now: Instant) -> Vec<(u32, String)> {
+        if matches!(self.last_check, Some(last) if now.duration_since(last) < CHECK_PERIOD) {
+            return Vec::new();
+        }
+        self.last_check = Some(now);
+        let pids = consumer_pids(std::slice::from_ref(&self.device));
+        let mut new = Vec::new();
+        for &pid in pids.difference(&self.known) {
+            let name = process_name(pid);
+            warn!(
+                event = Event::MasterInterference.code();
+                "{} (pid {}) has the master {:?} open too: {}.",
+                name,
+                pid,
+                self.device,
+                guidance(&name, &self.device)
+            );
+            new.push((pid, name));
+        }
+        self.known = pids;
+        new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interference::{guidance, InterferenceMonitor};
+    use serialport::{SerialPort, TTYPort};
+    use std::fs::File;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use std::time::Instant;
+
+    #[test]
+    fn test_guidance() {
+        assert_eq!(
+            guidance("agetty", Path::new("/dev/ttyS1")),
+            "a login prompt runs on it, stop it with `systemctl disable --now serial-getty@ttyS1.service`"
+        );
+        assert!(guidance("ModemManager", Path::new("/dev/ttyACM0")).contains("ID_MM_DEVICE_IGNORE"));
+    }
+
+    #[test]
+    fn test_interferer_detected() {
+        assert!(InterferenceMonitor::new(Path::new("ssh://bench:/dev/ttyACM0")).is_none());
+        assert!(InterferenceMonitor::new(Path::new("/tmp")).is_none());
+
+        let (_master, device) = TTYPort::pair().unwrap();
+        let device = device.name().unwrap();
+        let mut monitor = InterferenceMonitor::new(Path::new(&device)).unwrap();
+        assert!(monitor.poll(Instant::now()).is_empty());
+        let mut interferer = Command::new("sleep")
+            .arg("5")
+            .stdin(Stdio::from(File::open(&device).unwrap()))
+            .spawn()
+            .unwrap();
+        monitor.last_check = None;
+        let found = monitor.poll(Instant::now());
+        interferer.kill().unwrap();
+        interferer.wait().unwrap();
+        assert_eq!(found, vec![(interferer.id(), "sleep".to_string())]);
+        // reported once.
+        monitor.last_check = None;
+        assert!(monitor.poll(Instant::now()).is_empty());
+    }
+}
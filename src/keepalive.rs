@@ -0,0 +1,272 @@
+//! Keepalive data for the consumers with short internal timeouts, so they don't declare the device
+//! dead while the master is briefly gone, like during a USB re-enumeration: with the
+//! `keepalive=MS` endpoint option, once the master has been silent for that long the last GGA it
+//! sent is repeated every MS milliseconds with its fix quality set to 0 (invalid), until data comes
+//! again. With `keepalive=MS,BYTES` the given bytes are written instead, with the escapes of the
+//! banners, for example `keepalive=500,\r\n`.
+//!
+//! Only the endpoints in the raw format get keepalive data, nothing is sent before the master has
+//! sent anything.
+
+use crate::banner::parse_banner;
+use crate::endpoint::format::OutputFormat;
+use crate::endpoint::ManagedEndpoint;
+use crate::framing::Frame;
+use crate::nmea::{nmea_fields, nmea_sentence};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// The index of the fix quality in the fields of a GGA.
+const GGA_QUALITY: usize = 6;
+
+/// The `keepalive` endpoint option.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keepalive {
+    // how long the master is silent before the first keepalive, and between two of them.
+    pub interval: Duration,
+    // the bytes written, None to repeat the last GGA marked invalid.
+    pub idle: Option<Vec<u8>>,
+}
+
+impl FromStr for Keepalive {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = |err: &dyn fmt::Display| format!("invalid keepalive {:?}: {}", value, err);
+        let (interval, idle) = match value.split_once(',') {
+            Some((interval, idle)) => (
+                interval,
+                Some(parse_banner(idle).map_err(|err| invalid(&err))?),
+            ),
+            None => (value, None),
+        };
+        let interval = interval
+            .parse()
+            .ok()
+            .filter(|&ms| ms > 0)
+            .ok_or_else(|| invalid(&"expected <MS>[,<BYTES>]"))?;
+        if idle.as_ref().is_some_and(Vec::is_empty) {
+            return Err(invalid(&"the bytes are empty"));
+        }
+        Ok(Self {
+            interval: Duration::from_millis(interval),
+            idle,
+        })
+    }
+}
+
+impl fmt::Display for Keepalive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.interval.as_millis())?;
+        if let Some(idle) = &self.idle {
+            write!(f, ",{}", idle.escape_ascii())?;
+        }
+        Ok(())
+    }
+}
+
+/// A GGA with its fix quality set to 0, the fix is invalid.
+fn invalid_gga(frame: &Frame) -> Option<Vec<u8>> {
+    let mut fields = nmea_fields(&frame.data)?;
+    if fields.len() <= GGA_QUALITY {
+        return None;
+    }
+    fields[GGA_QUALITY] = "0";
+    Some(nmea_sentence(&fields))
+}
+
+/// Writes the keepalive data to the endpoints with a `keepalive` option while the master is silent.
+#[derive(Default)]
+pub struct Keepalives {
+    last_gga: Option<Vec<u8>>,
+    // when each endpoint was last sent keepalive data, during the current silence.
+    sent_at: HashMap<String, Instant>,
+}
+
+impl Keepalives {
+    /// Remember the last GGA of a read of the master.
+    pub fn observe(&mut self, frames: &[Frame]) {
+        if let Some(gga) = frames
+            .iter()
+            .rev()
+            .find(|frame| frame.message_type() == "GGA")
+            .and_then(invalid_gga)
+        {
+            self.last_gga = Some(gga);
+        }
+    }
+
+    // When the next keepalive of an endpoint is due and its data, None if it gets none.
+    fn next_for<'a>(
+        &'a self,
+        endpoint: &'a ManagedEndpoint,
+        last_master_data: Instant,
+    ) -> Option<(Instant, &'a [u8])> {
+        let keepalive = endpoint.options.keepalive.as_ref()?;
+        if endpoint.options.format != OutputFormat::Raw || endpoint.is_paused() {
+            return None;
+        }
+        let data = keepalive.idle.as_deref().or(self.last_gga.as_deref())?;
+        let since = match self.sent_at.get(&endpoint.name) {
+            Some(&sent_at) if sent_at > last_master_data => sent_at,
+            _ => last_master_data,
+        };
+        Some((since + keepalive.interval, data))
+    }
+
+    /// When the next keepalive is due, for the loop to wake up then.
+    ///
+    /// # Arguments
+    ///
+    /// * `last_master_data`: when the master last sent data, None if it never did.
+    /// * `endpoints`: all the endpoints.
+    ///
+    /// returns: Option<Instant> None if no endpoint will need one.
+    ///
+    pub fn next_due(
+        &self,
+        last_master_data: Option<Instant>,
+        endpoints: &[ManagedEndpoint],
+    ) -> Option<Instant> {
+        let last_master_data = last_master_data?;
+        endpoints
+            .iter()
+            .filter_map(|endpoint| Some(self.next_for(endpoint, last_master_data)?.0))
+            .min()
+    }
+
+    /// Send the keepalive data that is due.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: the current time.
+    /// * `last_master_data`: when the master last sent data, None if it never did.
+    /// * `endpoints`: all the endpoints, only the raw ones with a `keepalive` option get data.
+    ///
+    pub fn poll(
+        &mut self,
+        now: Instant,
+        last_master_data: Option<Instant>,
+        endpoints: &mut [ManagedEndpoint],
+    ) {
+        let Some(last_master_data) = last_master_data else {
+            return;
+        };
+        for endpoint in endpoints.iter_mut() {
+            let Some((due, data)) = self.next_for(endpoint, last_master_data) else {
+                continue;
+            };
+            if now < due {
+                continue;
+            }
+            let data = data.to_vec();
+            // the first keepalive of this silence.
+            if !matches!(self.sent_at.get(&endpoint.name), Some(&sent_at) if sent_at > last_master_data)
+            {
+                info!(
+                    "The master is silent, sending keepalive data to {}.",
+                    endpoint.name
+                );
+            }
+            self.sent_at.insert(endpoint.name.clone(), now);
+            if let Err(err) = endpoint.inject(&data) {
+                warn!(
+                    "Could not write the keepalive data to {}: {}.",
+                    endpoint.name, err
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::endpoint::file::FileEndpoint;
+    use crate::endpoint::{EndpointOptions, ManagedEndpoint};
+    use crate::framing::{Frame, Protocol};
+    use crate::keepalive::{Keepalive, Keepalives};
+    use crate::nmea::nmea_sentence;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_keepalive() {
+        let keepalive: Keepalive = r"500,\r\n".parse().unwrap();
+        assert_eq!(keepalive.interval, Duration::from_millis(500));
+        assert_eq!(keepalive.idle.as_deref(), Some(&b"\r\n"[..]));
+        assert_eq!(keepalive.to_string(), r"500,\r\n");
+        assert_eq!("1000".parse::<Keepalive>().unwrap().idle, None);
+        assert!("0".parse::<Keepalive>().is_err());
+        assert!("500,".parse::<Keepalive>().is_err());
+    }
+
+    #[test]
+    fn test_keepalive_gga() {
+        let path = PathBuf::from("/tmp/ttytee_keepalive_test.nmea");
+        let _ = std::fs::remove_file(&path);
+        let mut endpoints = vec![ManagedEndpoint::new(
+            "logger",
+            Box::new(FileEndpoint::open(&path).unwrap()),
+            EndpointOptions {
+                keepalive: Some("1000".parse().unwrap()),
+                ..Default::default()
+            },
+            Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+        )];
+        let gga = |quality| {
+            nmea_sentence(&[
+                "GPGGA",
+                "120000.00",
+                "4807.038",
+                "N",
+                "01131.000",
+                "E",
+                quality,
+                "08",
+                "0.9",
+                "545.4",
+                "M",
+                "46.9",
+                "M",
+                "",
+                "",
+            ])
+        };
+        let mut keepalives = Keepalives::default();
+        keepalives.observe(&[Frame {
+            protocol: Protocol::Nmea,
+            data: gga("1"),
+        }]);
+        let start = Instant::now();
+        assert_eq!(
+            keepalives.next_due(Some(start), &endpoints),
+            Some(start + Duration::from_millis(1000))
+        );
+        keepalives.poll(
+            start + Duration::from_millis(500),
+            Some(start),
+            &mut endpoints,
+        );
+        keepalives.poll(
+            start + Duration::from_millis(1000),
+            Some(start),
+            &mut endpoints,
+        );
+        keepalives.poll(
+            start + Duration::from_millis(1500),
+            Some(start),
+            &mut endpoints,
+        );
+        keepalives.poll(
+            start + Duration::from_millis(2000),
+            Some(start),
+            &mut endpoints,
+        );
+        drop(endpoints);
+        assert_eq!(std::fs::read(&path).unwrap(), [gga("0"), gga("0")].concat());
+    }
+}
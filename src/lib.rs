@@ -0,0 +1,5 @@
+//! The parts of ttytee usable on their own, for the property tests and the fuzz targets.
+
+pub mod framing;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
@@ -0,0 +1,214 @@
+//! The states of the master, for the monitoring: the reader thread moves the master from one to the
+//! other, each transition is logged once and the last ones are kept for the `master` command of the
+//! control socket, the current state is in the stats.
+//!
+//! * `opening`: the master is open, nothing was read from it yet.
+//! * `streaming`: data is coming.
+//! * `stalled`: the master has sent nothing for 5 s, the reads time out without error.
+//! * `reconnecting`: the reads fail, they are tried again after a growing delay.
+//! * `failed`: the reads failed 10 times in a row, they are still tried at the longest delay.
+
+use crate::events::Event;
+use log::{error, info, warn};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// How long the master can send nothing before it is stalled.
+pub const STALL_AFTER: Duration = Duration::from_secs(5);
+// How many consecutive errors fail the master.
+pub const FAILED_AFTER: u32 = 10;
+// How many transitions are kept.
+const HISTORY: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MasterState {
+    Opening,
+    Streaming,
+    Stalled,
+    Reconnecting,
+    Failed,
+}
+
+impl MasterState {
+    /// The event logged when the master enters the state.
+    pub fn event(self) -> Event {
+        match self {
+            Self::Opening => Event::MasterOpening,
+            Self::Streaming => Event::MasterStreaming,
+            Self::Stalled => Event::MasterStalled,
+            Self::Reconnecting => Event::MasterReconnecting,
+            Self::Failed => Event::MasterFailed,
+        }
+    }
+}
+
+impl fmt::Display for MasterState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Opening => write!(f, "opening"),
+            Self::Streaming => write!(f, "streaming"),
+            Self::Stalled => write!(f, "stalled"),
+            Self::Reconnecting => write!(f, "reconnecting"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// A change of state, with when and why it happened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transition {
+    pub state: MasterState,
+    // in seconds since the epoch.
+    pub at: f64,
+    pub reason: String,
+}
+
+/// The state of the master and its last transitions.
+pub struct MasterLifecycle {
+    state: MasterState,
+    since: Instant,
+    history: VecDeque<Transition>,
+    // the ends of file read from the master.
+    eofs: u64,
+    // the reads that timed out, failed or were interrupted.
+    timeouts: u64,
+    read_errors: u64,
+    interruptions: u64,
+}
+
+impl MasterLifecycle {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            state: MasterState::Opening,
+            since: now,
+            history: VecDeque::new(),
+            eofs: 0,
+            timeouts: 0,
+            read_errors: 0,
+            interruptions: 0,
+        }
+    }
+
+    pub fn state(&self) -> MasterState {
+        self.state
+    }
+
+    /// Account for an end of file of the master.
+    pub fn count_eof(&mut self) {
+        self.eofs += 1;
+    }
+
+    pub fn eofs(&self) -> u64 {
+        self.eofs
+    }
+
+    /// Account for a read of the master that timed out, it was silent.
+    pub fn count_timeout(&mut self) {
+        self.timeouts += 1;
+    }
+
+    /// Account for a read of the master that failed, not a timeout or an end of file.
+    pub fn count_read_error(&mut self) {
+        self.read_errors += 1;
+    }
+
+    /// Account for a read of the master interrupted by a signal, made again.
+    pub fn count_interruption(&mut self) {
+        self.interruptions += 1;
+    }
+
+    /// The reads that timed out, failed and were interrupted.
+    pub fn read_outcomes(&self) -> (u64, u64, u64) {
+        (self.timeouts, self.read_errors, self.interruptions)
+    }
+
+    /// Move the master to a state, nothing happens if it is in it already.
+    ///
+    /// # Arguments
+    ///
+    /// * `state`: the new state.
+    /// * `reason`: why, for the log and the history.
+    /// * `now`: the current time.
+    ///
+    pub fn transition(&mut self, state: MasterState, reason: &str, now: Instant) {
+        if state == self.state {
+            return;
+        }
+        let code = state.event().code();
+        match state {
+            MasterState::Opening | MasterState::Streaming => {
+                info!(event = code; "The master is {}: {}.", state, reason)
+            }
+            MasterState::Stalled | MasterState::Reconnecting => {
+                warn!(event = code; "The master is {}: {}.", state, reason)
+            }
+            MasterState::Failed => error!(event = code; "The master is {}: {}.", state, reason),
+        }
+        self.state = state;
+        self.since = now;
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since_epoch| since_epoch.as_secs_f64());
+        self.history.push_back(Transition {
+            state,
+            at,
+            reason: reason.to_string(),
+        });
+        while self.history.len() > HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    /// The state, for how long, and the last transitions, the records separated by `;`, for the
+    /// `master` command of the control socket.
+    pub fn describe(&self, now: Instant) -> String {
+        let mut text = format!(
+            "state={} for={:.1}",
+            self.state,
+            now.saturating_duration_since(self.since).as_secs_f64()
+        );
+        for transition in &self.history {
+            write!(
+                text,
+                ";{:.3} {}: {}",
+                transition.at, transition.state, transition.reason
+            )
+            .unwrap();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lifecycle::{MasterLifecycle, MasterState, HISTORY};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_transitions() {
+        let start = Instant::now();
+        let mut lifecycle = MasterLifecycle::new(start);
+        assert_eq!(lifecycle.state(), MasterState::Opening);
+        lifecycle.transition(MasterState::Streaming, "data received", start);
+        lifecycle.transition(MasterState::Streaming, "data received", start);
+        lifecycle.transition(
+            MasterState::Reconnecting,
+            "Input/output error",
+            start + Duration::from_secs(1),
+        );
+        let description = lifecycle.describe(start + Duration::from_millis(3500));
+        let records: Vec<&str> = description.split(';').collect();
+        assert_eq!(records[0], "state=reconnecting for=2.5");
+        assert_eq!(records.len(), 3);
+        assert!(records[1].ends_with(" streaming: data received"));
+        assert!(records[2].ends_with(" reconnecting: Input/output error"));
+
+        for _ in 0..HISTORY {
+            lifecycle.transition(MasterState::Stalled, "no data", start);
+            lifecycle.transition(MasterState::Streaming, "data received", start);
+        }
+        assert_eq!(lifecycle.history.len(), HISTORY);
+    }
+}
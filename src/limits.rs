@@ -0,0 +1,208 @@
+//! Self limits on the memory and the file descriptors, so ttytee degrades deterministically
+//! instead of getting killed by the OOM killer along with the autopilot.
+//!
+//! When a limit is exceeded, the backlogs of all the endpoints are dropped first. If it is still
+//! exceeded at the next check, the endpoints are disabled one at a time, the last configured first
+//! so slave0 and slave1 go last.
+
+use crate::endpoint::ManagedEndpoint;
+use crate::events::Event;
+use log::{error, warn};
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+// How often the usage is checked.
+const CHECK_PERIOD: Duration = Duration::from_secs(1);
+
+/// The resources used by the process.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Usage {
+    // resident memory in bytes.
+    pub memory: usize,
+    pub fds: usize,
+}
+
+/// Read the current usage from /proc.
+pub fn current_usage() -> io::Result<Usage> {
+    let statm = fs::read_to_string("/proc/self/statm")?;
+    let resident_pages: usize = statm
+        .split_whitespace()
+        .nth(1)
+        .and_then(|pages| pages.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unexpected statm format"))?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    Ok(Usage {
+        memory: resident_pages * page_size,
+        fds: fs::read_dir("/proc/self/fd")?.count(),
+    })
+}
+
+/// Checks the limits periodically and degrades the endpoints when they are exceeded.
+pub struct ResourceLimits {
+    max_memory: Option<usize>,
+    max_fds: Option<usize>,
+    last_check: Option<Instant>,
+    // the backlogs were dropped at the previous check and it was not enough.
+    backlogs_dropped: bool,
+}
+
+impl ResourceLimits {
+    /// Create the limits.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_memory`: the maximum resident memory in bytes, None for no limit.
+    /// * `max_fds`: the maximum number of open file descriptors, None for no limit.
+    ///
+    /// returns: ResourceLimits
+    ///
+    pub fn new(max_memory: Option<usize>, max_fds: Option<usize>) -> Self {
+        Self {
+            max_memory,
+            max_fds,
+            last_check: None,
+            backlogs_dropped: false,
+        }
+    }
+
+    fn exceeded(&self, usage: &Usage) -> Option<String> {
+        match (self.max_memory, self.max_fds) {
+            (Some(max_memory), _) if usage.memory > max_memory => Some(format!(
+                "{} MB of memory used, the limit is {} MB",
+                usage.memory >> 20,
+                max_memory >> 20
+            )),
+            (_, Some(max_fds)) if usage.fds > max_fds => Some(format!(
+                "{} file descriptors open, the limit is {}",
+                usage.fds, max_fds
+            )),
+            _ => None,
+        }
+    }
+
+    /// Check the usage if it is time to, and degrade the endpoints if needed.
+    pub fn poll(&mut self, now: Instant, endpoints: &mut [ManagedEndpoint]) {
+        if self.max_memory.is_none() && self.max_fds.is_none() {
+            return;
+        }
+        if matches!(self.last_check, Some(last_check) if now.duration_since(last_check) < CHECK_PERIOD)
+        {
+            return;
+        }
+        self.last_check = Some(now);
+        match current_usage() {
+            Ok(usage) => self.enforce(&usage, endpoints),
+            Err(err) => warn!("Could not read the resource usage: {}.", err),
+        }
+    }
+
+    fn enforce(&mut self, usage: &Usage, endpoints: &mut [ManagedEndpoint]) {
+        let Some(reason) = self.exceeded(usage) else {
+            self.backlogs_dropped = false;
+            return;
+        };
+        if !self.backlogs_dropped {
+            error!(
+                event = Event::MemoryLimit.code();
+                "{}, dropping the backlogs of all the endpoints.",
+                reason
+            );
+            for endpoint in endpoints.iter_mut() {
+                if let Err(err) = endpoint.discard() {
+                    warn!("Could not drop the backlog of {}: {}.", endpoint.name, err);
+                }
+            }
+            self.backlogs_dropped = true;
+            return;
+        }
+        match endpoints
+            .iter_mut()
+            .rev()
+            .find(|endpoint| !endpoint.health.is_disabled())
+        {
+            Some(endpoint) => {
+                error!(
+                    event = Event::MemoryLimit.code();
+                    "{}, disabling {}.",
+                    reason, endpoint.name
+                );
+                endpoint.discard().ok();
+                endpoint.health.disable();
+            }
+            None => {
+                error!(
+                    event = Event::MemoryLimit.code();
+                    "{}, all the endpoints are disabled already.",
+                    reason
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::endpoint::stdout::StdoutEndpoint;
+    use crate::endpoint::{EndpointOptions, ManagedEndpoint};
+    use crate::limits::{current_usage, ResourceLimits, Usage};
+    use std::time::Duration;
+
+    fn endpoint(name: &str) -> ManagedEndpoint {
+        ManagedEndpoint::new(
+            name,
+            Box::new(StdoutEndpoint),
+            EndpointOptions::default(),
+            Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+        )
+    }
+
+    #[test]
+    fn test_current_usage() {
+        let usage = current_usage().unwrap();
+        assert!(usage.memory > 0);
+        // at least stdin, stdout and stderr.
+        assert!(usage.fds >= 3);
+    }
+
+    #[test]
+    fn test_degradation_order() {
+        let mut endpoints = vec![endpoint("slave0"), endpoint("slave1"), endpoint("net")];
+        let mut limits = ResourceLimits::new(Some(10 << 20), Some(100));
+        let disabled = |endpoints: &[ManagedEndpoint]| -> Vec<String> {
+            endpoints
+                .iter()
+                .filter(|endpoint| endpoint.health.is_disabled())
+                .map(|endpoint| endpoint.name.clone())
+                .collect()
+        };
+        let over = Usage {
+            memory: 20 << 20,
+            fds: 10,
+        };
+        // the backlogs go first.
+        limits.enforce(&over, &mut endpoints);
+        assert!(disabled(&endpoints).is_empty());
+        limits.enforce(&over, &mut endpoints);
+        assert_eq!(disabled(&endpoints), vec!["net"]);
+        limits.enforce(
+            &Usage {
+                memory: 1 << 20,
+                fds: 200,
+            },
+            &mut endpoints,
+        );
+        assert_eq!(disabled(&endpoints), vec!["slave1", "net"]);
+        // back under the limits, the next time starts with the backlogs again.
+        limits.enforce(
+            &Usage {
+                memory: 1 << 20,
+                fds: 10,
+            },
+            &mut endpoints,
+        );
+        limits.enforce(&over, &mut endpoints);
+        assert_eq!(disabled(&endpoints).len(), 2);
+    }
+}
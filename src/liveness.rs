@@ -0,0 +1,212 @@
+//! Liveness of the endpoints, so a consumer that is gone for good doesn't keep a buffer filling
+//! up and the logs full of its errors: with the `dead-after=SECONDS` endpoint option, the
+//! endpoint is scored on its drain rate (the bytes its consumer reads per second) and its error
+//! rate, and paused when its consumer has read nothing for that long. It is enabled again, from
+//! the next read (or frame), as soon as a new consumer opens it: another process on a PTY, another
+//! client on a socket. Resuming it from the control socket enables it again as well.
+
+use crate::endpoint::ManagedEndpoint;
+use crate::events::Event;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+// How often the endpoints are scored and their consumers looked for.
+const CHECK_PERIOD: Duration = Duration::from_secs(1);
+
+/// The rates of an endpoint over the last check period.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Score {
+    // the bytes read by the consumer per second.
+    drain_rate: f64,
+    // the write errors per second.
+    error_rate: f64,
+}
+
+// What is known of an endpoint at the last check.
+struct Sample {
+    consumed: u64,
+    errors: u64,
+    drained_at: Option<Instant>,
+    // since when the endpoint is given a chance: the start or its last enabling.
+    alive_since: Instant,
+}
+
+// An endpoint paused because it was dead, with the consumers it had then.
+struct Dead {
+    consumers: HashSet<String>,
+}
+
+/// Scores the endpoints with a `dead-after` option, pauses the dead ones and resumes them when a
+/// consumer comes back.
+pub struct Liveness {
+    framed: bool,
+    last_check: Option<Instant>,
+    samples: HashMap<String, Sample>,
+    scores: HashMap<String, Score>,
+    dead: HashMap<String, Dead>,
+}
+
+impl Liveness {
+    pub fn new(framed: bool) -> Self {
+        Self {
+            framed,
+            last_check: None,
+            samples: HashMap::new(),
+            scores: HashMap::new(),
+            dead: HashMap::new(),
+        }
+    }
+
+    /// Score the endpoints if it is time to, pause the dead ones and resume the ones a consumer
+    /// opened again.
+    pub fn poll(&mut self, now: Instant, endpoints: &mut [ManagedEndpoint]) {
+        if matches!(self.last_check, Some(last_check) if now.duration_since(last_check) < CHECK_PERIOD)
+        {
+            return;
+        }
+        self.last_check = Some(now);
+        for endpoint in endpoints.iter_mut() {
+            let Some(dead_after) = endpoint.options.dead_after else {
+                continue;
+            };
+            if self.dead.contains_key(&endpoint.name) {
+                self.revive(endpoint, now);
+            } else {
+                self.check(endpoint, dead_after, now);
+            }
+        }
+    }
+
+    fn check(&mut self, endpoint: &mut ManagedEndpoint, dead_after: Duration, now: Instant) {
+        let sample = self
+            .samples
+            .entry(endpoint.name.clone())
+            .or_insert_with(|| Sample {
+                consumed: endpoint.consumed(),
+                errors: endpoint.health.errors(),
+                drained_at: endpoint.drained_at(),
+                alive_since: now,
+            });
+        // paused from the control socket or by the banners, it is not expected to be read.
+        if endpoint.is_paused() {
+            sample.alive_since = now;
+            return;
+        }
+        let elapsed = CHECK_PERIOD.as_secs_f64();
+        let drained = endpoint.drained_at() != sample.drained_at;
+        let score = Score {
+            // the discards also move what was consumed, only count the reads.
+            drain_rate: if drained {
+                endpoint.consumed().saturating_sub(sample.consumed) as f64 / elapsed
+            } else {
+                0.0
+            },
+            error_rate: endpoint.health.errors().saturating_sub(sample.errors) as f64 / elapsed,
+        };
+        sample.consumed = endpoint.consumed();
+        sample.errors = endpoint.health.errors();
+        sample.drained_at = endpoint.drained_at();
+        self.scores.insert(endpoint.name.clone(), score);
+        let last_sign = endpoint
+            .drained_at()
+            .map_or(sample.alive_since, |drained_at| {
+                drained_at.max(sample.alive_since)
+            });
+        if now.saturating_duration_since(last_sign) < dead_after {
+            return;
+        }
+        let consumers: HashSet<String> = endpoint
+            .endpoint
+            .consumers()
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        endpoint.pause();
+        // nobody will read what is waiting.
+        if let Err(err) = endpoint.discard() {
+            warn!("Could not clear the buffer of {}: {}.", endpoint.name, err);
+        }
+        warn!(
+            event = Event::EndpointDead.code();
+            "{} has read nothing for {}s ({:.1} errors/s), paused until a consumer opens it.",
+            endpoint.name,
+            dead_after.as_secs(),
+            score.error_rate
+        );
+        self.dead.insert(endpoint.name.clone(), Dead { consumers });
+    }
+
+    fn revive(&mut self, endpoint: &mut ManagedEndpoint, now: Instant) {
+        let dead = &self.dead[&endpoint.name];
+        // resumed from the control socket.
+        let resumed = !endpoint.is_paused();
+        let consumers = endpoint.endpoint.consumers().unwrap_or_default();
+        let newcomer = consumers
+            .iter()
+            .find(|consumer| !dead.consumers.contains(*consumer));
+        if !resumed {
+            let Some(newcomer) = newcomer else {
+                return;
+            };
+            if let Err(err) = endpoint.resume(self.framed) {
+                warn!("Could not enable {} again: {}.", endpoint.name, err);
+                return;
+            }
+            info!("{} opened {}, enabled again.", newcomer, endpoint.name);
+        }
+        self.dead.remove(&endpoint.name);
+        if let Some(sample) = self.samples.get_mut(&endpoint.name) {
+            sample.alive_since = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::endpoint::pty::PtyEndpoint;
+    use crate::endpoint::{EndpointOptions, ManagedEndpoint};
+    use crate::liveness::{Liveness, CHECK_PERIOD};
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+    use std::slice;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_dead_endpoint() {
+        let link = PathBuf::from("/tmp/ttytee_liveness_test.pty");
+        let mut options = EndpointOptions::default();
+        options.set("dead-after", "3").unwrap();
+        let mut endpoint = ManagedEndpoint::new(
+            "gone",
+            Box::new(PtyEndpoint::create(&link).unwrap()),
+            options,
+            Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+        );
+        let mut liveness = Liveness::new(false);
+        let start = Instant::now();
+        liveness.poll(start, slice::from_mut(&mut endpoint));
+        endpoint.send(b"$GPGGA", &[], 0, start).unwrap();
+        liveness.poll(start + CHECK_PERIOD * 2, slice::from_mut(&mut endpoint));
+        assert!(!endpoint.is_paused());
+        // nobody read anything for 3 s.
+        liveness.poll(start + CHECK_PERIOD * 3, slice::from_mut(&mut endpoint));
+        assert!(endpoint.is_paused() && liveness.dead.contains_key("gone"));
+        assert_eq!(liveness.scores["gone"].drain_rate, 0.0);
+        // its buffer was cleared.
+        assert_eq!(endpoint.endpoint.pending().unwrap(), 0);
+
+        let consumer = Command::new("head")
+            .args(["-c", "6"])
+            .stdin(File::open(&link).unwrap())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        liveness.poll(start + CHECK_PERIOD * 4, slice::from_mut(&mut endpoint));
+        assert!(!endpoint.is_paused() && !liveness.dead.contains_key("gone"));
+        endpoint.send(b"$GPRMC", &[], 0, start).unwrap();
+        assert_eq!(consumer.wait_with_output().unwrap().stdout, b"$GPRMC");
+    }
+}
@@ -0,0 +1,414 @@
+//! Loggers telling the instances apart when several ttytee run on the same host: a wrapper
+//! prefixing the messages with the name of the instance and the syslog and journald loggers using
+//! it as their identity. The daemons store the logs so nothing has to be writable on the target.
+//! The terminal and the log file can also get one JSON object per message, for the log collectors.
+
+use crate::endpoint::format::json_string;
+use crate::events::Event;
+use clap::ValueEnum;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use simplelog::{Config, SharedLogger};
+use std::fmt::Write as _;
+use std::io;
+use std::io::Write;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The socket of the local syslog daemon.
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+// The socket of the native protocol of journald.
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+// The daemon facility, see RFC 3164.
+const LOG_DAEMON: u8 = 3 << 3;
+
+/// Prefixes the messages of a logger with `[<name>] `.
+pub struct PrefixedLogger {
+    prefix: String,
+    logger: Box<dyn SharedLogger>,
+}
+
+impl PrefixedLogger {
+    pub fn new(name: &str, logger: Box<dyn SharedLogger>) -> Box<Self> {
+        Box::new(Self {
+            prefix: format!("[{}]", name),
+            logger,
+        })
+    }
+}
+
+impl Log for PrefixedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.logger.log(
+            &Record::builder()
+                .args(format_args!("{} {}", self.prefix, record.args()))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.logger.flush()
+    }
+}
+
+impl SharedLogger for PrefixedLogger {
+    fn level(&self) -> LevelFilter {
+        self.logger.level()
+    }
+
+    fn config(&self) -> Option<&Config> {
+        self.logger.config()
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+/// The logging daemons, on top of the terminal and the log file.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum LogTarget {
+    Syslog,
+    Journald,
+}
+
+/// Sends the messages to a local logging daemon.
+pub struct DaemonLogger {
+    target: LogTarget,
+    level: LevelFilter,
+    identity: String,
+    socket: Mutex<UnixDatagram>,
+}
+
+impl DaemonLogger {
+    /// Connect to the daemon.
+    ///
+    /// # Arguments
+    ///
+    /// * `target`: the daemon.
+    /// * `level`: the most verbose level sent.
+    /// * `identity`: the program name in the messages, the name of the instance.
+    ///
+    /// returns: Result<Box<DaemonLogger>, Error>
+    ///
+    pub fn new(target: LogTarget, level: LevelFilter, identity: &str) -> io::Result<Box<Self>> {
+        let path = match target {
+            LogTarget::Syslog => SYSLOG_SOCKET,
+            LogTarget::Journald => JOURNALD_SOCKET,
+        };
+        Self::with_socket(target, level, identity, Path::new(path))
+    }
+
+    fn with_socket(
+        target: LogTarget,
+        level: LevelFilter,
+        identity: &str,
+        path: &Path,
+    ) -> io::Result<Box<Self>> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Box::new(Self {
+            target,
+            level,
+            identity: identity.to_string(),
+            socket: Mutex::new(socket),
+        }))
+    }
+}
+
+// The syslog severity, journald uses the same.
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// A message in the format of RFC 3164 without the timestamp and the host, the daemon adds them.
+pub fn syslog_message(identity: &str, record: &Record) -> Vec<u8> {
+    format!(
+        "<{}>{}[{}]: {}",
+        LOG_DAEMON | severity(record.level()),
+        identity,
+        process::id(),
+        record.args()
+    )
+    .into_bytes()
+}
+
+/// A message in the native protocol of journald, one `FIELD=value` per line.
+pub fn journald_message(identity: &str, record: &Record) -> Vec<u8> {
+    let mut message = format!(
+        "PRIORITY={}\nSYSLOG_IDENTIFIER={}\nSYSLOG_PID={}\n",
+        severity(record.level()),
+        identity,
+        process::id()
+    );
+    if let (Some(file), Some(line)) = (record.file(), record.line()) {
+        message += &format!("CODE_FILE={}\nCODE_LINE={}\n", file, line);
+    }
+    if let Some(event) = Event::of(record) {
+        message += &format!("TTYTEE_EVENT={}\n", event.code());
+    }
+    let mut message = message.into_bytes();
+    // the text may span several lines, it is sent in the binary form: the size then the data.
+    let text = record.args().to_string();
+    message.extend_from_slice(b"MESSAGE\n");
+    message.extend_from_slice(&(text.len() as u64).to_le_bytes());
+    message.extend_from_slice(text.as_bytes());
+    message.push(b'\n');
+    message
+}
+
+impl Log for DaemonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let message = match self.target {
+                LogTarget::Syslog => syslog_message(&self.identity, record),
+                LogTarget::Journald => journald_message(&self.identity, record),
+            };
+            // nowhere to report it, the other loggers still get the message.
+            let _ = self.socket.lock().unwrap().send(&message);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for DaemonLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+/// The format of the messages on the terminal and in the log file.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // one JSON object per line.
+    Json,
+}
+
+/// A message as a line of JSON: `{"time":1699963200.123,"level":"warn","event":"TT2005",
+/// "instance":"gps-front","message":"Cleared stale buffer from slave0."}`, without the event if the
+/// message is not in the catalog and without the instance if it has no name.
+pub fn json_message(name: Option<&str>, record: &Record) -> String {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since_epoch| since_epoch.as_secs_f64());
+    let mut line = format!(
+        "{{\"time\":{:.3},\"level\":\"{}\"",
+        time,
+        record.level().as_str().to_lowercase()
+    );
+    if let Some(event) = Event::of(record) {
+        write!(line, ",\"event\":\"{}\"", event.code()).unwrap();
+    }
+    if let Some(name) = name {
+        line.push_str(",\"instance\":");
+        json_string(name, &mut line);
+    }
+    line.push_str(",\"message\":");
+    json_string(&record.args().to_string(), &mut line);
+    line.push_str("}\n");
+    line
+}
+
+/// Writes the messages as lines of JSON, the name of the instance in their own field.
+pub struct JsonLogger {
+    level: LevelFilter,
+    name: Option<String>,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonLogger {
+    /// # Arguments
+    ///
+    /// * `level`: the most verbose level written.
+    /// * `name`: the name of the instance, if any.
+    /// * `writer`: where to write, stderr or the log file.
+    ///
+    /// returns: Box<JsonLogger>
+    ///
+    pub fn new(level: LevelFilter, name: Option<&str>, writer: Box<dyn Write + Send>) -> Box<Self> {
+        Box::new(Self {
+            level,
+            name: name.map(str::to_string),
+            writer: Mutex::new(writer),
+        })
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let line = json_message(self.name.as_deref(), record);
+            // nowhere to report it, the other loggers still get the message.
+            let _ = self.writer.lock().unwrap().write_all(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self.writer.lock().unwrap().flush();
+    }
+}
+
+impl SharedLogger for JsonLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::Event;
+    use crate::logging::{journald_message, json_message, DaemonLogger, LogTarget, PrefixedLogger};
+    use log::{Level, LevelFilter, Log, Record};
+    use simplelog::{Config, WriteLogger};
+    use std::fs;
+    use std::os::unix::net::UnixDatagram;
+    use std::path::Path;
+    use std::process;
+
+    #[test]
+    fn test_syslog_logger() {
+        let path = Path::new("/tmp/ttytee_syslog_test.sock");
+        let _ = fs::remove_file(path);
+        let daemon = UnixDatagram::bind(path).unwrap();
+        let logger =
+            DaemonLogger::with_socket(LogTarget::Syslog, LevelFilter::Info, "gps-front", path)
+                .unwrap();
+        logger.log(
+            &Record::builder()
+                .args(format_args!("Too many errors on slave1."))
+                .level(Level::Error)
+                .build(),
+        );
+        logger.log(
+            &Record::builder()
+                .args(format_args!("Wrote 5 chrs."))
+                .level(Level::Debug)
+                .build(),
+        );
+        let mut message = [0; 256];
+        let size = daemon.recv(&mut message).unwrap();
+        assert_eq!(
+            &message[..size],
+            format!(
+                "<27>gps-front[{}]: Too many errors on slave1.",
+                process::id()
+            )
+            .as_bytes()
+        );
+        // the debug message was not sent.
+        daemon.set_nonblocking(true).unwrap();
+        assert!(daemon.recv(&mut message).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_journald_message() {
+        let message = journald_message(
+            "ttytee",
+            &Record::builder()
+                .args(format_args!("Could not open\nthe endpoint"))
+                .level(Level::Warn)
+                .build(),
+        );
+        let mut expected = format!(
+            "PRIORITY=4\nSYSLOG_IDENTIFIER=ttytee\nSYSLOG_PID={}\nMESSAGE\n",
+            process::id()
+        )
+        .into_bytes();
+        expected.extend_from_slice(&[27, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(b"Could not open\nthe endpoint\n");
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn test_json_message() {
+        let stale_clear = [("event", Event::StaleClear.code())];
+        let record = Record::builder()
+            .args(format_args!("Cleared stale buffer from \"slave0\"."))
+            .level(Level::Warn)
+            .key_values(&stale_clear)
+            .build();
+        let line = json_message(Some("gps-front"), &record);
+        assert!(line.starts_with("{\"time\":"));
+        assert!(line.ends_with(
+            ",\"level\":\"warn\",\"event\":\"TT2005\",\"instance\":\"gps-front\",\
+             \"message\":\"Cleared stale buffer from \\\"slave0\\\".\"}\n"
+        ));
+        let line = json_message(
+            None,
+            &Record::builder()
+                .args(format_args!("ttytee is starting..."))
+                .level(Level::Info)
+                .build(),
+        );
+        assert!(line.ends_with(",\"level\":\"info\",\"message\":\"ttytee is starting...\"}\n"));
+    }
+
+    #[test]
+    fn test_prefixed_logger() {
+        let path = Path::new("/tmp/ttytee_prefixed_test.log");
+        let logger = PrefixedLogger::new(
+            "gps-front",
+            WriteLogger::new(
+                LevelFilter::Info,
+                Config::default(),
+                fs::File::create(path).unwrap(),
+            ),
+        );
+        logger.log(
+            &Record::builder()
+                .args(format_args!("ttytee is starting..."))
+                .level(Level::Info)
+                .build(),
+        );
+        logger.flush();
+        assert!(fs::read_to_string(path)
+            .unwrap()
+            .ends_with("[gps-front] ttytee is starting...\n"));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+//! The merge of a second NMEA device into the stream of the master, for example a heading sensor
+//! sending HDT next to the GNSS receiver: the sentence types given to `--merge-sentences` are taken
+//! from the merged device only and dropped from the master, all the others from the master only,
+//! so the consumers never get two sources of the same data.
+//!
+//! The endpoints then get the stream rebuilt from the sentences: the merged ones are inserted as
+//! they arrive between two complete sentences of the master, never inside one, and the bytes of
+//! the master out of any frame are dropped.
+//!
+//! When one of the devices lags a little, `--merge-reorder` holds the sentences up to a window to
+//! write them in the order of their UTC time. The sentences without one (HDT) take the time of the
+//! last epoch seen, a day change is taken as the nearest day.
+
+use crate::backoff::Backoff;
+use crate::framing::{Frame, Framer, Protocol};
+use crate::nmea::sentence_time;
+use log::{debug, info};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The sentences waiting for the writers, the oldest are kept when they lag.
+const QUEUE_SIZE: usize = 64;
+// How often `running` is checked while the device is silent.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+const DAY: f64 = 86400.0;
+
+// A sentence held until it can be written in order.
+struct Held {
+    // in s since midnight UTC of the first day.
+    time: f64,
+    received_at: Instant,
+    frame: Frame,
+}
+
+/// Holds the sentences up to a window, to write them in the order of their time.
+struct Reorder {
+    window: Duration,
+    // in the order of their time, then of their arrival.
+    held: Vec<Held>,
+    // the latest time seen, in s since midnight UTC of the first day.
+    last_time: Option<f64>,
+}
+
+impl Reorder {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            held: Vec::new(),
+            last_time: None,
+        }
+    }
+
+    // The time of a sentence, the one of the last epoch if it has none.
+    fn time(&mut self, frame: &Frame) -> f64 {
+        let Some(time) = sentence_time(&frame.data) else {
+            return self.last_time.unwrap_or(0.0);
+        };
+        let time = match self.last_time {
+            // the nearest day, a sentence from before midnight can come after midnight.
+            Some(last) => time + ((last - time) / DAY).round() * DAY,
+            None => time,
+        };
+        self.last_time = Some(self.last_time.map_or(time, |last| last.max(time)));
+        time
+    }
+
+    fn push(&mut self, frame: Frame, now: Instant) {
+        let time = self.time(&frame);
+        let at = self.held.partition_point(|held| held.time <= time);
+        self.held.insert(
+            at,
+            Held {
+                time,
+                received_at: now,
+                frame,
+            },
+        );
+    }
+
+    // When the sentence held the longest is due.
+    fn next_release(&self) -> Option<Instant> {
+        self.held
+            .iter()
+            .map(|held| held.received_at + self.window)
+            .min()
+    }
+
+    // Release the sentences in order while one of them is held for the whole window.
+    fn release(&mut self, now: Instant, frames: &mut Vec<Frame>) {
+        while self.next_release().is_some_and(|due| due <= now) {
+            frames.push(self.held.remove(0).frame);
+        }
+    }
+}
+
+/// The side of the writers, rebuilding the stream from the sentences of the two devices.
+pub struct Merger {
+    types: Vec<String>,
+    sentences: Receiver<Frame>,
+    // received and not written yet.
+    received: Vec<Frame>,
+    reorder: Option<Reorder>,
+}
+
+/// The side of the merged device.
+pub struct MergeSender {
+    types: Vec<String>,
+    sentences: SyncSender<Frame>,
+}
+
+/// Create the queue between the merged device and the writers.
+///
+/// # Arguments
+///
+/// * `types`: the sentence types taken from the merged device, like HDT.
+/// * `reorder`: how long the sentences can be held to write them in order, None to write them as
+///   they come.
+///
+/// returns: (MergeSender, Merger)
+///
+pub fn merge_queue(types: &[String], reorder: Option<Duration>) -> (MergeSender, Merger) {
+    let (sender, sentences) = sync_channel(QUEUE_SIZE);
+    (
+        MergeSender {
+            types: types.to_vec(),
+            sentences: sender,
+        },
+        Merger {
+            types: types.to_vec(),
+            sentences,
+            received: Vec::new(),
+            reorder: reorder.map(Reorder::new),
+        },
+    )
+}
+
+// Whether a frame is a sentence of one of the types.
+fn is_one_of(frame: &Frame, types: &[String]) -> bool {
+    frame.protocol == Protocol::Nmea && types.contains(&frame.message_type())
+}
+
+impl Merger {
+    /// Whether sentences of the merged device, or held ones that are due, are waiting to be written.
+    pub fn pending(&mut self, now: Instant) -> bool {
+        self.received.extend(self.sentences.try_iter());
+        !self.received.is_empty() || self.next_release().is_some_and(|due| due <= now)
+    }
+
+    /// When the next held sentence is due, None if none is held.
+    pub fn next_release(&self) -> Option<Instant> {
+        self.reorder.as_ref().and_then(Reorder::next_release)
+    }
+
+    /// Replace the sentences of the master taken from the merged device with the ones received.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames`: the frames of the master, updated with the merged stream.
+    /// * `merged`: the bytes of the merged stream, replaced.
+    /// * `now`: the current time.
+    ///
+    /// returns: bool true if there is anything to write.
+    ///
+    pub fn merge(&mut self, frames: &mut Vec<Frame>, merged: &mut Vec<u8>, now: Instant) -> bool {
+        frames.retain(|frame| !is_one_of(frame, &self.types));
+        self.pending(now);
+        frames.append(&mut self.received);
+        if let Some(reorder) = &mut self.reorder {
+            for frame in frames.drain(..) {
+                reorder.push(frame, now);
+            }
+            reorder.release(now, frames);
+        }
+        merged.clear();
+        for frame in frames.iter() {
+            merged.extend_from_slice(&frame.data);
+        }
+        !merged.is_empty()
+    }
+}
+
+/// Read the merged device until `running` is cleared or the writers are gone, opening it again
+/// after the errors.
+///
+/// # Arguments
+///
+/// * `device`: the merged device.
+/// * `baudrate`: its baudrate.
+/// * `queue`: where to send its sentences of the merged types.
+/// * `running`: cleared to stop.
+/// * `backoff`: how long to wait before opening it again.
+///
+/// returns: ()
+///
+pub fn read_merged(
+    device: &Path,
+    baudrate: u32,
+    queue: MergeSender,
+    running: &AtomicBool,
+    mut backoff: Backoff,
+) {
+    let mut framer = Framer::new(&[Protocol::Nmea]);
+    let mut frames = Vec::new();
+    let mut buffer = [0; 1024];
+    while running.load(Ordering::Relaxed) {
+        let err = match serialport::new(device.to_string_lossy(), baudrate)
+            .timeout(READ_TIMEOUT)
+            .open_native()
+        {
+            Ok(mut port) => {
+                info!("Merging the sentences of {:?}.", device);
+                backoff.success();
+                loop {
+                    if !running.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let len = match io::Read::read(&mut port, &mut buffer) {
+                        Ok(0) => break io::Error::from(io::ErrorKind::UnexpectedEof),
+                        Ok(len) => len,
+                        Err(err) if err.kind() == io::ErrorKind::TimedOut => continue,
+                        Err(err) => break err,
+                    };
+                    frames.clear();
+                    framer.push(&buffer[..len], &mut frames);
+                    for frame in frames.drain(..) {
+                        if !is_one_of(&frame, &queue.types) {
+                            continue;
+                        }
+                        match queue.sentences.try_send(frame) {
+                            Ok(()) => {}
+                            Err(TrySendError::Full(frame)) => {
+                                debug!("Dropped {} from {:?}.", frame.message_type(), device);
+                            }
+                            Err(TrySendError::Disconnected(_)) => return,
+                        }
+                    }
+                }
+            }
+            Err(err) => err.into(),
+        };
+        let delay = backoff.failure(Instant::now());
+        debug!(
+            "Could not read {:?}: {}, opening it again in {} ms.",
+            device,
+            err,
+            delay.as_millis()
+        );
+        let retry_at = Instant::now() + delay;
+        while Instant::now() < retry_at && running.load(Ordering::Relaxed) {
+            thread::sleep(READ_TIMEOUT.min(retry_at.saturating_duration_since(Instant::now())));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::framing::Frame;
+    use crate::framing::{Framer, Protocol};
+    use crate::merge::{merge_queue, read_merged, Reorder};
+    use crate::nmea::nmea_sentence;
+    use serialport::{SerialPort, TTYPort};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_merge() {
+        let gga = nmea_sentence(&["GPGGA", "120000.00", "4807.038", "N", "01131.000", "E", "1"]);
+        let hdt = nmea_sentence(&["HEHDT", "274.07", "T"]);
+        let rmc = nmea_sentence(&["GPRMC", "120000.00", "A", "4807.038", "N", "01131.000", "E"]);
+        let (mut sensor, device) = TTYPort::pair().unwrap();
+        let device = PathBuf::from(device.name().unwrap());
+        let (sender, mut merger) = merge_queue(&["HDT".to_string()], None);
+        let running = AtomicBool::new(true);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(10));
+                read_merged(&device, 9600, sender, &running, backoff);
+            });
+            // the other sentences of the sensor are not merged.
+            sensor
+                .write_all(&nmea_sentence(&["HEROT", "0.0", "A"]))
+                .unwrap();
+            sensor.write_all(&hdt).unwrap();
+
+            let mut framer = Framer::new(&[Protocol::Nmea]);
+            let mut frames = Vec::new();
+            // the HDT of the master is dropped, the partial RMC waits for its end.
+            let mut master = gga.to_vec();
+            master.extend(nmea_sentence(&["GPHDT", "0.0", "T"]));
+            master.extend_from_slice(&rmc[..20]);
+            framer.push(&master, &mut frames);
+            let mut merged = Vec::new();
+            let start = Instant::now();
+            while merged.len() <= gga.len() && start.elapsed() < Duration::from_secs(5) {
+                thread::sleep(Duration::from_millis(10));
+                merger.merge(&mut frames, &mut merged, Instant::now());
+            }
+            assert_eq!(merged, [gga, hdt].concat());
+
+            frames.clear();
+            framer.push(&rmc[20..], &mut frames);
+            assert!(merger.merge(&mut frames, &mut merged, Instant::now()));
+            assert_eq!(merged, rmc);
+            running.store(false, Ordering::Relaxed);
+        });
+    }
+
+    #[test]
+    fn test_reorder() {
+        let frame = |fields: &[&str]| Frame {
+            protocol: Protocol::Nmea,
+            data: nmea_sentence(fields),
+        };
+        let gga = frame(&["GPGGA", "120001.00", "4807.038", "N", "01131.000", "E", "1"]);
+        // the sensor lags.
+        let gst = frame(&["GPGST", "120000.00", "0.5"]);
+        let hdt = frame(&["HEHDT", "274.07", "T"]);
+        let mut reorder = Reorder::new(Duration::from_millis(200));
+        let start = Instant::now();
+        reorder.push(gga.clone(), start);
+        reorder.push(gst.clone(), start + Duration::from_millis(50));
+        reorder.push(hdt.clone(), start + Duration::from_millis(60));
+        assert_eq!(
+            reorder.next_release(),
+            Some(start + Duration::from_millis(200))
+        );
+        let mut frames = Vec::new();
+        reorder.release(start + Duration::from_millis(100), &mut frames);
+        assert!(frames.is_empty());
+        reorder.release(start + Duration::from_millis(200), &mut frames);
+        assert_eq!(frames, vec![gst, gga]);
+        reorder.release(start + Duration::from_millis(300), &mut frames);
+        assert_eq!(frames.last(), Some(&hdt));
+        assert_eq!(reorder.next_release(), None);
+
+        // midnight is the next day.
+        reorder.push(frame(&["GPGGA", "235959.50"]), start);
+        reorder.push(frame(&["GPGGA", "000000.00"]), start);
+        reorder.push(frame(&["GPGGA", "235959.00"]), start);
+        frames.clear();
+        reorder.release(start + Duration::from_secs(1), &mut frames);
+        let times: Vec<_> = frames
+            .iter()
+            .map(|frame| String::from_utf8_lossy(&frame.data[7..16]))
+            .collect();
+        assert_eq!(times, ["235959.00", "235959.50", "000000.00"]);
+    }
+}
@@ -0,0 +1,200 @@
+//! Forwarding of the modem status lines and of the window size of the master, for the consumers
+//! that key off carrier detect: with `--forward-modem-lines` they are checked every 200 ms and each
+//! change is passed to the endpoints that can reflect it. A serial endpoint drives its DTR from the
+//! CD and the DSR of the master and its RTS from the CTS, which the consumer sees on its DCD, DSR
+//! and CTS through a null-modem cable. The PTYs have no modem lines, they get the window size and
+//! the endpoints in the metadata format get a line per change.
+
+use crate::endpoint::ManagedEndpoint;
+use log::{info, warn};
+use std::fmt::Write;
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// How often the lines of the master are read.
+const CHECK_PERIOD: Duration = Duration::from_millis(200);
+
+/// The modem status lines of the master, the inputs of a DTE.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModemLines {
+    pub cd: bool,
+    pub cts: bool,
+    pub dsr: bool,
+    pub ri: bool,
+}
+
+/// What the master shows besides its data.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LineState {
+    // None when the master has no modem lines, like a PTY or a USB CDC without them.
+    pub modem: Option<ModemLines>,
+    pub rows: u16,
+    pub cols: u16,
+}
+
+impl LineState {
+    /// The state as a line of JSON for the metadata format, for example
+    /// `{"received_at":1699963200.123456,"modem":{"cd":true,"cts":true,"dsr":true,"ri":false},"rows":0,"cols":0}`.
+    pub fn metadata_line(&self, received_at: f64) -> Vec<u8> {
+        let mut line = format!("{{\"received_at\":{:.6},\"modem\":", received_at);
+        match self.modem {
+            Some(lines) => write!(
+                line,
+                "{{\"cd\":{},\"cts\":{},\"dsr\":{},\"ri\":{}}}",
+                lines.cd, lines.cts, lines.dsr, lines.ri
+            )
+            .unwrap(),
+            None => line.push_str("null"),
+        }
+        writeln!(line, ",\"rows\":{},\"cols\":{}}}", self.rows, self.cols).unwrap();
+        line.into_bytes()
+    }
+}
+
+/// Read the modem status lines of a device.
+pub fn read_modem_lines(fd: RawFd) -> io::Result<ModemLines> {
+    let mut status: libc::c_int = 0;
+    if unsafe { libc::ioctl(fd, libc::TIOCMGET, &mut status) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ModemLines {
+        cd: status & libc::TIOCM_CD != 0,
+        cts: status & libc::TIOCM_CTS != 0,
+        dsr: status & libc::TIOCM_DSR != 0,
+        ri: status & libc::TIOCM_RI != 0,
+    })
+}
+
+/// Read the window size of a terminal, (rows, cols).
+pub fn read_window_size(fd: RawFd) -> io::Result<(u16, u16)> {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((size.ws_row, size.ws_col))
+}
+
+/// Set the window size of a terminal.
+pub fn set_window_size(fd: RawFd, rows: u16, cols: u16) -> io::Result<()> {
+    let size = libc::winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    if unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &size) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Periodically reads the lines of the master and forwards their changes to the endpoints.
+pub struct ModemForwarder {
+    fd: RawFd,
+    last_check: Option<Instant>,
+    last: Option<LineState>,
+}
+
+impl ModemForwarder {
+    pub fn new(fd: RawFd) -> Self {
+        Self {
+            fd,
+            last_check: None,
+            last: None,
+        }
+    }
+
+    fn read(&self) -> LineState {
+        let (rows, cols) = read_window_size(self.fd).unwrap_or_default();
+        LineState {
+            modem: read_modem_lines(self.fd).ok(),
+            rows,
+            cols,
+        }
+    }
+
+    /// Read the lines if it is time to, and forward them when they changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: the current time.
+    /// * `endpoints`: where to forward them.
+    ///
+    pub fn poll(&mut self, now: Instant, endpoints: &mut [ManagedEndpoint]) {
+        if matches!(self.last_check, Some(last_check) if now.duration_since(last_check) < CHECK_PERIOD)
+        {
+            return;
+        }
+        self.last_check = Some(now);
+        let state = self.read();
+        if self.last == Some(state) {
+            return;
+        }
+        match (self.last.and_then(|last| last.modem), state.modem) {
+            (Some(last), Some(lines)) if last.cd != lines.cd => {
+                info!(
+                    "Carrier {} on the master.",
+                    if lines.cd { "detected" } else { "lost" }
+                );
+            }
+            _ => {}
+        }
+        self.last = Some(state);
+        let received_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since_epoch| since_epoch.as_secs_f64());
+        for endpoint in endpoints.iter_mut() {
+            if let Err(err) = endpoint.forward_line_state(&state, received_at) {
+                warn!(
+                    "Could not forward the modem lines to {}: {}.",
+                    endpoint.name, err
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::endpoint::pty::PtyEndpoint;
+    use crate::endpoint::{EndpointOptions, ManagedEndpoint};
+    use crate::modem::{read_window_size, set_window_size, LineState, ModemForwarder};
+    use serialport::TTYPort;
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_metadata_line() {
+        let state = LineState {
+            modem: None,
+            rows: 24,
+            cols: 80,
+        };
+        assert_eq!(
+            state.metadata_line(1.5),
+            b"{\"received_at\":1.500000,\"modem\":null,\"rows\":24,\"cols\":80}\n"
+        );
+    }
+
+    #[test]
+    fn test_forward_window_size() {
+        let (master, _device) = TTYPort::pair().unwrap();
+        set_window_size(master.as_raw_fd(), 24, 80).unwrap();
+        let link = PathBuf::from("/tmp/ttytee_modem_test.pty");
+        let mut endpoints = vec![ManagedEndpoint::new(
+            "slave0",
+            Box::new(PtyEndpoint::create(&link).unwrap()),
+            EndpointOptions::default(),
+            Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+        )];
+        let mut forwarder = ModemForwarder::new(master.as_raw_fd());
+        forwarder.poll(Instant::now(), &mut endpoints);
+        let consumer = File::open(&link).unwrap();
+        assert_eq!(read_window_size(consumer.as_raw_fd()).unwrap(), (24, 80));
+    }
+}
@@ -0,0 +1,277 @@
+//! Decoding of the content of NMEA sentences, the framer only splits and validates them.
+
+use crate::framing::nmea_checksum;
+
+/// The fields of a sentence, starting with the address (GPGGA...) without the `$` and the checksum.
+pub fn nmea_fields(sentence: &[u8]) -> Option<Vec<&str>> {
+    let sentence = std::str::from_utf8(sentence).ok()?;
+    let body = sentence
+        .strip_prefix('$')
+        .or_else(|| sentence.strip_prefix('!'))?
+        .trim_end_matches(['\r', '\n']);
+    let body = match body.rsplit_once('*') {
+        Some((body, _checksum)) => body,
+        None => body,
+    };
+    Some(body.split(',').collect())
+}
+
+/// Build a sentence from its fields, with its checksum.
+pub fn nmea_sentence(fields: &[&str]) -> Vec<u8> {
+    let body = fields.join(",");
+    format!("${}*{:02X}\r\n", body, nmea_checksum(body.as_bytes())).into_bytes()
+}
+
+/// The indexes of the latitude field of the sentences carrying a position, the hemisphere and the
+/// longitude follow it.
+pub fn latitude_index(address: &str) -> Option<usize> {
+    if address.len() != 5 || address.starts_with('P') {
+        return None;
+    }
+    match &address[2..] {
+        "GGA" | "GNS" => Some(2),
+        "RMC" => Some(3),
+        "GLL" => Some(1),
+        _ => None,
+    }
+}
+
+/// The index of the UTC time field of the sentences carrying one.
+pub fn time_index(address: &str) -> Option<usize> {
+    if address.len() != 5 || address.starts_with('P') {
+        return None;
+    }
+    match &address[2..] {
+        "GGA" | "GNS" | "RMC" | "GST" | "GBS" | "GRS" | "ZDA" => Some(1),
+        "GLL" => Some(5),
+        _ => None,
+    }
+}
+
+/// The time of a sentence in s since midnight UTC, None if it has none.
+pub fn sentence_time(sentence: &[u8]) -> Option<f64> {
+    let fields = nmea_fields(sentence)?;
+    let time = fields.get(time_index(fields[0])?)?;
+    if time.len() < 6 || !time.is_ascii() {
+        return None;
+    }
+    let hours: u32 = time[..2].parse().ok()?;
+    let minutes: u32 = time[2..4].parse().ok()?;
+    let seconds: f64 = time[4..].parse().ok()?;
+    Some(f64::from(hours * 3600 + minutes * 60) + seconds)
+}
+
+/// Decode a latitude or a longitude from its ddmm.mmmm form and its hemisphere.
+pub fn coordinate(value: &str, hemisphere: &str) -> Option<f64> {
+    let dot = value.find('.').unwrap_or(value.len());
+    if dot < 2 {
+        return None;
+    }
+    let degrees: f64 = value[..dot - 2].parse().ok()?;
+    let minutes: f64 = value[dot - 2..].parse().ok()?;
+    let coordinate = degrees + minutes / 60.0;
+    match hemisphere {
+        "N" | "E" => Some(coordinate),
+        "S" | "W" => Some(-coordinate),
+        _ => None,
+    }
+}
+
+/// Encode a latitude or a longitude in its ddmm.mmmm form and its hemisphere.
+///
+/// # Arguments
+///
+/// * `coordinate`: in degrees, negative for S and W.
+/// * `latitude`: true for a latitude (2 digits of degrees), false for a longitude (3 digits).
+/// * `decimals`: the number of decimals of the minutes.
+///
+/// returns: (String, &str)
+///
+pub fn format_coordinate(
+    coordinate: f64,
+    latitude: bool,
+    decimals: usize,
+) -> (String, &'static str) {
+    let hemisphere = match (latitude, coordinate < 0.0) {
+        (true, false) => "N",
+        (true, true) => "S",
+        (false, false) => "E",
+        (false, true) => "W",
+    };
+    let scale = 10f64.powi(decimals as i32);
+    // rounded in 1/scale of minutes so 59.99999 becomes the next degree.
+    let total = (coordinate.abs() * 60.0 * scale).round();
+    let minutes_scale = 60.0 * scale;
+    let degrees = (total / minutes_scale).floor();
+    let minutes = (total - degrees * minutes_scale) / scale;
+    let width = if decimals == 0 { 2 } else { 3 + decimals };
+    let degrees_width = if latitude { 2 } else { 3 };
+    (
+        format!(
+            "{:0dw$}{:0w$.d$}",
+            degrees as u32,
+            minutes,
+            dw = degrees_width,
+            w = width,
+            d = decimals
+        ),
+        hemisphere,
+    )
+}
+
+/// The content of a GGA sentence: the fix of an epoch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gga {
+    // hhmmss.ss in UTC as sent by the receiver.
+    pub time: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    // 0 no fix, 1 GPS, 2 DGPS, 4 RTK fixed, 5 RTK float...
+    pub fix_quality: u8,
+    pub satellites: u32,
+    pub hdop: Option<f64>,
+    // above the mean sea level, in m.
+    pub altitude: Option<f64>,
+}
+
+impl Gga {
+    /// Decode a GGA sentence from any talker, None if it is another sentence or is malformed.
+    pub fn parse(sentence: &[u8]) -> Option<Self> {
+        let fields = nmea_fields(sentence)?;
+        if fields.len() < 10 || fields[0].len() != 5 || !fields[0].ends_with("GGA") {
+            return None;
+        }
+        Some(Self {
+            time: fields[1].to_string(),
+            latitude: coordinate(fields[2], fields[3]),
+            longitude: coordinate(fields[4], fields[5]),
+            fix_quality: fields[6].parse().ok()?,
+            satellites: fields[7].parse().unwrap_or(0),
+            hdop: fields[8].parse().ok(),
+            altitude: fields[9].parse().ok(),
+        })
+    }
+}
+
+/// The content of a RMC sentence: the date, the speed and the course of an epoch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rmc {
+    // hhmmss.ss in UTC as sent by the receiver.
+    pub time: String,
+    // ddmmyy.
+    pub date: String,
+    // the status is A, V when the receiver has no fix.
+    pub valid: bool,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    // over the ground, in knots.
+    pub speed: Option<f64>,
+    // over the ground, in degrees from the true north.
+    pub course: Option<f64>,
+}
+
+impl Rmc {
+    /// Decode a RMC sentence from any talker, None if it is another sentence or is malformed.
+    pub fn parse(sentence: &[u8]) -> Option<Self> {
+        let fields = nmea_fields(sentence)?;
+        if fields.len() < 10 || fields[0].len() != 5 || !fields[0].ends_with("RMC") {
+            return None;
+        }
+        Some(Self {
+            time: fields[1].to_string(),
+            date: fields[9].to_string(),
+            valid: fields[2] == "A",
+            latitude: coordinate(fields[3], fields[4]),
+            longitude: coordinate(fields[5], fields[6]),
+            speed: fields[7].parse().ok(),
+            course: fields[8].parse().ok(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nmea::{
+        coordinate, format_coordinate, nmea_fields, nmea_sentence, sentence_time, Gga, Rmc,
+    };
+
+    const GGA: &[u8] = b"$GPGGA,123519,4807.038,N,01131.000,W,1,08,0.9,545.4,M,46.9,M,,*47\r\n";
+
+    #[test]
+    fn test_nmea_fields() {
+        assert_eq!(
+            nmea_fields(b"$GPGSA,A,3,04*3A\r\n"),
+            Some(vec!["GPGSA", "A", "3", "04"])
+        );
+        assert_eq!(nmea_fields(b"GPGSA,A"), None);
+    }
+
+    #[test]
+    fn test_sentence_time() {
+        assert_eq!(sentence_time(GGA), Some(45319.0));
+        assert_eq!(
+            sentence_time(b"$GPGLL,4916.45,N,12311.12,W,225444.50,A*31\r\n"),
+            Some(82484.5)
+        );
+        assert_eq!(sentence_time(b"$HEHDT,274.07,T*03\r\n"), None);
+        assert_eq!(sentence_time(b"$GPGGA,,,,,,0*66\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_gga() {
+        let gga = Gga::parse(GGA).unwrap();
+        assert_eq!(gga.time, "123519");
+        assert!((gga.latitude.unwrap() - 48.1173).abs() < 1e-9);
+        assert!((gga.longitude.unwrap() + 11.516_666_666).abs() < 1e-6);
+        assert_eq!(gga.fix_quality, 1);
+        assert_eq!(gga.satellites, 8);
+        assert_eq!(gga.hdop, Some(0.9));
+        assert_eq!(gga.altitude, Some(545.4));
+
+        let no_fix = Gga::parse(b"$GNGGA,000001.00,,,,,0,00,99.99,,,,,,*7A\r\n").unwrap();
+        assert_eq!(no_fix.latitude, None);
+        assert_eq!(no_fix.fix_quality, 0);
+        assert_eq!(Gga::parse(b"$GPRMC,123519,A*00\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_rmc() {
+        let rmc =
+            Rmc::parse(b"$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n")
+                .unwrap();
+        assert_eq!(rmc.time, "123519");
+        assert_eq!(rmc.date, "230394");
+        assert!(rmc.valid);
+        assert!((rmc.longitude.unwrap() - 11.516_666_666).abs() < 1e-6);
+        assert_eq!(rmc.speed, Some(22.4));
+        assert_eq!(rmc.course, Some(84.4));
+        assert_eq!(Rmc::parse(GGA), None);
+    }
+
+    #[test]
+    fn test_nmea_sentence() {
+        assert_eq!(
+            nmea_sentence(&["GPGLL", "4916.45", "N", "12311.12", "W", "225444", "A"]),
+            b"$GPGLL,4916.45,N,12311.12,W,225444,A*31\r\n"
+        );
+    }
+
+    #[test]
+    fn test_format_coordinate() {
+        assert_eq!(
+            format_coordinate(48.1173, true, 3),
+            ("4807.038".to_string(), "N")
+        );
+        assert_eq!(
+            format_coordinate(-11.516_666_666, false, 4),
+            ("01131.0000".to_string(), "W")
+        );
+        assert_eq!(
+            format_coordinate(1.999_999, true, 2),
+            ("0200.00".to_string(), "N")
+        );
+        assert_eq!(format_coordinate(0.5, false, 0), ("00030".to_string(), "E"));
+        let (field, hemisphere) = format_coordinate(-33.856_789, true, 5);
+        assert!((coordinate(&field, hemisphere).unwrap() + 33.856_789).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,245 @@
+//! Time base for the time-sync consumers: with `--pps DEVICE` the pulses of a kernel PPS device
+//! (`/dev/pps0`) are paired with the RMC sentence of the master that follows them, the one telling
+//! the UTC time of the second the pulse marks. Each pair is a line of JSON written to the endpoints
+//! in the `timebase` format, which get nothing else:
+//! `{"pps":1699963200.000012,"sequence":42,"time":"120000.00","date":"141123","status":"A","received_at":1699963200.412345}`.
+
+use crate::endpoint::format::{json_string, OutputFormat};
+use crate::endpoint::ManagedEndpoint;
+use crate::framing::Frame;
+use crate::nmea::nmea_fields;
+use log::{error, warn};
+use std::fmt::Write;
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// A RMC received later than this after a pulse is about another second.
+const PAIRING_WINDOW: f64 = 1.0;
+// How long a fetch waits for a pulse, so the thread sees when ttytee stops.
+const FETCH_TIMEOUT_SECS: i64 = 1;
+
+// Mirror the structures of linux/pps.h.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct PpsKtime {
+    sec: i64,
+    nsec: i32,
+    flags: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct PpsKinfo {
+    assert_sequence: u32,
+    clear_sequence: u32,
+    assert_tu: PpsKtime,
+    clear_tu: PpsKtime,
+    current_mode: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct PpsFdata {
+    info: PpsKinfo,
+    timeout: PpsKtime,
+}
+
+// _IOWR('p', 0xa4, struct pps_fdata *)
+const PPS_FETCH: libc::c_ulong = (3 << 30)
+    | ((std::mem::size_of::<*mut PpsFdata>() as libc::c_ulong) << 16)
+    | ((b'p' as libc::c_ulong) << 8)
+    | 0xa4;
+
+/// A pulse of the PPS device.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PpsPulse {
+    pub sequence: u32,
+    // the time of the assert edge, in seconds since the epoch.
+    pub assert_at: f64,
+}
+
+// Wait for the next pulse, None on timeout.
+fn fetch(file: &File) -> io::Result<Option<PpsPulse>> {
+    let mut data = PpsFdata {
+        timeout: PpsKtime {
+            sec: FETCH_TIMEOUT_SECS,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    if unsafe { libc::ioctl(file.as_raw_fd(), PPS_FETCH, &mut data) } < 0 {
+        let err = io::Error::last_os_error();
+        if matches!(
+            err.raw_os_error(),
+            Some(libc::ETIMEDOUT) | Some(libc::EINTR)
+        ) {
+            return Ok(None);
+        }
+        return Err(err);
+    }
+    let assert = data.info.assert_tu;
+    Ok(Some(PpsPulse {
+        sequence: data.info.assert_sequence,
+        assert_at: assert.sec as f64 + assert.nsec as f64 / 1e9,
+    }))
+}
+
+/// Read the pulses of a PPS device from a thread, the last one is kept.
+///
+/// # Arguments
+///
+/// * `device`: the PPS device, like `/dev/pps0`.
+/// * `running`: the thread stops when it is cleared.
+///
+/// returns: Result<Arc<Mutex<Option<PpsPulse>>>, Error> the last pulse.
+///
+pub fn start_pps(
+    device: &Path,
+    running: Arc<AtomicBool>,
+) -> io::Result<Arc<Mutex<Option<PpsPulse>>>> {
+    let file = File::open(device)?;
+    let last_pulse = Arc::new(Mutex::new(None));
+    let pulses = last_pulse.clone();
+    let device = device.to_path_buf();
+    thread::Builder::new()
+        .name("pps".to_string())
+        .spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                match fetch(&file) {
+                    Ok(Some(pulse)) => *pulses.lock().unwrap() = Some(pulse),
+                    Ok(None) => {}
+                    Err(err) => {
+                        error!("Could not read the pulses of {:?}: {}", device, err);
+                        break;
+                    }
+                }
+            }
+        })?;
+    Ok(last_pulse)
+}
+
+/// Pairs the pulses with the RMC sentences and writes the pairs to the timebase endpoints.
+pub struct TimeBase {
+    last_pulse: Arc<Mutex<Option<PpsPulse>>>,
+    // the sequence of the last pulse paired, each is paired once.
+    paired: Option<u32>,
+}
+
+impl TimeBase {
+    pub fn new(last_pulse: Arc<Mutex<Option<PpsPulse>>>) -> Self {
+        Self {
+            last_pulse,
+            paired: None,
+        }
+    }
+
+    /// The record of a frame received at a time, if it is the RMC following the last pulse.
+    fn pair(&mut self, frame: &Frame, received_at: f64) -> Option<Vec<u8>> {
+        if frame.message_type() != "RMC" {
+            return None;
+        }
+        let pulse = (*self.last_pulse.lock().unwrap())?;
+        let since_pulse = received_at - pulse.assert_at;
+        if self.paired == Some(pulse.sequence) || !(0.0..PAIRING_WINDOW).contains(&since_pulse) {
+            return None;
+        }
+        // $GPRMC,120000.00,A,4807.038,N,01131.000,E,0.0,0.0,141123,,,A
+        let fields = nmea_fields(&frame.data)?;
+        self.paired = Some(pulse.sequence);
+        let mut record = format!(
+            "{{\"pps\":{:.6},\"sequence\":{},\"time\":",
+            pulse.assert_at, pulse.sequence
+        );
+        json_string(fields.get(1).copied().unwrap_or_default(), &mut record);
+        record.push_str(",\"date\":");
+        json_string(fields.get(9).copied().unwrap_or_default(), &mut record);
+        record.push_str(",\"status\":");
+        json_string(fields.get(2).copied().unwrap_or_default(), &mut record);
+        writeln!(record, ",\"received_at\":{:.6}}}", received_at).unwrap();
+        Some(record.into_bytes())
+    }
+
+    /// Write the pairs completed by the frames of a read to the timebase endpoints.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames`: the frames of the read.
+    /// * `received_at`: when it was received, in seconds since the epoch.
+    /// * `endpoints`: all the endpoints, only the timebase ones get the pairs.
+    ///
+    pub fn push(&mut self, frames: &[Frame], received_at: f64, endpoints: &mut [ManagedEndpoint]) {
+        for record in frames
+            .iter()
+            .filter_map(|frame| self.pair(frame, received_at))
+        {
+            for endpoint in endpoints
+                .iter_mut()
+                .filter(|endpoint| endpoint.options.format == OutputFormat::Timebase)
+            {
+                if let Err(err) = endpoint.inject(&record) {
+                    warn!(
+                        "Could not write the time base to {}: {}.",
+                        endpoint.name, err
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{Frame, Protocol};
+    use crate::nmea::nmea_sentence;
+    use crate::pps::{PpsPulse, TimeBase};
+    use std::sync::{Arc, Mutex};
+
+    fn rmc() -> Frame {
+        Frame {
+            protocol: Protocol::Nmea,
+            data: nmea_sentence(&[
+                "GPRMC",
+                "120000.00",
+                "A",
+                "4807.038",
+                "N",
+                "01131.000",
+                "E",
+                "0.0",
+                "0.0",
+                "141123",
+                "",
+                "",
+                "A",
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_pair() {
+        let last_pulse = Arc::new(Mutex::new(None));
+        let mut time_base = TimeBase::new(last_pulse.clone());
+        assert_eq!(time_base.pair(&rmc(), 1699963200.4), None);
+        *last_pulse.lock().unwrap() = Some(PpsPulse {
+            sequence: 42,
+            assert_at: 1699963200.000012,
+        });
+        assert_eq!(
+            String::from_utf8(time_base.pair(&rmc(), 1699963200.4).unwrap()).unwrap(),
+            "{\"pps\":1699963200.000012,\"sequence\":42,\"time\":\"120000.00\",\"date\":\"141123\",\"status\":\"A\",\"received_at\":1699963200.400000}\n"
+        );
+        // once per pulse.
+        assert_eq!(time_base.pair(&rmc(), 1699963200.5), None);
+        *last_pulse.lock().unwrap() = Some(PpsPulse {
+            sequence: 43,
+            assert_at: 1699963201.000012,
+        });
+        // the pulse is more than a second old.
+        assert_eq!(time_base.pair(&rmc(), 1699963202.4), None);
+    }
+}
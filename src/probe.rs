@@ -0,0 +1,298 @@
+//! Probe of a master, to configure a new receiver without guessing: each usual baudrate is listened
+//! to until the bytes read are frames, then the receiver is asked who it is (the UBX MON-VER poll
+//! and the `$PMTK605` query of the MediaTek receivers) and its messages are counted for a while.
+//!
+//! The report is a JSON object with the baudrate, the protocols, the rate of each message type,
+//! the versions from MON-VER and the texts (TXT, PMTK705) of the receiver, and the arguments of
+//! ttytee matching them, for the tools writing the configurations.
+
+use crate::endpoint::format::json_string;
+use crate::framing::{Frame, Framer, Protocol};
+use crate::nmea::{nmea_fields, nmea_sentence};
+use crate::ubx::{ubx_frame, MonVer};
+use clap::ValueEnum;
+use log::debug;
+use serialport::{ClearBuffer, SerialPort, TTYPort};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// The baudrates tried, the usual ones of the receivers first.
+const PROBE_BAUDRATES: [u32; 8] = [
+    9600, 115_200, 38_400, 4800, 19_200, 57_600, 230_400, 460_800,
+];
+// How long each baudrate is listened to.
+const DETECTION_WINDOW: Duration = Duration::from_millis(1500);
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+// The protocols of the receivers, Modbus takes any byte for a frame.
+const PROBE_PROTOCOLS: &[Protocol] = &[Protocol::Nmea, Protocol::Ubx, Protocol::Rtcm];
+
+// The queries of the identity of the receiver, the receivers ignore the ones they don't know.
+fn identity_queries() -> Vec<Vec<u8>> {
+    vec![ubx_frame(0x0A, 0x04, &[]), nmea_sentence(&["PMTK605"])]
+}
+
+/// Whether the bytes read at a baudrate are frames: at least 2 of them making half the bytes, the
+/// wrong baudrates give garbage with the odd frame by chance.
+fn framed(data: &[u8]) -> bool {
+    let mut framer = Framer::new(PROBE_PROTOCOLS);
+    let mut frames = Vec::new();
+    framer.push(data, &mut frames);
+    let framed_bytes: usize = frames.iter().map(|frame| frame.data.len()).sum();
+    frames.len() >= 2 && framed_bytes * 2 >= data.len()
+}
+
+/// What the probe learned about a receiver.
+#[derive(Debug, Default)]
+struct ProbeReport {
+    baudrate: u32,
+    protocols: Vec<Protocol>,
+    counts: BTreeMap<String, u64>,
+    identity: Option<MonVer>,
+    texts: Vec<String>,
+}
+
+impl ProbeReport {
+    fn observe(&mut self, frame: &Frame) {
+        if !self.protocols.contains(&frame.protocol) {
+            self.protocols.push(frame.protocol);
+        }
+        let message_type = frame.message_type();
+        *self.counts.entry(message_type.clone()).or_default() += 1;
+        if let Some(version) = MonVer::parse(&frame.data) {
+            self.identity = Some(version);
+        }
+        let text = match (message_type.as_str(), nmea_fields(&frame.data)) {
+            // $GPTXT,01,01,02,ANTSTATUS=OK
+            ("TXT", Some(fields)) => fields.get(4).map(|text| text.to_string()),
+            // $PMTK705,AXN_5.1.7_3333_19020118,0027,Quectel-L76,1.0
+            ("PMTK705", Some(fields)) => Some(fields[1..].join(",")),
+            _ => None,
+        };
+        if let Some(text) = text.filter(|text| !self.texts.contains(text)) {
+            self.texts.push(text);
+        }
+    }
+
+    /// The report in JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `master`: the probed device.
+    /// * `duration`: how long the messages were counted.
+    ///
+    /// returns: String
+    ///
+    fn to_json(&self, master: &Path, duration: Duration) -> String {
+        let protocols: Vec<String> = Protocol::value_variants()
+            .iter()
+            .filter(|protocol| self.protocols.contains(protocol))
+            .filter_map(|protocol| protocol.to_possible_value())
+            .map(|value| value.get_name().to_string())
+            .collect();
+        let mut json = String::from("{\"master\":");
+        json_string(&master.to_string_lossy(), &mut json);
+        write!(json, ",\"baudrate\":{},\"protocols\":[", self.baudrate).unwrap();
+        for (i, protocol) in protocols.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json_string(protocol, &mut json);
+        }
+        json.push_str("],\"rates\":{");
+        for (i, (message_type, count)) in self.counts.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json_string(message_type, &mut json);
+            write!(json, ":{:.2}", *count as f64 / duration.as_secs_f64()).unwrap();
+        }
+        json.push_str("},\"identity\":");
+        match &self.identity {
+            Some(version) => {
+                json.push_str("{\"software\":");
+                json_string(&version.software, &mut json);
+                json.push_str(",\"hardware\":");
+                json_string(&version.hardware, &mut json);
+                json.push_str(",\"extensions\":[");
+                for (i, extension) in version.extensions.iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    json_string(extension, &mut json);
+                }
+                json.push_str("]}");
+            }
+            None => json.push_str("null"),
+        }
+        json.push_str(",\"texts\":[");
+        for (i, text) in self.texts.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json_string(text, &mut json);
+        }
+        json.push_str("],\"args\":[\"--master\",");
+        json_string(&master.to_string_lossy(), &mut json);
+        writeln!(
+            json,
+            ",\"--baudrate\",\"{}\",\"--framer\",\"{}\"]}}",
+            self.baudrate,
+            protocols.join(",")
+        )
+        .unwrap();
+        json
+    }
+}
+
+// Everything the master sends during a window.
+fn read_for(port: &mut TTYPort, window: Duration, mut data: impl FnMut(&[u8])) -> io::Result<()> {
+    let start = Instant::now();
+    let mut buffer = [0; 4096];
+    while start.elapsed() < window {
+        match port.read(&mut buffer) {
+            Ok(len) => data(&buffer[..len]),
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Probe a master.
+///
+/// # Arguments
+///
+/// * `master`: the device of the receiver.
+/// * `duration`: how long the messages are counted once the baudrate is found.
+///
+/// returns: Result<String, Error> the report in JSON.
+///
+pub fn probe(master: &Path, duration: Duration) -> io::Result<String> {
+    let mut port = TTYPort::open(
+        &serialport::new(master.to_string_lossy(), PROBE_BAUDRATES[0]).timeout(READ_TIMEOUT),
+    )?;
+    let mut baudrate = None;
+    for candidate in PROBE_BAUDRATES {
+        port.set_baud_rate(candidate)?;
+        port.clear(ClearBuffer::Input)?;
+        let mut data = Vec::new();
+        read_for(&mut port, DETECTION_WINDOW, |read| {
+            data.extend_from_slice(read)
+        })?;
+        debug!("{} bytes read at {} bauds.", data.len(), candidate);
+        if framed(&data) {
+            baudrate = Some(candidate);
+            break;
+        }
+    }
+    let Some(baudrate) = baudrate else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no frames from {:?} at any baudrate", master),
+        ));
+    };
+    for query in identity_queries() {
+        port.write_all(&query)?;
+    }
+    let mut report = ProbeReport {
+        baudrate,
+        ..Default::default()
+    };
+    let mut framer = Framer::new(PROBE_PROTOCOLS);
+    let mut frames = Vec::new();
+    read_for(&mut port, duration, |read| framer.push(read, &mut frames))?;
+    for frame in &frames {
+        report.observe(frame);
+    }
+    Ok(report.to_json(master, duration))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nmea::nmea_sentence;
+    use crate::probe::{framed, probe};
+    use crate::ubx::tests::mon_ver_frame;
+    use crate::ubx::ubx_frame;
+    use serialport::{SerialPort, TTYPort};
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn gga() -> Vec<u8> {
+        nmea_sentence(&[
+            "GPGGA",
+            "123519",
+            "4807.038",
+            "N",
+            "01131.000",
+            "E",
+            "1",
+            "08",
+            "0.9",
+            "545.4",
+            "M",
+            "46.9",
+            "M",
+            "",
+            "",
+        ])
+    }
+
+    #[test]
+    fn test_framed() {
+        let mut data = gga();
+        assert!(!framed(&data));
+        data.extend(gga());
+        assert!(framed(&data));
+        data.extend([0x55; 300]);
+        assert!(!framed(&data));
+    }
+
+    #[test]
+    fn test_probe() {
+        let (mut receiver, device) = TTYPort::pair().unwrap();
+        receiver.set_timeout(Duration::from_millis(100)).unwrap();
+        let path = PathBuf::from(device.name().unwrap());
+        let running = Arc::new(AtomicBool::new(true));
+        let sending = running.clone();
+        let sender = thread::spawn(move || {
+            let poll = ubx_frame(0x0A, 0x04, &[]);
+            let mut buffer = [0; 1000];
+            while sending.load(Ordering::Relaxed) {
+                receiver.write_all(&gga()).unwrap();
+                receiver
+                    .write_all(&nmea_sentence(&["GPTXT", "01", "01", "02", "ANTSTATUS=OK"]))
+                    .unwrap();
+                if let Ok(len) = receiver.read(&mut buffer) {
+                    if buffer[..len].windows(poll.len()).any(|read| read == poll) {
+                        receiver.write_all(&mon_ver_frame()).unwrap();
+                    }
+                }
+            }
+        });
+        let report = probe(&path, Duration::from_secs(1)).unwrap();
+        running.store(false, Ordering::Relaxed);
+        sender.join().unwrap();
+        drop(device);
+        assert!(
+            report.contains(
+                ",\"baudrate\":9600,\"protocols\":[\"nmea\",\"ubx\"],\"rates\":{\"GGA\":"
+            ),
+            "{}",
+            report
+        );
+        assert!(report.contains(",\"MON-VER\":1.00,\"TXT\":"));
+        assert!(report.contains(
+            "\"identity\":{\"software\":\"EXT CORE 1.00 (fbbe3e)\",\"hardware\":\"00190000\""
+        ));
+        assert!(report.contains(",\"texts\":[\"ANTSTATUS=OK\"],"));
+        assert!(report.ends_with(",\"--baudrate\",\"9600\",\"--framer\",\"nmea,ubx\"]}\n"));
+    }
+}
@@ -0,0 +1,326 @@
+//! Push of the stats to a collector, for the fleets where scraping each vehicle is impractical:
+//! with `--stats-push udp://collector:9000` the counters that changed since the previous push are
+//! sent every `--stats-push-interval` seconds, in the InfluxDB line protocol (Telegraf, InfluxDB)
+//! or as JSON.
+//!
+//! The counters are the bytes read, the bytes out of frames and the invalid frames of the master,
+//! the count of each message type, and the bytes written, the bytes dropped and the partial writes
+//! of each endpoint. Each push carries their increase only, tagged with the host name and the
+//! instance name.
+
+use crate::endpoint::format::json_string;
+use crate::endpoint::ManagedEndpoint;
+use crate::stats::Stats;
+use clap::ValueEnum;
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::fmt::Write;
+use std::io;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum PushFormat {
+    /// The InfluxDB line protocol, one line per series.
+    #[default]
+    Influx,
+    /// A JSON object with the series.
+    Json,
+}
+
+/// Parse the collector of --stats-push, like `udp://collector:9000`.
+pub fn parse_push_target(url: &str) -> Result<String, String> {
+    url.strip_prefix("udp://")
+        .filter(|address| address.contains(':'))
+        .map(str::to_string)
+        .ok_or_else(|| format!("expected udp://HOST:PORT, got {:?}", url))
+}
+
+// A measurement and its tag, like (ttytee_endpoint, endpoint=slave0).
+type Series = (&'static str, Option<(&'static str, String)>);
+
+/// All the counters, by series and field.
+///
+/// # Arguments
+///
+/// * `stats`: the stats of the master.
+/// * `endpoints`: the endpoints.
+///
+/// returns: BTreeMap<(Series, &str), u64>
+///
+fn counters(stats: &Stats, endpoints: &[ManagedEndpoint]) -> BTreeMap<(Series, &'static str), u64> {
+    let mut counters = BTreeMap::new();
+    for (field, value) in stats.master_counters() {
+        counters.insert((("ttytee_master", None), field), value);
+    }
+    for (message_type, count, _) in stats.message_rates(Instant::now()) {
+        counters.insert(
+            (("ttytee_messages", Some(("type", message_type))), "count"),
+            count,
+        );
+    }
+    for endpoint in endpoints {
+        let series: Series = ("ttytee_endpoint", Some(("endpoint", endpoint.name.clone())));
+        counters.insert((series.clone(), "written"), endpoint.written());
+        counters.insert((series.clone(), "dropped"), endpoint.dropped());
+        counters.insert((series, "partial_writes"), endpoint.partial_writes());
+    }
+    counters
+}
+
+// The increase of the counters, without the ones that did not change.
+fn deltas(
+    previous: &BTreeMap<(Series, &'static str), u64>,
+    current: &BTreeMap<(Series, &'static str), u64>,
+) -> BTreeMap<Series, Vec<(&'static str, u64)>> {
+    let mut deltas: BTreeMap<Series, Vec<(&'static str, u64)>> = BTreeMap::new();
+    for ((series, field), &value) in current {
+        let delta = value.saturating_sub(
+            previous
+                .get(&(series.clone(), *field))
+                .copied()
+                .unwrap_or(0),
+        );
+        if delta > 0 {
+            deltas
+                .entry(series.clone())
+                .or_default()
+                .push((field, delta));
+        }
+    }
+    deltas
+}
+
+// The tag values of the line protocol escape the commas, the spaces and the equal signs.
+fn escape_tag(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+        .replace('=', "\\=")
+}
+
+/// Encode the deltas in the InfluxDB line protocol.
+///
+/// # Arguments
+///
+/// * `deltas`: the fields of each series.
+/// * `tags`: the tags of all the series, like the host name.
+/// * `time`: in ns since the epoch.
+///
+/// returns: String
+///
+fn encode_influx(
+    deltas: &BTreeMap<Series, Vec<(&'static str, u64)>>,
+    tags: &[(&str, &str)],
+    time: u128,
+) -> String {
+    let mut lines = String::new();
+    for ((measurement, tag), fields) in deltas {
+        lines.push_str(measurement);
+        for (key, value) in tags
+            .iter()
+            .copied()
+            .chain(tag.as_ref().map(|(key, value)| (*key, value.as_str())))
+        {
+            write!(lines, ",{}={}", key, escape_tag(value)).unwrap();
+        }
+        for (i, (field, value)) in fields.iter().enumerate() {
+            let separator = if i == 0 { ' ' } else { ',' };
+            write!(lines, "{}{}={}i", separator, field, value).unwrap();
+        }
+        writeln!(lines, " {}", time).unwrap();
+    }
+    lines
+}
+
+/// Encode the deltas as JSON, like
+/// `{"host":"rover1","time":1699963200.0,"series":[{"measurement":"ttytee_master","bytes_read":96}]}`.
+fn encode_json(
+    deltas: &BTreeMap<Series, Vec<(&'static str, u64)>>,
+    tags: &[(&str, &str)],
+    time: f64,
+) -> String {
+    let mut json = String::from("{");
+    for (key, value) in tags {
+        json_string(key, &mut json);
+        json.push(':');
+        json_string(value, &mut json);
+        json.push(',');
+    }
+    write!(json, "\"time\":{:.3},\"series\":[", time).unwrap();
+    for (i, ((measurement, tag), fields)) in deltas.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(json, "{{\"measurement\":\"{}\"", measurement).unwrap();
+        if let Some((key, value)) = tag {
+            write!(json, ",\"{}\":", key).unwrap();
+            json_string(value, &mut json);
+        }
+        for (field, value) in fields {
+            write!(json, ",\"{}\":{}", field, value).unwrap();
+        }
+        json.push('}');
+    }
+    json.push_str("]}\n");
+    json
+}
+
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } != 0 {
+        return "unknown".to_string();
+    }
+    CStr::from_bytes_until_nul(&buffer)
+        .map_or("unknown".into(), |name| name.to_string_lossy().into_owned())
+}
+
+pub struct StatsPusher {
+    socket: UdpSocket,
+    format: PushFormat,
+    period: Duration,
+    host: String,
+    instance: Option<String>,
+    last_push: Instant,
+    previous: BTreeMap<(Series, &'static str), u64>,
+}
+
+impl StatsPusher {
+    /// Create a socket sending to the collector.
+    ///
+    /// # Arguments
+    ///
+    /// * `address`: the collector, like `collector:9000`.
+    /// * `format`: the encoding of the stats.
+    /// * `period`: the time between 2 pushes.
+    /// * `instance`: the name of the instance, a tag of the stats.
+    /// * `now`: the current time.
+    ///
+    /// returns: Result<StatsPusher, Error>
+    ///
+    pub fn connect(
+        address: &str,
+        format: PushFormat,
+        period: Duration,
+        instance: Option<&str>,
+        now: Instant,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(address)?;
+        info!(
+            "Pushing the stats to {} every {} s.",
+            address,
+            period.as_secs()
+        );
+        Ok(Self {
+            socket,
+            format,
+            period,
+            host: hostname(),
+            instance: instance.map(str::to_string),
+            last_push: now,
+            previous: BTreeMap::new(),
+        })
+    }
+
+    /// Push the counters that changed if the period since the last push is over.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: the current time.
+    /// * `stats`: the stats of the master.
+    /// * `endpoints`: the endpoints.
+    ///
+    pub fn poll(&mut self, now: Instant, stats: &Stats, endpoints: &[ManagedEndpoint]) {
+        if now.duration_since(self.last_push) < self.period {
+            return;
+        }
+        self.last_push = now;
+        let current = counters(stats, endpoints);
+        let deltas = deltas(&self.previous, &current);
+        self.previous = current;
+        if deltas.is_empty() {
+            return;
+        }
+        let mut tags = vec![("host", self.host.as_str())];
+        if let Some(instance) = &self.instance {
+            tags.push(("instance", instance.as_str()));
+        }
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let push = match self.format {
+            PushFormat::Influx => encode_influx(&deltas, &tags, since_epoch.as_nanos()),
+            PushFormat::Json => encode_json(&deltas, &tags, since_epoch.as_secs_f64()),
+        };
+        // the collector may be unreachable while the vehicle is out of coverage.
+        if let Err(err) = self.socket.send(push.as_bytes()) {
+            debug!("Could not push the stats: {}.", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::push::{parse_push_target, PushFormat, StatsPusher};
+    use crate::stats::Stats;
+    use std::net::UdpSocket;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_push_target() {
+        assert_eq!(
+            parse_push_target("udp://collector:9000"),
+            Ok("collector:9000".to_string())
+        );
+        assert!(parse_push_target("tcp://collector:9000").is_err());
+        assert!(parse_push_target("udp://collector").is_err());
+    }
+
+    #[test]
+    fn test_push_deltas() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let address = receiver.local_addr().unwrap().to_string();
+        let start = Instant::now();
+        let period = Duration::from_secs(10);
+        let mut pusher = StatsPusher::connect(
+            &address,
+            PushFormat::Influx,
+            period,
+            Some("gps front"),
+            start,
+        )
+        .unwrap();
+        pusher.host = "rover1".to_string();
+        let mut stats = Stats::new(start);
+        stats.count_bytes(96);
+        stats.count_message("GGA", None, start);
+        pusher.poll(start + Duration::from_secs(9), &stats, &[]);
+        pusher.poll(start + period, &stats, &[]);
+        let mut buffer = [0; 1000];
+        let len = receiver.recv(&mut buffer).unwrap();
+        let push = String::from_utf8_lossy(&buffer[..len]).into_owned();
+        let lines: Vec<&str> = push.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(
+            lines[0].starts_with("ttytee_master,host=rover1,instance=gps\\ front bytes_read=96i ")
+        );
+        assert!(lines[1]
+            .starts_with("ttytee_messages,host=rover1,instance=gps\\ front,type=GGA count=1i "));
+
+        // only what changed.
+        stats.count_message("GGA", None, start);
+        pusher.format = PushFormat::Json;
+        pusher.poll(start + period * 2, &stats, &[]);
+        let len = receiver.recv(&mut buffer).unwrap();
+        let push = String::from_utf8_lossy(&buffer[..len]).into_owned();
+        assert!(push.starts_with("{\"host\":\"rover1\",\"instance\":\"gps front\",\"time\":"));
+        assert!(push.ends_with(
+            ",\"series\":[{\"measurement\":\"ttytee_messages\",\"type\":\"GGA\",\"count\":1}]}\n"
+        ));
+    }
+}
@@ -0,0 +1,198 @@
+//! Detection of anomalies in the data rate coming from the master.
+//!
+//! The monitor learns the nominal throughput of the stream (bytes/s smoothed over windows) and
+//! raises an alert when a window deviates from it by more than a threshold, for example when the
+//! receiver silently dropped from 10 Hz to 1 Hz.
+
+use crate::events::Event;
+use log::{error, info, warn};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Throughput is measured over windows of this duration.
+const WINDOW: Duration = Duration::from_secs(5);
+
+// Number of windows observed before the nominal rate is trusted.
+const LEARNING_WINDOWS: u32 = 6;
+
+// Smoothing factor of the nominal rate.
+const ALPHA: f64 = 0.1;
+
+#[derive(Debug, PartialEq)]
+pub enum RateEvent {
+    Anomaly { rate: f64, nominal: f64 },
+    Recovered { rate: f64, nominal: f64 },
+}
+
+pub struct RateMonitor {
+    threshold: f64,
+    hook: Option<String>,
+    window_start: Option<Instant>,
+    window_bytes: u64,
+    nominal: f64,
+    learned_windows: u32,
+    in_anomaly: bool,
+}
+
+impl RateMonitor {
+    /// Create a rate monitor.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold_percent`: the deviation from the nominal rate considered an anomaly.
+    /// * `hook`: an optional shell command run on each alert.
+    ///
+    /// returns: RateMonitor
+    ///
+    pub fn new(threshold_percent: u32, hook: Option<String>) -> Self {
+        Self {
+            threshold: threshold_percent as f64 / 100.0,
+            hook,
+            window_start: None,
+            window_bytes: 0,
+            nominal: 0.0,
+            learned_windows: 0,
+            in_anomaly: false,
+        }
+    }
+
+    /// Change the deviation from the nominal rate considered an anomaly.
+    pub fn set_threshold(&mut self, threshold_percent: u32) {
+        self.threshold = threshold_percent as f64 / 100.0;
+    }
+
+    /// Account for bytes received from the master, 0 if the read did not return anything.
+    pub fn observe(&mut self, bytes: usize, now: Instant) -> Option<RateEvent> {
+        self.window_bytes += bytes as u64;
+        let window_start = *self.window_start.get_or_insert(now);
+        let elapsed = now.duration_since(window_start);
+        if elapsed < WINDOW {
+            return None;
+        }
+        let rate = self.window_bytes as f64 / elapsed.as_secs_f64();
+        self.window_start = Some(now);
+        self.window_bytes = 0;
+        let event = self.evaluate(rate);
+        if let Some(event) = &event {
+            self.alert(event);
+        }
+        event
+    }
+
+    fn evaluate(&mut self, rate: f64) -> Option<RateEvent> {
+        if self.learned_windows < LEARNING_WINDOWS {
+            self.learned_windows += 1;
+            // a plain average while learning.
+            self.nominal += (rate - self.nominal) / self.learned_windows as f64;
+            if self.learned_windows == LEARNING_WINDOWS {
+                info!("Nominal data rate learned: {:.1} B/s.", self.nominal);
+            }
+            return None;
+        }
+        let nominal = self.nominal;
+        let deviates = (rate - nominal).abs() > nominal * self.threshold;
+        match (deviates, self.in_anomaly) {
+            (true, false) => {
+                self.in_anomaly = true;
+                Some(RateEvent::Anomaly { rate, nominal })
+            }
+            (false, true) => {
+                self.in_anomaly = false;
+                Some(RateEvent::Recovered { rate, nominal })
+            }
+            (false, false) => {
+                // only learn from the normal behavior.
+                self.nominal += ALPHA * (rate - nominal);
+                None
+            }
+            (true, true) => None,
+        }
+    }
+
+    fn alert(&self, event: &RateEvent) {
+        let (kind, rate, nominal) = match *event {
+            RateEvent::Anomaly { rate, nominal } => {
+                warn!(
+                    event = Event::RateAnomaly.code();
+                    "Data rate anomaly: {:.1} B/s while the nominal rate is {:.1} B/s.",
+                    rate, nominal
+                );
+                ("anomaly", rate, nominal)
+            }
+            RateEvent::Recovered { rate, nominal } => {
+                info!(
+                    "Data rate back to normal: {:.1} B/s (nominal {:.1} B/s).",
+                    rate, nominal
+                );
+                ("recovered", rate, nominal)
+            }
+        };
+        if let Some(hook) = &self.hook {
+            match Command::new("sh")
+                .arg("-c")
+                .arg(hook)
+                .env("TTYTEE_RATE_EVENT", kind)
+                .env("TTYTEE_RATE", format!("{:.1}", rate))
+                .env("TTYTEE_NOMINAL_RATE", format!("{:.1}", nominal))
+                .spawn()
+            {
+                // reap it in the background, the main loop cannot wait for it.
+                Ok(mut child) => {
+                    thread::spawn(move || child.wait());
+                }
+                Err(err) => error!("Could not run the rate alert hook {:?}: {}", hook, err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rate::{RateEvent, RateMonitor, LEARNING_WINDOWS, WINDOW};
+    use std::time::Instant;
+
+    #[test]
+    fn test_rate_drop_is_detected() {
+        let mut monitor = RateMonitor::new(50, None);
+        let mut now = Instant::now();
+        monitor.observe(0, now);
+        // 1000 B/s
+        for _ in 0..LEARNING_WINDOWS * 2 {
+            now += WINDOW;
+            assert_eq!(monitor.observe(5000, now), None);
+        }
+        // the receiver drops to 100 B/s
+        now += WINDOW;
+        assert_eq!(
+            monitor.observe(500, now),
+            Some(RateEvent::Anomaly {
+                rate: 100.0,
+                nominal: 1000.0
+            })
+        );
+        // no repeated alerts.
+        now += WINDOW;
+        assert_eq!(monitor.observe(500, now), None);
+        now += WINDOW;
+        assert_eq!(
+            monitor.observe(5000, now),
+            Some(RateEvent::Recovered {
+                rate: 1000.0,
+                nominal: 1000.0
+            })
+        );
+    }
+
+    #[test]
+    fn test_small_variations_are_tolerated() {
+        let mut monitor = RateMonitor::new(50, None);
+        let mut now = Instant::now();
+        monitor.observe(0, now);
+        for i in 0..LEARNING_WINDOWS * 4 {
+            now += WINDOW;
+            let bytes = if i % 2 == 0 { 4000 } else { 6000 };
+            assert_eq!(monitor.observe(bytes, now), None);
+        }
+    }
+}
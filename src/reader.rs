@@ -0,0 +1,513 @@
+//! The thread reading the master, so the reads are not delayed by a slow endpoint. It moves the
+//! master through the states of its lifecycle.
+//!
+//! The reads avoid allocating and copying at the high rates (460800 bauds and more on the small
+//! ARM boards): the buffers are allocated once and given back by the writers after use, a burst
+//! larger than a buffer is read at once into two of them with a vectored read, and the writers
+//! take all the reads queued at once as a single batch, written once to each endpoint.
+//!
+//! The outcomes of a read are told apart: a timeout is the master being silent, only counted and
+//! moving it to stalled after a while, an interrupted call (a signal) is retried right away, and a
+//! real error of the device is retried after a growing delay, the master reconnecting then failed.
+//! Each of them is counted in the stats.
+//!
+//! An end of file usually means that a USB serial adapter is gone, what happens then is the policy
+//! of `--on-master-eof`. A master reopened, or replaced by the failover master, keeps its file
+//! descriptor: the new device is duplicated over it, so the BREAKs, the modem lines and the cleanup
+//! still act on the master in use. The other handles of the master, like the one writing the NTRIP
+//! corrections, get the new device duplicated over them too.
+
+use crate::backend::{open_device, MasterSettings};
+use crate::backoff::Backoff;
+use crate::lifecycle::{MasterLifecycle, MasterState, FAILED_AFTER, STALL_AFTER};
+use clap::ValueEnum;
+use log::{debug, info};
+use serialport::{SerialPort, TTYPort};
+use std::io;
+use std::io::ErrorKind;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TryRecvError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use std::{mem, ptr, thread};
+
+// The size of a read buffer, the kernel buffers up to 4 KiB for a tty: a full one is read at once
+// into two of them.
+const READ_SIZE: usize = 2048;
+// The most bytes of the queued reads taken in a batch.
+const MAX_BATCH: usize = 64 * 1024;
+
+/// The side of the reader: where to send the reads, and the buffers given back.
+pub struct ReadSender {
+    reads: SyncSender<Vec<u8>>,
+    free: Receiver<Vec<u8>>,
+}
+
+impl ReadSender {
+    // A buffer to read into, a new one only when all of them are in use.
+    fn buffer(&self) -> Vec<u8> {
+        let mut buffer = self.free.try_recv().unwrap_or_default();
+        buffer.clear();
+        buffer.reserve(READ_SIZE);
+        buffer
+    }
+}
+
+/// The side of the writers: the reads in batches, and the buffers to give back.
+pub struct ReadQueue {
+    reads: Receiver<Vec<u8>>,
+    free: SyncSender<Vec<u8>>,
+}
+
+/// Create the queue of the reads of the master, with its buffers.
+///
+/// # Arguments
+///
+/// * `size`: how many reads can be waiting for the writers.
+///
+/// returns: (ReadSender, ReadQueue)
+///
+pub fn read_queue(size: usize) -> (ReadSender, ReadQueue) {
+    let (sender, reads) = sync_channel(size);
+    // the reads waiting, the one being written and the two being read into.
+    let (free, buffers) = sync_channel(size + 3);
+    for _ in 0..size + 3 {
+        free.send(Vec::with_capacity(READ_SIZE)).unwrap();
+    }
+    (
+        ReadSender {
+            reads: sender,
+            free: buffers,
+        },
+        ReadQueue { reads, free },
+    )
+}
+
+impl ReadQueue {
+    /// Wait for a read, and take the reads queued after it in the same batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout`: how long to wait for the first read.
+    ///
+    /// returns: Result<Vec<u8>, RecvTimeoutError> the batch, empty on a timeout of the master.
+    ///
+    pub fn recv_batch(&self, timeout: Duration) -> Result<Vec<u8>, RecvTimeoutError> {
+        let mut batch = self.reads.recv_timeout(timeout)?;
+        while batch.len() < MAX_BATCH {
+            match self.reads.try_recv() {
+                Ok(read) => {
+                    batch.extend_from_slice(&read);
+                    self.recycle(read);
+                }
+                Err(TryRecvError::Empty) => break,
+                // the last reads are written first.
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Give a buffer back to the reader once written.
+    pub fn recycle(&self, buffer: Vec<u8>) {
+        // the reads of nothing have no buffer, and there may be enough already.
+        if buffer.capacity() > 0 {
+            self.free.try_send(buffer).ok();
+        }
+    }
+}
+
+/// Wait for data up to a timeout, then read what is there into two buffers at once.
+///
+/// # Arguments
+///
+/// * `fd`: the master.
+/// * `timeout`: how long to wait for data.
+/// * `buffers`: where to read, the second one is filled only when the first one is full.
+///
+/// returns: Result<usize, Error> the bytes read, 0 on an end of file.
+///
+fn read_vectored(fd: RawFd, timeout: Duration, buffers: [&mut Vec<u8>; 2]) -> io::Result<usize> {
+    let mut poll_fd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // to the µs, for the silences between the frames of Modbus.
+    let timeout = libc::timespec {
+        tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    };
+    match unsafe { libc::ppoll(&mut poll_fd, 1, &timeout, ptr::null()) } {
+        0 => return Err(io::Error::from(ErrorKind::TimedOut)),
+        ready if ready < 0 => return Err(io::Error::last_os_error()),
+        _ => {}
+    }
+    let [first, second] = buffers;
+    let iovecs = [&mut *first, &mut *second].map(|buffer| {
+        let spare = buffer.spare_capacity_mut();
+        libc::iovec {
+            iov_base: spare.as_mut_ptr().cast(),
+            iov_len: spare.len(),
+        }
+    });
+    let len = unsafe { libc::readv(fd, iovecs.as_ptr(), 2) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let len = len as usize;
+    let in_first = len.min(iovecs[0].iov_len);
+    // the kernel wrote these bytes.
+    unsafe {
+        first.set_len(first.len() + in_first);
+        second.set_len(second.len() + len - in_first);
+    }
+    Ok(len)
+}
+
+/// What the reader does on an end of file of the master.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum EofPolicy {
+    /// Read the master again after a delay.
+    Retry,
+    /// Close the master and open its device again after a delay.
+    #[default]
+    Reopen,
+    /// Open the failover master instead, then the master again at the next end of file.
+    Failover,
+    /// Stop ttytee with the code 5.
+    Exit,
+}
+
+/// How the reader handles an end of file of the master.
+#[derive(Clone, Debug, Default)]
+pub struct EofHandling {
+    pub policy: EofPolicy,
+    // the devices opened in turn, the master then the failover master. Empty when the master is
+    // not a device (remote or I2C), it is then read again.
+    pub devices: Vec<PathBuf>,
+    pub settings: MasterSettings,
+    // the other handles of the master, like the one of the NTRIP corrections, moved to the new
+    // device too. They must stay open as long as the reader runs.
+    pub handles: Vec<RawFd>,
+}
+
+/// Open a device in place of the master, the file descriptor of the master is kept.
+///
+/// # Arguments
+///
+/// * `tty`: the master.
+/// * `device`: the device to open.
+/// * `settings`: how to open and configure it.
+/// * `handles`: the other handles of the master, they get the new device too.
+///
+/// returns: Result<(), Error>
+///
+fn reopen(
+    tty: &TTYPort,
+    device: &Path,
+    settings: &MasterSettings,
+    handles: &[RawFd],
+) -> io::Result<()> {
+    let port = open_device(device, settings)?;
+    // the new device replaces the old one, which is closed.
+    for fd in std::iter::once(tty.as_raw_fd()).chain(handles.iter().copied()) {
+        if unsafe { libc::dup2(port.as_raw_fd(), fd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Read the master until `running` is cleared, the receiving side is gone or an end of file stops
+/// ttytee.
+///
+/// # Arguments
+///
+/// * `tty`: the master.
+/// * `queue`: where to send what was read, empty when nothing was (timeout, EOF or error) so the
+///   writers still do their periodic work.
+/// * `running`: cleared to stop.
+/// * `timeout`: the read timeout in ms, it can change while running.
+/// * `backoff`: how long to wait after consecutive errors.
+/// * `lifecycle`: the state of the master.
+/// * `eof`: what to do on an end of file.
+///
+/// returns: ()
+///
+pub fn read_master(
+    tty: TTYPort,
+    queue: ReadSender,
+    running: &AtomicBool,
+    timeout: &AtomicU64,
+    mut backoff: Backoff,
+    lifecycle: &Mutex<MasterLifecycle>,
+    eof: &EofHandling,
+) {
+    let name = tty.name().unwrap_or_default();
+    // the two buffers read into, the second one is only used by the bursts.
+    let mut buffer = queue.buffer();
+    let mut spare = queue.buffer();
+    // since when nothing was read, the start at first.
+    let mut silent_since = Instant::now();
+    // the consecutive errors.
+    let mut errors = 0;
+    // the index in eof.devices of the device in use.
+    let mut device = 0;
+    while running.load(Ordering::Relaxed) {
+        // the bytes of a frame delimited by a silence are only sent once the line is silent.
+        let wanted_timeout = match eof.settings.frame_gap {
+            Some(gap) if !buffer.is_empty() => gap,
+            _ => Duration::from_millis(timeout.load(Ordering::Relaxed)),
+        };
+        let mut at_eof = false;
+        let failure =
+            match read_vectored(tty.as_raw_fd(), wanted_timeout, [&mut buffer, &mut spare]) {
+                Ok(0) => {
+                    at_eof = true;
+                    lifecycle.lock().unwrap().count_eof();
+                    if eof.policy == EofPolicy::Exit {
+                        lifecycle.lock().unwrap().transition(
+                            MasterState::Failed,
+                            "end of file, stopping",
+                            Instant::now(),
+                        );
+                        break;
+                    }
+                    Some("end of file".to_string())
+                }
+                Ok(read_len) => {
+                    debug!("Received from {}: {} bytes.", name, read_len);
+                    errors = 0;
+                    backoff.success();
+                    silent_since = Instant::now();
+                    lifecycle.lock().unwrap().transition(
+                        MasterState::Streaming,
+                        "data received",
+                        silent_since,
+                    );
+                    // the spare buffer is only used when the first one is full.
+                    if eof.settings.frame_gap.is_some() && spare.is_empty() {
+                        continue;
+                    }
+                    let read = mem::replace(&mut buffer, queue.buffer());
+                    if queue.reads.send(read).is_err() {
+                        // the writers are gone.
+                        break;
+                    }
+                    if !spare.is_empty() {
+                        let burst = mem::replace(&mut spare, queue.buffer());
+                        if queue.reads.send(burst).is_err() {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                // a signal, or nothing to read after all: the read is made again right away.
+                Err(err)
+                    if matches!(err.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock) =>
+                {
+                    lifecycle.lock().unwrap().count_interruption();
+                    continue;
+                }
+                // the silence after a frame.
+                Err(err) if err.kind() == ErrorKind::TimedOut && !buffer.is_empty() => {
+                    let read = mem::replace(&mut buffer, queue.buffer());
+                    if queue.reads.send(read).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                Err(err) if err.kind() == ErrorKind::TimedOut => {
+                    lifecycle.lock().unwrap().count_timeout();
+                    errors = 0;
+                    backoff.success();
+                    if silent_since.elapsed() >= STALL_AFTER {
+                        lifecycle.lock().unwrap().transition(
+                            MasterState::Stalled,
+                            &format!("no data for {} s", STALL_AFTER.as_secs()),
+                            Instant::now(),
+                        );
+                    }
+                    None
+                }
+                Err(err) => {
+                    lifecycle.lock().unwrap().count_read_error();
+                    Some(err.to_string())
+                }
+            };
+        if let Some(reason) = failure {
+            errors += 1;
+            let state = if errors >= FAILED_AFTER {
+                MasterState::Failed
+            } else {
+                MasterState::Reconnecting
+            };
+            debug!("Could not read from {}: {}.", name, reason);
+            lifecycle
+                .lock()
+                .unwrap()
+                .transition(state, &reason, Instant::now());
+            thread::sleep(backoff.failure(Instant::now()));
+            let reopens = matches!(eof.policy, EofPolicy::Reopen | EofPolicy::Failover);
+            if at_eof && reopens && !eof.devices.is_empty() {
+                if eof.policy == EofPolicy::Failover {
+                    device = (device + 1) % eof.devices.len();
+                }
+                let path = &eof.devices[device];
+                match reopen(&tty, path, &eof.settings, &eof.handles) {
+                    Ok(()) => {
+                        info!("Opened {:?} as the master.", path);
+                        lifecycle.lock().unwrap().transition(
+                            MasterState::Opening,
+                            &format!("{:?} opened", path),
+                            Instant::now(),
+                        );
+                    }
+                    Err(err) => debug!("Could not open {:?}: {}.", path, err),
+                }
+            }
+        }
+        // nothing was read, the writers still do their periodic work.
+        if queue.reads.send(Vec::new()).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::MasterSettings;
+    use crate::backoff::Backoff;
+    use crate::lifecycle::{MasterLifecycle, MasterState};
+    use crate::reader::{read_master, read_queue, read_vectored, reopen, EofHandling, READ_SIZE};
+    use serialport::{SerialPort, TTYPort};
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_read_master() {
+        let (mut gps, master) = TTYPort::pair().unwrap();
+        let (sender, reads) = read_queue(4);
+        let running = AtomicBool::new(true);
+        let timeout = AtomicU64::new(50);
+        let lifecycle = Mutex::new(MasterLifecycle::new(Instant::now()));
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(10));
+                let eof = EofHandling::default();
+                read_master(
+                    master, sender, &running, &timeout, backoff, &lifecycle, &eof,
+                );
+            });
+            // a timeout gives an empty read.
+            let recv = || reads.recv_batch(Duration::from_secs(5)).unwrap();
+            assert!(recv().is_empty());
+            assert!(lifecycle.lock().unwrap().read_outcomes().0 >= 1);
+            gps.write_all(b"$GPGGA\r\n").unwrap();
+            let read = (0..10).map(|_| recv()).find(|read| !read.is_empty());
+            assert_eq!(read.unwrap(), b"$GPGGA\r\n");
+            assert_eq!(lifecycle.lock().unwrap().state(), MasterState::Streaming);
+            // the device is gone.
+            drop(gps);
+            for _ in 0..3 {
+                recv();
+            }
+            assert_eq!(lifecycle.lock().unwrap().state(), MasterState::Reconnecting);
+            // a PTY with no other side is an end of file, not a timeout or an error.
+            let lifecycle = lifecycle.lock().unwrap();
+            assert!(lifecycle.eofs() >= 1);
+            assert_eq!(lifecycle.read_outcomes().1, 0);
+            drop(lifecycle);
+            // the reader stops when nobody listens anymore.
+            drop(reads);
+        });
+    }
+
+    #[test]
+    fn test_reads_until_silence() {
+        let (mut gps, master) = TTYPort::pair().unwrap();
+        let (sender, reads) = read_queue(4);
+        let running = AtomicBool::new(true);
+        let timeout = AtomicU64::new(1000);
+        let lifecycle = Mutex::new(MasterLifecycle::new(Instant::now()));
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(10));
+                let eof = EofHandling {
+                    settings: MasterSettings {
+                        frame_gap: Some(Duration::from_millis(50)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                read_master(
+                    master, sender, &running, &timeout, backoff, &lifecycle, &eof,
+                );
+            });
+            // a pause shorter than the silence is within the frame.
+            gps.write_all(&[0x11, 0x03, 0x00, 0x6B]).unwrap();
+            thread::sleep(Duration::from_millis(10));
+            gps.write_all(&[0x00, 0x03, 0x76, 0x87]).unwrap();
+            let read = reads.reads.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(read, [0x11, 0x03, 0x00, 0x6B, 0x00, 0x03, 0x76, 0x87]);
+            running.store(false, Ordering::Relaxed);
+        });
+    }
+
+    #[test]
+    fn test_vectored_read_and_batch() {
+        let (mut gps, master) = TTYPort::pair().unwrap();
+        let burst: Vec<u8> = (0..READ_SIZE + 100).map(|i| i as u8).collect();
+        gps.write_all(&burst).unwrap();
+        let (sender, reads) = read_queue(4);
+        let (mut first, mut second) = (sender.buffer(), sender.buffer());
+        let len = read_vectored(
+            master.as_raw_fd(),
+            Duration::from_secs(1),
+            [&mut first, &mut second],
+        )
+        .unwrap();
+        assert_eq!(len, READ_SIZE + 100);
+        assert_eq!(first.len(), READ_SIZE);
+        sender.reads.send(first).unwrap();
+        sender.reads.send(second).unwrap();
+        // the queued reads come as one batch.
+        assert_eq!(reads.recv_batch(Duration::from_secs(1)).unwrap(), burst);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let (_old_gps, mut master) = TTYPort::pair().unwrap();
+        let (mut new_gps, device) = TTYPort::pair().unwrap();
+        let path = PathBuf::from(device.name().unwrap());
+        drop(device);
+        let fd = master.as_raw_fd();
+        let settings = MasterSettings {
+            baudrate: 9600,
+            ..Default::default()
+        };
+        let mut corrections = master.try_clone_native().unwrap();
+        reopen(&master, &path, &settings, &[corrections.as_raw_fd()]).unwrap();
+        // the master keeps its file descriptor and reads the new device.
+        assert_eq!(master.as_raw_fd(), fd);
+        new_gps.write_all(b"$GPRMC\r\n").unwrap();
+        master.set_timeout(Duration::from_secs(1)).unwrap();
+        let mut buffer = [0; 16];
+        let len = master.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"$GPRMC\r\n");
+        // the corrections are written to the new device.
+        corrections.write_all(b"\xd3\x00\x00\x47\xea\x4b").unwrap();
+        new_gps.set_timeout(Duration::from_secs(1)).unwrap();
+        let mut buffer = [0; 6];
+        new_gps.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"\xd3\x00\x00\x47\xea\x4b");
+    }
+}
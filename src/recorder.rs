@@ -0,0 +1,246 @@
+//! A flight recorder keeping the last bytes received from the master in a memory mapped ring file.
+//!
+//! As the file is memory mapped, its content survives a crash of ttytee: the kernel writes the
+//! pages back even if the process dies. At startup the recording of the previous run is moved
+//! aside to `<path>.previous` so it is not overwritten by the new one.
+//!
+//! File layout: a header (magic, capacity, total number of bytes written) followed by the ring.
+
+use crate::cleanup::register_flight_recorder;
+use log::{error, info};
+use memmap2::MmapMut;
+use std::fs::{read, rename, File, OpenOptions};
+use std::io;
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+const MAGIC: &[u8; 8] = b"TTYTEEFR";
+const CAPACITY_OFFSET: usize = 8;
+const WRITTEN_OFFSET: usize = 16;
+const HEADER_LEN: usize = 24;
+
+pub struct FlightRecorder {
+    path: PathBuf,
+    map: MmapMut,
+    capacity: usize,
+}
+
+impl FlightRecorder {
+    /// Create a new ring file, moving aside the one from a previous run if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: where to create the ring file.
+    /// * `capacity`: how many bytes of history to keep.
+    ///
+    /// returns: Result<FlightRecorder, Error>
+    ///
+    pub fn create(path: &Path, capacity: usize) -> io::Result<Self> {
+        if path.exists() {
+            let previous = suffixed(path, "previous");
+            rename(path, &previous)?;
+            info!("Previous flight recording moved to {:?}.", previous);
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        file.set_len((HEADER_LEN + capacity) as u64)?;
+        // Safety: the file was just created by us and nobody else is supposed to touch it.
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        map[..CAPACITY_OFFSET].copy_from_slice(MAGIC);
+        map[CAPACITY_OFFSET..WRITTEN_OFFSET].copy_from_slice(&(capacity as u64).to_le_bytes());
+        map[WRITTEN_OFFSET..HEADER_LEN].copy_from_slice(&0u64.to_le_bytes());
+        register_flight_recorder(Some(path));
+        Ok(Self {
+            path: path.to_path_buf(),
+            map,
+            capacity,
+        })
+    }
+
+    /// The ring file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Total number of bytes recorded since the creation, including the overwritten ones.
+    pub fn written(&self) -> u64 {
+        u64::from_le_bytes(self.map[WRITTEN_OFFSET..HEADER_LEN].try_into().unwrap())
+    }
+
+    /// Append data to the ring, overwriting the oldest bytes if it is full.
+    pub fn record(&mut self, data: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        let written = self.written();
+        // only the tail of data can survive if it is bigger than the ring.
+        let data = &data[data.len().saturating_sub(self.capacity)..];
+        let position = (written % self.capacity as u64) as usize;
+        let first = data.len().min(self.capacity - position);
+        let ring = &mut self.map[HEADER_LEN..];
+        ring[position..position + first].copy_from_slice(&data[..first]);
+        ring[..data.len() - first].copy_from_slice(&data[first..]);
+        let written = written + data.len() as u64;
+        self.map[WRITTEN_OFFSET..HEADER_LEN].copy_from_slice(&written.to_le_bytes());
+    }
+
+    /// The recorded bytes in chronological order.
+    pub fn contents(&self) -> Vec<u8> {
+        linearize(&self.map[HEADER_LEN..], self.written())
+    }
+
+    /// Write the recorded bytes in chronological order into a file.
+    pub fn dump(&self, to: &Path) -> io::Result<()> {
+        File::create(to)?.write_all(&self.contents())
+    }
+
+    /// Synchronously write back the ring to the disk.
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+}
+
+impl Drop for FlightRecorder {
+    fn drop(&mut self) {
+        register_flight_recorder(None);
+        self.flush().ok();
+        // if we are unwinding, leave a readable dump of what happened right before.
+        if thread::panicking() {
+            let dump = dump_path(&self.path);
+            match self.dump(&dump) {
+                Ok(_) => error!("Flight recording dumped to {:?}.", dump),
+                Err(err) => error!("Could not dump the flight recording to {:?}: {}", dump, err),
+            }
+        }
+    }
+}
+
+/// Write the content of a ring file in chronological order into a file.
+///
+/// It reads the ring from the disk so it can be used from a panic hook or on the recording of a
+/// crashed run.
+///
+/// # Arguments
+///
+/// * `ring`: the ring file.
+/// * `to`: the file to create.
+///
+/// returns: Result<(), Error>
+///
+pub fn dump_ring_file(ring: &Path, to: &Path) -> io::Result<()> {
+    let content = read(ring)?;
+    if content.len() < HEADER_LEN || &content[..CAPACITY_OFFSET] != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "not a flight recording"));
+    }
+    let capacity = u64::from_le_bytes(content[CAPACITY_OFFSET..WRITTEN_OFFSET].try_into().unwrap());
+    let written = u64::from_le_bytes(content[WRITTEN_OFFSET..HEADER_LEN].try_into().unwrap());
+    let ring = &content[HEADER_LEN..];
+    // a truncated file does not hold the bytes its header counts.
+    if ring.len() as u64 != capacity {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "truncated flight recording, {} bytes of {} with {} written",
+                ring.len(),
+                capacity,
+                written
+            ),
+        ));
+    }
+    File::create(to)?.write_all(&linearize(ring, written))
+}
+
+/// The path used to dump a ring file.
+pub fn dump_path(ring: &Path) -> PathBuf {
+    suffixed(ring, "dump")
+}
+
+// Reorder a ring buffer, `written` being the total number of bytes that went through it.
+fn linearize(ring: &[u8], written: u64) -> Vec<u8> {
+    if ring.is_empty() {
+        return Vec::new();
+    }
+    if written <= ring.len() as u64 {
+        return ring[..written as usize].to_vec();
+    }
+    let position = (written % ring.len() as u64) as usize;
+    [&ring[position..], &ring[..position]].concat()
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut suffixed = path.as_os_str().to_owned();
+    suffixed.push(".");
+    suffixed.push(suffix);
+    PathBuf::from(suffixed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::recorder::{dump_ring_file, linearize, FlightRecorder, HEADER_LEN};
+    use std::fs::{read, remove_file, write};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_ring_wraps_around() {
+        let path = PathBuf::from("/tmp/ttytee_recorder_wrap");
+        remove_file(&path).ok();
+        let mut recorder = FlightRecorder::create(&path, 8).unwrap();
+        recorder.record(b"abc");
+        assert_eq!(recorder.contents(), b"abc");
+        recorder.record(b"defgh");
+        assert_eq!(recorder.contents(), b"abcdefgh");
+        recorder.record(b"ijk");
+        assert_eq!(recorder.contents(), b"defghijk");
+        recorder.record(b"0123456789");
+        assert_eq!(recorder.contents(), b"23456789");
+        assert_eq!(recorder.written(), 19);
+        drop(recorder);
+        remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_truncated_recording() {
+        let path = PathBuf::from("/tmp/ttytee_recorder_truncated");
+        let dump = PathBuf::from("/tmp/ttytee_recorder_truncated.dump");
+        remove_file(&path).ok();
+        let mut recorder = FlightRecorder::create(&path, 16).unwrap();
+        recorder.record(b"$GPGGA,crash");
+        std::mem::forget(recorder);
+        // the header is left, the ring is gone.
+        let content = read(&path).unwrap();
+        write(&path, &content[..HEADER_LEN]).unwrap();
+        assert!(dump_ring_file(&path, &dump).is_err());
+        assert!(!dump.exists());
+        assert_eq!(linearize(&[], 12), b"");
+        remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_previous_recording_is_kept() {
+        let path = PathBuf::from("/tmp/ttytee_recorder_previous");
+        let previous = PathBuf::from("/tmp/ttytee_recorder_previous.previous");
+        let dump = PathBuf::from("/tmp/ttytee_recorder_previous.dump");
+        remove_file(&path).ok();
+        let mut recorder = FlightRecorder::create(&path, 16).unwrap();
+        recorder.record(b"$GPGGA,crash");
+        // simulate a crash: the mapping goes away without any cleanup.
+        std::mem::forget(recorder);
+        dump_ring_file(&path, &dump).unwrap();
+        assert_eq!(read(&dump).unwrap(), b"$GPGGA,crash");
+
+        let recorder = FlightRecorder::create(&path, 16).unwrap();
+        assert_eq!(recorder.contents(), b"");
+        let content = read(&previous).unwrap();
+        assert!(content.windows(12).any(|w| w == b"$GPGGA,crash"));
+        recorder.dump(&dump).unwrap();
+        assert_eq!(read(&dump).unwrap(), b"");
+        drop(recorder);
+        for file in [path, previous, dump] {
+            remove_file(file).ok();
+        }
+    }
+}
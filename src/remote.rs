@@ -0,0 +1,157 @@
+//! A master on another machine: `--master ssh://user@host:/dev/ttyUSB0` runs `cat` on the remote
+//! device through ssh and shares it locally like a local one, for the bench debugging.
+//!
+//! ssh writes into a local PTY and ttytee reads the other side of it, so the reader, the timeouts
+//! and the endpoints are the same as for a local master. ssh is supervised like the `--spawn`
+//! consumers: it is restarted if the connection drops. It runs in batch mode, the authentication
+//! has to work without a password (keys or an agent).
+
+use crate::spawn::{SpawnSpec, SupervisedConsumer};
+use serialport::{SerialPort, TTYPort};
+use std::io;
+use std::path::Path;
+
+const SCHEME: &str = "ssh://";
+
+/// A serial device on another machine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteDevice {
+    // the ssh destination, like user@host or a host of ~/.ssh/config.
+    pub destination: String,
+    pub device: String,
+}
+
+/// Parse a remote master.
+///
+/// # Arguments
+///
+/// * `master`: the --master argument, like `ssh://user@host:/dev/ttyUSB0`.
+///
+/// returns: Option<Result<RemoteDevice, String>> None for a local master.
+///
+pub fn parse_remote_master(master: &Path) -> Option<Result<RemoteDevice, String>> {
+    let remote = master.to_str()?.strip_prefix(SCHEME)?;
+    Some(match remote.split_once(':') {
+        Some((destination, device))
+            // both end up in a shell command.
+            if !destination.is_empty()
+                && destination
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "@.-_".contains(c))
+                && device.starts_with('/')
+                && !device.contains(|c: char| c.is_whitespace() || c == '\'') =>
+        {
+            Ok(RemoteDevice {
+                destination: destination.to_string(),
+                device: device.to_string(),
+            })
+        }
+        _ => Err(format!(
+            "expected ssh://<DESTINATION>:<DEVICE PATH>, got {:?}",
+            master
+        )),
+    })
+}
+
+impl RemoteDevice {
+    /// The shell command copying the remote device to `{pty}`.
+    pub fn command(&self, baudrate: u32) -> String {
+        format!(
+            "ssh -T -o BatchMode=yes {} 'stty -F {} {} raw -echo && exec cat {}' > {{pty}}",
+            self.destination, self.device, baudrate, self.device
+        )
+    }
+}
+
+/// The local side of a remote master, ssh is stopped when it is dropped.
+pub struct RemoteMaster {
+    _ssh: SupervisedConsumer,
+    // kept open so the reads don't fail while ssh is restarted.
+    _pty: TTYPort,
+}
+
+impl RemoteMaster {
+    /// Start copying a remote device.
+    ///
+    /// # Arguments
+    ///
+    /// * `remote`: the device.
+    /// * `baudrate`: set on the remote device.
+    ///
+    /// returns: Result<(TTYPort, RemoteMaster), Error> the port to read as the master.
+    ///
+    pub fn start(remote: &RemoteDevice, baudrate: u32) -> io::Result<(TTYPort, Self)> {
+        Self::start_command(&remote.command(baudrate))
+    }
+
+    fn start_command(command: &str) -> io::Result<(TTYPort, Self)> {
+        let (master, pty) = TTYPort::pair()?;
+        let path = pty.name().expect("a PTY has a name");
+        let ssh = SupervisedConsumer::start(
+            &SpawnSpec {
+                slave: "master".to_string(),
+                command: command.to_string(),
+            },
+            Path::new(&path),
+        );
+        Ok((
+            master,
+            Self {
+                _ssh: ssh,
+                _pty: pty,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::remote::{parse_remote_master, RemoteDevice, RemoteMaster};
+    use serialport::SerialPort;
+    use std::io::Read;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_remote_master() {
+        assert_eq!(parse_remote_master(Path::new("/dev/ttyUSB0")), None);
+        let remote = parse_remote_master(Path::new("ssh://pi@bench:/dev/ttyACM0"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            remote,
+            RemoteDevice {
+                destination: "pi@bench".to_string(),
+                device: "/dev/ttyACM0".to_string()
+            }
+        );
+        assert_eq!(
+            remote.command(115200),
+            "ssh -T -o BatchMode=yes pi@bench 'stty -F /dev/ttyACM0 115200 raw -echo && exec cat /dev/ttyACM0' > {pty}"
+        );
+        assert!(parse_remote_master(Path::new("ssh://bench"))
+            .unwrap()
+            .is_err());
+        assert!(
+            parse_remote_master(Path::new("ssh://bench;reboot:/dev/ttyACM0"))
+                .unwrap()
+                .is_err()
+        );
+        assert!(parse_remote_master(Path::new("ssh://:/dev/ttyACM0"))
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_remote_master() {
+        // plays ssh.
+        let (mut master, _remote) =
+            RemoteMaster::start_command("printf '$GPGGA\\r\\n' > {pty}").unwrap();
+        // as done for a local master.
+        master.set_exclusive(true).unwrap();
+        master.set_timeout(Duration::from_secs(2)).unwrap();
+        let mut read = [0; 16];
+        let len = master.read(&mut read).unwrap();
+        assert_eq!(&read[..len], b"$GPGGA\r\n");
+    }
+}
@@ -0,0 +1,188 @@
+//! Replay into a master, to exercise a device under test with the capture tooling: a capture file
+//! is written to the device with the timing it was recorded with, for example the corrections of a
+//! NTRIP caster recorded once and fed again into a receiver. A file that is not a capture is
+//! written whole, paced by the device only.
+//!
+//! The silences longer than 5 s, like between the runs appended to the same capture, are shortened
+//! to 5 s.
+
+use crate::endpoint::capture::{parse, MAGIC};
+use serialport::TTYPort;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The longest silence replayed.
+const MAX_GAP: Duration = Duration::from_secs(5);
+
+/// What to write and when, since the start of the replay.
+#[derive(Debug, PartialEq)]
+struct Scheduled<'a> {
+    at: Duration,
+    data: &'a [u8],
+}
+
+/// The baudrate of the capture, if any, and the chunks to write.
+///
+/// # Arguments
+///
+/// * `data`: the content of a capture file or of any file.
+///
+/// returns: Result<(Option<u32>, Vec<Scheduled>), String> or where the capture is corrupted.
+///
+fn schedule(data: &[u8]) -> Result<(Option<u32>, Vec<Scheduled<'_>>), String> {
+    if !data.starts_with(MAGIC) {
+        return Ok((
+            None,
+            vec![Scheduled {
+                at: Duration::ZERO,
+                data,
+            }],
+        ));
+    }
+    let capture = parse(data)?;
+    let baudrate = capture
+        .headers
+        .lines()
+        .find_map(|line| line.strip_prefix("baudrate="))
+        .and_then(|baudrate| baudrate.parse().ok());
+    let mut at = Duration::ZERO;
+    let mut previous = None;
+    let mut scheduled = Vec::with_capacity(capture.chunks.len());
+    for chunk in capture.chunks {
+        if let Some(previous) = previous {
+            // the clock may have gone back between two runs.
+            at += Duration::from_micros(chunk.micros.saturating_sub(previous)).min(MAX_GAP);
+        }
+        previous = Some(chunk.micros);
+        scheduled.push(Scheduled {
+            at,
+            data: chunk.data,
+        });
+    }
+    Ok((baudrate, scheduled))
+}
+
+/// Write a file into a master.
+///
+/// # Arguments
+///
+/// * `input`: a capture file, replayed with its timing, or any file, written whole.
+/// * `master`: the device to write to.
+/// * `baudrate`: the baudrate of the device, the one of the capture or 9600 if None.
+/// * `speed`: how much faster than recorded, 0 to write as fast as the device takes it.
+///
+/// returns: Result<String, Error> a summary of what was written.
+///
+pub fn replay(
+    input: &Path,
+    master: &Path,
+    baudrate: Option<u32>,
+    speed: f64,
+) -> io::Result<String> {
+    if !(speed >= 0.0 && speed.is_finite()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid speed {}, expected a factor of 0 or more", speed),
+        ));
+    }
+    let data = std::fs::read(input)?;
+    let (recorded_baudrate, scheduled) = schedule(&data).map_err(|err| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{:?}: {}", input, err))
+    })?;
+    let baudrate = baudrate
+        .or(recorded_baudrate)
+        .unwrap_or(crate::DEFAULT_BAUDRATE);
+    let mut port = TTYPort::open(&serialport::new(master.to_string_lossy(), baudrate))?;
+    let start = Instant::now();
+    let mut written = 0;
+    for chunk in &scheduled {
+        if speed > 0.0 {
+            let due = start + chunk.at.div_f64(speed);
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        port.write_all(chunk.data)?;
+        written += chunk.data.len();
+    }
+    // waits until the device has sent everything.
+    port.flush()?;
+    Ok(format!(
+        "Replayed {} chunks, {} bytes into {:?} at {} bauds in {:.1} s.\n",
+        scheduled.len(),
+        written,
+        master,
+        baudrate,
+        start.elapsed().as_secs_f64()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::capture::{encode_chunk, CaptureHeader};
+    use crate::replay::{replay, schedule, Scheduled};
+    use serialport::{SerialPort, TTYPort};
+    use std::io::Read;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_replay() {
+        let header = CaptureHeader {
+            device: PathBuf::from("/dev/ttyUSB0"),
+            baudrate: 115200,
+        };
+        let mut capture = header.encode("2023-11-14T12:00:00+0000");
+        capture.extend(encode_chunk(b"first", 1_000_000));
+        capture.extend(encode_chunk(b"second", 1_200_000));
+        // a second run, a day later.
+        capture.extend(header.encode("2023-11-15T12:00:00+0000"));
+        capture.extend(encode_chunk(b"third", 86_401_000_000));
+        let (baudrate, scheduled) = schedule(&capture).unwrap();
+        assert_eq!(baudrate, Some(115200));
+        assert_eq!(
+            scheduled,
+            vec![
+                Scheduled {
+                    at: Duration::ZERO,
+                    data: b"first"
+                },
+                Scheduled {
+                    at: Duration::from_millis(200),
+                    data: b"second"
+                },
+                Scheduled {
+                    at: Duration::from_millis(5200),
+                    data: b"third"
+                },
+            ]
+        );
+        assert_eq!(schedule(b"raw").unwrap().1.len(), 1);
+
+        let path = PathBuf::from("/tmp/ttytee_replay_test.cap");
+        std::fs::write(&path, &capture[..capture.len() - 22]).unwrap();
+        let (mut receiver, device) = TTYPort::pair().unwrap();
+        receiver.set_timeout(Duration::from_millis(100)).unwrap();
+        let start = Instant::now();
+        let summary = replay(&path, &PathBuf::from(device.name().unwrap()), None, 2.0);
+        assert!(
+            matches!(&summary, Err(err) if err.to_string().contains("truncated header")),
+            "{:?}",
+            summary
+        );
+        std::fs::write(&path, &capture).unwrap();
+        let summary = replay(&path, &PathBuf::from(device.name().unwrap()), None, 4.0).unwrap();
+        assert!(summary.starts_with("Replayed 3 chunks, 16 bytes into "));
+        assert!(summary.contains(" at 115200 bauds "));
+        // the 5.2 s of the capture at 4 times the speed.
+        assert!(start.elapsed() >= Duration::from_millis(1300));
+        let mut received = Vec::new();
+        let mut buffer = [0; 32];
+        while let Ok(len) = receiver.read(&mut buffer) {
+            received.extend_from_slice(&buffer[..len]);
+        }
+        assert_eq!(received, b"firstsecondthird");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! Half-duplex RS-485 masters: the receiver and ttytee share a single pair of wires, so the driver
+//! must be switched to transmit only while the corrections are written, and they must not be
+//! written while the receiver talks. `--rs485 kernel` has the UART driver switch it with RTS
+//! (TIOCSRS485), `--rs485 rts` raises RTS around each write for the drivers without it.
+//!
+//! Either way the writes wait for the bus to be quiet for *rs485-turnaround* ms after the last bytes
+//! of the receiver, and for the bytes written to be sent before the bus is released.
+
+use clap::ValueEnum;
+use log::debug;
+use serialport::{SerialPort, TTYPort};
+use std::io;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The flags of struct serial_rs485 in linux/serial.h.
+const SER_RS485_ENABLED: u32 = 1 << 0;
+const SER_RS485_RTS_ON_SEND: u32 = 1 << 1;
+// The longest a write waits for the bus, a receiver that never stops would block the writer.
+const MAX_WAIT: Duration = Duration::from_secs(1);
+// How often the bus is checked while it is busy.
+const WAIT_PERIOD: Duration = Duration::from_millis(1);
+
+/// How the direction of the RS-485 driver is switched.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Rs485Mode {
+    /// By the UART driver, with TIOCSRS485.
+    Kernel,
+    /// By ttytee, raising RTS around each write.
+    Rts,
+}
+
+// struct serial_rs485 in linux/serial.h.
+#[repr(C)]
+#[derive(Default)]
+struct SerialRs485 {
+    flags: u32,
+    delay_rts_before_send: u32,
+    delay_rts_after_send: u32,
+    padding: [u32; 5],
+}
+
+/// Put the master in half-duplex mode, receiving while it does not write.
+///
+/// # Arguments
+///
+/// * `port`: the master, open.
+/// * `mode`: how the direction is switched.
+///
+/// returns: Result<(), Error> if the driver has no RS-485 mode or RTS cannot be set.
+///
+pub fn configure(port: &mut TTYPort, mode: Rs485Mode) -> io::Result<()> {
+    match mode {
+        Rs485Mode::Kernel => {
+            // without SER_RS485_RX_DURING_TX, the receiver does not get the bytes written back.
+            let config = SerialRs485 {
+                flags: SER_RS485_ENABLED | SER_RS485_RTS_ON_SEND,
+                ..Default::default()
+            };
+            if unsafe { libc::ioctl(port.as_raw_fd(), libc::TIOCSRS485, &config) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+        Rs485Mode::Rts => Ok(port.write_request_to_send(false)?),
+    }
+}
+
+/// When the receiver last talked on the bus, shared by the reading loop and the writers.
+pub struct Bus {
+    start: Instant,
+    // in µs since `start`, 0 before the first bytes.
+    last_receive: AtomicU64,
+    turnaround: Duration,
+}
+
+impl Bus {
+    pub fn new(turnaround: Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            last_receive: AtomicU64::new(0),
+            turnaround,
+        }
+    }
+
+    /// Account for bytes of the receiver.
+    pub fn received(&self, now: Instant) {
+        let since_start = now.saturating_duration_since(self.start).as_micros() as u64;
+        self.last_receive.store(since_start + 1, Ordering::Relaxed);
+    }
+
+    /// How long before the bus is quiet enough to write, zero if it is.
+    pub fn busy_for(&self, now: Instant) -> Duration {
+        match self.last_receive.load(Ordering::Relaxed) {
+            0 => Duration::ZERO,
+            last_receive => {
+                let free_at =
+                    self.start + Duration::from_micros(last_receive - 1) + self.turnaround;
+                free_at.saturating_duration_since(now)
+            }
+        }
+    }
+
+    // Wait for the bus to be quiet, at most MAX_WAIT.
+    fn wait(&self) {
+        let start = Instant::now();
+        loop {
+            let now = Instant::now();
+            let busy_for = self.busy_for(now);
+            if busy_for.is_zero() {
+                return;
+            }
+            if now.duration_since(start) >= MAX_WAIT {
+                debug!(
+                    "The RS-485 bus is still busy after {:?}, writing.",
+                    MAX_WAIT
+                );
+                return;
+            }
+            thread::sleep(busy_for.min(WAIT_PERIOD));
+        }
+    }
+}
+
+/// Writes to a half-duplex master between the messages of the receiver.
+pub struct HalfDuplexWriter<'a> {
+    port: &'a mut TTYPort,
+    bus: &'a Bus,
+    mode: Rs485Mode,
+}
+
+impl<'a> HalfDuplexWriter<'a> {
+    pub fn new(port: &'a mut TTYPort, bus: &'a Bus, mode: Rs485Mode) -> Self {
+        Self { port, bus, mode }
+    }
+}
+
+impl Write for HalfDuplexWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bus.wait();
+        if self.mode == Rs485Mode::Rts {
+            self.port.write_request_to_send(true)?;
+        }
+        let result = self.port.write_all(buf).and_then(|_| self.port.flush());
+        // the bus is released even after an error.
+        if self.mode == Rs485Mode::Rts {
+            self.port.write_request_to_send(false)?;
+        }
+        result.map(|_| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rs485::{Bus, HalfDuplexWriter, Rs485Mode};
+    use serialport::{SerialPort, TTYPort};
+    use std::io::{Read, Write};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_bus() {
+        let bus = Bus::new(Duration::from_millis(20));
+        let now = Instant::now();
+        assert_eq!(bus.busy_for(now), Duration::ZERO);
+        bus.received(now);
+        let busy_for = bus.busy_for(now);
+        assert!(busy_for > Duration::from_millis(19) && busy_for <= Duration::from_millis(20));
+        assert_eq!(
+            bus.busy_for(now + Duration::from_millis(20)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_half_duplex_writer() {
+        let (mut gps, mut master) = TTYPort::pair().unwrap();
+        let bus = Bus::new(Duration::from_millis(50));
+        // a PTY has no RS-485 driver, the writer only waits for the bus.
+        let mut writer = HalfDuplexWriter::new(&mut master, &bus, Rs485Mode::Kernel);
+        let start = Instant::now();
+        bus.received(start);
+        writer.write_all(b"\xd3\x00\x13").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        gps.set_timeout(Duration::from_secs(1)).unwrap();
+        let mut buffer = [0; 3];
+        gps.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"\xd3\x00\x13");
+    }
+}
@@ -0,0 +1,67 @@
+//! Decoding of the content of RTCM 3 messages, the framer only splits and validates them.
+
+use crate::framing::Frame;
+
+/// Whether the 12 bits after the message number are the reference station ID (DF003): the
+/// observations, the antenna descriptions, the texts and the MSM. The ephemerides carry a satellite
+/// number there.
+fn has_station_id(number: u16) -> bool {
+    match number {
+        1001..=1013 | 1029 | 1032 | 1033 | 1230 => true,
+        // MSM1 to MSM7 of GPS, GLONASS, Galileo, SBAS, QZSS, BeiDou and NavIC.
+        1071..=1137 => (1..=7).contains(&(number % 10)),
+        _ => false,
+    }
+}
+
+/// The reference station ID of a RTCM message, for the messages that have one.
+///
+/// # Arguments
+///
+/// * `frame`: a complete frame.
+///
+/// returns: Option<u16> None for another protocol or a message without station.
+///
+pub fn rtcm_station(frame: &Frame) -> Option<u16> {
+    let number = frame.rtcm_message_number()?;
+    if !has_station_id(number) {
+        return None;
+    }
+    // after the 3 bytes of header and the 12 bits of the message number.
+    let bits = frame.data.get(4..6)?;
+    Some((((bits[0] & 0x0F) as u16) << 8) | bits[1] as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{crc24q, Frame, Protocol};
+    use crate::rtcm::rtcm_station;
+
+    fn rtcm(payload: &[u8]) -> Frame {
+        let mut data = vec![0xD3];
+        data.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        data.extend_from_slice(payload);
+        let crc = crc24q(&data);
+        data.extend_from_slice(&crc.to_be_bytes()[1..]);
+        Frame {
+            protocol: Protocol::Rtcm,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_rtcm_station() {
+        // 1005, station 2003.
+        let frame = rtcm(&[0x3E, 0xD7, 0xD3, 0x02, 0x02, 0x98, 0x0E, 0xDE, 0xEF, 0x34]);
+        assert_eq!(frame.message_type(), "RTCM-1005");
+        assert_eq!(rtcm_station(&frame), Some(2003));
+        // 1077, station 1.
+        let frame = rtcm(&[0x43, 0x50, 0x01, 0x00]);
+        assert_eq!(frame.message_type(), "RTCM-1077");
+        assert_eq!(rtcm_station(&frame), Some(1));
+        // 1019, a GPS ephemeris, starts with a satellite number.
+        assert_eq!(rtcm_station(&rtcm(&[0x3F, 0xB1, 0x40, 0x00])), None);
+        assert_eq!(rtcm_station(&rtcm(&[])), None);
+        assert_eq!(rtcm(&[]).message_type(), "RTCM");
+    }
+}
@@ -0,0 +1,355 @@
+//! Landlock sandbox applied once everything is open, so a compromised ttytee, which often runs as
+//! root on the vehicles, can only touch the few paths it still needs.
+//!
+//! The network is not restricted, the TCP and UDP endpoints keep working. The spawned consumers and
+//! the hooks would inherit the sandbox so they cannot be used with it.
+//!
+//! Landlock only restricts the thread applying it and the threads it creates afterwards, so it is
+//! applied before ttytee starts any thread: the I2C poller, the PPS reader, the control socket and
+//! the reader of the master all start after it.
+//!
+//! There is no seccomp filter: the syscalls needed depend on the libc, the architecture and the
+//! endpoints (sqlite, CAN, the serial ioctls...), and a syscall missing from the list kills ttytee
+//! with SIGSYS in the field, an outage on an unattended vehicle. With no new privileges and the
+//! paths restricted, what a compromised ttytee can reach is already limited to its devices.
+
+use crate::endpoint::{EndpointKind, EndpointSpec};
+use crate::i2c::parse_i2c_master;
+use crate::reader::EofPolicy;
+use crate::remote::parse_remote_master;
+use crate::Args;
+use log::info;
+use std::ffi::CString;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+// From linux/landlock.h, the access rights of the first version of the ABI used here.
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+// all the 13 rights of the first version, the ones not allowed by a rule are denied.
+const ACCESS_FS_ALL: u64 = (1 << 13) - 1;
+const RULE_PATH_BENEATH: libc::c_int = 1;
+
+const READ: u64 = ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+const WRITE_FILES: u64 = READ | ACCESS_FS_WRITE_FILE | ACCESS_FS_MAKE_REG | ACCESS_FS_REMOVE_FILE;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: libc::c_int,
+}
+
+/// A path the sandboxed process can still access, with everything under it if it is a directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub path: PathBuf,
+    pub access: u64,
+}
+
+fn rule(path: &Path, access: u64) -> Rule {
+    Rule {
+        path: path.to_path_buf(),
+        access,
+    }
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// A device opened again, a USB adapter plugged again is a new inode: the rule is on the directory
+// of its real path, the links like /dev/serial/by-id are elsewhere.
+fn device_rule(device: &Path) -> Rule {
+    let device = device
+        .canonicalize()
+        .unwrap_or_else(|_| device.to_path_buf());
+    rule(
+        &parent_dir(&device),
+        ACCESS_FS_READ_FILE | ACCESS_FS_WRITE_FILE,
+    )
+}
+
+// The directory above the first strftime pattern of a path, it contains all the files to come.
+fn fixed_dir(pattern: &Path) -> PathBuf {
+    let mut dir = PathBuf::new();
+    for component in pattern.components() {
+        if matches!(component, Component::Normal(name) if name.as_bytes().contains(&b'%')) {
+            break;
+        }
+        dir.push(component);
+    }
+    if dir == pattern {
+        parent_dir(pattern)
+    } else if dir.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        dir
+    }
+}
+
+/// The paths ttytee still needs once everything is open.
+///
+/// # Arguments
+///
+/// * `args`: the command line.
+/// * `specs`: all the endpoints, slave0 and slave1 included.
+///
+/// returns: Vec<Rule>
+///
+pub fn rules(args: &Args, specs: &[EndpointSpec]) -> Vec<Rule> {
+    let mut rules = vec![
+        // the resource usage of the limits.
+        rule(Path::new("/proc"), READ),
+        // the local time of the file endpoints.
+        rule(Path::new("/etc/localtime"), ACCESS_FS_READ_FILE),
+        rule(Path::new("/usr/share/zoneinfo"), READ),
+    ];
+    for spec in specs {
+        match &spec.kind {
+            // the links are removed on exit.
+            EndpointKind::Pty(link) => {
+                rules.push(rule(&parent_dir(link), READ | ACCESS_FS_REMOVE_FILE))
+            }
+            EndpointKind::File(pattern) | EndpointKind::Capture(pattern) => {
+                rules.push(rule(&fixed_dir(pattern), WRITE_FILES | ACCESS_FS_MAKE_DIR))
+            }
+            // with its journal.
+            EndpointKind::Sqlite(path) => rules.push(rule(&parent_dir(path), WRITE_FILES)),
+            EndpointKind::Tcp(_)
+            | EndpointKind::CompressedTcp(_)
+            | EndpointKind::Gpsd(_)
+            | EndpointKind::Udp(_)
+            | EndpointKind::Stdout
+            | EndpointKind::Ntrip(_)
+            | EndpointKind::Can(_)
+            // opened before the sandbox.
+            | EndpointKind::Serial(_, _) => {}
+        }
+    }
+    // the ring is read and dumped next to it on a panic.
+    if let Some(path) = &args.flight_recorder {
+        rules.push(rule(&parent_dir(path), WRITE_FILES));
+    }
+    // created when a trigger fires.
+    if let Some(pattern) = &args.triggered_capture {
+        rules.push(rule(&fixed_dir(pattern), WRITE_FILES | ACCESS_FS_MAKE_DIR));
+    }
+    // opened again on an end of file.
+    let local_master =
+        parse_remote_master(&args.master).is_none() && parse_i2c_master(&args.master).is_none();
+    if local_master && matches!(args.on_master_eof, EofPolicy::Reopen | EofPolicy::Failover) {
+        rules.push(device_rule(&args.master));
+        if let Some(device) = &args.failover_master {
+            rules.push(device_rule(device));
+        }
+    }
+    // opened again after its errors.
+    if let Some(device) = &args.merge_master {
+        rules.push(device_rule(device));
+    }
+    if let Some(path) = &args.exit_report {
+        rules.push(rule(&parent_dir(path), WRITE_FILES));
+    }
+    if let Some(socket) = &args.control_socket {
+        rules.push(rule(
+            &parent_dir(socket),
+            READ | ACCESS_FS_MAKE_SOCK | ACCESS_FS_REMOVE_FILE,
+        ));
+    }
+    rules
+}
+
+fn landlock_error(err: io::Error) -> io::Error {
+    match err.raw_os_error() {
+        Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP) => io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Landlock is not supported or not enabled by this kernel",
+        ),
+        _ => err,
+    }
+}
+
+fn add_rule(ruleset: libc::c_int, rule: &Rule) -> io::Result<()> {
+    let path = CString::new(rule.path.as_os_str().as_bytes())?;
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd < 0 {
+        let err = io::Error::last_os_error();
+        // nothing to allow in what does not exist.
+        return match err.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            _ => Err(io::Error::new(
+                err.kind(),
+                format!("cannot open {:?}: {}", rule.path, err),
+            )),
+        };
+    }
+    let is_dir = unsafe {
+        let mut stat: libc::stat = mem::zeroed();
+        libc::fstat(fd, &mut stat) == 0 && stat.st_mode & libc::S_IFMT == libc::S_IFDIR
+    };
+    // the rights on directories are refused on files.
+    let file_access = ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE;
+    let attr = PathBeneathAttr {
+        allowed_access: if is_dir {
+            rule.access
+        } else {
+            rule.access & file_access
+        },
+        parent_fd: fd,
+    };
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset,
+            RULE_PATH_BENEATH,
+            &attr as *const PathBeneathAttr,
+            0,
+        )
+    };
+    let err = io::Error::last_os_error();
+    unsafe { libc::close(fd) };
+    if result != 0 {
+        return Err(landlock_error(err));
+    }
+    Ok(())
+}
+
+/// Restrict the calling thread, and the threads and processes it creates afterwards, to the
+/// given paths. It cannot gain privileges anymore either.
+///
+/// The other threads already running are not restricted, it must be called before any is started.
+pub fn apply(rules: &[Rule]) -> io::Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let attr = RulesetAttr {
+        handled_access_fs: ACCESS_FS_ALL,
+    };
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            mem::size_of::<RulesetAttr>(),
+            0,
+        )
+    } as libc::c_int;
+    if ruleset < 0 {
+        return Err(landlock_error(io::Error::last_os_error()));
+    }
+    let result = rules
+        .iter()
+        .try_for_each(|rule| add_rule(ruleset, rule))
+        .and_then(|()| {
+            if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) } != 0 {
+                return Err(landlock_error(io::Error::last_os_error()));
+            }
+            Ok(())
+        });
+    unsafe { libc::close(ruleset) };
+    result?;
+    info!("Sandboxed, {} paths are still accessible.", rules.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reader::EofPolicy;
+    use crate::sandbox::{
+        apply, fixed_dir, rule, rules, ACCESS_FS_MAKE_DIR, ACCESS_FS_READ_FILE,
+        ACCESS_FS_WRITE_FILE, WRITE_FILES,
+    };
+    use crate::Args;
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+
+    #[test]
+    fn test_fixed_dir() {
+        assert_eq!(
+            fixed_dir(Path::new("/var/log/gps/%Y/%j.nmea")),
+            PathBuf::from("/var/log/gps")
+        );
+        assert_eq!(
+            fixed_dir(Path::new("/var/log/gps.nmea")),
+            PathBuf::from("/var/log")
+        );
+        assert_eq!(fixed_dir(Path::new("gps-%F.nmea")), PathBuf::from("."));
+    }
+
+    #[test]
+    fn test_rules() {
+        let args = Args {
+            master: PathBuf::from("/dev/ttyUSB0"),
+            failover_master: Some(PathBuf::from("/run/ttytee/failover/ttyACM0")),
+            on_master_eof: EofPolicy::Failover,
+            ..Default::default()
+        };
+        let devices = ACCESS_FS_READ_FILE | ACCESS_FS_WRITE_FILE;
+        let allowed = rules(&args, &[]);
+        // the adapters plugged again are new devices in the same directory.
+        assert!(allowed.contains(&rule(Path::new("/dev"), devices)));
+        assert!(allowed.contains(&rule(Path::new("/run/ttytee/failover"), devices)));
+        let args = Args {
+            on_master_eof: EofPolicy::Exit,
+            ..args
+        };
+        assert!(!rules(&args, &[]).contains(&rule(Path::new("/dev"), devices)));
+    }
+
+    #[test]
+    fn test_flight_recorder_rule() {
+        let args = Args {
+            flight_recorder: Some(PathBuf::from("/var/lib/ttytee/flight.ring")),
+            ..Default::default()
+        };
+        assert!(rules(&args, &[]).contains(&rule(Path::new("/var/lib/ttytee"), WRITE_FILES)));
+    }
+
+    #[test]
+    fn test_triggered_capture_rule() {
+        let args = Args {
+            triggered_capture: Some(PathBuf::from("/var/lib/ttytee/captures/%F/%H%M%S.ttycap")),
+            ..Default::default()
+        };
+        assert!(rules(&args, &[]).contains(&rule(
+            Path::new("/var/lib/ttytee/captures"),
+            WRITE_FILES | ACCESS_FS_MAKE_DIR
+        )));
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let inside = PathBuf::from("/tmp/ttytee_sandbox_test");
+        fs::create_dir_all(&inside).unwrap();
+        // Landlock restricts the calling thread only, keep it away from the other tests.
+        thread::spawn(move || {
+            match apply(&[rule(&inside, WRITE_FILES)]) {
+                Err(err) if err.kind() == io::ErrorKind::Unsupported => return,
+                result => result.unwrap(),
+            }
+            fs::write(inside.join("allowed"), b"ok").unwrap();
+            let err = fs::write("/tmp/ttytee_sandbox_outside", b"nope").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        })
+        .join()
+        .unwrap();
+        fs::remove_dir_all("/tmp/ttytee_sandbox_test").unwrap();
+    }
+}
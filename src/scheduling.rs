@@ -0,0 +1,157 @@
+//! CPU affinity and real time scheduling of the threads, to reduce the jitter caused by the other
+//! processes on busy single board computers.
+
+use log::{info, warn};
+use std::io;
+use std::mem;
+
+/// The CPUs each thread is pinned to, empty to let the kernel choose.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Affinity {
+    // the thread reading the master.
+    pub reader: Vec<usize>,
+    // the thread writing to the endpoints.
+    pub writers: Vec<usize>,
+}
+
+fn parse_cpus(cpus: &str) -> Result<Vec<usize>, String> {
+    let invalid = || format!("expected a CPU or a range of CPUs like 2-3, got {:?}", cpus);
+    let (first, last) = cpus.split_once('-').unwrap_or((cpus, cpus));
+    let first: usize = first.parse().map_err(|_| invalid())?;
+    let last: usize = last.parse().map_err(|_| invalid())?;
+    if first > last || last >= libc::CPU_SETSIZE as usize {
+        return Err(invalid());
+    }
+    Ok((first..=last).collect())
+}
+
+/// Parse the CPU affinity of the threads from the command line.
+///
+/// # Arguments
+///
+/// * `spec`: a string of the form `reader=0,writers=1-3`.
+///
+/// returns: Result<Affinity, String>
+///
+pub fn parse_affinity(spec: &str) -> Result<Affinity, String> {
+    let mut affinity = Affinity::default();
+    for assignment in spec.split(',') {
+        let (thread, cpus) = assignment
+            .split_once('=')
+            .ok_or_else(|| format!("expected <THREAD>=<CPUS>, got {:?}", assignment))?;
+        match thread {
+            "reader" => affinity.reader = parse_cpus(cpus)?,
+            "writers" => affinity.writers = parse_cpus(cpus)?,
+            _ => {
+                return Err(format!(
+                    "unknown thread {:?}, expected reader or writers",
+                    thread
+                ))
+            }
+        }
+    }
+    Ok(affinity)
+}
+
+fn pin_current_thread(cpus: &[usize]) -> io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        for &cpu in cpus {
+            libc::CPU_SET(cpu, &mut set);
+        }
+        // 0 is the calling thread.
+        if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn set_realtime_priority(priority: i32) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    let result =
+        unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+    if result != 0 {
+        return Err(io::Error::from_raw_os_error(result));
+    }
+    Ok(())
+}
+
+/// Apply the CPU affinity and the real time priority to the calling thread.
+///
+/// The failures are only logged, ttytee still works without them.
+///
+/// # Arguments
+///
+/// * `thread`: the name of the thread in the logs.
+/// * `cpus`: the CPUs to pin it to, empty to leave it alone.
+/// * `priority`: the SCHED_FIFO priority, None to leave it alone.
+///
+/// returns: ()
+///
+pub fn tune_current_thread(thread: &str, cpus: &[usize], priority: Option<i32>) {
+    if !cpus.is_empty() {
+        match pin_current_thread(cpus) {
+            Ok(()) => info!("The {} thread is pinned to the CPUs {:?}.", thread, cpus),
+            Err(err) => warn!(
+                "Could not pin the {} thread to {:?}: {}.",
+                thread, cpus, err
+            ),
+        }
+    }
+    if let Some(priority) = priority {
+        match set_realtime_priority(priority) {
+            Ok(()) => info!(
+                "The {} thread runs with the real time priority {}.",
+                thread, priority
+            ),
+            Err(err) => warn!(
+                "Could not set the real time priority of the {} thread, it needs CAP_SYS_NICE: {}.",
+                thread, err
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scheduling::{parse_affinity, tune_current_thread, Affinity};
+    use std::thread;
+
+    #[test]
+    fn test_parse_affinity() {
+        assert_eq!(
+            parse_affinity("reader=0,writers=1-3"),
+            Ok(Affinity {
+                reader: vec![0],
+                writers: vec![1, 2, 3],
+            })
+        );
+        assert_eq!(
+            parse_affinity("writers=2").unwrap(),
+            Affinity {
+                reader: Vec::new(),
+                writers: vec![2],
+            }
+        );
+        assert!(parse_affinity("reader=3-1").is_err());
+        assert!(parse_affinity("reader=one").is_err());
+        assert!(parse_affinity("logger=0").is_err());
+        assert!(parse_affinity("reader").is_err());
+    }
+
+    #[test]
+    fn test_pin_thread() {
+        // on its own thread not to pin the other tests.
+        thread::spawn(|| {
+            // a CPU the test is allowed to run on, whatever the cpuset of the machine.
+            let cpu = unsafe { libc::sched_getcpu() } as usize;
+            tune_current_thread("test", &[cpu], None);
+            assert_eq!(unsafe { libc::sched_getcpu() } as usize, cpu);
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,50 @@
+//! Stopping on SIGTERM and SIGINT, the usual end of a service or of a run in a terminal.
+//!
+//! The handler only clears `RUNNING`: the reader notices it within its read timeout, the main loop
+//! ends like on an end of file and everything is released in order, the symlinks, the watchdog
+//! with its magic close, the spawned consumers, the manifest entry and the exit report. The handler
+//! is reset once it has run, so a second signal stops ttytee right away if the first one hangs.
+
+use std::io;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Cleared by the first SIGTERM or SIGINT.
+pub static RUNNING: AtomicBool = AtomicBool::new(true);
+
+extern "C" fn stop(_signal: libc::c_int) {
+    // an atomic store is async-signal-safe, nothing else is done here.
+    RUNNING.store(false, Ordering::Relaxed);
+}
+
+/// Clear `RUNNING` on SIGTERM and SIGINT instead of being killed by them.
+///
+/// returns: Result<(), Error> if a handler could not be installed.
+///
+pub fn install_stop_handler() -> io::Result<()> {
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // the interrupted system calls are made again, the reader polls with a timeout anyway.
+        action.sa_flags = libc::SA_RESTART | libc::SA_RESETHAND;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        if unsafe { libc::sigaction(signal, &action, ptr::null_mut()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::signals::{install_stop_handler, RUNNING};
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn test_stop_handler() {
+        install_stop_handler().unwrap();
+        assert!(RUNNING.load(Ordering::Relaxed));
+        unsafe { libc::raise(libc::SIGTERM) };
+        assert!(!RUNNING.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,264 @@
+//! Deterministic simulation of the fan-out for the tests: the time is virtual and the consumers
+//! are in memory, so the slow consumers, the bursts and the disconnections are reproduced exactly
+//! without real PTYs nor sleeps.
+
+use crate::backoff::Backoff;
+use crate::endpoint::{fan_out, Endpoint, EndpointOptions, ManagedEndpoint};
+use crate::framing::{Framer, Protocol};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A clock only moving when told to.
+pub struct VirtualClock {
+    origin: Instant,
+    elapsed: Duration,
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn now(&self) -> Instant {
+        self.origin + self.elapsed
+    }
+
+    pub fn advance(&mut self, duration: Duration) {
+        self.elapsed += duration;
+    }
+}
+
+/// What a simulated consumer did, shared with the test.
+#[derive(Default)]
+pub struct Consumer {
+    // bytes read so far.
+    pub received: Vec<u8>,
+    // bytes dropped from its buffer by ttytee.
+    pub dropped: usize,
+    // how fast it reads, None for instantly.
+    pub bytes_per_second: Option<u64>,
+    pub disconnected: bool,
+    buffer: VecDeque<u8>,
+    // the bytes it could read but couldn't, in thousandths, so slow rates add up.
+    credit_millis: u64,
+}
+
+impl Consumer {
+    fn read_for(&mut self, duration: Duration) {
+        let readable = match self.bytes_per_second {
+            None => self.buffer.len(),
+            Some(bytes_per_second) => {
+                self.credit_millis += bytes_per_second * duration.as_millis() as u64;
+                let readable = (self.credit_millis / 1000) as usize;
+                self.credit_millis %= 1000;
+                readable.min(self.buffer.len())
+            }
+        };
+        self.received.extend(self.buffer.drain(..readable));
+    }
+}
+
+// The endpoint side of a simulated consumer, like the master side of its PTY.
+struct SimulatedEndpoint(Arc<Mutex<Consumer>>);
+
+impl Endpoint for SimulatedEndpoint {
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut consumer = self.0.lock().unwrap();
+        if consumer.disconnected {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        consumer.buffer.extend(data);
+        if consumer.bytes_per_second.is_none() {
+            consumer.read_for(Duration::ZERO);
+        }
+        Ok(())
+    }
+
+    fn pending(&self) -> io::Result<usize> {
+        Ok(self.0.lock().unwrap().buffer.len())
+    }
+
+    fn discard(&mut self) -> io::Result<()> {
+        let mut consumer = self.0.lock().unwrap();
+        consumer.dropped += consumer.buffer.len();
+        consumer.buffer.clear();
+        Ok(())
+    }
+}
+
+/// The fan-out with a simulated master and simulated consumers.
+pub struct Simulation {
+    pub clock: VirtualClock,
+    pub endpoints: Vec<ManagedEndpoint>,
+    consumers: Vec<Arc<Mutex<Consumer>>>,
+    framer: Framer,
+    sequence: u64,
+    // an endpoint with the exit policy failed.
+    pub exited: bool,
+}
+
+impl Simulation {
+    pub fn new(protocols: &[Protocol]) -> Self {
+        Self {
+            clock: VirtualClock::new(),
+            endpoints: Vec::new(),
+            consumers: Vec::new(),
+            framer: Framer::new(protocols),
+            sequence: 0,
+            exited: false,
+        }
+    }
+
+    /// Add a consumer reading at the given rate, None for instantly.
+    pub fn add_consumer(
+        &mut self,
+        name: &str,
+        options: EndpointOptions,
+        bytes_per_second: Option<u64>,
+    ) -> Arc<Mutex<Consumer>> {
+        let consumer = Arc::new(Mutex::new(Consumer {
+            bytes_per_second,
+            ..Default::default()
+        }));
+        self.endpoints.push(ManagedEndpoint::new(
+            name,
+            Box::new(SimulatedEndpoint(Arc::clone(&consumer))),
+            options,
+            Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+        ));
+        self.consumers.push(Arc::clone(&consumer));
+        consumer
+    }
+
+    /// The master sends some data, now.
+    pub fn master_sends(&mut self, data: &[u8]) {
+        let mut frames = Vec::new();
+        self.framer.push(data, &mut frames);
+        let now = self.clock.now();
+        self.exited |= fan_out(&mut self.endpoints, data, &frames, self.sequence, now);
+        self.sequence += frames.len() as u64;
+    }
+
+    /// Let the time pass, the consumers read meanwhile.
+    pub fn advance(&mut self, duration: Duration) {
+        for consumer in &self.consumers {
+            consumer.lock().unwrap().read_for(duration);
+        }
+        self.clock.advance(duration);
+    }
+
+    /// The master sends the same data periodically.
+    pub fn master_sends_every(&mut self, period: Duration, times: usize, data: &[u8]) {
+        for _ in 0..times {
+            self.master_sends(data);
+            self.advance(period);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::EndpointOptions;
+    use crate::framing::Protocol;
+    use crate::simulation::Simulation;
+    use std::time::Duration;
+
+    const GGA: &[u8] = b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n";
+
+    #[test]
+    fn test_slow_consumer_gets_fresh_data() {
+        let mut simulation = Simulation::new(&[Protocol::Nmea]);
+        let options = EndpointOptions {
+            stale_timeout: Duration::from_millis(500),
+            ..Default::default()
+        };
+        let fast = simulation.add_consumer("fast", options.clone(), None);
+        // a third of the 10 Hz stream.
+        let slow = simulation.add_consumer("slow", options, Some(GGA.len() as u64 * 10 / 3));
+        simulation.master_sends_every(Duration::from_millis(100), 100, GGA);
+        let fast = fast.lock().unwrap();
+        assert_eq!(fast.received.len(), GGA.len() * 100);
+        assert_eq!(fast.dropped, 0);
+        let slow = slow.lock().unwrap();
+        // what it could not read was skipped instead of piling up.
+        assert!(slow.received.len() + slow.buffer.len() < GGA.len() * 100);
+        assert!(slow.buffer.len() <= 2048 + GGA.len());
+    }
+
+    #[test]
+    fn test_stalled_consumer_gets_fresh_data() {
+        let mut simulation = Simulation::new(&[Protocol::Nmea]);
+        let stalled = simulation.add_consumer(
+            "stalled",
+            EndpointOptions {
+                stale_timeout: Duration::from_millis(500),
+                max_backlog: 5 * GGA.len(),
+                ..Default::default()
+            },
+            Some(0),
+        );
+        simulation.master_sends_every(Duration::from_millis(100), 10, GGA);
+        stalled.lock().unwrap().bytes_per_second = None;
+        simulation.master_sends(GGA);
+        let stalled = stalled.lock().unwrap();
+        // the 5 sentences waiting for more than 500 ms were dropped, it got the last one right away.
+        assert_eq!(stalled.dropped, 5 * GGA.len());
+        assert_eq!(stalled.received, GGA);
+    }
+
+    #[test]
+    fn test_burst_is_capped_by_the_backlog() {
+        let mut simulation = Simulation::new(&[Protocol::Nmea]);
+        let stuck = simulation.add_consumer(
+            "stuck",
+            EndpointOptions {
+                max_backlog: 2 * GGA.len(),
+                ..Default::default()
+            },
+            Some(0),
+        );
+        for _ in 0..20 {
+            simulation.master_sends(GGA);
+        }
+        // the backlog is checked before writing, so it can go over by one write.
+        assert_eq!(stuck.lock().unwrap().buffer.len(), 2 * GGA.len());
+    }
+
+    #[test]
+    fn test_disconnected_consumer_is_disabled() {
+        let mut simulation = Simulation::new(&[Protocol::Nmea]);
+        let healthy = simulation.add_consumer("healthy", EndpointOptions::default(), None);
+        let mut options = EndpointOptions::default();
+        options.set("on-write-error", "disable:3").unwrap();
+        let gone = simulation.add_consumer("gone", options, None);
+        simulation.master_sends_every(Duration::from_millis(100), 5, GGA);
+        gone.lock().unwrap().disconnected = true;
+        // the backoff leaves it alone for a while after each error.
+        simulation.master_sends_every(Duration::from_millis(100), 50, GGA);
+        assert!(!simulation.endpoints[1]
+            .health
+            .is_ready(simulation.clock.now()));
+        assert!(simulation.endpoints[1].health.is_disabled());
+        assert_eq!(gone.lock().unwrap().received.len(), GGA.len() * 5);
+        assert_eq!(healthy.lock().unwrap().received.len(), GGA.len() * 55);
+        assert!(!simulation.exited);
+    }
+
+    #[test]
+    fn test_exit_policy() {
+        let mut simulation = Simulation::new(&[Protocol::Nmea]);
+        let mut options = EndpointOptions::default();
+        options.set("on-write-error", "exit").unwrap();
+        let critical = simulation.add_consumer("critical", options, None);
+        simulation.master_sends(GGA);
+        assert!(!simulation.exited);
+        critical.lock().unwrap().disconnected = true;
+        simulation.master_sends(GGA);
+        assert!(simulation.exited);
+    }
+}
@@ -0,0 +1,231 @@
+//! Supervision of consumer processes launched by ttytee itself.
+//!
+//! Instead of orchestrating the startup ordering from a shell script, ttytee can be given
+//! `--spawn 'slave0: gpsd -N {pty}'` and it will start the consumer once the PTY exists,
+//! restart it if it exits and stop it when ttytee stops.
+//!
+//! The consumers also get a SIGTERM from the kernel when ttytee dies without stopping them, killed
+//! or after a panic, so they are never left reading a PTY that is gone.
+
+use crate::events::Event;
+use log::{debug, error, info, warn};
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// Placeholder substituted by the real PTY path in the command line.
+const PTY_PLACEHOLDER: &str = "{pty}";
+
+// How often the supervisor checks on its child.
+const POLL_PERIOD: Duration = Duration::from_millis(100);
+
+// Wait time before restarting a consumer that exited.
+const RESPAWN_DELAY: Duration = Duration::from_secs(1);
+
+// How long a consumer has to exit after a SIGTERM before it gets killed.
+const TERMINATION_GRACE: Duration = Duration::from_secs(2);
+
+/// A consumer process to launch, attached to one of the slaves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpawnSpec {
+    pub slave: String,
+    pub command: String,
+}
+
+/// Parse a spawn specification from the command line.
+///
+/// # Arguments
+///
+/// * `spec`: a string of the form `slave0: gpsd -N {pty}`.
+///
+/// returns: Result<SpawnSpec, String>
+///
+pub fn parse_spawn_spec(spec: &str) -> Result<SpawnSpec, String> {
+    let (slave, command) = spec
+        .split_once(':')
+        .ok_or_else(|| format!("expected <SLAVE>: <COMMAND>, got {:?}", spec))?;
+    let slave = slave.trim();
+    let command = command.trim();
+    if slave.is_empty() {
+        return Err(format!("no slave given in {:?}", spec));
+    }
+    if command.is_empty() {
+        return Err(format!("no command given for {}", slave));
+    }
+    Ok(SpawnSpec {
+        slave: slave.to_string(),
+        command: command.to_string(),
+    })
+}
+
+/// A consumer process that is restarted when it exits and stopped at drop time.
+pub struct SupervisedConsumer {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl SupervisedConsumer {
+    /// Start supervising a consumer.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec`: what to launch.
+    /// * `pty`: the real path of the PTY the consumer should read from, substituted for `{pty}`.
+    ///
+    /// returns: SupervisedConsumer
+    ///
+    pub fn start(spec: &SpawnSpec, pty: &Path) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let command = spec
+            .command
+            .replace(PTY_PLACEHOLDER, &pty.to_string_lossy());
+        let slave = spec.slave.clone();
+        let stop_ref = Arc::clone(&stop);
+        let handle = thread::spawn(move || supervise(&slave, &command, &stop_ref));
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for SupervisedConsumer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+fn supervise(slave: &str, command: &str, stop: &AtomicBool) {
+    let parent = std::process::id() as libc::pid_t;
+    while !stop.load(Ordering::Relaxed) {
+        // exec so the signals are delivered to the consumer and not to the shell.
+        let mut command_line = Command::new("sh");
+        command_line.arg("-c").arg(format!("exec {}", command));
+        // the signal is sent when the thread that forked exits, this one lives as long as the child.
+        unsafe {
+            command_line.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // ttytee died before the prctl.
+                if libc::getppid() != parent {
+                    return Err(io::Error::other("ttytee is gone"));
+                }
+                Ok(())
+            });
+        }
+        let mut child = match command_line.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                error!(
+                    event = Event::ConsumerSpawnFailed.code();
+                    "Could not spawn {:?} for {}: {}.",
+                    command, slave, err
+                );
+                wait_or_stop(RESPAWN_DELAY, stop);
+                continue;
+            }
+        };
+        info!("Spawned {:?} for {} (pid {}).", command, slave, child.id());
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                terminate(&mut child);
+                return;
+            }
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    warn!(
+                        event = Event::ConsumerExited.code();
+                        "Consumer {:?} of {} exited: {}.",
+                        command, slave, status
+                    );
+                    break;
+                }
+                Ok(None) => thread::sleep(POLL_PERIOD),
+                Err(err) => {
+                    error!("Could not check on consumer {:?}: {}.", command, err);
+                    terminate(&mut child);
+                    break;
+                }
+            }
+        }
+        wait_or_stop(RESPAWN_DELAY, stop);
+    }
+}
+
+// Sleep for the given duration unless asked to stop in the meantime.
+fn wait_or_stop(duration: Duration, stop: &AtomicBool) {
+    let deadline = Instant::now() + duration;
+    while !stop.load(Ordering::Relaxed) && Instant::now() < deadline {
+        thread::sleep(POLL_PERIOD);
+    }
+}
+
+// Ask the child nicely with a SIGTERM, then kill it if it does not comply in time.
+fn terminate(child: &mut Child) {
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    let deadline = Instant::now() + TERMINATION_GRACE;
+    while Instant::now() < deadline {
+        if let Ok(Some(status)) = child.try_wait() {
+            debug!("Consumer pid {} stopped: {}.", child.id(), status);
+            return;
+        }
+        thread::sleep(POLL_PERIOD);
+    }
+    warn!(
+        "Consumer pid {} did not stop in time, killing it.",
+        child.id()
+    );
+    child.kill().ok();
+    child.wait().ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spawn::{parse_spawn_spec, SpawnSpec, SupervisedConsumer};
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_spawn_spec() {
+        assert_eq!(
+            parse_spawn_spec("slave0: gpsd -N {pty}"),
+            Ok(SpawnSpec {
+                slave: "slave0".to_string(),
+                command: "gpsd -N {pty}".to_string()
+            })
+        );
+        assert!(parse_spawn_spec(": gpsd").is_err());
+        assert!(parse_spawn_spec("slave1:").is_err());
+        assert!(parse_spawn_spec("gpsd -N").is_err());
+    }
+
+    #[test]
+    fn test_supervised_consumer_restarts() {
+        let output = PathBuf::from("/tmp/ttytee_spawn_test");
+        std::fs::remove_file(&output).ok();
+        let spec = SpawnSpec {
+            slave: "slave0".to_string(),
+            command: format!("echo {{pty}} >> {}", output.display()),
+        };
+        let consumer = SupervisedConsumer::start(&spec, &PathBuf::from("/dev/pts/42"));
+        thread::sleep(Duration::from_millis(1500));
+        drop(consumer);
+        let content = std::fs::read_to_string(&output).unwrap();
+        assert!(content.lines().count() >= 2);
+        assert!(content.lines().all(|line| line == "/dev/pts/42"));
+        std::fs::remove_file(&output).ok();
+    }
+}
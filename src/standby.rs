@@ -0,0 +1,122 @@
+//! Hot standby of two instances on the same host: both are given the same `--standby-lock` file
+//! and the one holding its exclusive lock is the primary, the other one waits before opening
+//! anything. When the primary stops for any reason, killed included, the kernel releases its lock
+//! and the backup takes over the master, the links and the control socket, the consumers only see
+//! their PTY reopened.
+//!
+//! The primary writes its pid in the file, for the log of the backup.
+
+use log::info;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// How often the backup tries to take the lock.
+const POLL_PERIOD: Duration = Duration::from_millis(100);
+
+/// The lock of the primary, held until it is dropped or the process ends.
+pub struct StandbyLock {
+    _file: File,
+}
+
+// Try to take the lock without waiting, false if another instance holds it.
+fn try_lock(file: &File) -> io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Become the primary, waiting for the current primary to stop if there is one.
+///
+/// # Arguments
+///
+/// * `path`: the lock file shared with the other instance, created if needed.
+/// * `running`: cleared to stop waiting.
+///
+/// returns: Result<Option<StandbyLock>, Error> None if stopped while waiting.
+///
+pub fn wait_for_primary(path: &Path, running: &AtomicBool) -> io::Result<Option<StandbyLock>> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    let mut waiting = false;
+    while !try_lock(&file)? {
+        if !waiting {
+            let mut primary = String::new();
+            file.read_to_string(&mut primary).ok();
+            info!(
+                "Standing by, the primary (pid {}) holds {:?}.",
+                primary.trim(),
+                path
+            );
+            waiting = true;
+        }
+        if !running.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        thread::sleep(POLL_PERIOD);
+    }
+    if waiting {
+        info!("The primary is gone, taking over.");
+    }
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", std::process::id())?;
+    Ok(Some(StandbyLock { _file: file }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::standby::wait_for_primary;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_standby() {
+        let path = PathBuf::from("/tmp/ttytee_standby_test.lock");
+        fs::remove_file(&path).ok();
+        let running = Arc::new(AtomicBool::new(true));
+        let primary = wait_for_primary(&path, &running).unwrap().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+        // the lock is per open file, a second one in the same process waits too.
+        let backup = {
+            let path = path.clone();
+            let running = running.clone();
+            thread::spawn(move || {
+                let lock = wait_for_primary(&path, &running).unwrap();
+                (lock.is_some(), Instant::now())
+            })
+        };
+        thread::sleep(Duration::from_millis(300));
+        let released = Instant::now();
+        drop(primary);
+        let (taken_over, at) = backup.join().unwrap();
+        assert!(taken_over);
+        assert!(at >= released);
+
+        let _primary = wait_for_primary(&path, &running).unwrap().unwrap();
+        running.store(false, Ordering::Relaxed);
+        assert!(wait_for_primary(&path, &running).unwrap().is_none());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,457 @@
+//! Statistics about the stream going through ttytee, periodically reported in the log.
+
+use crate::endpoint::format::json_string;
+use crate::endpoint::ManagedEndpoint;
+use crate::events::event_counts;
+use crate::lifecycle::{MasterLifecycle, MasterState};
+use crate::uart::UartErrors;
+use log::info;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Default)]
+struct MessageTypeStats {
+    count: u64,
+    // count at the last report, to compute the rate over the report period.
+    reported_count: u64,
+    last_seen: Option<Instant>,
+    // the reference stations of the RTCM messages.
+    stations: BTreeSet<u16>,
+}
+
+pub struct Stats {
+    bytes_read: u64,
+    skipped_bytes: u64,
+    invalid_frames: u64,
+    uart_errors: Option<UartErrors>,
+    master_state: MasterState,
+    eofs: u64,
+    // the reads of the master that timed out, failed and were interrupted.
+    timeouts: u64,
+    read_errors: u64,
+    interruptions: u64,
+    message_types: BTreeMap<String, MessageTypeStats>,
+    last_report: Instant,
+}
+
+impl Stats {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            bytes_read: 0,
+            skipped_bytes: 0,
+            invalid_frames: 0,
+            uart_errors: None,
+            master_state: MasterState::Opening,
+            eofs: 0,
+            timeouts: 0,
+            read_errors: 0,
+            interruptions: 0,
+            message_types: BTreeMap::new(),
+            last_report: now,
+        }
+    }
+
+    /// Account for bytes read from the master.
+    pub fn count_bytes(&mut self, bytes: usize) {
+        self.bytes_read += bytes as u64;
+    }
+
+    /// Update the counters of the framer: bytes out of any frame and frames with a bad checksum.
+    pub fn set_framing_errors(&mut self, skipped_bytes: u64, invalid_frames: u64) {
+        self.skipped_bytes = skipped_bytes;
+        self.invalid_frames = invalid_frames;
+    }
+
+    /// Update the hardware error counters of the master.
+    pub fn set_uart_errors(&mut self, errors: UartErrors) {
+        self.uart_errors = Some(errors);
+    }
+
+    /// Update the state of the master and the counts of the outcomes of its reads.
+    pub fn update_master(&mut self, lifecycle: &MasterLifecycle) {
+        self.master_state = lifecycle.state();
+        self.eofs = lifecycle.eofs();
+        (self.timeouts, self.read_errors, self.interruptions) = lifecycle.read_outcomes();
+    }
+
+    /// Account for a frame received from the master.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_type`: the type of the frame.
+    /// * `station`: the reference station of a RTCM message.
+    /// * `now`: the current time.
+    ///
+    pub fn count_message(&mut self, message_type: &str, station: Option<u16>, now: Instant) {
+        if !self.message_types.contains_key(message_type) {
+            self.message_types
+                .insert(message_type.to_string(), MessageTypeStats::default());
+        }
+        let stats = self.message_types.get_mut(message_type).unwrap();
+        stats.count += 1;
+        stats.last_seen = Some(now);
+        stats.stations.extend(station);
+    }
+
+    /// The totals of the master: bytes read, bytes out of frames, frames with a bad checksum, ends
+    /// of file, and the reads that timed out, failed or were interrupted.
+    pub fn master_counters(&self) -> [(&'static str, u64); 7] {
+        [
+            ("bytes_read", self.bytes_read),
+            ("skipped_bytes", self.skipped_bytes),
+            ("invalid_frames", self.invalid_frames),
+            ("eofs", self.eofs),
+            ("timeouts", self.timeouts),
+            ("read_errors", self.read_errors),
+            ("interruptions", self.interruptions),
+        ]
+    }
+
+    /// The counters of the master, of the endpoints and of the message types in a line, the records
+    /// separated by `;`, for the `stats` command of the control socket.
+    pub fn snapshot(&self, endpoints: &[ManagedEndpoint]) -> String {
+        let mut records = vec![
+            self.master_counters()
+                .iter()
+                .fold("master".to_string(), |record, (name, value)| {
+                    format!("{} {}={}", record, name, value)
+                })
+                + &format!(" state={}", self.master_state),
+        ];
+        for endpoint in endpoints {
+            records.push(format!(
+                "endpoint {} written={} dropped={} pending={} errors={} state={}",
+                endpoint.name,
+                endpoint.written(),
+                endpoint.dropped(),
+                endpoint.endpoint.pending().unwrap_or(0),
+                endpoint.health.errors(),
+                endpoint_state(endpoint)
+            ));
+        }
+        for (message_type, stats) in &self.message_types {
+            records.push(format!("message {} count={}", message_type, stats.count));
+        }
+        for (event, count) in event_counts() {
+            records.push(format!(
+                "event {} name={} count={}",
+                event.code(),
+                event.name(),
+                count
+            ));
+        }
+        records.join(";")
+    }
+
+    /// The summary of a run as a JSON object, for the log and the post-run analysis: why and when
+    /// it ended, how long it ran, the counters of the master, the totals of each endpoint, the
+    /// messages and the events.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoints`: the endpoints.
+    /// * `uptime`: how long ttytee ran.
+    /// * `exit_code`: the code ttytee exits with.
+    /// * `reason`: why it stops.
+    ///
+    /// returns: String like `{"exit_code":5,"reason":"master end of file","uptime":3600.0,...}`.
+    ///
+    pub fn exit_report(
+        &self,
+        endpoints: &[ManagedEndpoint],
+        uptime: Duration,
+        exit_code: i32,
+        reason: &str,
+    ) -> String {
+        let ended_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since_epoch| since_epoch.as_secs_f64());
+        let mut report = format!("{{\"exit_code\":{},\"reason\":", exit_code);
+        json_string(reason, &mut report);
+        write!(
+            report,
+            ",\"ended_at\":{:.3},\"uptime\":{:.3},\"master\":{{\"state\":\"{}\"",
+            ended_at,
+            uptime.as_secs_f64(),
+            self.master_state
+        )
+        .unwrap();
+        for (name, value) in self.master_counters() {
+            write!(report, ",\"{}\":{}", name, value).unwrap();
+        }
+        report.push_str("},\"endpoints\":[");
+        for (index, endpoint) in endpoints.iter().enumerate() {
+            report.push_str(if index == 0 {
+                "{\"name\":"
+            } else {
+                ",{\"name\":"
+            });
+            json_string(&endpoint.name, &mut report);
+            write!(
+                report,
+                ",\"state\":\"{}\",\"written\":{},\"dropped\":{},\"partial_writes\":{},\"errors\":{}}}",
+                endpoint_state(endpoint),
+                endpoint.written(),
+                endpoint.dropped(),
+                endpoint.partial_writes(),
+                endpoint.health.errors()
+            )
+            .unwrap();
+        }
+        report.push_str("],\"messages\":{");
+        for (index, (message_type, stats)) in self.message_types.iter().enumerate() {
+            if index > 0 {
+                report.push(',');
+            }
+            json_string(message_type, &mut report);
+            write!(report, ":{}", stats.count).unwrap();
+        }
+        report.push_str("},\"events\":{");
+        for (index, (event, count)) in event_counts().into_iter().enumerate() {
+            if index > 0 {
+                report.push(',');
+            }
+            write!(report, "\"{}\":{}", event.code(), count).unwrap();
+        }
+        report.push_str("}}");
+        report
+    }
+
+    /// Per message type total count and rate in Hz since the last report.
+    pub fn message_rates(&self, now: Instant) -> Vec<(String, u64, f64)> {
+        let period = now.duration_since(self.last_report).as_secs_f64();
+        self.message_types
+            .iter()
+            .map(|(message_type, stats)| {
+                let rate = if period > 0.0 {
+                    (stats.count - stats.reported_count) as f64 / period
+                } else {
+                    0.0
+                };
+                (message_type.clone(), stats.count, rate)
+            })
+            .collect()
+    }
+
+    /// Per message type count, rate, age of the last one and stations, for the report.
+    pub fn message_summary(&self, now: Instant) -> Vec<String> {
+        self.message_rates(now)
+            .into_iter()
+            .zip(self.message_types.values())
+            .map(|((message_type, count, rate), stats)| {
+                let mut summary = format!("{} @ {:.1} Hz ({}", message_type, rate, count);
+                if let Some(last_seen) = stats.last_seen {
+                    summary += &format!(
+                        ", {:.1} s ago",
+                        now.saturating_duration_since(last_seen).as_secs_f64()
+                    );
+                }
+                if !stats.stations.is_empty() {
+                    let stations: Vec<String> = stats.stations.iter().map(u16::to_string).collect();
+                    summary += &format!(", station {}", stations.join("/"));
+                }
+                summary + ")"
+            })
+            .collect()
+    }
+
+    /// Log the statistics if the period since the last report is over.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: the current time.
+    /// * `period`: the minimum time between 2 reports.
+    /// * `endpoints`: the endpoints, for the stats of their groups.
+    ///
+    pub fn report_every(&mut self, now: Instant, period: Duration, endpoints: &[ManagedEndpoint]) {
+        if now.duration_since(self.last_report) < period {
+            return;
+        }
+        info!(
+            "Stats: {} bytes read from master, {}, {} ends of file, {} read errors, {} timeouts, {} interrupted reads.",
+            self.bytes_read,
+            self.master_state,
+            self.eofs,
+            self.read_errors,
+            self.timeouts,
+            self.interruptions
+        );
+        if let Some(errors) = &self.uart_errors {
+            info!(
+                "Stats: UART errors {} framing, {} parity, {} overrun, {} buffer overrun, {} break.",
+                errors.frame, errors.parity, errors.overrun, errors.buf_overrun, errors.brk
+            );
+        }
+        let summary = self.message_summary(now);
+        if !summary.is_empty() {
+            info!(
+                "Stats: {} bytes out of frames, {} invalid frames.",
+                self.skipped_bytes, self.invalid_frames
+            );
+            info!("Stats: messages {}.", summary.join(", "));
+        }
+        for line in group_stats(endpoints) {
+            info!("Stats: {}", line);
+        }
+        for endpoint in endpoints
+            .iter()
+            .filter(|endpoint| endpoint.partial_writes() > 0)
+        {
+            info!(
+                "Stats: {} could only take {} chunks in part at first.",
+                endpoint.name,
+                endpoint.partial_writes()
+            );
+        }
+        for stats in self.message_types.values_mut() {
+            stats.reported_count = stats.count;
+        }
+        self.last_report = now;
+    }
+}
+
+// The delivery state of an endpoint, for the stats.
+fn endpoint_state(endpoint: &ManagedEndpoint) -> &'static str {
+    if endpoint.health.is_disabled() {
+        "disabled"
+    } else if endpoint.is_paused() {
+        "paused"
+    } else {
+        "flowing"
+    }
+}
+
+/// The totals of each group of endpoints, one line per group.
+pub fn group_stats(endpoints: &[ManagedEndpoint]) -> Vec<String> {
+    let mut groups: BTreeMap<&str, Vec<&ManagedEndpoint>> = BTreeMap::new();
+    for endpoint in endpoints {
+        if let Some(group) = &endpoint.options.group {
+            groups.entry(group).or_default().push(endpoint);
+        }
+    }
+    groups
+        .iter()
+        .map(|(group, members)| {
+            format!(
+                "group {}: {} endpoints ({} disabled, {} paused), {} bytes written, {} bytes dropped.",
+                group,
+                members.len(),
+                members
+                    .iter()
+                    .filter(|endpoint| endpoint.health.is_disabled())
+                    .count(),
+                members.iter().filter(|endpoint| endpoint.is_paused()).count(),
+                members.iter().map(|endpoint| endpoint.written()).sum::<u64>(),
+                members.iter().map(|endpoint| endpoint.dropped()).sum::<u64>()
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::endpoint::stdout::StdoutEndpoint;
+    use crate::endpoint::{EndpointOptions, ManagedEndpoint};
+    use crate::stats::{group_stats, Stats};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_message_rates() {
+        let start = Instant::now();
+        let mut stats = Stats::new(start);
+        for _ in 0..10 {
+            for _ in 0..5 {
+                stats.count_message("GGA", None, start);
+            }
+            stats.count_message("RMC", None, start);
+        }
+        let now = start + Duration::from_secs(10);
+        assert_eq!(
+            stats.message_rates(now),
+            vec![("GGA".to_string(), 50, 5.0), ("RMC".to_string(), 10, 1.0)]
+        );
+        stats.report_every(now, Duration::from_secs(10), &[]);
+        stats.count_message("RMC", None, now);
+        assert_eq!(
+            stats.message_rates(now + Duration::from_secs(1)),
+            vec![("GGA".to_string(), 50, 0.0), ("RMC".to_string(), 11, 1.0)]
+        );
+    }
+
+    #[test]
+    fn test_message_summary() {
+        let start = Instant::now();
+        let mut stats = Stats::new(start);
+        stats.count_message("RTCM-1005", Some(2003), start);
+        stats.count_message("RTCM-1077", Some(2003), start);
+        stats.count_message("RTCM-1077", Some(12), start + Duration::from_secs(1));
+        assert_eq!(
+            stats.message_summary(start + Duration::from_secs(2)),
+            vec![
+                "RTCM-1005 @ 0.5 Hz (1, 2.0 s ago, station 2003)",
+                "RTCM-1077 @ 1.0 Hz (2, 1.0 s ago, station 12/2003)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exit_report() {
+        let start = Instant::now();
+        let mut stats = Stats::new(start);
+        stats.count_bytes(1200);
+        stats.count_message("GGA", None, start);
+        let endpoints = vec![ManagedEndpoint::new(
+            "slave0",
+            Box::new(StdoutEndpoint),
+            EndpointOptions::default(),
+            Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+        )];
+        let report = stats.exit_report(
+            &endpoints,
+            Duration::from_millis(61500),
+            5,
+            "master end of file",
+        );
+        assert!(
+            report.starts_with("{\"exit_code\":5,\"reason\":\"master end of file\",\"ended_at\":")
+        );
+        assert!(report
+            .contains(",\"uptime\":61.500,\"master\":{\"state\":\"opening\",\"bytes_read\":1200,"));
+        assert!(report.contains(
+            "\"endpoints\":[{\"name\":\"slave0\",\"state\":\"flowing\",\"written\":0,\"dropped\":0,\"partial_writes\":0,\"errors\":0}],\"messages\":{\"GGA\":1},\"events\":{"
+        ));
+        assert!(report.ends_with("}}"));
+    }
+
+    #[test]
+    fn test_group_stats() {
+        let endpoint = |name: &str, group: Option<&str>| {
+            let mut options = EndpointOptions::default();
+            if let Some(group) = group {
+                options.set("group", group).unwrap();
+            }
+            ManagedEndpoint::new(
+                name,
+                Box::new(StdoutEndpoint),
+                options,
+                Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+            )
+        };
+        let mut endpoints = vec![
+            endpoint("slave0", Some("realtime")),
+            endpoint("slave1", None),
+            endpoint("net", Some("besteffort")),
+            endpoint("log", Some("besteffort")),
+        ];
+        endpoints[2].pause();
+        assert_eq!(
+            group_stats(&endpoints),
+            vec![
+                "group besteffort: 2 endpoints (0 disabled, 1 paused), 0 bytes written, 0 bytes dropped.",
+                "group realtime: 1 endpoints (0 disabled, 0 paused), 0 bytes written, 0 bytes dropped.",
+            ]
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Lock of the settings of the master: with `--lock-termios` its termios are read once it is
+//! configured, then checked periodically and restored when another process changed them, like
+//! ModemManager probing what it takes for a modem. Each change is logged with what changed.
+
+use crate::events::Event;
+use log::{debug, warn};
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+
+// How often the settings of the master are checked.
+const CHECK_PERIOD: Duration = Duration::from_secs(2);
+
+// termios2 has the baudrates in numbers, serialport sets them this way.
+pub fn get_termios(fd: RawFd) -> io::Result<libc::termios2> {
+    let mut termios: libc::termios2 = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TCGETS2, &mut termios) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(termios)
+}
+
+pub fn set_termios(fd: RawFd, termios: &libc::termios2) -> io::Result<()> {
+    if unsafe { libc::ioctl(fd, libc::TCSETS2, termios) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// What differs between 2 settings, empty if nothing.
+///
+/// # Arguments
+///
+/// * `expected`: the settings of the master.
+/// * `actual`: the settings found.
+///
+/// returns: Vec<String> like `["baudrate 115200 -> 9600", "c_lflag 0 -> 0x8a3b"]`.
+///
+fn drift(expected: &libc::termios2, actual: &libc::termios2) -> Vec<String> {
+    let mut changes = Vec::new();
+    if expected.c_ospeed != actual.c_ospeed || expected.c_ispeed != actual.c_ispeed {
+        changes.push(format!(
+            "baudrate {} -> {}",
+            expected.c_ospeed, actual.c_ospeed
+        ));
+    }
+    for (name, expected, actual) in [
+        ("c_iflag", expected.c_iflag, actual.c_iflag),
+        ("c_oflag", expected.c_oflag, actual.c_oflag),
+        // the speed is in the c_cflag too.
+        (
+            "c_cflag",
+            expected.c_cflag & !libc::CBAUD,
+            actual.c_cflag & !libc::CBAUD,
+        ),
+        ("c_lflag", expected.c_lflag, actual.c_lflag),
+    ] {
+        if expected != actual {
+            changes.push(format!("{} {:#x} -> {:#x}", name, expected, actual));
+        }
+    }
+    if expected.c_cc != actual.c_cc {
+        changes.push("c_cc".to_string());
+    }
+    changes
+}
+
+/// Periodically checks the settings of the master and restores them when they changed.
+pub struct TermiosGuard {
+    fd: RawFd,
+    expected: libc::termios2,
+    last_check: Instant,
+    restored: u64,
+}
+
+impl TermiosGuard {
+    /// Lock the current settings of a device.
+    ///
+    /// # Arguments
+    ///
+    /// * `fd`: the device, configured.
+    /// * `now`: the current time.
+    ///
+    /// returns: Result<TermiosGuard, Error>
+    ///
+    pub fn new(fd: RawFd, now: Instant) -> io::Result<Self> {
+        Ok(Self {
+            fd,
+            expected: get_termios(fd)?,
+            last_check: now,
+            restored: 0,
+        })
+    }
+
+    /// Check the settings if it is time to, and restore them if they changed.
+    pub fn poll(&mut self, now: Instant) {
+        if now.duration_since(self.last_check) < CHECK_PERIOD {
+            return;
+        }
+        self.last_check = now;
+        let actual = match get_termios(self.fd) {
+            Ok(actual) => actual,
+            Err(err) => {
+                debug!("Could not read the settings of the master: {}.", err);
+                return;
+            }
+        };
+        let changes = drift(&self.expected, &actual);
+        if changes.is_empty() {
+            return;
+        }
+        self.restored += 1;
+        if let Err(err) = set_termios(self.fd, &self.expected) {
+            warn!(
+                event = Event::MasterSettingsChanged.code();
+                "The settings of the master changed ({}), they could not be restored: {}.",
+                changes.join(", "),
+                err
+            );
+        } else {
+            warn!(
+                event = Event::MasterSettingsChanged.code();
+                "The settings of the master changed ({}), restored them ({} times so far).",
+                changes.join(", "),
+                self.restored
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::termios::{drift, get_termios, set_termios, TermiosGuard, CHECK_PERIOD};
+    use serialport::{SerialPort, TTYPort};
+    use std::os::unix::io::AsRawFd;
+    use std::time::Instant;
+
+    #[test]
+    fn test_restore() {
+        let (_master, mut device) = TTYPort::pair().unwrap();
+        device.set_baud_rate(115_200).unwrap();
+        let start = Instant::now();
+        let mut guard = TermiosGuard::new(device.as_raw_fd(), start).unwrap();
+        let expected = get_termios(device.as_raw_fd()).unwrap();
+
+        // another process changes the port.
+        device.set_baud_rate(9600).unwrap();
+        let mut changed = get_termios(device.as_raw_fd()).unwrap();
+        changed.c_lflag |= libc::ECHO;
+        set_termios(device.as_raw_fd(), &changed).unwrap();
+        let changes = drift(&expected, &get_termios(device.as_raw_fd()).unwrap());
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0], "baudrate 115200 -> 9600");
+        assert!(changes[1].starts_with("c_lflag "));
+
+        // not yet.
+        guard.poll(start);
+        assert_eq!(guard.restored, 0);
+        guard.poll(start + CHECK_PERIOD);
+        assert_eq!(guard.restored, 1);
+        assert!(drift(&expected, &get_termios(device.as_raw_fd()).unwrap()).is_empty());
+        assert_eq!(device.baud_rate().unwrap(), 115_200);
+    }
+}
@@ -0,0 +1,181 @@
+//! The properties every framer must have whatever the input, shared by the property tests and the
+//! fuzz targets (testing feature).
+//!
+//! The reads from the master can split the stream anywhere and a noisy line can corrupt it: the
+//! framer must never panic, its output must not depend on how the stream was split, and it must
+//! find the frames again after any garbage.
+
+use crate::framing::{Frame, Framer, Protocol};
+
+// The longest a false UBX sync can keep the framer waiting: the biggest accepted frame.
+const RESYNC_PADDING: usize = 6 + 8192 + 2;
+
+fn push_all(protocols: &[Protocol], chunks: &[&[u8]]) -> (Vec<Frame>, u64, u64) {
+    let mut framer = Framer::new(protocols);
+    let mut frames = Vec::new();
+    for chunk in chunks {
+        framer.push(chunk, &mut frames);
+    }
+    (frames, framer.skipped_bytes(), framer.checksum_errors())
+}
+
+/// Check that splitting the data anywhere gives the same frames as pushing it at once.
+///
+/// # Arguments
+///
+/// * `protocols`: the protocols of the framer.
+/// * `data`: any bytes.
+/// * `splits`: where to split the data, they are taken modulo its length.
+///
+/// returns: ()
+///
+pub fn assert_split_invariant(protocols: &[Protocol], data: &[u8], splits: &[usize]) {
+    let mut splits: Vec<usize> = splits
+        .iter()
+        .map(|split| split % (data.len() + 1))
+        .collect();
+    splits.sort_unstable();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for split in splits {
+        chunks.push(&data[start..split]);
+        start = split;
+    }
+    chunks.push(&data[start..]);
+    assert_eq!(push_all(protocols, &[data]), push_all(protocols, &chunks));
+}
+
+/// Check that the framer finds valid frames again after some garbage.
+///
+/// # Arguments
+///
+/// * `protocols`: the protocols of the framer.
+/// * `garbage`: any bytes, like a corrupted frame.
+/// * `valid`: frames of the given protocols with valid checksums.
+///
+/// returns: ()
+///
+pub fn assert_resyncs(protocols: &[Protocol], garbage: &[u8], valid: &[Frame]) {
+    let mut data = garbage.to_vec();
+    for frame in valid {
+        data.extend_from_slice(&frame.data);
+    }
+    // enough bytes out of any frame to get past the longest false sync.
+    data.extend(std::iter::repeat_n(0, RESYNC_PADDING));
+    let (frames, _, _) = push_all(protocols, &[&data]);
+    // the garbage may end up looking like a frame but all the valid ones come out in order.
+    let mut found = frames.iter();
+    for frame in valid {
+        assert!(
+            found.any(|found| found == frame),
+            "lost sync, {:?} not found",
+            frame
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{crc24q, nmea_checksum, ubx_checksum, Frame, Protocol};
+    use crate::testing::{assert_resyncs, assert_split_invariant};
+    use proptest::prelude::*;
+
+    const PROTOCOLS: &[Protocol] = &[Protocol::Nmea, Protocol::Ubx, Protocol::Rtcm];
+
+    fn nmea_frame() -> impl Strategy<Value = Frame> {
+        "[A-Z]{5}(,[A-Z0-9.]{0,8}){0,12}".prop_map(|body| {
+            let checksum = nmea_checksum(body.as_bytes());
+            Frame {
+                protocol: Protocol::Nmea,
+                data: format!("${}*{:02X}\r\n", body, checksum).into_bytes(),
+            }
+        })
+    }
+
+    fn ubx_frame() -> impl Strategy<Value = Frame> {
+        (
+            any::<u8>(),
+            any::<u8>(),
+            prop::collection::vec(any::<u8>(), 0..200),
+        )
+            .prop_map(|(class, id, payload)| {
+                let mut data = vec![0xB5, 0x62, class, id];
+                data.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+                data.extend_from_slice(&payload);
+                let (a, b) = ubx_checksum(&data[2..]);
+                data.extend_from_slice(&[a, b]);
+                Frame {
+                    protocol: Protocol::Ubx,
+                    data,
+                }
+            })
+    }
+
+    fn rtcm_frame() -> impl Strategy<Value = Frame> {
+        prop::collection::vec(any::<u8>(), 0..200).prop_map(|payload| {
+            let mut data = vec![0xD3];
+            data.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            data.extend_from_slice(&payload);
+            let crc = crc24q(&data);
+            data.extend_from_slice(&crc.to_be_bytes()[1..]);
+            Frame {
+                protocol: Protocol::Rtcm,
+                data,
+            }
+        })
+    }
+
+    fn frames() -> impl Strategy<Value = Vec<Frame>> {
+        prop::collection::vec(prop_oneof![nmea_frame(), ubx_frame(), rtcm_frame()], 1..10)
+    }
+
+    // valid frames with some bytes corrupted, the worst case for the framer.
+    fn corrupted_stream() -> impl Strategy<Value = Vec<u8>> {
+        (
+            frames(),
+            prop::collection::vec((any::<usize>(), any::<u8>()), 0..10),
+        )
+            .prop_map(|(frames, corruptions)| {
+                let mut data: Vec<u8> = frames.into_iter().flat_map(|frame| frame.data).collect();
+                for (position, byte) in corruptions {
+                    let position = position % data.len();
+                    data[position] = byte;
+                }
+                data
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn split_invariant_on_noise(
+            data in prop::collection::vec(any::<u8>(), 0..2000),
+            splits in prop::collection::vec(any::<usize>(), 0..20),
+        ) {
+            assert_split_invariant(PROTOCOLS, &data, &splits);
+            assert_split_invariant(&[Protocol::Nmea], &data, &splits);
+            assert_split_invariant(&[Protocol::Ubx], &data, &splits);
+            assert_split_invariant(&[Protocol::Rtcm], &data, &splits);
+        }
+
+        #[test]
+        fn split_invariant_on_corrupted_frames(
+            data in corrupted_stream(),
+            splits in prop::collection::vec(any::<usize>(), 0..20),
+        ) {
+            assert_split_invariant(PROTOCOLS, &data, &splits);
+        }
+
+        #[test]
+        fn resyncs_after_noise(
+            garbage in prop::collection::vec(any::<u8>(), 0..500),
+            valid in frames(),
+        ) {
+            assert_resyncs(PROTOCOLS, &garbage, &valid);
+        }
+
+        #[test]
+        fn resyncs_after_corrupted_frames(garbage in corrupted_stream(), valid in frames()) {
+            assert_resyncs(PROTOCOLS, &garbage, &valid);
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! Live dashboard of a running instance for the debugging sessions over ssh: `ttytee top
+//! --control-socket /run/ttytee.sock` polls the `stats` command of the control socket and redraws
+//! the throughput of the master, the state, rate, backlog and errors of each endpoint, the rate of
+//! each message type and the last errors seen, in color.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+// Move to the top left corner and clear the screen.
+const CLEAR: &str = "\x1b[H\x1b[2J";
+// How many of the last errors are shown.
+const RECENT_ERRORS: usize = 5;
+
+/// An endpoint in a snapshot.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct EndpointRow {
+    name: String,
+    counters: BTreeMap<String, u64>,
+    state: String,
+}
+
+/// The counters of an instance at a time, from the reply to `stats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Snapshot {
+    master: BTreeMap<String, u64>,
+    master_state: String,
+    endpoints: Vec<EndpointRow>,
+    messages: BTreeMap<String, u64>,
+}
+
+// The key=value counters of a record.
+fn counters<'a>(fields: impl Iterator<Item = &'a str>) -> (BTreeMap<String, u64>, String) {
+    let mut counters = BTreeMap::new();
+    let mut state = String::new();
+    for (key, value) in fields.filter_map(|field| field.split_once('=')) {
+        match value.parse() {
+            Ok(value) => {
+                counters.insert(key.to_string(), value);
+            }
+            Err(_) => state = value.to_string(),
+        }
+    }
+    (counters, state)
+}
+
+/// Parse the reply to `stats`, without its `ok`.
+fn parse_snapshot(reply: &str) -> Result<Snapshot, String> {
+    let mut snapshot = Snapshot::default();
+    for record in reply.split(';') {
+        let mut fields = record.split_whitespace();
+        match fields.next() {
+            Some("master") => (snapshot.master, snapshot.master_state) = counters(fields),
+            Some("endpoint") => {
+                let name = fields.next().unwrap_or_default().to_string();
+                let (counters, state) = counters(fields);
+                snapshot.endpoints.push(EndpointRow {
+                    name,
+                    counters,
+                    state,
+                });
+            }
+            Some("message") => {
+                let name = fields.next().unwrap_or_default().to_string();
+                let count = counters(fields).0.get("count").copied().unwrap_or(0);
+                snapshot.messages.insert(name, count);
+            }
+            // the events are for the alerting rules, not for the dashboard.
+            Some("event") => {}
+            _ => return Err(format!("unexpected stats record {:?}", record)),
+        }
+    }
+    Ok(snapshot)
+}
+
+/// The dashboard and the last errors, redrawn at each snapshot.
+struct Dashboard {
+    previous: Option<(Snapshot, Instant)>,
+    // the last errors seen, with when they were seen since the start of top.
+    errors: VecDeque<String>,
+    start: Instant,
+}
+
+impl Dashboard {
+    fn new(start: Instant) -> Self {
+        Self {
+            previous: None,
+            errors: VecDeque::new(),
+            start,
+        }
+    }
+
+    // The increase of a counter since the previous snapshot.
+    fn delta(
+        previous: Option<&BTreeMap<String, u64>>,
+        now: &BTreeMap<String, u64>,
+        key: &str,
+    ) -> u64 {
+        let Some(previous) = previous else {
+            return 0;
+        };
+        let before = previous.get(key).copied().unwrap_or(0);
+        now.get(key).copied().unwrap_or(0).saturating_sub(before)
+    }
+
+    fn error(&mut self, at: Instant, text: String) {
+        self.errors.push_back(format!(
+            "+{:>5}s  {}",
+            at.duration_since(self.start).as_secs(),
+            text
+        ));
+        while self.errors.len() > RECENT_ERRORS {
+            self.errors.pop_front();
+        }
+    }
+
+    /// Draw a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot`: the counters now.
+    /// * `now`: when they were read.
+    ///
+    /// returns: String the screen, with the escape sequences of the colors.
+    ///
+    fn draw(&mut self, snapshot: Snapshot, now: Instant) -> String {
+        let elapsed = self
+            .previous
+            .as_ref()
+            .map_or(0.0, |(_, at)| now.duration_since(*at).as_secs_f64());
+        let rate = |delta: u64| {
+            if elapsed > 0.0 {
+                delta as f64 / elapsed
+            } else {
+                0.0
+            }
+        };
+        let previous = self.previous.take();
+        let before = previous.as_ref().map(|(snapshot, _)| snapshot);
+
+        let mut screen = String::from(CLEAR);
+        let master_before = before.map(|snapshot| &snapshot.master);
+        let invalid = Self::delta(master_before, &snapshot.master, "invalid_frames");
+        let state_color = match snapshot.master_state.as_str() {
+            "streaming" => GREEN,
+            "opening" | "stalled" => YELLOW,
+            _ => RED,
+        };
+        writeln!(
+            screen,
+            "{}master{}  {}{}{}  {:.0} B/s  {} bytes  {} bytes out of frames  {}{} invalid frames{}",
+            BOLD,
+            RESET,
+            state_color,
+            snapshot.master_state,
+            RESET,
+            rate(Self::delta(master_before, &snapshot.master, "bytes_read")),
+            snapshot.master.get("bytes_read").unwrap_or(&0),
+            snapshot.master.get("skipped_bytes").unwrap_or(&0),
+            if invalid > 0 { RED } else { "" },
+            snapshot.master.get("invalid_frames").unwrap_or(&0),
+            RESET
+        )
+        .unwrap();
+        if invalid > 0 {
+            self.error(now, format!("master: {} invalid frames", invalid));
+        }
+        let state_before = before.map(|snapshot| snapshot.master_state.as_str());
+        if state_color == RED && state_before != Some(snapshot.master_state.as_str()) {
+            self.error(now, format!("master: {}", snapshot.master_state));
+        }
+
+        writeln!(
+            screen,
+            "\n{}{:<16} {:<9} {:>10} {:>10} {:>10} {:>8}{}",
+            BOLD, "endpoint", "state", "B/s", "dropped", "backlog", "errors", RESET
+        )
+        .unwrap();
+        for row in &snapshot.endpoints {
+            let row_before = before
+                .and_then(|snapshot| {
+                    snapshot
+                        .endpoints
+                        .iter()
+                        .find(|other| other.name == row.name)
+                })
+                .map(|row| &row.counters);
+            let errors = Self::delta(row_before, &row.counters, "errors");
+            let dropped = Self::delta(row_before, &row.counters, "dropped");
+            let color = match row.state.as_str() {
+                "flowing" if errors == 0 && dropped == 0 => GREEN,
+                "flowing" | "paused" => YELLOW,
+                _ => RED,
+            };
+            writeln!(
+                screen,
+                "{:<16} {}{:<9}{} {:>10.0} {:>10} {:>10} {}{:>8}{}",
+                row.name,
+                color,
+                row.state,
+                RESET,
+                rate(Self::delta(row_before, &row.counters, "written")),
+                row.counters.get("dropped").unwrap_or(&0),
+                row.counters.get("pending").unwrap_or(&0),
+                if errors > 0 { RED } else { "" },
+                row.counters.get("errors").unwrap_or(&0),
+                RESET
+            )
+            .unwrap();
+            if errors > 0 {
+                self.error(now, format!("{}: {} write errors", row.name, errors));
+            }
+            if dropped > 0 {
+                self.error(now, format!("{}: {} bytes dropped", row.name, dropped));
+            }
+        }
+
+        if !snapshot.messages.is_empty() {
+            writeln!(
+                screen,
+                "\n{}{:<16} {:>8} {:>10}{}",
+                BOLD, "message", "Hz", "count", RESET
+            )
+            .unwrap();
+            let messages_before = before.map(|snapshot| &snapshot.messages);
+            for (message_type, count) in &snapshot.messages {
+                writeln!(
+                    screen,
+                    "{:<16} {:>8.1} {:>10}",
+                    message_type,
+                    rate(Self::delta(
+                        messages_before,
+                        &snapshot.messages,
+                        message_type
+                    )),
+                    count
+                )
+                .unwrap();
+            }
+        }
+
+        writeln!(screen, "\n{}recent errors{}", BOLD, RESET).unwrap();
+        for error in &self.errors {
+            writeln!(screen, "{}{}{}", RED, error, RESET).unwrap();
+        }
+        self.previous = Some((snapshot, now));
+        screen
+    }
+}
+
+/// Show the dashboard of an instance until it stops.
+///
+/// # Arguments
+///
+/// * `socket`: the control socket of the instance.
+/// * `interval`: how often the dashboard is redrawn.
+/// * `out`: the terminal.
+///
+/// returns: Result<(), Error>
+///
+pub fn top(socket: &Path, interval: Duration, out: &mut dyn Write) -> io::Result<()> {
+    let client = UnixStream::connect(socket)?;
+    let mut replies = BufReader::new(client.try_clone()?);
+    let mut dashboard = Dashboard::new(Instant::now());
+    loop {
+        (&client).write_all(b"stats\n")?;
+        let mut reply = String::new();
+        if replies.read_line(&mut reply)? == 0 {
+            // the instance stopped.
+            return Ok(());
+        }
+        let reply = reply.trim_end();
+        let snapshot = reply
+            .strip_prefix("ok ")
+            .ok_or_else(|| reply.to_string())
+            .and_then(parse_snapshot)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        out.write_all(dashboard.draw(snapshot, Instant::now()).as_bytes())?;
+        out.flush()?;
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::top::{parse_snapshot, Dashboard};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_dashboard() {
+        let first = parse_snapshot(
+            "master bytes_read=1000 skipped_bytes=0 invalid_frames=0 eofs=0 state=streaming;endpoint slave0 written=1000 dropped=0 pending=0 errors=0 state=flowing;message GGA count=10",
+        )
+        .unwrap();
+        assert_eq!(first.endpoints[0].state, "flowing");
+        assert_eq!(first.messages["GGA"], 10);
+        assert!(parse_snapshot("nonsense").is_err());
+
+        let start = Instant::now();
+        let mut dashboard = Dashboard::new(start);
+        dashboard.draw(first, start);
+        let second = parse_snapshot(
+            "master bytes_read=3000 skipped_bytes=0 invalid_frames=0 eofs=1 state=reconnecting;endpoint slave0 written=2000 dropped=500 pending=200 errors=2 state=paused;message GGA count=12",
+        )
+        .unwrap();
+        let screen = dashboard.draw(second, start + Duration::from_secs(2));
+        assert!(
+            screen.contains("master\x1b[0m  \x1b[31mreconnecting\x1b[0m  1000 B/s  3000 bytes"),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("slave0           \x1b[33mpaused   \x1b[0m        500        500        200 \x1b[31m       2"));
+        assert!(screen.contains("GGA                   1.0         12"));
+        assert!(screen.contains("s  slave0: 2 write errors\x1b[0m\n"));
+        assert!(screen.contains("s  slave0: 500 bytes dropped\x1b[0m\n"));
+        assert!(screen.contains("s  master: reconnecting\x1b[0m\n"));
+    }
+}
@@ -0,0 +1,143 @@
+//! Degradation of the stream of an endpoint, to test how its consumer copes with a bad link
+//! through the same tee as in production: `loss=1%` drops frames and `dup=0.1%` repeats them, at
+//! random. The delays are the `delay` endpoint option.
+
+use crate::framing::Frame;
+use crate::transform::Transform;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A xorshift64* generator, good enough to pick the frames to degrade.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // the state must not be 0.
+        Self(seed | 1)
+    }
+
+    /// Seeded from the clock, each endpoint gets its own sequence.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_nanos() as u64);
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A number in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Parse a percentage like `0.1%`, the `%` is optional.
+pub fn parse_percent(value: &str) -> Result<f64, String> {
+    value
+        .strip_suffix('%')
+        .unwrap_or(value)
+        .parse::<f64>()
+        .ok()
+        .filter(|percent| (0.0..=100.0).contains(percent))
+        .ok_or_else(|| format!("expected a percentage like 1%, got {:?}", value))
+}
+
+pub struct Loss {
+    probability: f64,
+    rng: Rng,
+}
+
+impl Loss {
+    /// Drop frames at random.
+    ///
+    /// # Arguments
+    ///
+    /// * `probability`: the probability to drop a frame, in [0, 1].
+    /// * `rng`: the random generator.
+    ///
+    /// returns: Loss
+    ///
+    pub fn new(probability: f64, rng: Rng) -> Self {
+        Self { probability, rng }
+    }
+}
+
+impl Transform for Loss {
+    fn apply(&mut self, frame: Frame, output: &mut Vec<Frame>) {
+        if self.rng.next_f64() >= self.probability {
+            output.push(frame);
+        }
+    }
+}
+
+pub struct Duplicate {
+    probability: f64,
+    rng: Rng,
+}
+
+impl Duplicate {
+    /// Repeat frames at random.
+    ///
+    /// # Arguments
+    ///
+    /// * `probability`: the probability to send a frame twice, in [0, 1].
+    /// * `rng`: the random generator.
+    ///
+    /// returns: Duplicate
+    ///
+    pub fn new(probability: f64, rng: Rng) -> Self {
+        Self { probability, rng }
+    }
+}
+
+impl Transform for Duplicate {
+    fn apply(&mut self, frame: Frame, output: &mut Vec<Frame>) {
+        if self.rng.next_f64() < self.probability {
+            output.push(frame.clone());
+        }
+        output.push(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{Frame, Protocol};
+    use crate::transform::chaos::{parse_percent, Duplicate, Loss, Rng};
+    use crate::transform::Transform;
+
+    fn run(transform: &mut dyn Transform, count: usize) -> usize {
+        let mut output = Vec::new();
+        for _ in 0..count {
+            let frame = Frame {
+                protocol: Protocol::Nmea,
+                data: b"$GPGGA\r\n".to_vec(),
+            };
+            transform.apply(frame, &mut output);
+        }
+        output.len()
+    }
+
+    #[test]
+    fn test_parse_percent() {
+        assert_eq!(parse_percent("0.1%"), Ok(0.1));
+        assert_eq!(parse_percent("50"), Ok(50.0));
+        assert!(parse_percent("101%").is_err());
+        assert!(parse_percent("-1%").is_err());
+        assert!(parse_percent("often").is_err());
+    }
+
+    #[test]
+    fn test_loss_and_duplicate() {
+        let received = run(&mut Loss::new(0.1, Rng::new(42)), 10_000);
+        assert!((8_800..9_200).contains(&received), "{}", received);
+        assert_eq!(run(&mut Loss::new(0.0, Rng::new(42)), 100), 100);
+        assert_eq!(run(&mut Loss::new(1.0, Rng::new(42)), 100), 0);
+        let received = run(&mut Duplicate::new(0.01, Rng::new(7)), 10_000);
+        assert!((10_050..10_150).contains(&received), "{}", received);
+    }
+}
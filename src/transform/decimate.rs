@@ -0,0 +1,72 @@
+//! Decimation of a message type, for the consumers that don't need the full rate.
+
+use crate::framing::Frame;
+use crate::transform::Transform;
+
+pub struct Decimate {
+    message_type: String,
+    every: u32,
+    // occurrences of the message type since the last forwarded one.
+    count: u32,
+}
+
+impl Decimate {
+    /// Forward only every Nth occurrence of a message type.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_type`: for example GGA or NAV-PVT.
+    /// * `every`: 5 forwards the 1st, 6th, 11th... occurrences.
+    ///
+    /// returns: Decimate
+    ///
+    pub fn new(message_type: &str, every: u32) -> Self {
+        Self {
+            message_type: message_type.to_string(),
+            every,
+            count: 0,
+        }
+    }
+}
+
+impl Transform for Decimate {
+    fn apply(&mut self, frame: Frame, output: &mut Vec<Frame>) {
+        if frame.message_type() != self.message_type {
+            output.push(frame);
+            return;
+        }
+        if self.count == 0 {
+            output.push(frame);
+        }
+        self.count = (self.count + 1) % self.every;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{Frame, Protocol};
+    use crate::transform::decimate::Decimate;
+    use crate::transform::Transform;
+
+    #[test]
+    fn test_decimate() {
+        let nmea = |sentence: &str| Frame {
+            protocol: Protocol::Nmea,
+            data: sentence.as_bytes().to_vec(),
+        };
+        let mut decimate = Decimate::new("GGA", 5);
+        let mut output = Vec::new();
+        for i in 0..10 {
+            decimate.apply(nmea(&format!("$GPGGA,{}\r\n", i)), &mut output);
+            decimate.apply(nmea(&format!("$GPRMC,{}\r\n", i)), &mut output);
+        }
+        let forwarded: Vec<String> = output
+            .iter()
+            .map(|frame| String::from_utf8_lossy(&frame.data).trim().to_string())
+            .collect();
+        assert_eq!(forwarded.len(), 12);
+        assert_eq!(forwarded[0], "$GPGGA,0");
+        assert!(forwarded.contains(&"$GPGGA,5".to_string()));
+        assert!(!forwarded.contains(&"$GPGGA,1".to_string()));
+    }
+}
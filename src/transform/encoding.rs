@@ -0,0 +1,128 @@
+//! Re-encoding of the frames for the consumers expecting another wire encoding than the master, like
+//! a SLIP master read by a consumer that wants its datagrams with a length prefix: each frame is
+//! decoded from the framing of its protocol, then encoded again for the endpoint.
+
+use crate::framing::{Frame, SLIP_END, SLIP_ESC, SLIP_ESC_END, SLIP_ESC_ESC};
+use crate::transform::Transform;
+use std::fmt;
+use std::str::FromStr;
+
+/// How the frames are encoded for an endpoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// The payload alone, for the consumers that get one frame per read.
+    Raw,
+    /// The payload after its length on 2 bytes, big endian.
+    LengthPrefixed,
+    /// The payload escaped between two END bytes, RFC 1055.
+    Slip,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(encoding: &str) -> Result<Self, Self::Err> {
+        match encoding {
+            "raw" => Ok(Self::Raw),
+            "length-prefixed" => Ok(Self::LengthPrefixed),
+            "slip" => Ok(Self::Slip),
+            _ => Err(format!(
+                "unknown encoding {:?}, expected raw, length-prefixed or slip",
+                encoding
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Raw => write!(f, "raw"),
+            Self::LengthPrefixed => write!(f, "length-prefixed"),
+            Self::Slip => write!(f, "slip"),
+        }
+    }
+}
+
+pub struct Encode {
+    encoding: Encoding,
+}
+
+impl Encode {
+    pub fn new(encoding: Encoding) -> Self {
+        Self { encoding }
+    }
+}
+
+impl Transform for Encode {
+    fn apply(&mut self, mut frame: Frame, output: &mut Vec<Frame>) {
+        let payload = frame.payload();
+        frame.data = match self.encoding {
+            Encoding::Raw => payload,
+            Encoding::LengthPrefixed => {
+                // the longest frames of the framers are well under 64 KB.
+                let Ok(len) = u16::try_from(payload.len()) else {
+                    return;
+                };
+                let mut data = len.to_be_bytes().to_vec();
+                data.extend_from_slice(&payload);
+                data
+            }
+            Encoding::Slip => {
+                let mut data = Vec::with_capacity(payload.len() + 2);
+                data.push(SLIP_END);
+                for c in payload {
+                    match c {
+                        SLIP_END => data.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+                        SLIP_ESC => data.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+                        c => data.push(c),
+                    }
+                }
+                data.push(SLIP_END);
+                data
+            }
+        };
+        output.push(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{Frame, Protocol, SLIP_END};
+    use crate::transform::encoding::{Encode, Encoding};
+    use crate::transform::Transform;
+
+    fn encode(encoding: Encoding, frame: Frame) -> Vec<u8> {
+        let mut output = Vec::new();
+        Encode::new(encoding).apply(frame, &mut output);
+        output.remove(0).data
+    }
+
+    #[test]
+    fn test_encode() {
+        let slip = Frame {
+            protocol: Protocol::Slip,
+            data: vec![SLIP_END, 0x01, 0xDB, 0xDC, 0x02],
+        };
+        assert_eq!(encode(Encoding::Raw, slip.clone()), [0x01, SLIP_END, 0x02]);
+        assert_eq!(
+            encode(Encoding::LengthPrefixed, slip.clone()),
+            [0x00, 0x03, 0x01, SLIP_END, 0x02]
+        );
+        assert_eq!(
+            encode(Encoding::Slip, slip),
+            [SLIP_END, 0x01, 0xDB, 0xDC, 0x02, SLIP_END]
+        );
+        // the other protocols carry their own headers, the whole frame is the payload.
+        let gga = Frame {
+            protocol: Protocol::Nmea,
+            data: b"$GPGGA,1\r\n".to_vec(),
+        };
+        assert_eq!(
+            encode(Encoding::LengthPrefixed, gga),
+            b"\x00\x0a$GPGGA,1\r\n"
+        );
+        assert_eq!("length-prefixed".parse(), Ok(Encoding::LengthPrefixed));
+        assert!("cobs".parse::<Encoding>().is_err());
+    }
+}
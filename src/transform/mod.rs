@@ -0,0 +1,290 @@
+//! Per endpoint transforms of the frames, for the consumers that need the stream adapted.
+//!
+//! An endpoint with transforms gets the frames of the master transformed one by one instead of the
+//! raw stream, so it needs --framer and the bytes out of any frame are not forwarded to it.
+
+pub mod chaos;
+pub mod decimate;
+pub mod encoding;
+pub mod privacy;
+pub mod talker;
+pub mod ubx_nmea;
+
+use crate::framing::Frame;
+use encoding::Encoding;
+use std::fmt;
+
+/// A transform of the frames going to an endpoint.
+pub trait Transform: Send {
+    /// Transform a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame`: the frame from the master or from the previous transform.
+    /// * `output`: where to push the resulting frames, none to drop it.
+    ///
+    fn apply(&mut self, frame: Frame, output: &mut Vec<Frame>);
+}
+
+/// The endpoint options that are transforms.
+pub const TRANSFORM_KEYS: &[&str] = &[
+    "rewrite-talker",
+    "decimate",
+    "truncate-position",
+    "offset-position",
+    "ubx-to-nmea",
+    "loss",
+    "dup",
+    "encode",
+];
+
+/// A transform as configured, they are instantiated for each endpoint.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransformSpec {
+    /// Replace the talker id of the NMEA sentences, like GN -> GP.
+    RewriteTalker { from: String, to: String },
+    /// Forward only every Nth occurrence of a message type.
+    Decimate { message_type: String, every: u32 },
+    /// Truncate the minutes of the NMEA positions to N decimals.
+    TruncatePosition { decimals: usize },
+    /// Shift the NMEA positions by a fixed offset in degrees.
+    OffsetPosition { latitude: f64, longitude: f64 },
+    /// Convert the UBX NAV-PVT messages to NMEA with this talker id, drop the other UBX messages.
+    UbxToNmea { talker: String },
+    /// Drop this percentage of the frames at random.
+    Loss { percent: f64 },
+    /// Send this percentage of the frames twice, at random.
+    Duplicate { percent: f64 },
+    /// Decode the frames from the framing of their protocol and encode them like this.
+    Encode { encoding: Encoding },
+}
+
+impl TransformSpec {
+    /// Parse a transform from its endpoint option.
+    ///
+    /// # Arguments
+    ///
+    /// * `key`: the option name, for example `rewrite-talker`.
+    /// * `value`: its value, for example `GN:GP`.
+    ///
+    /// returns: Result<Option<TransformSpec>, String> None if the key is not a transform.
+    ///
+    pub fn parse(key: &str, value: &str) -> Result<Option<Self>, String> {
+        match key {
+            "rewrite-talker" => {
+                let (from, to) = value
+                    .split_once(':')
+                    .filter(|(from, to)| talker::is_talker_id(from) && talker::is_talker_id(to))
+                    .ok_or_else(|| format!("expected <FROM>:<TO> talker ids, got {:?}", value))?;
+                Ok(Some(Self::RewriteTalker {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                }))
+            }
+            "decimate" => {
+                let (message_type, every) = value
+                    .rsplit_once(':')
+                    .and_then(|(message_type, every)| Some((message_type, every.parse().ok()?)))
+                    .filter(|&(message_type, every)| !message_type.is_empty() && every > 0)
+                    .ok_or_else(|| {
+                        format!("expected <MESSAGE_TYPE>:<N> with N > 0, got {:?}", value)
+                    })?;
+                Ok(Some(Self::Decimate {
+                    message_type: message_type.to_string(),
+                    every,
+                }))
+            }
+            "truncate-position" => Ok(Some(Self::TruncatePosition {
+                decimals: value
+                    .parse()
+                    .map_err(|err| format!("invalid number of decimals {:?}: {}", value, err))?,
+            })),
+            "offset-position" => {
+                let (latitude, longitude) = value
+                    .split_once(':')
+                    .and_then(|(latitude, longitude)| {
+                        Some((latitude.parse().ok()?, longitude.parse().ok()?))
+                    })
+                    .ok_or_else(|| {
+                        format!(
+                            "expected <LATITUDE>:<LONGITUDE> in degrees, got {:?}",
+                            value
+                        )
+                    })?;
+                Ok(Some(Self::OffsetPosition {
+                    latitude,
+                    longitude,
+                }))
+            }
+            "ubx-to-nmea" if talker::is_talker_id(value) => Ok(Some(Self::UbxToNmea {
+                talker: value.to_string(),
+            })),
+            "ubx-to-nmea" => Err(format!("expected a talker id like GN, got {:?}", value)),
+            "loss" => Ok(Some(Self::Loss {
+                percent: chaos::parse_percent(value)?,
+            })),
+            "dup" => Ok(Some(Self::Duplicate {
+                percent: chaos::parse_percent(value)?,
+            })),
+            "encode" => Ok(Some(Self::Encode {
+                encoding: value.parse()?,
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn instantiate(&self) -> Box<dyn Transform> {
+        match self {
+            Self::RewriteTalker { from, to } => Box::new(talker::RewriteTalker::new(from, to)),
+            Self::Decimate {
+                message_type,
+                every,
+            } => Box::new(decimate::Decimate::new(message_type, *every)),
+            Self::TruncatePosition { decimals } => {
+                Box::new(privacy::TruncatePosition::new(*decimals))
+            }
+            Self::OffsetPosition {
+                latitude,
+                longitude,
+            } => Box::new(privacy::OffsetPosition::new(*latitude, *longitude)),
+            Self::UbxToNmea { talker } => Box::new(ubx_nmea::UbxToNmea::new(talker)),
+            Self::Loss { percent } => {
+                Box::new(chaos::Loss::new(percent / 100.0, chaos::Rng::from_time()))
+            }
+            Self::Duplicate { percent } => Box::new(chaos::Duplicate::new(
+                percent / 100.0,
+                chaos::Rng::from_time(),
+            )),
+            Self::Encode { encoding } => Box::new(encoding::Encode::new(*encoding)),
+        }
+    }
+}
+
+impl fmt::Display for TransformSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::RewriteTalker { from, to } => write!(f, "rewrite-talker={}:{}", from, to),
+            Self::Decimate {
+                message_type,
+                every,
+            } => write!(f, "decimate={}:{}", message_type, every),
+            Self::TruncatePosition { decimals } => write!(f, "truncate-position={}", decimals),
+            Self::OffsetPosition {
+                latitude,
+                longitude,
+            } => write!(f, "offset-position={}:{}", latitude, longitude),
+            Self::UbxToNmea { talker } => write!(f, "ubx-to-nmea={}", talker),
+            Self::Loss { percent } => write!(f, "loss={}%", percent),
+            Self::Duplicate { percent } => write!(f, "dup={}%", percent),
+            Self::Encode { encoding } => write!(f, "encode={}", encoding),
+        }
+    }
+}
+
+/// The transforms of an endpoint, applied in order.
+#[derive(Default)]
+pub struct Pipeline {
+    transforms: Vec<Box<dyn Transform>>,
+}
+
+impl Pipeline {
+    pub fn new(specs: &[TransformSpec]) -> Self {
+        Self {
+            transforms: specs.iter().map(TransformSpec::instantiate).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    /// Run frames through all the transforms.
+    pub fn run(&mut self, frames: &[Frame]) -> Vec<Frame> {
+        let mut frames = frames.to_vec();
+        for transform in &mut self.transforms {
+            let mut output = Vec::with_capacity(frames.len());
+            for frame in frames {
+                transform.apply(frame, &mut output);
+            }
+            frames = output;
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{Frame, Protocol};
+    use crate::transform::{Pipeline, TransformSpec};
+
+    #[test]
+    fn test_parse_transform_spec() {
+        assert_eq!(
+            TransformSpec::parse("rewrite-talker", "GN:GP"),
+            Ok(Some(TransformSpec::RewriteTalker {
+                from: "GN".to_string(),
+                to: "GP".to_string()
+            }))
+        );
+        assert!(TransformSpec::parse("rewrite-talker", "GNSS:GP").is_err());
+        assert!(TransformSpec::parse("rewrite-talker", "GN").is_err());
+        assert_eq!(
+            TransformSpec::parse("decimate", "NAV-PVT:10"),
+            Ok(Some(TransformSpec::Decimate {
+                message_type: "NAV-PVT".to_string(),
+                every: 10
+            }))
+        );
+        assert!(TransformSpec::parse("decimate", "GGA:0").is_err());
+        assert!(TransformSpec::parse("decimate", "GGA").is_err());
+        assert_eq!(
+            TransformSpec::parse("offset-position", "-0.25:1.5"),
+            Ok(Some(TransformSpec::OffsetPosition {
+                latitude: -0.25,
+                longitude: 1.5
+            }))
+        );
+        assert!(TransformSpec::parse("offset-position", "north").is_err());
+        assert!(TransformSpec::parse("truncate-position", "-1").is_err());
+        assert_eq!(
+            TransformSpec::parse("loss", "1%"),
+            Ok(Some(TransformSpec::Loss { percent: 1.0 }))
+        );
+        assert_eq!(
+            TransformSpec::parse("dup", "0.1%")
+                .unwrap()
+                .unwrap()
+                .to_string(),
+            "dup=0.1%"
+        );
+        assert_eq!(
+            TransformSpec::parse("encode", "slip")
+                .unwrap()
+                .unwrap()
+                .to_string(),
+            "encode=slip"
+        );
+        assert!(TransformSpec::parse("encode", "slip16").is_err());
+        assert_eq!(TransformSpec::parse("max-backlog", "10"), Ok(None));
+    }
+
+    #[test]
+    fn test_pipeline_order() {
+        let specs = [
+            TransformSpec::parse("rewrite-talker", "GN:GL")
+                .unwrap()
+                .unwrap(),
+            TransformSpec::parse("rewrite-talker", "GL:GP")
+                .unwrap()
+                .unwrap(),
+        ];
+        let frame = Frame {
+            protocol: Protocol::Nmea,
+            data: b"$GNGSA,A,3\r\n".to_vec(),
+        };
+        assert_eq!(
+            Pipeline::new(&specs).run(&[frame])[0].data,
+            b"$GPGSA,A,3\r\n"
+        );
+    }
+}
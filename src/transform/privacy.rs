@@ -0,0 +1,140 @@
+//! Degradation of the positions in the NMEA sentences, so a stream shared with third parties
+//! doesn't reveal the exact coordinates.
+
+use crate::framing::{Frame, Protocol};
+use crate::nmea::{coordinate, format_coordinate, latitude_index, nmea_fields, nmea_sentence};
+use crate::transform::Transform;
+
+/// Rewrite the latitude and the longitude of a sentence carrying a position, if it has one.
+fn rewrite_position(frame: &mut Frame, rewrite: impl Fn(&str, &str, bool) -> (String, String)) {
+    if frame.protocol != Protocol::Nmea {
+        return;
+    }
+    let Some(mut fields) = nmea_fields(&frame.data) else {
+        return;
+    };
+    let Some(index) = latitude_index(fields[0]) else {
+        return;
+    };
+    if fields.len() < index + 4 || fields[index].is_empty() || fields[index + 2].is_empty() {
+        // no fix, nothing to hide.
+        return;
+    }
+    let (latitude, north_south) = rewrite(fields[index], fields[index + 1], true);
+    let (longitude, east_west) = rewrite(fields[index + 2], fields[index + 3], false);
+    fields[index] = &latitude;
+    fields[index + 1] = &north_south;
+    fields[index + 2] = &longitude;
+    fields[index + 3] = &east_west;
+    frame.data = nmea_sentence(&fields);
+}
+
+fn decimals(field: &str) -> usize {
+    field.find('.').map_or(0, |dot| field.len() - dot - 1)
+}
+
+/// Truncate the minutes of the positions to a number of decimals.
+pub struct TruncatePosition {
+    decimals: usize,
+}
+
+impl TruncatePosition {
+    /// 0 decimals of minutes is about 1.8 km, 1 about 185 m, 2 about 18 m...
+    pub fn new(decimals: usize) -> Self {
+        Self { decimals }
+    }
+}
+
+impl Transform for TruncatePosition {
+    fn apply(&mut self, mut frame: Frame, output: &mut Vec<Frame>) {
+        rewrite_position(&mut frame, |value, hemisphere, _| {
+            // the dropped digits are zeroed so the field keeps its format.
+            let keep = value
+                .find('.')
+                .map_or(value.len(), |dot| dot + 1 + self.decimals);
+            let truncated = value
+                .char_indices()
+                .map(|(i, c)| if i >= keep { '0' } else { c })
+                .collect();
+            (truncated, hemisphere.to_string())
+        });
+        output.push(frame);
+    }
+}
+
+/// Shift the positions by a fixed offset.
+pub struct OffsetPosition {
+    latitude: f64,
+    longitude: f64,
+}
+
+impl OffsetPosition {
+    /// The offsets are in degrees.
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+}
+
+impl Transform for OffsetPosition {
+    fn apply(&mut self, mut frame: Frame, output: &mut Vec<Frame>) {
+        rewrite_position(&mut frame, |value, hemisphere, latitude| {
+            let Some(position) = coordinate(value, hemisphere) else {
+                return (value.to_string(), hemisphere.to_string());
+            };
+            let shifted = if latitude {
+                (position + self.latitude).clamp(-90.0, 90.0)
+            } else {
+                // back in [-180, 180).
+                (position + self.longitude + 180.0).rem_euclid(360.0) - 180.0
+            };
+            let (value, hemisphere) = format_coordinate(shifted, latitude, decimals(value));
+            (value, hemisphere.to_string())
+        });
+        output.push(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{nmea_checksum_ok, Frame, Protocol};
+    use crate::transform::privacy::{OffsetPosition, TruncatePosition};
+    use crate::transform::Transform;
+
+    const GGA: &[u8] = b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n";
+    const RMC: &[u8] = b"$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n";
+
+    fn apply(transform: &mut dyn Transform, sentence: &[u8]) -> String {
+        let mut output = Vec::new();
+        transform.apply(
+            Frame {
+                protocol: Protocol::Nmea,
+                data: sentence.to_vec(),
+            },
+            &mut output,
+        );
+        assert!(nmea_checksum_ok(&output[0].data));
+        String::from_utf8(output.remove(0).data).unwrap()
+    }
+
+    #[test]
+    fn test_truncate_position() {
+        let mut truncate = TruncatePosition::new(1);
+        assert!(apply(&mut truncate, GGA).starts_with("$GPGGA,123519,4807.000,N,01131.000,E,1,08"));
+        assert!(
+            apply(&mut truncate, RMC).starts_with("$GPRMC,123519,A,4807.000,N,01131.000,E,022.4")
+        );
+        let no_fix = b"$GPGGA,000001.00,,,,,0,00,99.99,,,,,,*67\r\n";
+        assert_eq!(apply(&mut truncate, no_fix).as_bytes(), no_fix);
+    }
+
+    #[test]
+    fn test_offset_position() {
+        let mut offset = OffsetPosition::new(-50.0, 0.5);
+        assert!(apply(&mut offset, GGA).starts_with("$GPGGA,123519,0152.962,S,01201.000,E,1,08"));
+        let mut offset = OffsetPosition::new(0.0, -20.0);
+        assert!(apply(&mut offset, RMC).contains(",4807.038,N,00829.000,W,"));
+    }
+}
@@ -0,0 +1,70 @@
+//! Rewriting of the talker id of the NMEA sentences, for the legacy consumers that only accept
+//! GP sentences from the multi-constellation receivers (GN).
+
+use crate::framing::{nmea_checksum, Frame, Protocol};
+use crate::transform::Transform;
+
+/// Whether this is a valid talker id: 2 upper case letters.
+pub fn is_talker_id(id: &str) -> bool {
+    id.len() == 2 && id.bytes().all(|c| c.is_ascii_uppercase())
+}
+
+pub struct RewriteTalker {
+    from: [u8; 2],
+    to: [u8; 2],
+}
+
+impl RewriteTalker {
+    pub fn new(from: &str, to: &str) -> Self {
+        let id = |id: &str| [id.as_bytes()[0], id.as_bytes()[1]];
+        Self {
+            from: id(from),
+            to: id(to),
+        }
+    }
+}
+
+impl Transform for RewriteTalker {
+    fn apply(&mut self, mut frame: Frame, output: &mut Vec<Frame>) {
+        if frame.protocol == Protocol::Nmea && frame.data.get(1..3) == Some(&self.from[..]) {
+            frame.data[1..3].copy_from_slice(&self.to);
+            if let Some(star) = frame.data.iter().position(|&c| c == b'*') {
+                let checksum = format!("{:02X}", nmea_checksum(&frame.data[1..star]));
+                frame.data[star + 1..star + 3].copy_from_slice(checksum.as_bytes());
+            }
+        }
+        output.push(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{nmea_checksum_ok, Frame, Protocol};
+    use crate::transform::talker::RewriteTalker;
+    use crate::transform::Transform;
+
+    fn nmea(sentence: &[u8]) -> Frame {
+        Frame {
+            protocol: Protocol::Nmea,
+            data: sentence.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_rewrite_talker() {
+        let mut rewrite = RewriteTalker::new("GN", "GP");
+        let mut output = Vec::new();
+        rewrite.apply(
+            nmea(b"$GNGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*59\r\n"),
+            &mut output,
+        );
+        rewrite.apply(nmea(b"$GLGSV,1,1,00*65\r\n"), &mut output);
+        assert_eq!(
+            output[0].data,
+            b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n"
+        );
+        assert!(nmea_checksum_ok(&output[0].data));
+        // the other talkers are left alone.
+        assert_eq!(output[1].data, b"$GLGSV,1,1,00*65\r\n");
+    }
+}
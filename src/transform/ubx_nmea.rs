@@ -0,0 +1,182 @@
+//! Conversion of the UBX NAV-PVT messages into NMEA GGA and RMC sentences, for the consumers that
+//! only understand NMEA while the receiver runs in the more efficient UBX only mode.
+
+use crate::framing::{Frame, Protocol};
+use crate::nmea::{format_coordinate, nmea_sentence};
+use crate::transform::Transform;
+use crate::ubx::NavPvt;
+
+// knots in 1 m/s.
+const KNOTS_PER_MPS: f64 = 1.943_844;
+
+pub struct UbxToNmea {
+    talker: String,
+}
+
+impl UbxToNmea {
+    /// Convert with the given talker id, like GN or GP.
+    pub fn new(talker: &str) -> Self {
+        Self {
+            talker: talker.to_string(),
+        }
+    }
+
+    fn gga(
+        &self,
+        pvt: &NavPvt,
+        time: &str,
+        latitude: &(String, &str),
+        longitude: &(String, &str),
+    ) -> Vec<u8> {
+        let quality = match (pvt.fix_ok, pvt.fix_type, pvt.carrier_solution) {
+            (false, _, _) | (_, 0, _) | (_, 5, _) => "0",
+            (_, 1, _) => "6",
+            (_, _, 2) => "4",
+            (_, _, 1) => "5",
+            _ if pvt.differential => "2",
+            _ => "1",
+        };
+        let satellites = format!("{:02}", pvt.satellites);
+        // NAV-PVT has no HDOP, the PDOP is the closest.
+        let dop = format!("{:.1}", pvt.pdop);
+        let altitude = format!("{:.1}", pvt.height_msl);
+        let separation = format!("{:.1}", pvt.height - pvt.height_msl);
+        nmea_sentence(&[
+            &format!("{}GGA", self.talker),
+            time,
+            &latitude.0,
+            latitude.1,
+            &longitude.0,
+            longitude.1,
+            quality,
+            &satellites,
+            &dop,
+            &altitude,
+            "M",
+            &separation,
+            "M",
+            "",
+            "",
+        ])
+    }
+
+    fn rmc(
+        &self,
+        pvt: &NavPvt,
+        time: &str,
+        latitude: &(String, &str),
+        longitude: &(String, &str),
+    ) -> Vec<u8> {
+        let valid = pvt.fix_ok && (2..=4).contains(&pvt.fix_type);
+        let date = if pvt.valid_date {
+            format!("{:02}{:02}{:02}", pvt.day, pvt.month, pvt.year % 100)
+        } else {
+            String::new()
+        };
+        let speed = format!("{:.3}", pvt.ground_speed * KNOTS_PER_MPS);
+        let course = format!("{:.2}", pvt.heading);
+        let mode = match (valid, pvt.differential) {
+            (false, _) => "N",
+            (true, true) => "D",
+            (true, false) => "A",
+        };
+        nmea_sentence(&[
+            &format!("{}RMC", self.talker),
+            time,
+            if valid { "A" } else { "V" },
+            &latitude.0,
+            latitude.1,
+            &longitude.0,
+            longitude.1,
+            &speed,
+            &course,
+            &date,
+            "",
+            "",
+            mode,
+        ])
+    }
+}
+
+impl Transform for UbxToNmea {
+    fn apply(&mut self, frame: Frame, output: &mut Vec<Frame>) {
+        if frame.protocol == Protocol::Nmea {
+            output.push(frame);
+            return;
+        }
+        // the other UBX messages have no NMEA equivalent.
+        let Some(pvt) = NavPvt::parse(&frame.data) else {
+            return;
+        };
+        let time = if pvt.valid_time {
+            // the nano field rounds the time, it can be negative.
+            let centiseconds = (pvt.second as i64 * 100 + (pvt.nano as i64 / 10_000_000)).max(0);
+            format!(
+                "{:02}{:02}{:02}.{:02}",
+                pvt.hour,
+                pvt.minute,
+                centiseconds / 100,
+                centiseconds % 100
+            )
+        } else {
+            String::new()
+        };
+        let (latitude, longitude) = if pvt.fix_type == 0 {
+            ((String::new(), ""), (String::new(), ""))
+        } else {
+            (
+                format_coordinate(pvt.latitude, true, 5),
+                format_coordinate(pvt.longitude, false, 5),
+            )
+        };
+        for sentence in [
+            self.gga(&pvt, &time, &latitude, &longitude),
+            self.rmc(&pvt, &time, &latitude, &longitude),
+        ] {
+            output.push(Frame {
+                protocol: Protocol::Nmea,
+                data: sentence,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framing::{nmea_checksum_ok, Frame, Protocol};
+    use crate::nmea::Gga;
+    use crate::transform::ubx_nmea::UbxToNmea;
+    use crate::transform::Transform;
+    use crate::ubx::tests::nav_pvt_frame;
+
+    #[test]
+    fn test_nav_pvt_to_nmea() {
+        let mut convert = UbxToNmea::new("GN");
+        let mut output = Vec::new();
+        convert.apply(
+            Frame {
+                protocol: Protocol::Ubx,
+                data: nav_pvt_frame(),
+            },
+            &mut output,
+        );
+        // not a NAV-PVT, dropped.
+        convert.apply(
+            Frame {
+                protocol: Protocol::Ubx,
+                data: vec![0xB5, 0x62, 0x01, 0x03, 0, 0, 0x04, 0x0D],
+            },
+            &mut output,
+        );
+        assert_eq!(output.len(), 2);
+        assert!(output.iter().all(|frame| nmea_checksum_ok(&frame.data)));
+        let gga = String::from_utf8(output[0].data.clone()).unwrap();
+        assert!(gga
+            .starts_with("$GNGGA,123519.50,4807.03800,N,01131.00000,E,1,12,0.9,545.4,M,46.9,M,,*"));
+        let rmc = String::from_utf8(output[1].data.clone()).unwrap();
+        assert!(rmc
+            .starts_with("$GNRMC,123519.50,A,4807.03800,N,01131.00000,E,22.401,84.40,141123,,,A*"));
+        let gga = Gga::parse(&output[0].data).unwrap();
+        assert_eq!(gga.satellites, 12);
+    }
+}
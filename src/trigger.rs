@@ -0,0 +1,422 @@
+//! Captures triggered by events: with `--triggered-capture PATH` the last `--capture-pre-roll`
+//! seconds of the master are kept in memory, and a capture file is only written when a
+//! `--capture-trigger` fires, starting with them. It stops after `--capture-max-duration` seconds or
+//! `--capture-max-size` MB, so the interesting moments are recorded instead of days of routine
+//! data.
+//!
+//! The triggers are:
+//!
+//! * `pattern:TEXT` the master sends TEXT, with the escapes of the banners (`\r`, `\n`, `\xHH`...).
+//! * `stall:MS` the master sends nothing for MS milliseconds.
+//! * `checksum-errors:N/SECONDS` the framer finds N checksum errors within SECONDS, needs --framer.
+
+use crate::banner::parse_banner;
+use crate::endpoint::capture::{encode_chunk, CaptureHeader};
+use crate::endpoint::file::{format_time, now_micros, open_append};
+use crate::events::Event;
+use log::{error, info};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trigger {
+    Pattern(Vec<u8>),
+    Stall(Duration),
+    ChecksumErrors { count: usize, window: Duration },
+}
+
+impl FromStr for Trigger {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (kind, parameter) = value.split_once(':').unwrap_or((value, ""));
+        match kind {
+            "pattern" if !parameter.is_empty() => Ok(Self::Pattern(parse_banner(parameter)?)),
+            "stall" => parameter
+                .parse()
+                .ok()
+                .filter(|&ms| ms > 0)
+                .map(|ms| Self::Stall(Duration::from_millis(ms)))
+                .ok_or_else(|| format!("expected stall:MS with MS > 0, got {:?}", value)),
+            "checksum-errors" => parameter
+                .split_once('/')
+                .and_then(|(count, seconds)| Some((count.parse().ok()?, seconds.parse().ok()?)))
+                .filter(|&(count, seconds)| count > 0 && seconds > 0)
+                .map(|(count, seconds)| Self::ChecksumErrors {
+                    count,
+                    window: Duration::from_secs(seconds),
+                })
+                .ok_or_else(|| {
+                    format!(
+                        "expected checksum-errors:N/SECONDS with N and SECONDS > 0, got {:?}",
+                        value
+                    )
+                }),
+            _ => Err(format!(
+                "expected pattern:TEXT, stall:MS or checksum-errors:N/SECONDS, got {:?}",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pattern(pattern) => write!(f, "pattern:{}", pattern.escape_ascii()),
+            Self::Stall(stall) => write!(f, "stall:{}", stall.as_millis()),
+            Self::ChecksumErrors { count, window } => {
+                write!(f, "checksum-errors:{}/{}", count, window.as_secs())
+            }
+        }
+    }
+}
+
+/// The limits of the triggered captures.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CaptureLimits {
+    // kept in memory before a trigger.
+    pub pre_roll: Duration,
+    // recorded after a trigger.
+    pub max_duration: Duration,
+    // of a capture file, pre-roll included.
+    pub max_size: usize,
+}
+
+// The capture file being written.
+struct ActiveCapture {
+    path: PathBuf,
+    file: File,
+    started: Instant,
+    written: usize,
+}
+
+pub struct TriggeredCapture {
+    pattern: PathBuf,
+    header: CaptureHeader,
+    triggers: Vec<Trigger>,
+    limits: CaptureLimits,
+    // (time of receipt, time in µs since the epoch, data) of the pre-roll.
+    pre_roll: VecDeque<(Instant, u64, Vec<u8>)>,
+    pre_roll_size: usize,
+    // the end of the previous reads, for the patterns split between reads.
+    tail: Vec<u8>,
+    last_data: Option<Instant>,
+    stalled: bool,
+    checksum_errors: u64,
+    error_times: VecDeque<Instant>,
+    active: Option<ActiveCapture>,
+}
+
+impl TriggeredCapture {
+    /// Watch the master for the triggers.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern`: the path of the captures, with optional strftime patterns.
+    /// * `header`: the description of the master, written at the start of the captures.
+    /// * `triggers`: what starts a capture.
+    /// * `limits`: the pre-roll, duration and size of the captures.
+    ///
+    /// returns: TriggeredCapture
+    ///
+    pub fn new(
+        pattern: &Path,
+        header: CaptureHeader,
+        triggers: Vec<Trigger>,
+        limits: CaptureLimits,
+    ) -> Self {
+        Self {
+            pattern: pattern.to_path_buf(),
+            header,
+            triggers,
+            limits,
+            pre_roll: VecDeque::new(),
+            pre_roll_size: 0,
+            tail: Vec::new(),
+            last_data: None,
+            stalled: false,
+            checksum_errors: 0,
+            error_times: VecDeque::new(),
+            active: None,
+        }
+    }
+
+    /// Record a read of the master, start or stop a capture as needed. The errors are logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `data`: the read, empty on a timeout.
+    /// * `checksum_errors`: the checksum errors of the framer so far.
+    /// * `now`: the current time.
+    ///
+    pub fn record(&mut self, data: &[u8], checksum_errors: u64, now: Instant) {
+        let fired = self.fired_trigger(data, checksum_errors, now);
+        let micros = now_micros();
+        if let Some(active) = &mut self.active {
+            if !data.is_empty() {
+                if let Err(err) = active.file.write_all(&encode_chunk(data, micros)) {
+                    error!(
+                        event = Event::CaptureFailed.code();
+                        "Could not write the capture {:?}: {}",
+                        active.path, err
+                    );
+                }
+                active.written += data.len();
+            }
+            if now.saturating_duration_since(active.started) >= self.limits.max_duration
+                || active.written >= self.limits.max_size
+            {
+                info!(
+                    "The triggered capture {:?} is complete, {} bytes.",
+                    active.path, active.written
+                );
+                self.active = None;
+            }
+        } else if !data.is_empty() {
+            self.pre_roll.push_back((now, micros, data.to_vec()));
+            self.pre_roll_size += data.len();
+            while let Some((received_at, _, data)) = self.pre_roll.front() {
+                if now.saturating_duration_since(*received_at) <= self.limits.pre_roll
+                    && self.pre_roll_size <= self.limits.max_size
+                {
+                    break;
+                }
+                self.pre_roll_size -= data.len();
+                self.pre_roll.pop_front();
+            }
+        }
+        if let (Some(trigger), None) = (fired, &self.active) {
+            if let Err(err) = self.start(&trigger, now) {
+                error!(
+                    event = Event::CaptureFailed.code();
+                    "Could not start the capture triggered by {}: {}",
+                    trigger, err
+                );
+            }
+        }
+    }
+
+    // The trigger firing on this read, if any.
+    fn fired_trigger(
+        &mut self,
+        data: &[u8],
+        checksum_errors: u64,
+        now: Instant,
+    ) -> Option<Trigger> {
+        let mut window = std::mem::take(&mut self.tail);
+        window.extend_from_slice(data);
+        let new_errors = checksum_errors.saturating_sub(self.checksum_errors);
+        self.checksum_errors = checksum_errors;
+        if !data.is_empty() {
+            self.last_data = Some(now);
+            self.stalled = false;
+        }
+        let mut fired = None;
+        let mut longest_pattern = 0;
+        for trigger in &self.triggers {
+            let firing = match trigger {
+                Trigger::Pattern(pattern) => {
+                    longest_pattern = longest_pattern.max(pattern.len());
+                    window.windows(pattern.len()).any(|bytes| bytes == pattern)
+                }
+                Trigger::Stall(stall) => {
+                    let stalled = !self.stalled
+                        && matches!(self.last_data, Some(last) if now.saturating_duration_since(last) >= *stall);
+                    self.stalled |= stalled;
+                    stalled
+                }
+                Trigger::ChecksumErrors { count, window } => {
+                    for _ in 0..new_errors.min(*count as u64) {
+                        self.error_times.push_back(now);
+                    }
+                    while matches!(self.error_times.front(), Some(&at) if now.saturating_duration_since(at) > *window)
+                    {
+                        self.error_times.pop_front();
+                    }
+                    if self.error_times.len() >= *count {
+                        self.error_times.clear();
+                        true
+                    } else {
+                        false
+                    }
+                }
+            };
+            if firing && fired.is_none() {
+                fired = Some(trigger.clone());
+            }
+        }
+        // a pattern found is not found again in the next read.
+        let kept = if fired.is_some() {
+            0
+        } else {
+            longest_pattern.saturating_sub(1)
+        };
+        self.tail = window.split_off(window.len().saturating_sub(kept));
+        fired
+    }
+
+    // Open a capture file and write the pre-roll to it.
+    fn start(&mut self, trigger: &Trigger, now: Instant) -> io::Result<()> {
+        let micros = now_micros();
+        let time = (micros / 1_000_000) as i64;
+        let pattern = self
+            .pattern
+            .to_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "non UTF-8 path"))?;
+        let path = PathBuf::from(format_time(pattern, time)?);
+        let mut file = open_append(&path)?;
+        let start = format_time("%Y-%m-%dT%H:%M:%S%z", time)?;
+        let mut capture = self.header.encode(&start);
+        let mut written = 0;
+        for (_, micros, data) in self.pre_roll.drain(..) {
+            capture.extend(encode_chunk(&data, micros));
+            written += data.len();
+        }
+        self.pre_roll_size = 0;
+        file.write_all(&capture)?;
+        info!(
+            "Capture triggered by {}, writing {:?} with {} bytes of pre-roll.",
+            trigger, path, written
+        );
+        self.active = Some(ActiveCapture {
+            path,
+            file,
+            started: now,
+            written,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::capture::{parse, CaptureHeader};
+    use crate::trigger::{CaptureLimits, Trigger, TriggeredCapture};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_trigger() {
+        assert_eq!(
+            "pattern:$GPTXT,01,01,02".parse(),
+            Ok(Trigger::Pattern(b"$GPTXT,01,01,02".to_vec()))
+        );
+        assert_eq!(
+            "stall:2000".parse(),
+            Ok(Trigger::Stall(Duration::from_secs(2)))
+        );
+        let trigger: Trigger = "checksum-errors:5/10".parse().unwrap();
+        assert_eq!(
+            trigger,
+            Trigger::ChecksumErrors {
+                count: 5,
+                window: Duration::from_secs(10)
+            }
+        );
+        assert_eq!(trigger.to_string(), "checksum-errors:5/10");
+        assert!("stall:0".parse::<Trigger>().is_err());
+        assert!("checksum-errors:5".parse::<Trigger>().is_err());
+        assert!("pattern:".parse::<Trigger>().is_err());
+        assert!("reboot".parse::<Trigger>().is_err());
+    }
+
+    fn captured(path: &PathBuf) -> Vec<Vec<u8>> {
+        let data = fs::read(path).unwrap();
+        let capture = parse(&data).unwrap();
+        capture
+            .chunks
+            .iter()
+            .map(|chunk| chunk.data.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_triggered_capture() {
+        let path = std::env::temp_dir().join("ttytee_trigger_test.cap");
+        fs::remove_file(&path).ok();
+        let limits = CaptureLimits {
+            pre_roll: Duration::from_secs(1),
+            max_duration: Duration::from_secs(2),
+            max_size: 1 << 20,
+        };
+        let header = CaptureHeader {
+            device: PathBuf::from("/dev/ttyUSB0"),
+            baudrate: 9600,
+        };
+        let triggers = vec![
+            "pattern:$GPTXT,01".parse().unwrap(),
+            "stall:500".parse().unwrap(),
+        ];
+        let mut capture = TriggeredCapture::new(&path, header, triggers, limits);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        capture.record(b"$GPGGA,1\r\n", 0, start);
+        capture.record(b"$GPGGA,2\r\n", 0, at(1100));
+        // nothing is written until a trigger fires.
+        assert!(!path.exists());
+        // the pattern is split between 2 reads.
+        capture.record(b"$GPT", 0, at(1200));
+        capture.record(b"XT,01\r\n", 0, at(1300));
+        capture.record(b"$GPGGA,3\r\n", 0, at(1400));
+        // the first read left the pre-roll.
+        assert_eq!(
+            captured(&path),
+            vec![
+                b"$GPGGA,2\r\n".to_vec(),
+                b"$GPT".to_vec(),
+                b"XT,01\r\n".to_vec(),
+                b"$GPGGA,3\r\n".to_vec()
+            ]
+        );
+        // the capture stops after its duration.
+        capture.record(b"$GPGGA,4\r\n", 0, at(3300));
+        capture.record(b"$GPGGA,5\r\n", 0, at(3400));
+        assert_eq!(captured(&path).len(), 5);
+
+        // then the master stalls, the next capture is appended with its own header.
+        capture.record(b"", 0, at(3800));
+        capture.record(b"", 0, at(3900));
+        let data = fs::read(&path).unwrap();
+        let chunks = parse(&data).unwrap();
+        assert_eq!(chunks.headers.matches("device=").count(), 2);
+        assert_eq!(chunks.chunks.last().unwrap().data, b"$GPGGA,5\r\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_checksum_error_burst() {
+        let path = std::env::temp_dir().join("ttytee_trigger_errors_test.cap");
+        fs::remove_file(&path).ok();
+        let limits = CaptureLimits {
+            pre_roll: Duration::from_secs(10),
+            max_duration: Duration::from_secs(10),
+            max_size: 16,
+        };
+        let header = CaptureHeader {
+            device: PathBuf::from("/dev/ttyUSB0"),
+            baudrate: 9600,
+        };
+        let triggers = vec!["checksum-errors:3/1".parse().unwrap()];
+        let mut capture = TriggeredCapture::new(&path, header, triggers, limits);
+        let start = Instant::now();
+        capture.record(b"$GPGGA,1*00\r\n", 1, start);
+        // too far apart.
+        capture.record(b"$GPGGA,2*00\r\n", 2, start + Duration::from_secs(2));
+        assert!(!path.exists());
+        capture.record(b"$GPGGA,3*00\r\n", 4, start + Duration::from_millis(2500));
+        // limited in size, the pre-roll included.
+        assert_eq!(captured(&path), vec![b"$GPGGA,3*00\r\n".to_vec()]);
+        capture.record(b"$GPGGA,4*00\r\n", 4, start + Duration::from_millis(2600));
+        capture.record(b"$GPGGA,5*00\r\n", 4, start + Duration::from_millis(2700));
+        assert_eq!(captured(&path).len(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+//! Hardware error counters of the master UART.
+//!
+//! The kernel counts the framing, parity and overrun errors of the UART drivers, they are the
+//! telltale signs of a bad cable or a wrong baudrate. The BREAK conditions are counted too, they are
+//! logged on their own since some devices send them on purpose, to switch modes.
+
+use crate::events::Event;
+use log::{debug, info, warn};
+use std::io;
+use std::os::unix::io::RawFd;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the counters are read from the master.
+const CHECK_PERIOD: Duration = Duration::from_secs(5);
+
+// Mirrors struct serial_icounter_struct from linux/serial.h.
+#[repr(C)]
+#[derive(Default)]
+struct SerialIcounterStruct {
+    cts: libc::c_int,
+    dsr: libc::c_int,
+    rng: libc::c_int,
+    dcd: libc::c_int,
+    rx: libc::c_int,
+    tx: libc::c_int,
+    frame: libc::c_int,
+    overrun: libc::c_int,
+    parity: libc::c_int,
+    brk: libc::c_int,
+    buf_overrun: libc::c_int,
+    reserved: [libc::c_int; 9],
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UartErrors {
+    pub frame: u64,
+    pub parity: u64,
+    pub overrun: u64,
+    pub buf_overrun: u64,
+    pub brk: u64,
+}
+
+impl UartErrors {
+    // The errors, the BREAK conditions aside.
+    fn total(&self) -> u64 {
+        self.frame + self.parity + self.overrun + self.buf_overrun
+    }
+
+    fn since(&self, before: &UartErrors) -> UartErrors {
+        UartErrors {
+            frame: self.frame.saturating_sub(before.frame),
+            parity: self.parity.saturating_sub(before.parity),
+            overrun: self.overrun.saturating_sub(before.overrun),
+            buf_overrun: self.buf_overrun.saturating_sub(before.buf_overrun),
+            brk: self.brk.saturating_sub(before.brk),
+        }
+    }
+}
+
+/// Read the error counters of a serial device.
+pub fn read_uart_errors(fd: RawFd) -> io::Result<UartErrors> {
+    let mut counters = SerialIcounterStruct::default();
+    if unsafe { libc::ioctl(fd, libc::TIOCGICOUNT, &mut counters) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(UartErrors {
+        frame: counters.frame as u64,
+        parity: counters.parity as u64,
+        overrun: counters.overrun as u64,
+        buf_overrun: counters.buf_overrun as u64,
+        brk: counters.brk as u64,
+    })
+}
+
+/// Send a BREAK on a serial device, holding the line low for a while.
+///
+/// # Arguments
+///
+/// * `fd`: the serial device.
+/// * `duration`: how long the BREAK lasts.
+///
+/// returns: Result<(), Error>
+///
+pub fn send_break(fd: RawFd, duration: Duration) -> io::Result<()> {
+    if unsafe { libc::ioctl(fd, libc::TIOCSBRK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    thread::sleep(duration);
+    if unsafe { libc::ioctl(fd, libc::TIOCCBRK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Periodically reads the error counters of the master and warns when they increase.
+pub struct UartMonitor {
+    fd: RawFd,
+    last_check: Option<Instant>,
+    errors: Option<UartErrors>,
+    supported: bool,
+}
+
+impl UartMonitor {
+    pub fn new(fd: RawFd) -> Self {
+        Self {
+            fd,
+            last_check: None,
+            errors: None,
+            supported: true,
+        }
+    }
+
+    /// Check the counters if it is time to, returns the total errors since the start.
+    pub fn poll(&mut self, now: Instant) -> Option<UartErrors> {
+        if !self.supported
+            || matches!(self.last_check, Some(last) if now.duration_since(last) < CHECK_PERIOD)
+        {
+            return self.errors;
+        }
+        self.last_check = Some(now);
+        match read_uart_errors(self.fd) {
+            Ok(errors) => self.update(errors),
+            Err(err) => {
+                // PTYs and some USB adapters don't have these counters.
+                debug!("The master has no UART error counters: {}.", err);
+                self.supported = false;
+            }
+        }
+        self.errors
+    }
+
+    fn update(&mut self, errors: UartErrors) {
+        if let Some(before) = &self.errors {
+            let new = errors.since(before);
+            if new.total() > 0 {
+                warn!(
+                    event = Event::UartErrors.code();
+                    "UART errors on master: {} framing, {} parity, {} overrun, {} buffer overrun. \
+                     Check the cable and the baudrate.",
+                    new.frame, new.parity, new.overrun, new.buf_overrun
+                );
+            }
+            if new.brk > 0 {
+                info!("{} BREAK received on master.", new.brk);
+            }
+        }
+        self.errors = Some(errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::uart::{UartErrors, UartMonitor};
+    use serialport::TTYPort;
+    use std::os::unix::io::AsRawFd;
+    use std::time::Instant;
+
+    #[test]
+    fn test_pty_has_no_counters() {
+        let (master, _slave) = TTYPort::pair().unwrap();
+        let mut monitor = UartMonitor::new(master.as_raw_fd());
+        assert_eq!(monitor.poll(Instant::now()), None);
+        assert!(!monitor.supported);
+    }
+
+    #[test]
+    fn test_errors_accumulate() {
+        let mut monitor = UartMonitor::new(-1);
+        monitor.update(UartErrors::default());
+        let errors = UartErrors {
+            frame: 3,
+            overrun: 1,
+            brk: 2,
+            ..Default::default()
+        };
+        monitor.update(errors);
+        assert_eq!(monitor.errors, Some(errors));
+        assert_eq!(errors.since(&UartErrors::default()).total(), 4);
+    }
+}
@@ -0,0 +1,202 @@
+//! Decoding of the content of UBX messages, the framer only splits and validates them.
+
+use crate::framing::ubx_checksum;
+
+/// The content of a NAV-PVT message: the navigation solution of an epoch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NavPvt {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    // fraction of the second in ns, can be negative.
+    pub nano: i32,
+    pub valid_date: bool,
+    pub valid_time: bool,
+    // 0 no fix, 1 dead reckoning, 2 2D, 3 3D, 4 GNSS + dead reckoning, 5 time only.
+    pub fix_type: u8,
+    pub fix_ok: bool,
+    pub differential: bool,
+    // 0 none, 1 RTK float, 2 RTK fixed.
+    pub carrier_solution: u8,
+    pub satellites: u8,
+    // in degrees.
+    pub longitude: f64,
+    pub latitude: f64,
+    // in m.
+    pub height: f64,
+    pub height_msl: f64,
+    // in m/s.
+    pub ground_speed: f64,
+    // in degrees.
+    pub heading: f64,
+    pub pdop: f64,
+}
+
+fn u16_at(payload: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([payload[offset], payload[offset + 1]])
+}
+
+fn i32_at(payload: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes([
+        payload[offset],
+        payload[offset + 1],
+        payload[offset + 2],
+        payload[offset + 3],
+    ])
+}
+
+impl NavPvt {
+    /// Decode a complete UBX frame, None if it is another message or is too short.
+    pub fn parse(frame: &[u8]) -> Option<Self> {
+        if frame.len() < 8 || frame[2..4] != [0x01, 0x07] {
+            return None;
+        }
+        let payload = &frame[6..frame.len() - 2];
+        if payload.len() < 92 {
+            return None;
+        }
+        Some(Self {
+            year: u16_at(payload, 4),
+            month: payload[6],
+            day: payload[7],
+            hour: payload[8],
+            minute: payload[9],
+            second: payload[10],
+            nano: i32_at(payload, 16),
+            valid_date: payload[11] & 0x01 != 0,
+            valid_time: payload[11] & 0x02 != 0,
+            fix_type: payload[20],
+            fix_ok: payload[21] & 0x01 != 0,
+            differential: payload[21] & 0x02 != 0,
+            carrier_solution: payload[21] >> 6,
+            satellites: payload[23],
+            longitude: i32_at(payload, 24) as f64 * 1e-7,
+            latitude: i32_at(payload, 28) as f64 * 1e-7,
+            height: i32_at(payload, 32) as f64 / 1000.0,
+            height_msl: i32_at(payload, 36) as f64 / 1000.0,
+            ground_speed: i32_at(payload, 60) as f64 / 1000.0,
+            heading: i32_at(payload, 64) as f64 * 1e-5,
+            pdop: u16_at(payload, 76) as f64 * 0.01,
+        })
+    }
+}
+
+/// The content of a MON-VER message: the versions of the receiver.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonVer {
+    pub software: String,
+    pub hardware: String,
+    // like FWVER=HPG 1.32 or PROTVER=27.31.
+    pub extensions: Vec<String>,
+}
+
+// A string padded with NUL bytes.
+fn padded_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&c| c == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).trim().to_string()
+}
+
+impl MonVer {
+    /// Decode a complete UBX frame, None if it is another message or is too short.
+    pub fn parse(frame: &[u8]) -> Option<Self> {
+        if frame.len() < 8 || frame[2..4] != [0x0A, 0x04] {
+            return None;
+        }
+        let payload = &frame[6..frame.len() - 2];
+        if payload.len() < 40 {
+            return None;
+        }
+        Some(Self {
+            software: padded_string(&payload[..30]),
+            hardware: padded_string(&payload[30..40]),
+            extensions: payload[40..]
+                .chunks_exact(30)
+                .map(padded_string)
+                .filter(|extension| !extension.is_empty())
+                .collect(),
+        })
+    }
+}
+
+/// Build an UBX frame, like the poll of a message with an empty payload.
+pub fn ubx_frame(class: u8, id: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0xB5, 0x62, class, id];
+    frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    frame.extend_from_slice(payload);
+    let (a, b) = ubx_checksum(&frame[2..]);
+    frame.extend_from_slice(&[a, b]);
+    frame
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::ubx::{ubx_frame, MonVer, NavPvt};
+
+    /// A NAV-PVT frame of a 3D fix at 48.1173 N 11.5166667 E, 2023-11-14 12:35:19.5.
+    pub fn nav_pvt_frame() -> Vec<u8> {
+        let mut payload = vec![0u8; 92];
+        payload[4..6].copy_from_slice(&2023u16.to_le_bytes());
+        payload[6] = 11;
+        payload[7] = 14;
+        payload[8] = 12;
+        payload[9] = 35;
+        payload[10] = 19;
+        payload[11] = 0x03;
+        payload[16..20].copy_from_slice(&500_000_000i32.to_le_bytes());
+        payload[20] = 3;
+        payload[21] = 0x01;
+        payload[23] = 12;
+        payload[24..28].copy_from_slice(&115_166_667i32.to_le_bytes());
+        payload[28..32].copy_from_slice(&481_173_000i32.to_le_bytes());
+        payload[32..36].copy_from_slice(&592_300i32.to_le_bytes());
+        payload[36..40].copy_from_slice(&545_400i32.to_le_bytes());
+        payload[60..64].copy_from_slice(&11_524i32.to_le_bytes());
+        payload[64..68].copy_from_slice(&8_440_000i32.to_le_bytes());
+        payload[76..78].copy_from_slice(&90u16.to_le_bytes());
+        ubx_frame(0x01, 0x07, &payload)
+    }
+
+    /// A MON-VER frame of a ZED-F9P.
+    pub fn mon_ver_frame() -> Vec<u8> {
+        let mut payload = vec![0u8; 130];
+        for (offset, field) in [
+            (0, "EXT CORE 1.00 (fbbe3e)"),
+            (30, "00190000"),
+            (40, "ROM BASE 0x118B2060"),
+            (70, "FWVER=HPG 1.32"),
+            (100, "PROTVER=27.31"),
+        ] {
+            payload[offset..offset + field.len()].copy_from_slice(field.as_bytes());
+        }
+        ubx_frame(0x0A, 0x04, &payload)
+    }
+
+    #[test]
+    fn test_parse_nav_pvt() {
+        let pvt = NavPvt::parse(&nav_pvt_frame()).unwrap();
+        assert_eq!((pvt.year, pvt.month, pvt.day), (2023, 11, 14));
+        assert_eq!((pvt.hour, pvt.minute, pvt.second), (12, 35, 19));
+        assert!(pvt.valid_date && pvt.valid_time && pvt.fix_ok);
+        assert_eq!(pvt.fix_type, 3);
+        assert_eq!(pvt.satellites, 12);
+        assert!((pvt.latitude - 48.1173).abs() < 1e-9);
+        assert!((pvt.height_msl - 545.4).abs() < 1e-9);
+        assert!((pvt.pdop - 0.9).abs() < 1e-9);
+        assert_eq!(NavPvt::parse(&[0xB5, 0x62, 0x01, 0x03, 0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_parse_mon_ver() {
+        let version = MonVer::parse(&mon_ver_frame()).unwrap();
+        assert_eq!(version.software, "EXT CORE 1.00 (fbbe3e)");
+        assert_eq!(version.hardware, "00190000");
+        assert_eq!(
+            version.extensions,
+            vec!["ROM BASE 0x118B2060", "FWVER=HPG 1.32", "PROTVER=27.31"]
+        );
+        assert_eq!(MonVer::parse(&ubx_frame(0x0A, 0x04, &[])), None);
+    }
+}
@@ -0,0 +1,363 @@
+//! Instantiation by udev, so plugging a receiver stands up a tee for it: with `--from-udev` the
+//! master and its properties come from the environment of a udev `RUN` (DEVNAME, ID_SERIAL_SHORT,
+//! ID_VENDOR_ID...) or, when it is not there like in a systemd unit, from the udev database for
+//! the `--master` given. The name of the instance and the slaves derive from a template, and the
+//! instance registers itself in the runtime manifest `/run/ttytee/instances/NAME.json` while it
+//! runs. The entry is removed when it stops, an entry whose pid is not running is left by an
+//! instance that was killed: it is ignored and removed by the next registration.
+//!
+//! The template is the default one or an `--udev-template FILE` of `KEY=VALUE` lines, the keys are
+//! `name`, `slave0`, `slave1` and `baudrate`. The values can use the udev properties like
+//! `{ID_MODEL_ID}`, `{kernel}` the name of the device (ttyACM0), `{serial}` its serial number (or
+//! `{kernel}` when it has none) and `{name}` the name of the instance.
+
+use crate::endpoint::format::json_string;
+use crate::Args;
+use log::{debug, info};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+pub const RUNTIME_DIR: &str = "/run/ttytee";
+const UDEV_DATA: &str = "/run/udev/data";
+
+/// How an instance is named and where its slaves are.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UdevTemplate {
+    pub name: String,
+    pub slave0: String,
+    pub slave1: String,
+    pub baudrate: Option<u32>,
+}
+
+impl Default for UdevTemplate {
+    fn default() -> Self {
+        Self {
+            name: "{serial}".to_string(),
+            slave0: format!("{}/{{name}}/slave0.pty", RUNTIME_DIR),
+            slave1: format!("{}/{{name}}/slave1.pty", RUNTIME_DIR),
+            baudrate: None,
+        }
+    }
+}
+
+/// Read an --udev-template file, the keys it doesn't have keep their default.
+pub fn read_udev_template(path: &str) -> Result<UdevTemplate, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let mut template = UdevTemplate::default();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |err: &str| format!("{}, line {}: {}", path, number + 1, err);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid("expected KEY=VALUE"))?;
+        let value = value.trim().to_string();
+        match key.trim() {
+            "name" => template.name = value,
+            "slave0" => template.slave0 = value,
+            "slave1" => template.slave1 = value,
+            "baudrate" => {
+                template.baudrate = Some(value.parse().map_err(|_| invalid("invalid baudrate"))?)
+            }
+            key => return Err(invalid(&format!("unknown key {:?}", key))),
+        }
+    }
+    Ok(template)
+}
+
+/// Replace the `{VARIABLE}` of a template.
+///
+/// # Arguments
+///
+/// * `template`: a value of the template, like `/run/ttytee/{name}/slave0.pty`.
+/// * `variables`: the udev properties and the variables of ttytee.
+///
+/// returns: Result<String, String> or the unknown variable.
+///
+fn expand(template: &str, variables: &HashMap<String, String>) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed {{ in {:?}", template))?;
+        let variable = &rest[start + 1..start + end];
+        let value = variables
+            .get(variable)
+            .ok_or_else(|| format!("unknown variable {{{}}} in {:?}", variable, template))?;
+        expanded.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// The udev properties of a device from the udev database.
+fn udev_database(device: &Path) -> HashMap<String, String> {
+    let Ok(metadata) = fs::metadata(device) else {
+        return HashMap::new();
+    };
+    let rdev = metadata.rdev();
+    let data = Path::new(UDEV_DATA).join(format!("c{}:{}", libc::major(rdev), libc::minor(rdev)));
+    let mut properties: HashMap<String, String> = fs::read_to_string(data)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.strip_prefix("E:")?.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    properties.insert("DEVNAME".to_string(), device.display().to_string());
+    properties
+}
+
+/// Configure the instance for the device udev gives.
+///
+/// # Arguments
+///
+/// * `args`: the command line, the master, the name, the slaves and the baudrate are set.
+/// * `environment`: the environment of ttytee.
+///
+/// returns: Result<(), String>
+///
+pub fn apply_udev(args: &mut Args, environment: HashMap<String, String>) -> Result<(), String> {
+    let mut variables = if environment.contains_key("DEVNAME") {
+        environment
+    } else {
+        udev_database(&args.master)
+    };
+    let device = PathBuf::from(
+        variables
+            .get("DEVNAME")
+            .ok_or_else(|| format!("no udev properties for {:?}", args.master))?,
+    );
+    let kernel = device
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let serial = ["ID_SERIAL_SHORT", "ID_SERIAL"]
+        .iter()
+        .find_map(|key| variables.get(*key))
+        .cloned()
+        .unwrap_or_else(|| kernel.clone());
+    variables.insert("kernel".to_string(), kernel);
+    variables.insert("serial".to_string(), serial);
+    let template = args.udev_template.clone().unwrap_or_default();
+    let name = expand(&template.name, &variables)?;
+    variables.insert("name".to_string(), name.clone());
+    args.slave0 = PathBuf::from(expand(&template.slave0, &variables)?);
+    args.slave1 = PathBuf::from(expand(&template.slave1, &variables)?);
+    args.master = device;
+    args.name = Some(name);
+    if let Some(baudrate) = template.baudrate {
+        args.baudrate = baudrate;
+    }
+    Ok(())
+}
+
+// Whether a process is running, a process of another user too.
+fn running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    let signaled = unsafe { libc::kill(pid, 0) } == 0;
+    signaled || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// The pid of a manifest entry.
+fn entry_pid(entry: &str) -> Option<u32> {
+    let start = entry.find("\"pid\":")? + "\"pid\":".len();
+    let digits = entry[start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(&entry[start..], |end| &entry[start..start + end]);
+    digits.parse().ok()
+}
+
+/// The entries of the running instances in the runtime manifest.
+///
+/// The entries of the instances that are gone, killed before they could remove them, are skipped
+/// and removed.
+///
+/// # Arguments
+///
+/// * `dir`: the runtime directory.
+///
+/// returns: Vec<String> the JSON entries, in no particular order.
+///
+pub fn live_entries(dir: &Path) -> Vec<String> {
+    let Ok(files) = fs::read_dir(dir.join("instances")) else {
+        return Vec::new();
+    };
+    let mut entries = Vec::new();
+    for path in files.flatten().map(|file| file.path()) {
+        let Ok(entry) = fs::read_to_string(&path) else {
+            continue;
+        };
+        if entry_pid(&entry).is_some_and(running) {
+            entries.push(entry);
+        } else {
+            debug!("Removing the stale instance entry {:?}.", path);
+            fs::remove_file(&path).ok();
+        }
+    }
+    entries
+}
+
+/// The entry of an instance in the runtime manifest, removed when dropped.
+pub struct ManifestEntry {
+    path: PathBuf,
+}
+
+impl ManifestEntry {
+    /// Register an instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir`: the runtime directory.
+    /// * `name`: the name of the instance.
+    /// * `master`: its master.
+    /// * `slaves`: the links to its PTYs.
+    ///
+    /// returns: Result<ManifestEntry, Error>
+    ///
+    pub fn register(dir: &Path, name: &str, master: &Path, slaves: &[&Path]) -> io::Result<Self> {
+        let others = live_entries(dir).len();
+        let dir = dir.join("instances");
+        fs::create_dir_all(&dir)?;
+        let mut entry = String::from("{\"name\":");
+        json_string(name, &mut entry);
+        write!(entry, ",\"pid\":{},\"master\":", std::process::id()).unwrap();
+        json_string(&master.to_string_lossy(), &mut entry);
+        entry.push_str(",\"slaves\":[");
+        for (i, slave) in slaves.iter().enumerate() {
+            if i > 0 {
+                entry.push(',');
+            }
+            json_string(&slave.to_string_lossy(), &mut entry);
+        }
+        entry.push_str("]}\n");
+        let path = dir.join(format!("{}.json", name));
+        fs::write(&path, entry)?;
+        info!(
+            "Registered the instance {} in {:?}, {} other instance(s) running.",
+            name, path, others
+        );
+        Ok(Self { path })
+    }
+}
+
+impl Drop for ManifestEntry {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::udev::{
+        apply_udev, entry_pid, expand, live_entries, read_udev_template, ManifestEntry,
+    };
+    use crate::Args;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn udev_environment() -> HashMap<String, String> {
+        [
+            ("DEVNAME", "/dev/ttyACM0"),
+            ("ID_VENDOR_ID", "1546"),
+            ("ID_MODEL_ID", "01a9"),
+            ("ID_SERIAL_SHORT", "0123456"),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn test_expand() {
+        let variables = udev_environment();
+        assert_eq!(
+            expand("gps-{ID_VENDOR_ID}:{ID_MODEL_ID}", &variables),
+            Ok("gps-1546:01a9".to_string())
+        );
+        assert!(expand("{ID_PATH}", &variables).is_err());
+        assert!(expand("{ID_PATH", &variables).is_err());
+    }
+
+    #[test]
+    fn test_apply_udev() {
+        let mut args = Args::default();
+        apply_udev(&mut args, udev_environment()).unwrap();
+        assert_eq!(args.master, PathBuf::from("/dev/ttyACM0"));
+        assert_eq!(args.name.as_deref(), Some("0123456"));
+        assert_eq!(args.slave1, PathBuf::from("/run/ttytee/0123456/slave1.pty"));
+
+        let template = std::env::temp_dir().join("ttytee_udev_template.conf");
+        fs::write(
+            &template,
+            "# per model\nname=gps-{ID_MODEL_ID}-{kernel}\nslave0=/tmp/{name}.pty\nbaudrate=115200\n",
+        )
+        .unwrap();
+        let mut args = Args {
+            udev_template: Some(read_udev_template(template.to_str().unwrap()).unwrap()),
+            ..Default::default()
+        };
+        apply_udev(&mut args, udev_environment()).unwrap();
+        assert_eq!(args.slave0, PathBuf::from("/tmp/gps-01a9-ttyACM0.pty"));
+        assert_eq!(args.baudrate, 115_200);
+        fs::write(&template, "port=2947\n").unwrap();
+        assert!(read_udev_template(template.to_str().unwrap())
+            .unwrap_err()
+            .ends_with("line 1: unknown key \"port\""));
+        fs::remove_file(&template).unwrap();
+
+        // not started by udev and no udev database.
+        let mut args = Args {
+            master: PathBuf::from("/nonexistent/ttyACM0"),
+            ..Default::default()
+        };
+        assert!(apply_udev(&mut args, HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_manifest_entry() {
+        let dir = std::env::temp_dir().join("ttytee_manifest_test");
+        fs::remove_dir_all(&dir).ok();
+        let entry = ManifestEntry::register(
+            &dir,
+            "0123456",
+            Path::new("/dev/ttyACM0"),
+            &[Path::new("/run/ttytee/0123456/slave0.pty")],
+        )
+        .unwrap();
+        let path = dir.join("instances/0123456.json");
+        let manifest = fs::read_to_string(&path).unwrap();
+        assert!(manifest.starts_with("{\"name\":\"0123456\",\"pid\":"));
+        assert!(manifest.ends_with(
+            ",\"master\":\"/dev/ttyACM0\",\"slaves\":[\"/run/ttytee/0123456/slave0.pty\"]}\n"
+        ));
+        assert_eq!(entry_pid(&manifest), Some(std::process::id()));
+        assert_eq!(live_entries(&dir), vec![manifest]);
+
+        // left by an instance that was killed.
+        let stale = dir.join("instances/7654321.json");
+        fs::write(
+            &stale,
+            "{\"name\":\"7654321\",\"pid\":999999999,\"master\":\"/dev/ttyACM1\"}\n",
+        )
+        .unwrap();
+        assert_eq!(live_entries(&dir).len(), 1);
+        assert!(!stale.exists());
+        drop(entry);
+        assert!(!path.exists());
+        assert!(live_entries(&dir).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,875 @@
+//! Validation of the configuration before anything is opened, so all the problems are reported
+//! at once instead of failing mid-run.
+
+use crate::backend::MasterBackend;
+use crate::endpoint::format::OutputFormat;
+use crate::endpoint::{EndpointKind, EndpointSpec};
+use crate::framing::Protocol;
+use crate::i2c::parse_i2c_master;
+use crate::instances::{find_loop, writers_of};
+use crate::reader::EofPolicy;
+use crate::remote::parse_remote_master;
+use crate::transform::TransformSpec;
+use crate::trigger::Trigger;
+use crate::{endpoint_options, Args};
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::CString;
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+/// A problem in the configuration.
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    // stable identifier of the kind of problem, for the scripts parsing the log.
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.code, self.message)
+    }
+}
+
+pub fn problem(code: &'static str, message: String) -> Problem {
+    Problem { code, message }
+}
+
+// The path made absolute and normalized without resolving it, the links to create don't exist yet.
+fn absolute(path: &Path) -> PathBuf {
+    let path = match env::current_dir() {
+        Ok(current) if path.is_relative() => current.join(path),
+        _ => path.to_path_buf(),
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn is_writable_dir(dir: &Path) -> bool {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let Ok(dir) = CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(dir.as_ptr(), libc::W_OK | libc::X_OK) == 0 }
+}
+
+/// Check the configuration.
+///
+/// # Arguments
+///
+/// * `args`: the command line.
+/// * `specs`: all the endpoints, slave0 and slave1 included.
+///
+/// returns: Vec<Problem> empty if the configuration is valid.
+///
+pub fn validate(args: &Args, specs: &[EndpointSpec]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if args.master_read_timeout == 0 {
+        problems.push(problem(
+            "invalid-timeout",
+            "--master-read-timeout must be more than 0 ms.".to_string(),
+        ));
+    }
+    if (args.open_retries > 0 || args.wait_for_master) && args.open_retry_delay == 0 {
+        problems.push(problem(
+            "invalid-timeout",
+            "--open-retry-delay must be more than 0 ms.".to_string(),
+        ));
+    }
+    if args.stats_interval == Some(0) {
+        problems.push(problem(
+            "invalid-timeout",
+            "--stats-interval must be more than 0 s.".to_string(),
+        ));
+    }
+    if args.stats_push.is_some() && args.stats_push_interval == 0 {
+        problems.push(problem(
+            "invalid-timeout",
+            "--stats-push-interval must be more than 0 s.".to_string(),
+        ));
+    }
+    if args.flight_recorder.is_some() && args.flight_recorder_size == 0 {
+        problems.push(problem(
+            "invalid-size",
+            "--flight-recorder-size must be more than 0 MB.".to_string(),
+        ));
+    }
+
+    if args.triggered_capture.is_some() && args.capture_trigger.is_empty() {
+        problems.push(problem(
+            "missing-trigger",
+            "--triggered-capture needs at least a --capture-trigger.".to_string(),
+        ));
+    }
+    if args.triggered_capture.is_none() && !args.capture_trigger.is_empty() {
+        problems.push(problem(
+            "missing-trigger",
+            "--capture-trigger needs --triggered-capture.".to_string(),
+        ));
+    }
+    if !args.control_admin.is_empty() && args.control_socket.is_none() {
+        problems.push(problem(
+            "missing-control-socket",
+            "--control-admin needs --control-socket.".to_string(),
+        ));
+    }
+    if args.udev_template.is_some() && !args.from_udev {
+        problems.push(problem(
+            "missing-udev",
+            "--udev-template needs --from-udev.".to_string(),
+        ));
+    }
+    if args.on_master_eof == EofPolicy::Failover && args.failover_master.is_none() {
+        problems.push(problem(
+            "missing-failover",
+            "--on-master-eof failover needs --failover-master.".to_string(),
+        ));
+    }
+    if args.failover_master.is_some() && args.on_master_eof != EofPolicy::Failover {
+        problems.push(problem(
+            "missing-failover",
+            "--failover-master needs --on-master-eof failover.".to_string(),
+        ));
+    }
+    if args.master_backend == MasterBackend::RawLinux && !cfg!(feature = "raw-linux") {
+        problems.push(problem(
+            "missing-feature",
+            "--master-backend raw-linux needs a build with --features raw-linux.".to_string(),
+        ));
+    }
+    if (args.vmin > 0 || args.vtime > 0) && args.master_backend != MasterBackend::RawLinux {
+        problems.push(problem(
+            "missing-backend",
+            "--vmin and --vtime need --master-backend raw-linux.".to_string(),
+        ));
+    }
+    // a read of the reader would wait for the bytes with no end.
+    if args.vmin > 0 && args.vtime == 0 {
+        problems.push(problem(
+            "invalid-vmin",
+            "--vmin needs --vtime, the reads would wait for the bytes with no end.".to_string(),
+        ));
+    }
+    if args.merge_master.is_some() == args.merge_sentences.is_empty() {
+        problems.push(problem(
+            "missing-merge",
+            "--merge-master and --merge-sentences go together.".to_string(),
+        ));
+    }
+    if args.merge_baudrate.is_some() && args.merge_master.is_none() {
+        problems.push(problem(
+            "missing-merge",
+            "--merge-baudrate needs --merge-master.".to_string(),
+        ));
+    }
+    if args.merge_reorder.is_some() && args.merge_master.is_none() {
+        problems.push(problem(
+            "missing-merge",
+            "--merge-reorder needs --merge-master.".to_string(),
+        ));
+    }
+    if args.merge_master.is_some() && !args.framer.contains(&Protocol::Nmea) {
+        problems.push(problem(
+            "missing-framer",
+            "--merge-master needs --framer nmea.".to_string(),
+        ));
+    }
+    // the frames of Modbus and SLIP contain any byte, they would be found in the other protocols.
+    for protocol in [Protocol::Modbus, Protocol::Slip] {
+        if args.framer.contains(&protocol) && args.framer.len() > 1 {
+            problems.push(problem(
+                "invalid-framer",
+                format!(
+                    "--framer {} cannot be combined with another protocol.",
+                    protocol.to_possible_value().unwrap().get_name()
+                ),
+            ));
+        }
+    }
+    if args.triggered_capture.is_some()
+        && (args.capture_max_duration == 0 || args.capture_max_size == 0)
+    {
+        problems.push(problem(
+            "invalid-size",
+            "--capture-max-duration and --capture-max-size must be more than 0.".to_string(),
+        ));
+    }
+    if args
+        .capture_trigger
+        .iter()
+        .any(|trigger| matches!(trigger, Trigger::ChecksumErrors { .. }))
+        && args.framer.is_empty()
+    {
+        problems.push(problem(
+            "missing-framer",
+            "The checksum-errors trigger needs --framer.".to_string(),
+        ));
+    }
+
+    if args.max_memory == Some(0) {
+        problems.push(problem(
+            "invalid-size",
+            "--max-memory must be more than 0 MB.".to_string(),
+        ));
+    }
+    if args.max_fds == Some(0) {
+        problems.push(problem(
+            "invalid-size",
+            "--max-fds must be more than 0.".to_string(),
+        ));
+    }
+    // the syslog identity ends at the first space, [ or :.
+    if let Some(name) = &args.name {
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || "[]:".contains(c)) {
+            problems.push(problem(
+                "invalid-name",
+                format!("The instance name {:?} must be a single word.", name),
+            ));
+        }
+    }
+
+    if let Some(Err(err)) = parse_remote_master(&args.master) {
+        problems.push(problem(
+            "invalid-master",
+            format!("Invalid --master: {}.", err),
+        ));
+    }
+    if let Some(Err(err)) = parse_i2c_master(&args.master) {
+        problems.push(problem(
+            "invalid-master",
+            format!("Invalid --master: {}.", err),
+        ));
+    }
+    if args.sandbox && matches!(parse_remote_master(&args.master), Some(Ok(_))) {
+        problems.push(problem(
+            "sandbox-conflict",
+            "The ssh of a remote master cannot be restarted in the --sandbox.".to_string(),
+        ));
+    }
+    if args.ntrip.is_some() && matches!(parse_remote_master(&args.master), Some(Ok(_))) {
+        problems.push(problem(
+            "unwritable-master",
+            "The NTRIP corrections cannot be written to a remote master.".to_string(),
+        ));
+    }
+    if args.ntrip.is_some() && matches!(parse_i2c_master(&args.master), Some(Ok(_))) {
+        problems.push(problem(
+            "unwritable-master",
+            "The NTRIP corrections cannot be written to an I2C master.".to_string(),
+        ));
+    }
+    if args.init_commands.is_some()
+        && (matches!(parse_remote_master(&args.master), Some(Ok(_)))
+            || matches!(parse_i2c_master(&args.master), Some(Ok(_))))
+    {
+        problems.push(problem(
+            "unwritable-master",
+            "The --init-commands cannot be written to a remote or an I2C master.".to_string(),
+        ));
+    }
+    if args.rs485.is_some()
+        && (matches!(parse_remote_master(&args.master), Some(Ok(_)))
+            || matches!(parse_i2c_master(&args.master), Some(Ok(_))))
+    {
+        problems.push(problem(
+            "invalid-rs485",
+            "--rs485 needs a local serial master, not a remote or an I2C one.".to_string(),
+        ));
+    }
+
+    let master = args
+        .master
+        .canonicalize()
+        .unwrap_or_else(|_| absolute(&args.master));
+    let mut names = HashSet::new();
+    let mut targets: HashMap<String, &str> = HashMap::new();
+    for spec in specs {
+        if !names.insert(spec.name.as_str()) {
+            problems.push(problem(
+                "duplicate-name",
+                format!("Several endpoints are named {}.", spec.name),
+            ));
+        }
+        let target = match &spec.kind {
+            EndpointKind::Pty(path) => {
+                let link = absolute(path);
+                // the link may exist already, left by a crash or made by another instance.
+                let target = path.canonicalize().ok();
+                if link == master
+                    || link == absolute(&args.master)
+                    || target.as_ref() == Some(&master)
+                {
+                    problems.push(problem(
+                        "path-is-master",
+                        format!("{} would replace the master {:?}.", spec.name, args.master),
+                    ));
+                }
+                let dir = path.parent().unwrap_or(Path::new(""));
+                if !is_writable_dir(dir) {
+                    problems.push(problem(
+                        "unwritable-directory",
+                        format!(
+                            "The link of {} cannot be created in {:?}, check it exists and is writable.",
+                            spec.name,
+                            absolute(dir)
+                        ),
+                    ));
+                }
+                if let Some((pid, _)) = target
+                    .as_deref()
+                    .map(writers_of)
+                    .unwrap_or_default()
+                    .first()
+                {
+                    problems.push(problem(
+                        "link-in-use",
+                        format!(
+                            "The link of {} is a slave of another ttytee instance (pid {}).",
+                            spec.name, pid
+                        ),
+                    ));
+                }
+                Some(link.to_string_lossy().into_owned())
+            }
+            EndpointKind::File(path) | EndpointKind::Capture(path) | EndpointKind::Sqlite(path) => {
+                Some(absolute(path).to_string_lossy().into_owned())
+            }
+            EndpointKind::Tcp(address)
+            | EndpointKind::CompressedTcp(address)
+            | EndpointKind::Gpsd(address) => Some(format!("tcp {}", address)),
+            EndpointKind::Ntrip(mountpoint) => {
+                Some(format!("tcp {}:{}", mountpoint.host, mountpoint.port))
+            }
+            EndpointKind::Serial(device, _) => {
+                let device = device.canonicalize().unwrap_or_else(|_| absolute(device));
+                if device == master {
+                    problems.push(problem(
+                        "path-is-master",
+                        format!("{} would write to the master {:?}.", spec.name, args.master),
+                    ));
+                }
+                Some(device.to_string_lossy().into_owned())
+            }
+            EndpointKind::Udp(_) | EndpointKind::Stdout | EndpointKind::Can(_) => None,
+        };
+        if let Some(target) = target {
+            if let Some(other) = targets.insert(target.clone(), &spec.name) {
+                problems.push(problem(
+                    "duplicate-path",
+                    format!("{} and {} both use {}.", other, spec.name, target),
+                ));
+            }
+        }
+
+        let options = endpoint_options(args, spec);
+        if options.stale_timeout.is_zero() {
+            problems.push(problem(
+                "invalid-timeout",
+                format!("The stale timeout of {} must be more than 0 ms.", spec.name),
+            ));
+        }
+        if options.max_backlog == 0 {
+            problems.push(problem(
+                "invalid-size",
+                format!(
+                    "The max backlog of {} must be more than 0 bytes.",
+                    spec.name
+                ),
+            ));
+        }
+        if options.banner.is_some() && !matches!(spec.kind, EndpointKind::Pty(_)) {
+            problems.push(problem(
+                "not-a-pty",
+                format!(
+                    "{} is not a PTY endpoint, it cannot have a banner.",
+                    spec.name
+                ),
+            ));
+        }
+        if options.max_lag_frames.is_some() && args.framer.is_empty() {
+            problems.push(problem(
+                "missing-framer",
+                format!("The frame lag budget of {} needs --framer.", spec.name),
+            ));
+        }
+        if !options.transforms.is_empty() && args.framer.is_empty() {
+            problems.push(problem(
+                "missing-framer",
+                format!("The transforms of {} need --framer.", spec.name),
+            ));
+        }
+        // the hexdump shows the reads of the master, the frames they complete only when there are.
+        if !matches!(options.format, OutputFormat::Raw | OutputFormat::Hexdump)
+            && args.framer.is_empty()
+        {
+            problems.push(problem(
+                "missing-framer",
+                format!(
+                    "The {} format of {} needs --framer.",
+                    options.format, spec.name
+                ),
+            ));
+        }
+        if options.format == OutputFormat::Timebase && args.pps.is_none() {
+            problems.push(problem(
+                "missing-pps",
+                format!("The timebase format of {} needs --pps.", spec.name),
+            ));
+        }
+        // the other transforms and formats expect the frames as the master sent them.
+        let encode = options
+            .transforms
+            .iter()
+            .position(|transform| matches!(transform, TransformSpec::Encode { .. }));
+        if encode.is_some_and(|encode| {
+            encode + 1 < options.transforms.len() || options.format != OutputFormat::Raw
+        }) {
+            problems.push(problem(
+                "invalid-encoding",
+                format!(
+                    "The encode of {} must be its last transform, with the raw format.",
+                    spec.name
+                ),
+            ));
+        }
+    }
+
+    let links: Vec<PathBuf> = specs
+        .iter()
+        .filter_map(|spec| match &spec.kind {
+            EndpointKind::Pty(path) => Some(absolute(path)),
+            _ => None,
+        })
+        .collect();
+    let chain = find_loop(&args.master, &links);
+    if !chain.is_empty() {
+        problems.push(problem(
+            "feedback-loop",
+            format!(
+                "The master {:?} is fed by the ttytee instance(s) {:?} reading a slave of this one, the data would loop.",
+                args.master, chain
+            ),
+        ));
+    }
+
+    for (name, _) in &args.on_write_error {
+        if !specs.iter().any(|spec| spec.name == *name) {
+            problems.push(problem(
+                "unknown-endpoint",
+                format!("Unknown endpoint {} in --on-write-error.", name),
+            ));
+        }
+    }
+    let groups: HashSet<String> = specs
+        .iter()
+        .filter_map(|spec| endpoint_options(args, spec).group)
+        .collect();
+    for (group, _, _) in &args.group_option {
+        if !groups.contains(group) {
+            problems.push(problem(
+                "unknown-group",
+                format!("No endpoint is in the group {} of --group-option.", group),
+            ));
+        }
+    }
+    for group in &groups {
+        if names.contains(group.as_str()) {
+            problems.push(problem(
+                "duplicate-name",
+                format!("{} is the name of both an endpoint and a group.", group),
+            ));
+        }
+    }
+    for (name, _, _) in &args.endpoint_option {
+        if !specs.iter().any(|spec| spec.name == *name) {
+            problems.push(problem(
+                "unknown-endpoint",
+                format!("Unknown endpoint {} in --endpoint-option.", name),
+            ));
+        }
+    }
+    for name in &args.watchdog_consumer {
+        if !specs.iter().any(|spec| spec.name == *name) {
+            problems.push(problem(
+                "unknown-endpoint",
+                format!("Unknown endpoint {} in --watchdog-consumer.", name),
+            ));
+        }
+    }
+    if args.sandbox && !args.spawn.is_empty() {
+        problems.push(problem(
+            "sandbox-conflict",
+            "The consumers of --spawn cannot run in the --sandbox.".to_string(),
+        ));
+    }
+    if args.sandbox && args.rate_alert_hook.is_some() {
+        problems.push(problem(
+            "sandbox-conflict",
+            "The --rate-alert-hook cannot run in the --sandbox.".to_string(),
+        ));
+    }
+    for spawn in &args.spawn {
+        match specs.iter().find(|spec| spec.name == spawn.slave) {
+            Some(EndpointSpec {
+                kind: EndpointKind::Pty(_),
+                ..
+            }) => {}
+            Some(_) => problems.push(problem(
+                "not-a-pty",
+                format!(
+                    "{} is not a PTY endpoint, cannot spawn a consumer on it.",
+                    spawn.slave
+                ),
+            )),
+            None => problems.push(problem(
+                "unknown-endpoint",
+                format!("Unknown endpoint {} in --spawn.", spawn.slave),
+            )),
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::MasterBackend;
+    use crate::control::ControlAdmin;
+    use crate::endpoint::parse_endpoint_spec;
+    use crate::framing::Protocol;
+    use crate::init::InitCommands;
+    use crate::ntrip::parse_ntrip_source;
+    use crate::reader::EofPolicy;
+    use crate::rs485::Rs485Mode;
+    use crate::spawn::parse_spawn_spec;
+    use crate::validate::validate;
+    use crate::{endpoint_options, endpoint_specs, Args};
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::PathBuf;
+
+    fn valid_args() -> Args {
+        Args {
+            master: PathBuf::from("/dev/ttyUSB0"),
+            slave0: PathBuf::from("/tmp/slave0"),
+            slave1: PathBuf::from("/tmp/slave1"),
+            master_read_timeout: 1000,
+            slave_read_timeout: 1000,
+            ..Default::default()
+        }
+    }
+
+    fn codes(args: &Args) -> Vec<&'static str> {
+        validate(args, &endpoint_specs(args))
+            .iter()
+            .map(|problem| problem.code)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        assert!(codes(&valid_args()).is_empty());
+    }
+
+    #[test]
+    fn test_remote_master() {
+        let args = Args {
+            master: PathBuf::from("ssh://bench"),
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["invalid-master"]);
+        let args = Args {
+            master: PathBuf::from("ssh://bench:/dev/ttyACM0"),
+            sandbox: true,
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["sandbox-conflict"]);
+        let args = Args {
+            master: PathBuf::from("ssh://bench:/dev/ttyACM0"),
+            ntrip: Some(parse_ntrip_source("ntrip://caster/BASE1").unwrap()),
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["unwritable-master"]);
+        let args = Args {
+            master: PathBuf::from("i2c:///dev/i2c-1:42"),
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["invalid-master"]);
+        let args = Args {
+            master: PathBuf::from("i2c:///dev/i2c-1:0x42"),
+            ntrip: Some(parse_ntrip_source("ntrip://caster/BASE1").unwrap()),
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["unwritable-master"]);
+        let args = Args {
+            master: PathBuf::from("ssh://bench:/dev/ttyACM0"),
+            init_commands: Some(InitCommands {
+                path: PathBuf::from("init.txt"),
+                steps: Vec::new(),
+            }),
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["unwritable-master"]);
+        let args = Args {
+            master: PathBuf::from("ssh://bench:/dev/ttyACM0"),
+            rs485: Some(Rs485Mode::Kernel),
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["invalid-rs485"]);
+        let args = Args {
+            master: PathBuf::from("/dev/ttyS1"),
+            endpoint: vec![parse_endpoint_spec("serial:///dev/ttyS1:115200").unwrap()],
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["path-is-master"]);
+    }
+
+    #[test]
+    fn test_triggered_capture() {
+        let args = Args {
+            triggered_capture: Some(PathBuf::from("/tmp/gps-%H%M%S.cap")),
+            capture_max_duration: 300,
+            capture_max_size: 16,
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["missing-trigger"]);
+        let args = Args {
+            capture_trigger: vec!["checksum-errors:5/10".parse().unwrap()],
+            ..args
+        };
+        assert_eq!(codes(&args), vec!["missing-framer"]);
+        let args = Args {
+            framer: vec![Protocol::Nmea],
+            ..args
+        };
+        assert!(codes(&args).is_empty());
+    }
+
+    #[test]
+    fn test_group_options() {
+        let args = Args {
+            endpoint: vec![
+                parse_endpoint_spec("tcp://0.0.0.0:5000?name=net&group=besteffort").unwrap(),
+                parse_endpoint_spec("udp://10.0.0.1:5000?group=besteffort&max-lag-frames=2")
+                    .unwrap(),
+            ],
+            group_option: vec![(
+                "besteffort".to_string(),
+                "max-lag-frames".to_string(),
+                "5".to_string(),
+            )],
+            framer: vec![Protocol::Nmea],
+            ..valid_args()
+        };
+        assert!(codes(&args).is_empty());
+        // the options of the endpoints win over the ones of the group.
+        let lag_budgets: Vec<Option<usize>> = endpoint_specs(&args)
+            .iter()
+            .map(|spec| endpoint_options(&args, spec).max_lag_frames)
+            .collect();
+        assert_eq!(lag_budgets, vec![None, None, Some(5), Some(2)]);
+    }
+
+    #[test]
+    fn test_hexdump_without_framer() {
+        let args = Args {
+            hexdump_pty: Some(PathBuf::from("/tmp/hexdump")),
+            ..valid_args()
+        };
+        assert!(codes(&args).is_empty());
+        let args = Args {
+            endpoint: vec![parse_endpoint_spec("udp://127.0.0.1:5000?format=json").unwrap()],
+            ..args
+        };
+        assert_eq!(codes(&args), vec!["missing-framer"]);
+    }
+
+    #[test]
+    fn test_timebase_needs_pps() {
+        let args = Args {
+            endpoint: vec![parse_endpoint_spec("udp://127.0.0.1:5000?format=timebase").unwrap()],
+            framer: vec![Protocol::Nmea],
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["missing-pps"]);
+        let args = Args {
+            pps: Some(PathBuf::from("/dev/pps0")),
+            ..args
+        };
+        assert!(codes(&args).is_empty());
+    }
+
+    #[test]
+    fn test_failover_needs_device() {
+        let args = Args {
+            on_master_eof: EofPolicy::Failover,
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["missing-failover"]);
+        let args = Args {
+            failover_master: Some(PathBuf::from("/dev/ttyUSB1")),
+            ..args
+        };
+        assert!(codes(&args).is_empty());
+    }
+
+    #[test]
+    fn test_modbus_framer_alone() {
+        let args = Args {
+            framer: vec![Protocol::Modbus],
+            ..valid_args()
+        };
+        assert!(codes(&args).is_empty());
+        let args = Args {
+            framer: vec![Protocol::Nmea, Protocol::Modbus],
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["invalid-framer"]);
+        let args = Args {
+            framer: vec![Protocol::Slip, Protocol::Ubx],
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["invalid-framer"]);
+    }
+
+    #[test]
+    fn test_encode_last() {
+        let args = Args {
+            endpoint: vec![parse_endpoint_spec(
+                "udp://10.0.0.1:5000?decimate=SLIP:2&encode=length-prefixed",
+            )
+            .unwrap()],
+            framer: vec![Protocol::Slip],
+            ..valid_args()
+        };
+        assert!(codes(&args).is_empty());
+        let args = Args {
+            endpoint: vec![
+                parse_endpoint_spec("udp://10.0.0.1:5000?encode=slip&decimate=SLIP:2").unwrap(),
+                parse_endpoint_spec("udp://10.0.0.1:5001?encode=raw&format=json").unwrap(),
+            ],
+            framer: vec![Protocol::Slip],
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["invalid-encoding", "invalid-encoding"]);
+    }
+
+    #[test]
+    fn test_merge_needs_sentences_and_framer() {
+        let args = Args {
+            merge_master: Some(PathBuf::from("/dev/ttyUSB1")),
+            framer: Vec::new(),
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["missing-merge", "missing-framer"]);
+        let args = Args {
+            merge_sentences: vec!["HDT".to_string()],
+            framer: vec![Protocol::Nmea],
+            ..args
+        };
+        assert!(codes(&args).is_empty());
+    }
+
+    #[test]
+    fn test_vmin_needs_raw_backend() {
+        let args = Args {
+            vmin: 16,
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["missing-backend", "invalid-vmin"]);
+        let args = Args {
+            master_backend: MasterBackend::RawLinux,
+            vtime: 1,
+            ..args
+        };
+        let expected: &[&str] = if cfg!(feature = "raw-linux") {
+            &[]
+        } else {
+            &["missing-feature"]
+        };
+        assert_eq!(codes(&args), expected);
+    }
+
+    #[test]
+    fn test_control_admin_needs_socket() {
+        let args = Args {
+            control_admin: vec![ControlAdmin::Gid(20)],
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["missing-control-socket"]);
+        let args = Args {
+            control_socket: Some(PathBuf::from("/tmp/ttytee.sock")),
+            ..args
+        };
+        assert!(codes(&args).is_empty());
+    }
+
+    #[test]
+    fn test_link_resolving_to_master() {
+        let link = PathBuf::from("/tmp/ttytee_validate_link");
+        fs::remove_file(&link).ok();
+        symlink("/dev/null", &link).unwrap();
+        let args = Args {
+            master: PathBuf::from("/dev/null"),
+            slave0: link.clone(),
+            ..valid_args()
+        };
+        assert_eq!(codes(&args), vec!["path-is-master"]);
+        fs::remove_file(&link).unwrap();
+    }
+
+    #[test]
+    fn test_all_problems_are_reported() {
+        let args = Args {
+            slave1: PathBuf::from("/tmp/../tmp/slave0"),
+            master_read_timeout: 0,
+            endpoint: vec![
+                parse_endpoint_spec("pty:///dev/ttyUSB0?stale-timeout=0").unwrap(),
+                parse_endpoint_spec("pty:///nonexistent/slave2?name=slave0").unwrap(),
+                parse_endpoint_spec("tcp://0.0.0.0:5000?name=net").unwrap(),
+            ],
+            spawn: vec![parse_spawn_spec("net: gpsd {pty}").unwrap()],
+            name: Some("gps front".to_string()),
+            watchdog_consumer: vec!["gpsd".to_string()],
+            group_option: vec![(
+                "realtime".to_string(),
+                "max-lag-frames".to_string(),
+                "5".to_string(),
+            )],
+            ..valid_args()
+        };
+        let mut codes = codes(&args);
+        codes.sort();
+        assert_eq!(
+            codes,
+            vec![
+                "duplicate-name",
+                "duplicate-path",
+                "invalid-name",
+                "invalid-timeout",
+                "invalid-timeout",
+                "not-a-pty",
+                "path-is-master",
+                "unknown-endpoint",
+                "unknown-group",
+                "unwritable-directory",
+            ]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Feeding of a hardware watchdog tied to the data flow, for the unattended installations: the
+//! watchdog is fed only while the master sends data and a priority consumer reads it, so a wedged
+//! GNSS pipeline ends with a board reset instead of going unnoticed.
+//!
+//! The watchdog is opened once, before the sandbox. When ttytee stops normally, on SIGTERM and SIGINT
+//! too, the magic close character is written so the board is not reset, a crash leaves it armed.
+
+use crate::endpoint::ManagedEndpoint;
+use crate::events::Event;
+use log::{info, warn};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// How often the watchdog is fed, well under the usual timeouts of 15 to 60 s.
+const FEED_PERIOD: Duration = Duration::from_secs(1);
+// How long the master or the consumers can be silent before the feeding stops.
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A hardware watchdog fed while the data flows.
+pub struct Watchdog {
+    file: File,
+    // the priority consumers, any endpoint counts when empty.
+    consumers: Vec<String>,
+    last_feed: Option<Instant>,
+    // to log the changes only.
+    starving: bool,
+}
+
+impl Watchdog {
+    /// Open a watchdog device, which arms it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: the device, usually /dev/watchdog.
+    /// * `consumers`: the names of the endpoints that must be reading, any endpoint when empty.
+    ///
+    /// returns: Result<Watchdog, Error>
+    ///
+    pub fn open(path: &Path, consumers: &[String]) -> io::Result<Self> {
+        Ok(Self {
+            file: OpenOptions::new().append(true).open(path)?,
+            consumers: consumers.to_vec(),
+            last_feed: None,
+            starving: false,
+        })
+    }
+
+    /// Whether the data is flowing: the master sent data recently and a priority consumer read it.
+    fn flowing(
+        &self,
+        now: Instant,
+        last_master_data: Option<Instant>,
+        endpoints: &[ManagedEndpoint],
+    ) -> bool {
+        let recent = |at: Option<Instant>| matches!(at, Some(at) if now.saturating_duration_since(at) < STALL_TIMEOUT);
+        recent(last_master_data)
+            && endpoints
+                .iter()
+                .filter(|endpoint| {
+                    self.consumers.is_empty() || self.consumers.contains(&endpoint.name)
+                })
+                .any(|endpoint| recent(endpoint.drained_at()))
+    }
+
+    /// Feed the watchdog if it is time to and the data is flowing.
+    ///
+    /// # Arguments
+    ///
+    /// * `now`: the current time.
+    /// * `last_master_data`: the last time data was read from the master.
+    /// * `endpoints`: the endpoints, to find out whether the priority consumers are reading.
+    ///
+    pub fn poll(
+        &mut self,
+        now: Instant,
+        last_master_data: Option<Instant>,
+        endpoints: &[ManagedEndpoint],
+    ) {
+        if matches!(self.last_feed, Some(last_feed) if now.duration_since(last_feed) < FEED_PERIOD)
+        {
+            return;
+        }
+        if !self.flowing(now, last_master_data, endpoints) {
+            if !self.starving {
+                warn!(
+                    event = Event::WatchdogStarving.code();
+                    "The data is not flowing anymore, the watchdog is not fed."
+                );
+                self.starving = true;
+            }
+            return;
+        }
+        if self.starving {
+            info!("The data is flowing again, the watchdog is fed.");
+            self.starving = false;
+        }
+        self.last_feed = Some(now);
+        if let Err(err) = self.file.write_all(b"\0") {
+            warn!("Could not feed the watchdog: {}.", err);
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        // the magic close, the drivers with nowayout ignore it.
+        self.file.write_all(b"V").ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::Backoff;
+    use crate::endpoint::{Endpoint, EndpointOptions, ManagedEndpoint};
+    use crate::watchdog::Watchdog;
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    // A consumer reading as much as the test says.
+    struct Backlog(Arc<AtomicUsize>);
+
+    impl Endpoint for Backlog {
+        fn write(&mut self, data: &[u8]) -> io::Result<()> {
+            self.0.fetch_add(data.len(), Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn pending(&self) -> io::Result<usize> {
+            Ok(self.0.load(Ordering::Relaxed))
+        }
+    }
+
+    #[test]
+    fn test_feeding() {
+        let path = PathBuf::from("/tmp/ttytee_watchdog_test");
+        fs::write(&path, b"").unwrap();
+        let backlog = Arc::new(AtomicUsize::new(0));
+        let mut endpoints = vec![ManagedEndpoint::new(
+            "gpsd",
+            Box::new(Backlog(backlog.clone())),
+            EndpointOptions::default(),
+            Backoff::new(Duration::from_millis(50), Duration::from_secs(5)),
+        )];
+        let mut watchdog = Watchdog::open(&path, &["gpsd".to_string()]).unwrap();
+        let start = Instant::now();
+        let second = |n: u64| start + Duration::from_secs(n);
+
+        // the master sends but nothing is read.
+        endpoints[0].send(b"$GPGGA", &[], 0, second(0)).unwrap();
+        endpoints[0].send(b"$GPGGA", &[], 0, second(1)).unwrap();
+        watchdog.poll(second(1), Some(second(1)), &endpoints);
+        assert_eq!(fs::read(&path).unwrap(), b"");
+
+        // the consumer reads.
+        backlog.store(0, Ordering::Relaxed);
+        endpoints[0].send(b"$GPGGA", &[], 0, second(2)).unwrap();
+        watchdog.poll(second(2), Some(second(2)), &endpoints);
+        // not more than once per second.
+        watchdog.poll(second(2), Some(second(2)), &endpoints);
+        assert_eq!(fs::read(&path).unwrap(), b"\0");
+
+        // the master is silent.
+        watchdog.poll(second(9), Some(second(2)), &endpoints);
+        assert_eq!(fs::read(&path).unwrap(), b"\0");
+
+        // an endpoint that is not a priority consumer doesn't count.
+        let mut other = Watchdog::open(&path, &["net".to_string()]).unwrap();
+        other.poll(second(2), Some(second(2)), &endpoints);
+        drop(other);
+        drop(watchdog);
+        assert_eq!(fs::read(&path).unwrap(), b"\0VV");
+        fs::remove_file(&path).unwrap();
+    }
+}